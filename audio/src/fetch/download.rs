@@ -0,0 +1,72 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use http_body_util::BodyExt;
+use hyper::{header::CONTENT_RANGE, StatusCode};
+
+use librespot_core::{cdn_url::CdnUrl, Error, FileId, Session};
+
+use super::AudioFileError;
+
+// Large enough to keep the number of requests down, small enough to not lose
+// much when the connection drops halfway.
+const DOWNLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Downloads an audio file as is, i.e. still encrypted, to `path`. When `path`
+/// already holds part of the file from an earlier attempt, the download resumes
+/// from there.
+///
+/// `progress` is called with the number of bytes downloaded and the file size
+/// after every chunk. Returning an error from it aborts the download.
+pub async fn download_file<F>(
+    session: &Session,
+    file_id: FileId,
+    path: &Path,
+    mut progress: F,
+) -> Result<usize, Error>
+where
+    F: FnMut(usize, usize) -> Result<(), Error>,
+{
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut offset = file.metadata()?.len() as usize;
+    let mut file_size = None;
+
+    let cdn_url = CdnUrl::new(file_id).resolve_audio(session).await?;
+
+    while file_size.map_or(true, |file_size| offset < file_size) {
+        let response = session
+            .spclient()
+            .request_from_cdn(&cdn_url, offset, DOWNLOAD_CHUNK_SIZE)
+            .await?;
+
+        let code = response.status();
+        if code == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+            // An earlier attempt got the whole file, but did not finish up.
+            break;
+        } else if code != StatusCode::PARTIAL_CONTENT {
+            return Err(AudioFileError::StatusCode(code).into());
+        }
+
+        let header_value = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .ok_or(AudioFileError::Header)?;
+        let str_value = header_value.to_str()?;
+        let slash_index = str_value.find('/').unwrap_or_default();
+        let total: usize = str_value[slash_index + 1..].parse()?;
+        file_size = Some(total);
+
+        let data = response.into_body().collect().await?.to_bytes();
+        if data.is_empty() {
+            return Err(AudioFileError::NoData.into());
+        }
+
+        file.write_all(&data)?;
+        offset += data.len();
+
+        progress(offset, total)?;
+    }
+
+    file.sync_all()?;
+
+    Ok(offset)
+}
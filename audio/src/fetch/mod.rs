@@ -1,3 +1,4 @@
+mod download;
 mod receive;
 
 use std::{
@@ -11,9 +12,8 @@ use std::{
     time::Duration,
 };
 
-use futures_util::{future::IntoStream, StreamExt, TryFutureExt};
+use futures_util::TryFutureExt;
 use hyper::{body::Incoming, header::CONTENT_RANGE, Response, StatusCode};
-use hyper_util::client::legacy::ResponseFuture;
 use parking_lot::{Condvar, Mutex};
 use tempfile::NamedTempFile;
 use thiserror::Error;
@@ -21,6 +21,7 @@ use tokio::sync::{mpsc, oneshot, Semaphore};
 
 use librespot_core::{cdn_url::CdnUrl, Error, FileId, Session};
 
+pub use self::download::download_file;
 use self::receive::audio_file_fetch;
 
 use crate::range_set::{Range, RangeSet};
@@ -93,6 +94,11 @@ pub struct AudioFetchParams {
 
     /// The time we will wait to obtain status updates on downloading.
     pub download_timeout: Duration,
+
+    /// The number of requests to the Spotify servers that may be in flight at once per file.
+    /// Larger requests are split up to make use of them. More requests help on high latency
+    /// links, fewer keep memory use down.
+    pub concurrent_requests: usize,
 }
 
 impl Default for AudioFetchParams {
@@ -110,6 +116,7 @@ impl Default for AudioFetchParams {
             download_timeout: Duration::from_secs(
                 (minimum_download_size / minimum_throughput) as u64,
             ),
+            concurrent_requests: 1,
         }
     }
 }
@@ -133,7 +140,6 @@ pub enum AudioFile {
 
 #[derive(Debug)]
 pub struct StreamingRequest {
-    streamer: IntoStream<ResponseFuture>,
     initial_response: Option<Response<Incoming>>,
     offset: usize,
     length: usize,
@@ -186,6 +192,64 @@ impl StreamLoaderController {
         }
     }
 
+    /// The number of bytes from the read position to the end of the file.
+    /// For cached files, this is the whole file.
+    pub fn remaining(&self) -> usize {
+        match self.stream_shared {
+            Some(ref shared) => self.len().saturating_sub(shared.read_position()),
+            None => self.len(),
+        }
+    }
+
+    /// The number of bytes from the read position on that can be read without
+    /// waiting for the network.
+    pub fn buffered(&self) -> usize {
+        match self.stream_shared {
+            Some(ref shared) => shared
+                .download_status
+                .lock()
+                .downloaded
+                .contained_length_from_value(shared.read_position()),
+            None => self.len(),
+        }
+    }
+
+    /// The number of bytes of the file that were downloaded so far. Zero for
+    /// cached files.
+    pub fn downloaded(&self) -> usize {
+        match self.stream_shared {
+            Some(ref shared) => shared.download_status.lock().downloaded.len(),
+            None => 0,
+        }
+    }
+
+    /// Requests `length` bytes from the read position on without waiting for
+    /// them. Returns the number of bytes buffered so far.
+    pub fn fetch_buffered(&self, length: usize) -> usize {
+        let shared = match self.stream_shared {
+            Some(ref shared) => shared,
+            None => return self.len(),
+        };
+
+        let start = shared.read_position();
+        let length = length.min(self.len().saturating_sub(start));
+        self.fetch(Range::new(start, length));
+
+        shared
+            .download_status
+            .lock()
+            .downloaded
+            .contained_length_from_value(start)
+    }
+
+    /// The measured network throughput in bytes per second, if any.
+    pub fn throughput(&self) -> Option<usize> {
+        self.stream_shared
+            .as_ref()
+            .map(|shared| shared.throughput())
+            .filter(|&throughput| throughput > 0)
+    }
+
     pub fn ping_time(&self) -> Option<Duration> {
         self.stream_shared.as_ref().map(|shared| shared.ping_time())
     }
@@ -436,15 +500,13 @@ impl AudioFileStreaming {
         // larger than the audio file we're going to stream later on. This is OK; requesting
         // `Content-Range` > `Content-Length` will return the complete file with status code
         // 206 Partial Content.
-        let mut streamer =
-            session
-                .spclient()
-                .stream_from_cdn(&cdn_url, 0, minimum_download_size)?;
-
         // Get the first chunk with the headers to get the file size.
         // The remainder of that chunk with possibly also a response body is then
         // further processed in `audio_file_fetch`.
-        let response = streamer.next().await.ok_or(AudioFileError::NoData)??;
+        let response = session
+            .spclient()
+            .request_from_cdn(&cdn_url, 0, minimum_download_size)
+            .await?;
 
         let code = response.status();
         if code != StatusCode::PARTIAL_CONTENT {
@@ -466,7 +528,6 @@ impl AudioFileStreaming {
         let file_size = str_value[slash_index + 1..].parse()?;
 
         let initial_request = StreamingRequest {
-            streamer,
             initial_response: Some(response),
             offset: 0,
             length: upper_bound + 1,
@@ -482,7 +543,7 @@ impl AudioFileStreaming {
                 downloaded: RangeSet::new(),
             }),
             download_streaming: AtomicBool::new(false),
-            download_slots: Semaphore::new(1),
+            download_slots: Semaphore::new(AudioFetchParams::get().concurrent_requests.max(1)),
             ping_time_ms: AtomicUsize::new(0),
             read_position: AtomicUsize::new(0),
             throughput: AtomicUsize::new(0),
@@ -6,11 +6,11 @@ use std::{
 };
 
 use bytes::Bytes;
-use futures_util::StreamExt;
 use http_body_util::BodyExt;
 use hyper::StatusCode;
 use tempfile::NamedTempFile;
 use tokio::sync::{mpsc, oneshot};
+use tracing::Instrument;
 
 use librespot_core::{http_client::HttpClient, session::Session, Error};
 
@@ -34,7 +34,32 @@ enum ReceivedData {
 
 const ONE_SECOND: Duration = Duration::from_secs(1);
 
+fn spawn_receive_data(
+    session: &Session,
+    shared: &Arc<AudioFileShared>,
+    file_data_tx: &mpsc::UnboundedSender<ReceivedData>,
+    request: StreamingRequest,
+) {
+    let span = tracing::debug_span!(
+        "audio_fetch",
+        file_id = %shared.cdn_url.file_id,
+        offset = request.offset,
+        length = request.length
+    );
+
+    session.spawn(
+        receive_data(
+            session.clone(),
+            shared.clone(),
+            file_data_tx.clone(),
+            request,
+        )
+        .instrument(span),
+    );
+}
+
 async fn receive_data(
+    session: Session,
     shared: Arc<AudioFileShared>,
     file_data_tx: mpsc::UnboundedSender<ReceivedData>,
     mut request: StreamingRequest,
@@ -47,6 +72,7 @@ async fn receive_data(
     let request_time = Instant::now();
     let mut measure_ping_time = true;
     let mut measure_throughput = true;
+    let mut requested = false;
 
     let result: Result<_, Error> = loop {
         let response = match request.initial_response.take() {
@@ -54,21 +80,30 @@ async fn receive_data(
                 // the request was already made outside of this function
                 measure_ping_time = false;
                 measure_throughput = false;
+                requested = true;
 
                 data
             }
-            None => match request.streamer.next().await {
-                Some(Ok(response)) => response,
-                Some(Err(e)) => break Err(e.into()),
-                None => {
-                    if actual_length != request.length {
-                        let msg = format!("did not expect body to contain {actual_length} bytes");
-                        break Err(Error::data_loss(msg));
-                    }
+            None if !requested => {
+                requested = true;
 
-                    break Ok(());
+                match session
+                    .spclient()
+                    .request_from_cdn(&shared.cdn_url, request.offset, request.length)
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(e) => break Err(e),
                 }
-            },
+            }
+            None => {
+                if actual_length != request.length {
+                    let msg = format!("did not expect body to contain {actual_length} bytes");
+                    break Err(Error::data_loss(msg));
+                }
+
+                break Ok(());
+            }
         };
 
         if measure_ping_time {
@@ -110,8 +145,6 @@ async fn receive_data(
         offset += data_size;
     };
 
-    drop(request.streamer);
-
     if measure_throughput {
         let duration = Instant::now().duration_since(request_time).as_millis();
         if actual_length > 0 && duration > 0 {
@@ -132,6 +165,15 @@ async fn receive_data(
 
     drop(permit);
 
+    if let Some(metrics) = session.metrics() {
+        metrics.audio_fetch(
+            shared.cdn_url.file_id,
+            actual_length,
+            request_time.elapsed(),
+            result.as_ref().err(),
+        );
+    }
+
     if let Err(e) = result {
         error!(
             "Streamer error requesting range {} +{}: {:?}",
@@ -186,7 +228,7 @@ impl AudioFileFetch {
         let mut ranges_to_request = RangeSet::new();
         ranges_to_request.add_range(&Range::new(offset, length));
 
-        // The iteration that follows spawns streamers fast, without awaiting them,
+        // The iteration that follows spawns requests fast, without awaiting them,
         // so holding the lock for the entire scope of this function should be faster
         // then locking and unlocking multiple times.
         let mut download_status = self.shared.download_status.lock();
@@ -196,27 +238,37 @@ impl AudioFileFetch {
 
         // TODO : refresh cdn_url when the token expired
 
+        // Split up the ranges so all download slots can be used, without going
+        // below the minimum download size.
+        let parts = self.params.concurrent_requests.max(1);
+
         for range in ranges_to_request.iter() {
-            let streamer = self.session.spclient().stream_from_cdn(
-                &self.shared.cdn_url,
-                range.start,
-                range.length,
-            )?;
-
-            download_status.requested.add_range(range);
-
-            let streaming_request = StreamingRequest {
-                streamer,
-                initial_response: None,
-                offset: range.start,
-                length: range.length,
-            };
+            let part_length = max(
+                range.length.div_ceil(parts),
+                self.params.minimum_download_size,
+            );
+
+            let mut start = range.start;
+            while start < range.end() {
+                let part = Range::new(start, min(part_length, range.end() - start));
 
-            self.session.spawn(receive_data(
-                self.shared.clone(),
-                self.file_data_tx.clone(),
-                streaming_request,
-            ));
+                download_status.requested.add_range(&part);
+
+                let streaming_request = StreamingRequest {
+                    initial_response: None,
+                    offset: part.start,
+                    length: part.length,
+                };
+
+                spawn_receive_data(
+                    &self.session,
+                    &self.shared,
+                    &self.file_data_tx,
+                    streaming_request,
+                );
+
+                start = part.end();
+            }
         }
 
         Ok(())
@@ -419,11 +471,7 @@ pub(super) async fn audio_file_fetch(
         download_status.requested.add_range(&requested_range);
     }
 
-    session.spawn(receive_data(
-        shared.clone(),
-        file_data_tx.clone(),
-        initial_request,
-    ));
+    spawn_receive_data(&session, &shared, &file_data_tx, initial_request);
 
     let params = AudioFetchParams::get();
 
@@ -7,6 +7,13 @@ pub struct ConnectConfig {
     pub is_group: bool,
     pub initial_volume: Option<u16>,
     pub has_volume_ctrl: bool,
+    /// Whether remote clients may log this device out, e.g. when it was
+    /// authenticated through zeroconf.
+    pub supports_logout: bool,
+    /// Whether to save the queue, position, shuffle and repeat in the cache,
+    /// and restore them on startup. The restored track is loaded, paused,
+    /// once the device becomes active without a client loading another one.
+    pub persist_state: bool,
 }
 
 impl Default for ConnectConfig {
@@ -17,6 +24,8 @@ impl Default for ConnectConfig {
             is_group: false,
             initial_volume: Some(50),
             has_volume_ctrl: true,
+            supports_logout: false,
+            persist_state: false,
         }
     }
 }
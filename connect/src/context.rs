@@ -8,7 +8,9 @@ use serde::{
     Deserialize,
 };
 
+// Fields missing from a response are left empty.
 #[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
 pub struct StationContext {
     pub uri: String,
     pub title: String,
@@ -7,4 +7,5 @@ use librespot_protocol as protocol;
 
 pub mod config;
 pub mod context;
+pub mod radio;
 pub mod spirc;
@@ -0,0 +1,104 @@
+//! Radio stations started from seeds, ready to be loaded into [`Spirc`].
+//!
+//! [`Spirc`]: crate::spirc::Spirc
+
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::{
+    context::StationContext,
+    core::{spotify_id::SpotifyItemType, Error, Session, SpotifyId},
+    protocol::spirc::TrackRef,
+    spirc::SpircLoadCommand,
+};
+
+#[derive(Debug, Error)]
+pub enum RadioError {
+    #[error("a radio needs at least one seed")]
+    NoSeeds,
+    #[error(
+        "cannot start a radio from <{0}>, only tracks, artists, albums and playlists are supported"
+    )]
+    Seed(String),
+}
+
+impl From<RadioError> for Error {
+    fn from(err: RadioError) -> Self {
+        Error::invalid_argument(err)
+    }
+}
+
+/// The first page of a radio station. The rest is fetched by `Spirc` as the
+/// station plays.
+#[derive(Debug, Clone)]
+pub struct Radio {
+    pub uri: String,
+    pub title: String,
+    pub image_uri: String,
+    pub tracks: Vec<TrackRef>,
+}
+
+impl Radio {
+    /// Starts a radio station from tracks, artists, albums or playlists. With
+    /// several seeds, the tracks of their stations are interleaved, and the
+    /// station of the first one continues once they run out.
+    pub async fn get(session: &Session, seeds: &[SpotifyId]) -> Result<Self, Error> {
+        if seeds.is_empty() {
+            return Err(RadioError::NoSeeds.into());
+        }
+
+        let mut stations = Vec::with_capacity(seeds.len());
+        for seed in seeds {
+            let uri = seed.to_uri()?;
+            if !matches!(
+                seed.item_type,
+                SpotifyItemType::Track
+                    | SpotifyItemType::Artist
+                    | SpotifyItemType::Album
+                    | SpotifyItemType::Playlist
+            ) {
+                return Err(RadioError::Seed(uri).into());
+            }
+
+            let response = session
+                .spclient()
+                .get_apollo_station("stations", &uri, None, Vec::new(), false)
+                .await?;
+            let station: StationContext = serde_json::from_slice(&response)?;
+            stations.push(station);
+        }
+
+        let mut seen = HashSet::new();
+        let mut tracks = Vec::new();
+        let longest = stations.iter().map(|s| s.tracks.len()).max().unwrap_or(0);
+        for i in 0..longest {
+            for station in &stations {
+                if let Some(track) = station.tracks.get(i) {
+                    if seen.insert(track.uri().to_owned()) {
+                        tracks.push(track.clone());
+                    }
+                }
+            }
+        }
+
+        let first = stations.swap_remove(0);
+        Ok(Self {
+            uri: first.uri,
+            title: first.title,
+            image_uri: first.image_uri,
+            tracks,
+        })
+    }
+
+    pub fn into_load_command(self, start_playing: bool) -> SpircLoadCommand {
+        SpircLoadCommand {
+            context_uri: self.uri,
+            start_playing,
+            shuffle: false,
+            repeat: false,
+            playing_track_index: 0,
+            tracks: self.tracks,
+        }
+    }
+}
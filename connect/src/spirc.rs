@@ -1,17 +1,17 @@
 use std::{
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use futures_util::{stream::FusedStream, FutureExt, StreamExt};
 
-use protobuf::Message;
+use protobuf::{Message, MessageField};
 use rand::prelude::SliceRandom;
 use thiserror::Error;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use crate::{
@@ -76,6 +76,17 @@ enum SpircPlayStatus {
 
 type BoxedStream<T> = Pin<Box<dyn FusedStream<Item = T> + Send>>;
 
+// What the saved state was saved for. It is saved again once this changes,
+// rather than on every notify.
+#[derive(PartialEq)]
+struct SavedState {
+    context_uri: String,
+    playing_track_index: u32,
+    tracks: usize,
+    shuffle: bool,
+    repeat: bool,
+}
+
 struct SpircTask {
     player: Arc<Player>,
     mixer: Arc<dyn Mixer>,
@@ -95,16 +106,102 @@ struct SpircTask {
     sender: MercurySender,
     commands: Option<mpsc::UnboundedReceiver<SpircCommand>>,
     player_events: Option<PlayerEventChannel>,
-
+    state_tx: watch::Sender<SpircState>,
+
+    supports_logout: bool,
+    persist_state: bool,
+    saved_state: Option<SavedState>,
+    // The position of a track restored from the cache, loaded once the device
+    // becomes active.
+    resume_position: Option<u32>,
+    logged_out: Arc<AtomicBool>,
     shutdown: bool,
     session: Session,
     resolve_context: Option<String>,
     autoplay_context: bool,
     context: Option<PageContext>,
+    // Track order from before shuffling, restored when shuffle is disabled.
+    unshuffled_tracks: Option<Vec<TrackRef>>,
 
     spirc_id: usize,
 }
 
+struct Subscriptions {
+    remote_update: BoxedStream<Result<(String, Frame), Error>>,
+    connection_id_update: BoxedStream<Result<String, Error>>,
+    user_attributes_update: BoxedStream<Result<UserAttributesUpdate, Error>>,
+    user_attributes_mutation: BoxedStream<Result<UserAttributesMutation, Error>>,
+}
+
+impl Subscriptions {
+    // Must be called before connecting the session, so no messages are missed.
+    fn new(session: &Session) -> Self {
+        let remote_update = Box::pin(
+            session
+                .mercury()
+                .listen_for("hm://remote/user/")
+                .map(UnboundedReceiverStream::new)
+                .flatten_stream()
+                .map(|response| -> Result<(String, Frame), Error> {
+                    let uri_split: Vec<&str> = response.uri.split('/').collect();
+                    let username = match uri_split.get(4) {
+                        Some(s) => s.to_string(),
+                        None => String::new(),
+                    };
+
+                    let data = response.payload.first().ok_or(SpircError::NoData)?;
+                    Ok((username, Frame::parse_from_bytes(data)?))
+                }),
+        );
+
+        let connection_id_update = Box::pin(
+            session
+                .mercury()
+                .listen_for("hm://pusher/v1/connections/")
+                .map(UnboundedReceiverStream::new)
+                .flatten_stream()
+                .map(|response| -> Result<String, Error> {
+                    let connection_id = response
+                        .uri
+                        .strip_prefix("hm://pusher/v1/connections/")
+                        .ok_or_else(|| SpircError::InvalidUri(response.uri.clone()))?;
+                    Ok(connection_id.to_owned())
+                }),
+        );
+
+        let user_attributes_update = Box::pin(
+            session
+                .mercury()
+                .listen_for("spotify:user:attributes:update")
+                .map(UnboundedReceiverStream::new)
+                .flatten_stream()
+                .map(|response| -> Result<UserAttributesUpdate, Error> {
+                    let data = response.payload.first().ok_or(SpircError::NoData)?;
+                    Ok(UserAttributesUpdate::parse_from_bytes(data)?)
+                }),
+        );
+
+        let user_attributes_mutation = Box::pin(
+            session
+                .mercury()
+                .listen_for("spotify:user:attributes:mutated")
+                .map(UnboundedReceiverStream::new)
+                .flatten_stream()
+                .map(|response| -> Result<UserAttributesMutation, Error> {
+                    let data = response.payload.first().ok_or(SpircError::NoData)?;
+                    Ok(UserAttributesMutation::parse_from_bytes(data)?)
+                }),
+        );
+
+        Self {
+            remote_update,
+            connection_id_update,
+            user_attributes_update,
+            user_attributes_mutation,
+        }
+    }
+}
+
 static SPIRC_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
@@ -126,6 +223,35 @@ pub enum SpircCommand {
     Load(SpircLoadCommand),
 }
 
+/// A snapshot of the state that was last reported to Spotify Connect.
+#[derive(Debug, Clone)]
+pub struct SpircState {
+    pub is_active: bool,
+    pub status: PlayStatus,
+    pub context_uri: String,
+    pub playing_track_index: u32,
+    /// Shared with the previous snapshot while the tracks stay the same.
+    pub tracks: Arc<[TrackRef]>,
+    pub shuffle: bool,
+    pub repeat: bool,
+    pub volume: u16,
+}
+
+impl Default for SpircState {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            status: PlayStatus::default(),
+            context_uri: String::new(),
+            playing_track_index: 0,
+            tracks: Vec::new().into(),
+            shuffle: false,
+            repeat: false,
+            volume: 0,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SpircLoadCommand {
     pub context_uri: String,
@@ -157,11 +283,19 @@ impl From<SpircLoadCommand> for State {
 const CONTEXT_TRACKS_HISTORY: usize = 10;
 const CONTEXT_FETCH_THRESHOLD: u32 = 5;
 
+// Enough to ride out a router reboot.
+const RECONNECT_TRIES: u32 = 10;
+const RECONNECT_INTERVAL_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_INTERVAL_MAX: Duration = Duration::from_secs(60);
+
 const VOLUME_STEPS: i64 = 64;
 const VOLUME_STEP_SIZE: u16 = 1024; // (u16::MAX + 1) / VOLUME_STEPS
 
+#[derive(Clone)]
 pub struct Spirc {
     commands: mpsc::UnboundedSender<SpircCommand>,
+    state_rx: watch::Receiver<SpircState>,
+    logged_out: Arc<AtomicBool>,
 }
 
 fn initial_state() -> State {
@@ -200,10 +334,9 @@ fn initial_device_state(config: ConnectConfig) -> DeviceState {
         protocol::spirc::CapabilityType::kGaiaEqConnectId,
         1,
     ));
-    // TODO: implement logout
     msg.capabilities.push(int_capability(
         protocol::spirc::CapabilityType::kSupportsLogout,
-        0,
+        config.supports_logout as i64,
     ));
     msg.capabilities.push(int_capability(
         protocol::spirc::CapabilityType::kIsObservable,
@@ -225,7 +358,6 @@ fn initial_device_state(config: ConnectConfig) -> DeviceState {
         protocol::spirc::CapabilityType::kSupportsExternalEpisodes,
         1,
     ));
-    // TODO: how would such a rename command be triggered? Handle it.
     msg.capabilities.push(int_capability(
         protocol::spirc::CapabilityType::kSupportsRename,
         1,
@@ -253,6 +385,10 @@ fn initial_device_state(config: ConnectConfig) -> DeviceState {
         .stringValue
         .push("audio/episode+track".to_string());
     supported_types.stringValue.push("audio/track".to_string());
+    // Chapters of audiobooks, which are played like episodes.
+    supported_types
+        .stringValue
+        .push("audio/audiobook".to_string());
     // other known types:
     // - "audio/ad"
     // - "audio/interruption"
@@ -267,7 +403,14 @@ fn url_encode(bytes: impl AsRef<[u8]>) -> String {
     form_urlencoded::byte_serialize(bytes.as_ref()).collect()
 }
 
+fn sender_uri(session: &Session) -> String {
+    format!("hm://remote/user/{}/", url_encode(session.username()))
+}
+
 impl Spirc {
+    /// Connects `session` and runs Spotify Connect on it. The returned task
+    /// reconnects with a new session when the connection is lost, and shuts
+    /// down the session in use when it ends.
     pub async fn new(
         config: ConnectConfig,
         session: Session,
@@ -280,62 +423,7 @@ impl Spirc {
 
         let ident = session.device_id().to_owned();
 
-        let remote_update = Box::pin(
-            session
-                .mercury()
-                .listen_for("hm://remote/user/")
-                .map(UnboundedReceiverStream::new)
-                .flatten_stream()
-                .map(|response| -> Result<(String, Frame), Error> {
-                    let uri_split: Vec<&str> = response.uri.split('/').collect();
-                    let username = match uri_split.get(4) {
-                        Some(s) => s.to_string(),
-                        None => String::new(),
-                    };
-
-                    let data = response.payload.first().ok_or(SpircError::NoData)?;
-                    Ok((username, Frame::parse_from_bytes(data)?))
-                }),
-        );
-
-        let connection_id_update = Box::pin(
-            session
-                .mercury()
-                .listen_for("hm://pusher/v1/connections/")
-                .map(UnboundedReceiverStream::new)
-                .flatten_stream()
-                .map(|response| -> Result<String, Error> {
-                    let connection_id = response
-                        .uri
-                        .strip_prefix("hm://pusher/v1/connections/")
-                        .ok_or_else(|| SpircError::InvalidUri(response.uri.clone()))?;
-                    Ok(connection_id.to_owned())
-                }),
-        );
-
-        let user_attributes_update = Box::pin(
-            session
-                .mercury()
-                .listen_for("spotify:user:attributes:update")
-                .map(UnboundedReceiverStream::new)
-                .flatten_stream()
-                .map(|response| -> Result<UserAttributesUpdate, Error> {
-                    let data = response.payload.first().ok_or(SpircError::NoData)?;
-                    Ok(UserAttributesUpdate::parse_from_bytes(data)?)
-                }),
-        );
-
-        let user_attributes_mutation = Box::pin(
-            session
-                .mercury()
-                .listen_for("spotify:user:attributes:mutated")
-                .map(UnboundedReceiverStream::new)
-                .flatten_stream()
-                .map(|response| -> Result<UserAttributesMutation, Error> {
-                    let data = response.payload.first().ok_or(SpircError::NoData)?;
-                    Ok(UserAttributesMutation::parse_from_bytes(data)?)
-                }),
-        );
+        let subscriptions = Subscriptions::new(&session);
 
         // pre-acquire client_token, preventing multiple request while running
         let _ = session.spclient().client_token().await?;
@@ -343,20 +431,22 @@ impl Spirc {
         // Connect *after* all message listeners are registered
         session.connect(credentials, true).await?;
 
-        let canonical_username = &session.username();
-        debug!("canonical_username: {}", canonical_username);
-        let sender_uri = format!("hm://remote/user/{}/", url_encode(canonical_username));
-
-        let sender = session.mercury().sender(sender_uri);
+        debug!("canonical_username: {}", session.username());
+        let sender = session.mercury().sender(sender_uri(&session));
 
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
 
         let initial_volume = config.initial_volume;
+        let supports_logout = config.supports_logout;
+        let persist_state = config.persist_state;
+        let logged_out = Arc::new(AtomicBool::new(false));
 
         let device = initial_device_state(config);
 
         let player_events = player.get_player_event_channel();
 
+        let (state_tx, state_rx) = watch::channel(SpircState::default());
+
         let mut task = SpircTask {
             player,
             mixer,
@@ -370,24 +460,37 @@ impl Spirc {
             play_request_id: None,
             play_status: SpircPlayStatus::Stopped,
 
-            remote_update,
-            connection_id_update,
-            user_attributes_update,
-            user_attributes_mutation,
+            remote_update: subscriptions.remote_update,
+            connection_id_update: subscriptions.connection_id_update,
+            user_attributes_update: subscriptions.user_attributes_update,
+            user_attributes_mutation: subscriptions.user_attributes_mutation,
             sender,
             commands: Some(cmd_rx),
             player_events: Some(player_events),
+            state_tx,
 
+            supports_logout,
+            persist_state,
+            saved_state: None,
+            resume_position: None,
+            logged_out: logged_out.clone(),
             shutdown: false,
             session,
 
             resolve_context: None,
             autoplay_context: false,
             context: None,
+            unshuffled_tracks: None,
 
             spirc_id,
         };
 
+        if persist_state {
+            if let Some(state) = task.session.cache().and_then(|cache| cache.connect_state()) {
+                task.restore_state(&state);
+            }
+        }
+
         if let Some(volume) = initial_volume {
             task.set_volume(volume);
         } else {
@@ -395,7 +498,11 @@ impl Spirc {
             task.set_volume(current_volume);
         }
 
-        let spirc = Spirc { commands: cmd_tx };
+        let spirc = Spirc {
+            commands: cmd_tx,
+            state_rx,
+            logged_out,
+        };
 
         task.hello()?;
 
@@ -447,10 +554,42 @@ impl Spirc {
     pub fn load(&self, command: SpircLoadCommand) -> Result<(), Error> {
         Ok(self.commands.send(SpircCommand::Load(command))?)
     }
+
+    /// Returns a receiver that is updated whenever the Connect state is reported.
+    pub fn subscribe_state(&self) -> watch::Receiver<SpircState> {
+        self.state_rx.clone()
+    }
+
+    /// Returns `true` once a remote client logged this device out. The cached
+    /// credentials have been removed by then and the spirc task is shutting down.
+    pub fn is_logged_out(&self) -> bool {
+        self.logged_out.load(Ordering::Acquire)
+    }
 }
 
 impl SpircTask {
     async fn run(mut self) {
+        loop {
+            self.run_session().await;
+
+            // Only reconnect when the connection was lost, not on purpose.
+            if self.shutdown || !self.session.is_invalid() || !self.reconnect().await {
+                break;
+            }
+        }
+
+        if self.sender.flush().await.is_err() {
+            warn!("Cannot flush spirc event sender when done.");
+        }
+
+        // The session may have been replaced while reconnecting, so the
+        // caller can't shut down the one in use.
+        if !self.session.is_invalid() {
+            self.session.shutdown();
+        }
+    }
+
+    async fn run_session(&mut self) {
         while !self.session.is_invalid() && !self.shutdown {
             let commands = self.commands.as_mut();
             let player_events = self.player_events.as_mut();
@@ -532,7 +671,10 @@ impl SpircTask {
                 },
                 context_uri = async { self.resolve_context.take() }, if self.resolve_context.is_some() => {
                     let context_uri = context_uri.unwrap(); // guaranteed above
-                    if context_uri.contains("spotify:show:") || context_uri.contains("spotify:episode:") {
+                    if context_uri.contains("spotify:show:")
+                        || context_uri.contains("spotify:episode:")
+                        || context_uri.contains("spotify:audiobook:")
+                    {
                         continue; // not supported by apollo stations
                     }
 
@@ -577,10 +719,95 @@ impl SpircTask {
                 else => break
             }
         }
+    }
 
-        if self.sender.flush().await.is_err() {
-            warn!("Cannot flush spirc event sender when done.");
+    /// Replaces the session after it lost its connection, e.g. because the
+    /// router rebooted, keeping the playback state and publishing it again.
+    /// Returns whether a new session could be connected.
+    async fn reconnect(&mut self) -> bool {
+        let mut interval = RECONNECT_INTERVAL_MIN;
+
+        for attempt in 1..=RECONNECT_TRIES {
+            info!("Connection lost, reconnecting (attempt {attempt} of {RECONNECT_TRIES})");
+
+            let (session, credentials) = self.session.recreate();
+            let credentials = match credentials {
+                Some(credentials) => credentials,
+                None => return false,
+            };
+
+            let subscriptions = Subscriptions::new(&session);
+            let connected = async {
+                session.spclient().client_token().await?;
+                session.connect(credentials, true).await
+            };
+
+            let connected = match self.unless_shutdown(connected).await {
+                Some(connected) => connected,
+                None => {
+                    session.shutdown();
+                    return false;
+                }
+            };
+
+            match connected {
+                Ok(()) => {
+                    self.player.set_session(session.clone());
+                    self.sender = session.mercury().sender(sender_uri(&session));
+                    self.remote_update = subscriptions.remote_update;
+                    self.connection_id_update = subscriptions.connection_id_update;
+                    self.user_attributes_update = subscriptions.user_attributes_update;
+                    self.user_attributes_mutation = subscriptions.user_attributes_mutation;
+                    self.session = session;
+
+                    info!("Reconnected");
+                    if let Err(e) = self.hello().and_then(|_| self.notify(None)) {
+                        warn!("Unable to publish state after reconnecting: {e}");
+                    }
+                    return true;
+                }
+                Err(e) => {
+                    warn!("Unable to reconnect: {e}");
+                    session.shutdown();
+                }
+            }
+
+            if self
+                .unless_shutdown(tokio::time::sleep(interval))
+                .await
+                .is_none()
+            {
+                return false;
+            }
+            interval = (interval * 2).min(RECONNECT_INTERVAL_MAX);
         }
+
+        error!("Giving up reconnecting after {RECONNECT_TRIES} attempts");
+        false
+    }
+
+    /// Waits for `future` while watching for commands, returning `None` when
+    /// the spirc is shut down in the meantime. Without a session there is no
+    /// way to act on other commands, so they are dropped.
+    async fn unless_shutdown<F: Future>(&mut self, future: F) -> Option<F::Output> {
+        tokio::pin!(future);
+        loop {
+            let commands = self.commands.as_mut();
+            tokio::select! {
+                output = &mut future => return Some(output),
+                cmd = async { commands?.recv().await }, if commands.is_some() => match cmd {
+                    Some(SpircCommand::Shutdown) | None => break,
+                    Some(cmd) => debug!("Ignoring SpircCommand::{cmd:?} while reconnecting"),
+                },
+            }
+        }
+
+        info!("Shut down while reconnecting");
+        self.shutdown = true;
+        if let Some(rx) = self.commands.as_mut() {
+            rx.close()
+        }
+        None
     }
 
     fn now_ms(&mut self) -> i64 {
@@ -601,6 +828,7 @@ impl SpircTask {
     fn handle_command(&mut self, cmd: SpircCommand) -> Result<(), Error> {
         if matches!(cmd, SpircCommand::Shutdown) {
             trace!("Received SpircCommand::Shutdown");
+            self.save_state(true);
             CommandSender::new(self, MessageType::kMessageTypeGoodbye).send()?;
             self.handle_disconnect();
             self.shutdown = true;
@@ -644,11 +872,11 @@ impl SpircTask {
                     self.notify(None)
                 }
                 SpircCommand::Shuffle(shuffle) => {
-                    self.state.set_shuffle(shuffle);
+                    self.handle_shuffle(shuffle);
                     self.notify(None)
                 }
                 SpircCommand::Repeat(repeat) => {
-                    self.state.set_repeat(repeat);
+                    self.handle_repeat(repeat);
                     self.notify(None)
                 }
                 SpircCommand::SetPosition(position) => {
@@ -895,6 +1123,18 @@ impl SpircTask {
             );
         }
 
+        // Let the other clients know which command our reported state is based on.
+        if !matches!(
+            update.typ(),
+            MessageType::kMessageTypeHello
+                | MessageType::kMessageTypeGoodbye
+                | MessageType::kMessageTypeProbe
+                | MessageType::kMessageTypeNotify
+        ) {
+            self.state.set_last_command_ident(ident.to_owned());
+            self.state.set_last_command_msgid(update.seq_nr());
+        }
+
         match update.typ() {
             MessageType::kMessageTypeHello => self.notify(Some(ident)),
 
@@ -939,31 +1179,12 @@ impl SpircTask {
             }
 
             MessageType::kMessageTypeRepeat => {
-                let repeat = update.state.repeat();
-                self.state.set_repeat(repeat);
-
-                self.player.emit_repeat_changed_event(repeat);
-
+                self.handle_repeat(update.state.repeat());
                 self.notify(None)
             }
 
             MessageType::kMessageTypeShuffle => {
-                let shuffle = update.state.shuffle();
-                self.state.set_shuffle(shuffle);
-                if shuffle {
-                    let current_index = self.state.playing_track_index();
-                    let tracks = &mut self.state.track;
-                    if !tracks.is_empty() {
-                        tracks.swap(0, current_index as usize);
-                        if let Some((_, rest)) = tracks.split_first_mut() {
-                            let mut rng = rand::thread_rng();
-                            rest.shuffle(&mut rng);
-                        }
-                        self.state.set_playing_track_index(0);
-                    }
-                }
-                self.player.emit_shuffle_changed_event(shuffle);
-
+                self.handle_shuffle(update.state.shuffle());
                 self.notify(None)
             }
 
@@ -1003,6 +1224,33 @@ impl SpircTask {
                 self.notify(None)
             }
 
+            MessageType::kMessageTypeLogout => {
+                if !self.supports_logout {
+                    warn!("Ignoring logout request, logout is not supported");
+                    return Ok(());
+                }
+                self.handle_logout()
+            }
+
+            MessageType::kMessageTypeUpdateMetadata => {
+                if let Some(metadata) = update.metadata.as_ref() {
+                    self.player.emit_backend_metadata_changed_event(
+                        metadata.type_().to_owned(),
+                        metadata.metadata().to_owned(),
+                    );
+                }
+                Ok(())
+            }
+
+            MessageType::kMessageTypeRename => {
+                let new_name = update.new_name();
+                if !new_name.is_empty() && new_name != self.device.name() {
+                    info!("Device renamed to \"{}\"", new_name);
+                    self.device.set_name(new_name.to_owned());
+                }
+                self.notify(None)
+            }
+
             MessageType::kMessageTypeVolume => {
                 self.set_volume(update.volume() as u16);
                 self.notify(None)
@@ -1026,10 +1274,35 @@ impl SpircTask {
         self.device.set_is_active(false);
         self.handle_stop();
 
+        // Don't wait for the player to report that it stopped: the notify that
+        // follows a takeover must not advertise a stale playing state.
+        self.state.set_status(PlayStatus::kPlayStatusStop);
+        self.play_status = SpircPlayStatus::Stopped;
+        self.update_state_position(0);
+
         self.player
             .emit_session_disconnected_event(self.session.connection_id(), self.session.username());
     }
 
+    fn handle_logout(&mut self) -> Result<(), Error> {
+        info!("Logged out by remote client");
+
+        if let Some(cache) = self.session.cache() {
+            cache.remove_credentials();
+        }
+
+        CommandSender::new(self, MessageType::kMessageTypeGoodbye).send()?;
+        self.handle_disconnect();
+
+        self.logged_out.store(true, Ordering::Release);
+        self.shutdown = true;
+        if let Some(rx) = self.commands.as_mut() {
+            rx.close()
+        }
+
+        Ok(())
+    }
+
     fn handle_stop(&mut self) {
         self.player.stop();
     }
@@ -1059,9 +1332,16 @@ impl SpircTask {
         self.player.emit_shuffle_changed_event(self.state.shuffle());
 
         self.player.emit_repeat_changed_event(self.state.repeat());
+
+        if let Some(position_ms) = self.resume_position.take() {
+            if !self.state.track.is_empty() {
+                self.load_track(false, position_ms);
+            }
+        }
     }
 
     fn handle_load(&mut self, state: &State) -> Result<(), Error> {
+        self.resume_position = None;
         if !self.device.is_active() {
             self.handle_activate();
         }
@@ -1265,7 +1545,7 @@ impl SpircTask {
                 self.autoplay_context = true;
                 self.resolve_context = Some(self.state.context_uri().to_owned());
                 self.update_tracks_from_context();
-                self.player.set_auto_normalise_as_album(false);
+                self.update_auto_normalisation();
             } else {
                 new_index = 0;
                 continue_playing &= self.state.repeat();
@@ -1323,6 +1603,36 @@ impl SpircTask {
         }
     }
 
+    fn handle_shuffle(&mut self, shuffle: bool) {
+        self.state.set_shuffle(shuffle);
+        self.update_auto_normalisation();
+
+        if shuffle {
+            if !self.state.track.is_empty() {
+                if self.unshuffled_tracks.is_none() {
+                    self.unshuffled_tracks = Some(self.state.track.clone());
+                }
+                let current_index = self.state.playing_track_index() as usize;
+                shuffle_tracks(&mut self.state.track, current_index);
+                self.state.set_playing_track_index(0);
+            }
+        } else if let Some(tracks) = self.unshuffled_tracks.take() {
+            let current_index = self.state.playing_track_index() as usize;
+            let (tracks, new_index) = unshuffle_tracks(tracks, &self.state.track, current_index);
+            self.state.track = tracks;
+            self.state.set_playing_track_index(new_index as u32);
+        }
+
+        self.player.emit_shuffle_changed_event(shuffle);
+    }
+
+    fn handle_repeat(&mut self, repeat: bool) {
+        if self.state.repeat() != repeat {
+            self.state.set_repeat(repeat);
+            self.player.emit_repeat_changed_event(repeat);
+        }
+    }
+
     fn handle_volume_up(&mut self) {
         let volume = (self.device.volume() as u16).saturating_add(VOLUME_STEP_SIZE);
         self.set_volume(volume);
@@ -1363,6 +1673,10 @@ impl SpircTask {
             track_vec.extend_from_slice(new_tracks);
             self.state.track = track_vec;
 
+            if let Some(ref mut unshuffled_tracks) = self.unshuffled_tracks {
+                unshuffled_tracks.extend_from_slice(new_tracks);
+            }
+
             // Update playing index
             if let Some(new_index) = self
                 .state
@@ -1390,12 +1704,12 @@ impl SpircTask {
         self.autoplay_context = false;
         self.resolve_context = Some(context_uri.to_owned());
 
-        self.player
-            .set_auto_normalise_as_album(context_uri.starts_with("spotify:album:"));
-
         self.state.set_playing_track_index(index);
         self.state.track = tracks.to_vec();
         self.state.set_context_uri(context_uri.to_owned());
+        self.player
+            .set_context_uri(Some(context_uri.to_owned()).filter(|uri| !uri.is_empty()));
+        self.unshuffled_tracks = None;
         // has_shuffle/repeat seem to always be true in these replace msgs,
         // but to replicate the behaviour of the Android client we have to
         // ignore false values.
@@ -1405,6 +1719,17 @@ impl SpircTask {
         if state.shuffle() {
             self.state.set_shuffle(true);
         }
+
+        self.update_auto_normalisation();
+    }
+
+    fn update_auto_normalisation(&self) {
+        // Album gain only makes sense while an album plays in order.
+        let as_album = !self.autoplay_context
+            && !self.state.shuffle()
+            && self.state.context_uri().starts_with("spotify:album:");
+
+        self.player.set_auto_normalise_as_album(as_album);
     }
 
     // Helper to find corresponding index(s) for track_id
@@ -1506,6 +1831,9 @@ impl SpircTask {
     }
 
     fn notify(&mut self, recipient: Option<&str>) -> Result<(), Error> {
+        self.publish_state();
+        self.save_state(false);
+
         let status = self.state.status();
 
         // When in loading state, the Spotify UI is disabled for interaction.
@@ -1524,6 +1852,79 @@ impl SpircTask {
         cs.send()
     }
 
+    // Restores what `save_state` saved, without loading anything until the
+    // device becomes active.
+    fn restore_state(&mut self, state: &State) {
+        if state.track.is_empty() {
+            return;
+        }
+
+        debug!(
+            "Restoring {} tracks of <{}> from the cache",
+            state.track.len(),
+            state.context_uri()
+        );
+        self.update_tracks(state);
+        self.update_state_position(state.position_ms());
+        self.resume_position = Some(state.position_ms());
+    }
+
+    // Saves the state when the track or the context changed, or with `always`
+    // also to keep the position, e.g. when shutting down.
+    fn save_state(&mut self, always: bool) {
+        // Only the state of this device is worth restoring, not what is left
+        // after another device took over.
+        if !self.persist_state || !self.device.is_active() {
+            return;
+        }
+
+        let saved_state = SavedState {
+            context_uri: self.state.context_uri().to_owned(),
+            playing_track_index: self.state.playing_track_index(),
+            tracks: self.state.track.len(),
+            shuffle: self.state.shuffle(),
+            repeat: self.state.repeat(),
+        };
+        if !always && self.saved_state.as_ref() == Some(&saved_state) {
+            return;
+        }
+        self.saved_state = Some(saved_state);
+
+        let position = self.position();
+        if let Some(cache) = self.session.cache() {
+            let mut state = self.state.clone();
+            state.set_position_ms(position);
+            cache.save_connect_state(&state);
+        }
+    }
+
+    fn publish_state(&self) {
+        // Most notifies don't change the tracks, so only copy them when they
+        // did. The borrow must end before sending.
+        let tracks = {
+            let published = self.state_tx.borrow();
+            if published.tracks[..] == self.state.track[..] {
+                published.tracks.clone()
+            } else {
+                self.state.track.as_slice().into()
+            }
+        };
+
+        let state = SpircState {
+            is_active: self.device.is_active(),
+            status: self.state.status(),
+            context_uri: self.state.context_uri().to_owned(),
+            playing_track_index: self.state.playing_track_index(),
+            tracks,
+            shuffle: self.state.shuffle(),
+            repeat: self.state.repeat(),
+            volume: self.device.volume() as u16,
+        };
+
+        // Nobody may be watching, which is fine.
+        self.state_tx.send_replace(state);
+    }
+
     fn set_volume(&mut self, volume: u16) {
         let old_volume = self.device.volume();
         let new_volume = volume as u32;
@@ -1540,6 +1941,52 @@ impl SpircTask {
     }
 }
 
+/// Moves the current track to the front and shuffles the rest.
+fn shuffle_tracks(tracks: &mut [TrackRef], current_index: usize) {
+    if current_index < tracks.len() {
+        tracks.swap(0, current_index);
+    }
+    if let Some((_, rest)) = tracks.split_first_mut() {
+        let mut rng = rand::thread_rng();
+        rest.shuffle(&mut rng);
+    }
+}
+
+/// Restores the order saved when shuffling started, returning the tracks
+/// and the index of the current track within them.
+///
+/// Tracks queued while shuffled should still play next, so they are carried
+/// over instead of whatever was queued when shuffling started. If the current
+/// track is not part of the saved order, playback continues from the start.
+fn unshuffle_tracks(
+    mut unshuffled: Vec<TrackRef>,
+    shuffled: &[TrackRef],
+    current_index: usize,
+) -> (Vec<TrackRef>, usize) {
+    let queue_tracks: Vec<TrackRef> = shuffled
+        .iter()
+        .skip(current_index + 1)
+        .take_while(|track| track.queued())
+        .cloned()
+        .collect();
+    unshuffled.retain(|track| !track.queued());
+
+    let new_index = shuffled
+        .get(current_index)
+        .and_then(|current| {
+            unshuffled
+                .iter()
+                .position(|track| track.gid() == current.gid() && track.uri() == current.uri())
+        })
+        .unwrap_or_default();
+
+    for (pos, track) in (new_index + 1..).zip(queue_tracks) {
+        unshuffled.insert(pos.min(unshuffled.len()), track);
+    }
+
+    (unshuffled, new_index)
+}
+
 impl Drop for SpircTask {
     fn drop(&mut self) {
         debug!("drop Spirc[{}]", self.spirc_id);
@@ -1579,10 +2026,103 @@ impl<'a> CommandSender<'a> {
     }
 
     fn send(mut self) -> Result<(), Error> {
-        if self.frame.state.is_none() && self.spirc.device.is_active() {
-            *self.frame.state.mut_or_insert_default() = self.spirc.state.clone();
+        // The state can hold a lot of tracks, so lend it to the frame for
+        // serialization instead of cloning it for every notify.
+        let lend_state = self.frame.state.is_none() && self.spirc.device.is_active();
+        if lend_state {
+            self.frame.state = MessageField::some(std::mem::take(&mut self.spirc.state));
         }
 
-        self.spirc.sender.send(self.frame.write_to_bytes()?)
+        let data = self.frame.write_to_bytes();
+
+        if lend_state {
+            if let Some(state) = self.frame.state.take() {
+                self.spirc.state = state;
+            }
+        }
+
+        self.spirc.sender.send(data?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn track(n: u8, queued: bool) -> TrackRef {
+        let mut track = TrackRef::new();
+        track.set_gid(vec![n]);
+        track.set_uri(format!("spotify:track:{n}"));
+        track.set_queued(queued);
+        track
+    }
+
+    fn gids(tracks: &[TrackRef]) -> Vec<u8> {
+        tracks.iter().map(|track| track.gid()[0]).collect()
+    }
+
+    #[test]
+    fn test_shuffle_then_unshuffle_with_queued_tracks() {
+        let unshuffled: Vec<TrackRef> = (0..6).map(|n| track(n, false)).collect();
+        let mut tracks = unshuffled.clone();
+        shuffle_tracks(&mut tracks, 2);
+        assert_eq!(tracks[0].gid(), [2]);
+        let mut sorted = gids(&tracks);
+        sorted.sort_unstable();
+        assert_eq!(sorted, [0, 1, 2, 3, 4, 5]);
+
+        // Skip to the next track and queue two tracks after it.
+        let current = tracks[1].gid()[0];
+        tracks.insert(2, track(10, true));
+        tracks.insert(3, track(11, true));
+
+        let (tracks, index) = unshuffle_tracks(unshuffled, &tracks, 1);
+        assert_eq!(index, current as usize);
+        assert_eq!(tracks[index].gid(), [current]);
+        assert_eq!(gids(&tracks[index + 1..index + 3]), [10, 11]);
+        assert_eq!(tracks.len(), 8);
+    }
+
+    #[test]
+    fn test_unshuffle_drops_stale_queue() {
+        let unshuffled = vec![track(0, false), track(20, true), track(1, false)];
+        let shuffled = vec![track(1, false), track(0, false)];
+
+        let (tracks, index) = unshuffle_tracks(unshuffled, &shuffled, 0);
+        assert_eq!(gids(&tracks), [0, 1]);
+        assert_eq!(index, 1);
+    }
+
+    #[test]
+    fn test_unshuffle_missing_current_track() {
+        let unshuffled: Vec<TrackRef> = (0..3).map(|n| track(n, false)).collect();
+        let shuffled = vec![track(7, false), track(8, true), track(2, false)];
+
+        let (tracks, index) = unshuffle_tracks(unshuffled, &shuffled, 0);
+        assert_eq!(index, 0);
+        assert_eq!(gids(&tracks), [0, 8, 1, 2]);
+    }
+
+    #[test]
+    fn test_unshuffle_restores_saved_order() {
+        let unshuffled: Vec<TrackRef> = (10..14).map(|n| track(n, false)).collect();
+        let mut tracks = unshuffled.clone();
+        shuffle_tracks(&mut tracks, 3);
+        assert_eq!(tracks[0].gid(), [13]);
+
+        let (tracks, index) = unshuffle_tracks(unshuffled, &tracks, 0);
+        assert_eq!(gids(&tracks), [10, 11, 12, 13]);
+        assert_eq!(index, 3);
+    }
+
+    #[test]
+    fn test_shuffle_empty() {
+        let mut tracks = Vec::new();
+        shuffle_tracks(&mut tracks, 0);
+        assert!(tracks.is_empty());
+
+        let (tracks, index) = unshuffle_tracks(Vec::new(), &[], 0);
+        assert!(tracks.is_empty());
+        assert_eq!(index, 0);
     }
 }
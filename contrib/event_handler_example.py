@@ -32,6 +32,13 @@ elif player_event == 'auto_play_changed':
 elif player_event == 'filter_explicit_content_changed':
     json_dict['filter'] = os.environ['FILTER']
 
+elif player_event == 'backend_metadata_changed':
+    json_dict['metadata_key'] = os.environ['METADATA_KEY']
+    json_dict['metadata_value'] = os.environ['METADATA_VALUE']
+
+elif player_event == 'idle':
+    json_dict['idle_timeout_ms'] = os.environ['IDLE_TIMEOUT_MS']
+
 elif player_event == 'volume_changed':
     json_dict['volume'] = os.environ['VOLUME']
 
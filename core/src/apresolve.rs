@@ -1,13 +1,17 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, time::Duration};
 
 use bytes::Bytes;
 use hyper::{Method, Request};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::Error;
 
 pub type SocketAddress = (String, u16);
 
+// How long resolved access points are reused from the cache before resolving
+// them again.
+const CACHED_ACCESS_POINTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
 #[derive(Default)]
 pub struct AccessPoints {
     accesspoint: VecDeque<SocketAddress>,
@@ -15,11 +19,12 @@ pub struct AccessPoints {
     spclient: VecDeque<SocketAddress>,
 }
 
-#[derive(Deserialize, Default)]
+/// Lists of `host:port` addresses per kind of endpoint.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ApResolveData {
-    accesspoint: Vec<String>,
-    dealer: Vec<String>,
-    spclient: Vec<String>,
+    pub accesspoint: Vec<String>,
+    pub dealer: Vec<String>,
+    pub spclient: Vec<String>,
 }
 
 impl ApResolveData {
@@ -44,6 +49,7 @@ impl AccessPoints {
 component! {
     ApResolver : ApResolverInner {
         data: AccessPoints = AccessPoints::default(),
+        cache_used: bool = false,
     }
 }
 
@@ -94,8 +100,37 @@ impl ApResolver {
         Ok(data)
     }
 
+    // Static access points from the config are used as is. Otherwise resolved
+    // ones are reused from the cache, but only once per session: when they do
+    // not work, they are resolved again.
+    async fn resolve_data(&self) -> Result<ApResolveData, Error> {
+        if let Some(data) = &self.session().config().access_points {
+            return Ok(data.clone());
+        }
+
+        let cache = self.session().cache().cloned();
+        let use_cache = !self.lock(|inner| std::mem::replace(&mut inner.cache_used, true));
+        if use_cache {
+            if let Some(data) = cache
+                .as_ref()
+                .and_then(|cache| cache.access_points(CACHED_ACCESS_POINTS_TTL))
+            {
+                debug!("Using cached access points");
+                return Ok(data);
+            }
+        }
+
+        let data = self.try_apresolve().await?;
+        if let Some(cache) = cache {
+            cache.save_access_points(&data);
+        }
+
+        Ok(data)
+    }
+
     async fn apresolve(&self) {
-        let result = self.try_apresolve().await;
+        let is_static = self.session().config().access_points.is_some();
+        let result = self.resolve_data().await;
 
         self.lock(|inner| {
             let (data, error) = match result {
@@ -103,18 +138,51 @@ impl ApResolver {
                 Err(e) => (ApResolveData::default(), Some(e)),
             };
 
+            let configured = [
+                !data.accesspoint.is_empty(),
+                !data.dealer.is_empty(),
+                !data.spclient.is_empty(),
+            ];
             inner.data = self.parse_resolve_to_access_points(data);
 
-            if inner.data.is_any_empty() {
+            if is_static {
+                let port = self.port_config();
+                for (kind, configured, access_points) in [
+                    ("accesspoint", configured[0], &inner.data.accesspoint),
+                    ("dealer", configured[1], &inner.data.dealer),
+                    ("spclient", configured[2], &inner.data.spclient),
+                ] {
+                    if configured && access_points.is_empty() {
+                        match port {
+                            Some(port) => {
+                                warn!("No static {kind} address uses port {port}, using fallbacks")
+                            }
+                            None => warn!("No static {kind} address is valid, using fallbacks"),
+                        }
+                    }
+                }
+            } else if inner.data.is_any_empty() {
                 warn!("Failed to resolve all access points, using fallbacks");
                 if let Some(error) = error {
                     warn!("Resolve access points error: {}", error);
                 }
+            }
+
+            if !inner.data.is_any_empty() {
+                return;
+            }
 
-                let fallback = self.parse_resolve_to_access_points(ApResolveData::fallback());
-                inner.data.accesspoint.extend(fallback.accesspoint);
-                inner.data.dealer.extend(fallback.dealer);
-                inner.data.spclient.extend(fallback.spclient);
+            // Static access points may be given for some kinds only, the others
+            // come from the fallbacks.
+            let fallback = self.parse_resolve_to_access_points(ApResolveData::fallback());
+            for (access_points, fallback) in [
+                (&mut inner.data.accesspoint, fallback.accesspoint),
+                (&mut inner.data.dealer, fallback.dealer),
+                (&mut inner.data.spclient, fallback.spclient),
+            ] {
+                if !is_static || access_points.is_empty() {
+                    access_points.extend(fallback);
+                }
             }
         })
     }
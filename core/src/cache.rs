@@ -5,24 +5,43 @@ use std::{
     io::{self, Read, Write},
     path::{Path, PathBuf},
     sync::Arc,
-    time::SystemTime,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use parking_lot::Mutex;
 use priority_queue::PriorityQueue;
 use thiserror::Error;
 
-use crate::{authentication::Credentials, error::ErrorKind, Error, FileId};
+use protobuf::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apresolve::ApResolveData,
+    authentication::Credentials,
+    credentials_store::{CredentialsStore, FileCredentialsStore, USERS_DIR},
+    error::ErrorKind,
+    Error, FileId,
+};
+use librespot_protocol::spirc::State;
+
+// Access times are kept here as well, as file systems are often mounted
+// without updating them.
+const ACCESS_TIMES_FILE: &str = "access-times.json";
 
 #[derive(Debug, Error)]
 pub enum CacheError {
     #[error("audio cache location is not configured")]
     Path,
+    #[error("cannot keep a cache for user {0:?}")]
+    Username(String),
 }
 
 impl From<CacheError> for Error {
     fn from(err: CacheError) -> Self {
-        Error::failed_precondition(err)
+        match err {
+            CacheError::Path => Error::failed_precondition(err),
+            CacheError::Username(_) => Error::invalid_argument(err),
+        }
     }
 }
 
@@ -88,6 +107,13 @@ impl SizeLimiter {
         }
     }
 
+    /// Returns the timestamp of an element, if it exists.
+    fn accessed(&self, file: &Path) -> Option<SystemTime> {
+        self.queue
+            .get_priority(file)
+            .map(|Reverse(accessed)| *accessed)
+    }
+
     /// Updates the timestamp of an existing element. Returns `true` if the item did exist.
     fn update(&mut self, file: &Path, access_time: SystemTime) -> bool {
         self.queue
@@ -109,10 +135,18 @@ impl SizeLimiter {
 
         true
     }
+
+    /// Removes all elements, returning their paths.
+    fn clear(&mut self) -> Vec<PathBuf> {
+        self.queue.clear();
+        self.in_use = 0;
+        self.sizes.drain().map(|(file, _)| file).collect()
+    }
 }
 
 struct FsSizeLimiter {
     limiter: Mutex<SizeLimiter>,
+    location: PathBuf,
 }
 
 impl FsSizeLimiter {
@@ -161,6 +195,10 @@ impl FsSizeLimiter {
                 }
                 Ok(file_type) if file_type.is_file() => {
                     let path = entry.path();
+                    if path.file_name() == Some(ACCESS_TIMES_FILE.as_ref()) {
+                        continue;
+                    }
+
                     match Self::get_metadata(&path) {
                         Ok((access_time, size)) => {
                             limiter.add(&path, size, access_time);
@@ -188,10 +226,54 @@ impl FsSizeLimiter {
         }
     }
 
+    fn read_access_times(location: &Path) -> HashMap<PathBuf, SystemTime> {
+        let read = || -> Result<HashMap<PathBuf, u64>, Error> {
+            let contents = fs::read_to_string(location.join(ACCESS_TIMES_FILE))?;
+            Ok(serde_json::from_str(&contents)?)
+        };
+
+        match read() {
+            Ok(access_times) => access_times
+                .into_iter()
+                .map(|(file, secs)| (location.join(file), UNIX_EPOCH + Duration::from_secs(secs)))
+                .collect(),
+            Err(e) => {
+                if e.kind != ErrorKind::NotFound {
+                    warn!("Error reading access times from cache: {}", e);
+                }
+                HashMap::new()
+            }
+        }
+    }
+
+    fn access_times(location: &Path, limiter: &SizeLimiter) -> HashMap<PathBuf, u64> {
+        limiter
+            .queue
+            .iter()
+            .filter_map(|(file, Reverse(accessed))| {
+                let file = file.strip_prefix(location).ok()?;
+                let secs = accessed.duration_since(UNIX_EPOCH).ok()?.as_secs();
+                Some((file.to_owned(), secs))
+            })
+            .collect()
+    }
+
+    fn write_access_times(location: &Path, access_times: &HashMap<PathBuf, u64>) {
+        let result = serde_json::to_string(access_times)
+            .map_err(io::Error::from)
+            .and_then(|data| fs::write(location.join(ACCESS_TIMES_FILE), data));
+
+        if let Err(e) = result {
+            warn!("Cannot save access times to cache: {}", e);
+        }
+    }
+
     fn add(&self, file: &Path, size: u64) {
         self.limiter.lock().add(file, size, SystemTime::now())
     }
 
+    // Access times only change in memory here. They are saved when pruning
+    // and when the cache is dropped.
     fn touch(&self, file: &Path) -> bool {
         self.limiter.lock().update(file, SystemTime::now())
     }
@@ -200,6 +282,40 @@ impl FsSizeLimiter {
         self.limiter.lock().remove(file)
     }
 
+    // Saves the access times, so the least recently used files are still known
+    // after a restart. The file is written without holding the lock.
+    fn save_access_times(&self) {
+        let access_times = Self::access_times(&self.location, &self.limiter.lock());
+        Self::write_access_times(&self.location, &access_times);
+    }
+
+    fn usage(&self) -> u64 {
+        self.limiter.lock().in_use
+    }
+
+    fn clear(&self) -> Result<(), Error> {
+        let files = self.limiter.lock().clear();
+        let result = Self::remove_files(files);
+        self.save_access_times();
+        result
+    }
+
+    fn remove_files(files: Vec<PathBuf>) -> Result<(), Error> {
+        let mut last_error = None;
+
+        for file in files {
+            if let Err(e) = fs::remove_file(&file) {
+                warn!("Could not remove file {:?} from cache dir: {}", file, e);
+                last_error = Some(e);
+            }
+        }
+
+        match last_error {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
     fn prune_internal<F: FnMut() -> Option<PathBuf>>(mut pop: F) -> Result<(), Error> {
         let mut first = true;
         let mut count = 0;
@@ -232,27 +348,65 @@ impl FsSizeLimiter {
     }
 
     fn prune(&self) -> Result<(), Error> {
-        Self::prune_internal(|| self.limiter.lock().pop())
+        let result = Self::prune_internal(|| self.limiter.lock().pop());
+        self.save_access_times();
+        result
     }
 
     fn new(path: &Path, limit: u64) -> Result<Self, Error> {
         let mut limiter = SizeLimiter::new(limit);
 
         Self::init_dir(&mut limiter, path);
+
+        for (file, accessed) in Self::read_access_times(path) {
+            if limiter
+                .accessed(&file)
+                .is_some_and(|current| current < accessed)
+            {
+                limiter.update(&file, accessed);
+            }
+        }
+
         Self::prune_internal(|| limiter.pop())?;
+        Self::write_access_times(path, &Self::access_times(path, &limiter));
 
         Ok(Self {
             limiter: Mutex::new(limiter),
+            location: path.to_owned(),
         })
     }
 }
 
-/// A cache for volume, credentials and audio files.
+impl Drop for FsSizeLimiter {
+    fn drop(&mut self) {
+        self.save_access_times();
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedAccessPoints {
+    // Seconds since the Unix epoch.
+    resolved_at: u64,
+    #[serde(flatten)]
+    data: ApResolveData,
+}
+
+/// A cache for volume, credentials, the Spotify Connect state and audio files.
+///
+/// Audio files are stored as they come from the CDN, i.e. still encrypted, and
+/// are only decrypted while being read for playback. The keys to decrypt them
+/// are never written to disk, so the cache on its own does not expose any
+/// audio.
 #[derive(Clone)]
 pub struct Cache {
-    credentials_location: Option<PathBuf>,
+    credentials_store: Option<Arc<dyn CredentialsStore>>,
+    // Set for caches returned by `for_user`.
+    user: Option<String>,
     volume_location: Option<PathBuf>,
+    connect_state_location: Option<PathBuf>,
+    access_points_location: Option<PathBuf>,
     audio_location: Option<PathBuf>,
+    downloads_location: Option<PathBuf>,
     size_limiter: Option<Arc<FsSizeLimiter>>,
 }
 
@@ -269,15 +423,22 @@ impl Cache {
             fs::create_dir_all(location)?;
         }
 
-        let credentials_location = credentials_path
-            .as_ref()
-            .map(|p| p.as_ref().join("credentials.json"));
+        let credentials_store = credentials_path.as_ref().map(|p| {
+            let store: Arc<dyn CredentialsStore> = Arc::new(FileCredentialsStore::new(p.as_ref()));
+            store
+        });
 
         if let Some(location) = &volume_path {
             fs::create_dir_all(location)?;
         }
 
         let volume_location = volume_path.as_ref().map(|p| p.as_ref().join("volume"));
+        let connect_state_location = volume_path
+            .as_ref()
+            .map(|p| p.as_ref().join("connect-state"));
+        let access_points_location = volume_path
+            .as_ref()
+            .map(|p| p.as_ref().join("access-points.json"));
 
         if let Some(location) = &audio_path {
             fs::create_dir_all(location)?;
@@ -291,48 +452,173 @@ impl Cache {
         let audio_location = audio_path.map(|p| p.as_ref().to_owned());
 
         let cache = Cache {
-            credentials_location,
+            credentials_store,
+            user: None,
             volume_location,
+            connect_state_location,
+            access_points_location,
             audio_location,
+            downloads_location: None,
             size_limiter,
         };
 
         Ok(cache)
     }
 
+    /// Sets the directory holding audio files that were downloaded for offline
+    /// use. These are read like cached files, but never evicted.
+    pub fn with_downloads<P: AsRef<Path>>(mut self, downloads_path: P) -> Self {
+        self.downloads_location = Some(downloads_path.as_ref().to_owned());
+        self
+    }
+
+    pub fn downloads_location(&self) -> Option<&Path> {
+        self.downloads_location.as_deref()
+    }
+
+    /// Keeps credentials in `store` rather than in `credentials.json`, e.g. in
+    /// the keyring of the OS. This works even without a credentials path.
+    pub fn with_credentials_store(mut self, store: Arc<dyn CredentialsStore>) -> Self {
+        self.credentials_store = Some(store);
+        self
+    }
+
+    /// Returns a cache that keeps the credentials, volume and Spotify Connect
+    /// state of `username` apart from those of the device, the latter two in
+    /// a `users/<username>` directory next to the usual one. Audio files and
+    /// downloads stay shared between all users.
+    pub fn for_user(&self, username: &str) -> Result<Self, Error> {
+        if username.is_empty()
+            || username.starts_with('.')
+            || username.contains(std::path::is_separator)
+        {
+            return Err(CacheError::Username(username.to_owned()).into());
+        }
+
+        let user_location = |location: &Option<PathBuf>| -> Result<Option<PathBuf>, Error> {
+            let (dir, file_name) = match location.as_deref() {
+                Some(location) => match (location.parent(), location.file_name()) {
+                    (Some(dir), Some(file_name)) => (dir, file_name),
+                    _ => return Ok(None),
+                },
+                None => return Ok(None),
+            };
+
+            let user_dir = dir.join(USERS_DIR).join(username);
+            fs::create_dir_all(&user_dir)?;
+            Ok(Some(user_dir.join(file_name)))
+        };
+
+        Ok(Self {
+            user: Some(username.to_owned()),
+            volume_location: user_location(&self.volume_location)?,
+            connect_state_location: user_location(&self.connect_state_location)?,
+            ..self.clone()
+        })
+    }
+
+    /// Lists the users that have credentials stored by a cache returned from
+    /// [`for_user`](Self::for_user).
+    pub fn users(&self) -> Vec<String> {
+        let store = match &self.credentials_store {
+            Some(store) => store,
+            None => return Vec::new(),
+        };
+
+        // Stores that can't list their entries, e.g. the keyring, are asked
+        // about the users that `for_user` created a directory for.
+        let user_dirs = self
+            .volume_location
+            .as_deref()
+            .and_then(Path::parent)
+            .and_then(|dir| fs::read_dir(dir.join(USERS_DIR)).ok());
+
+        let mut users = store.users();
+        for entry in user_dirs.into_iter().flatten().flatten() {
+            if let Ok(user) = entry.file_name().into_string() {
+                if !users.contains(&user) && matches!(store.load(Some(&user)), Ok(Some(_))) {
+                    users.push(user);
+                }
+            }
+        }
+
+        users.sort();
+        users
+    }
+
     pub fn credentials(&self) -> Option<Credentials> {
-        let location = self.credentials_location.as_ref()?;
+        let store = self.credentials_store.as_ref()?;
 
-        // This closure is just convencience to enable the question mark operator
-        let read = || -> Result<Credentials, Error> {
-            let mut file = File::open(location)?;
-            let mut contents = String::new();
-            file.read_to_string(&mut contents)?;
+        match store.load(self.user.as_deref()) {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                warn!("Error reading credentials from cache: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn save_credentials(&self, cred: &Credentials) {
+        if let Some(store) = &self.credentials_store {
+            if let Err(e) = store.save(self.user.as_deref(), cred) {
+                warn!("Cannot save credentials to cache: {}", e)
+            }
+        }
+    }
+
+    /// Returns the access points saved by [`save_access_points`](Self::save_access_points),
+    /// unless they were resolved more than `max_age` ago.
+    pub fn access_points(&self, max_age: Duration) -> Option<ApResolveData> {
+        let location = self.access_points_location.as_ref()?;
+
+        let read = || -> Result<CachedAccessPoints, Error> {
+            let contents = fs::read_to_string(location)?;
             Ok(serde_json::from_str(&contents)?)
         };
 
         match read() {
-            Ok(c) => Some(c),
+            Ok(cached) => {
+                let resolved_at = UNIX_EPOCH + Duration::from_secs(cached.resolved_at);
+                let age = SystemTime::now()
+                    .duration_since(resolved_at)
+                    .unwrap_or_default();
+                (age <= max_age).then_some(cached.data)
+            }
             Err(e) => {
-                // If the file did not exist, the file was probably not written
-                // before. Otherwise, log the error.
                 if e.kind != ErrorKind::NotFound {
-                    warn!("Error reading credentials from cache: {}", e);
+                    warn!("Error reading access points from cache: {}", e);
                 }
                 None
             }
         }
     }
 
-    pub fn save_credentials(&self, cred: &Credentials) {
-        if let Some(location) = &self.credentials_location {
+    pub fn save_access_points(&self, data: &ApResolveData) {
+        if let Some(location) = &self.access_points_location {
+            let resolved_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let cached = CachedAccessPoints {
+                resolved_at,
+                data: data.clone(),
+            };
+
             let result = File::create(location).and_then(|mut file| {
-                let data = serde_json::to_string(cred)?;
+                let data = serde_json::to_string(&cached)?;
                 write!(file, "{data}")
             });
 
             if let Err(e) = result {
-                warn!("Cannot save credentials to cache: {}", e)
+                warn!("Cannot save access points to cache: {}", e);
+            }
+        }
+    }
+
+    pub fn remove_credentials(&self) {
+        if let Some(store) = &self.credentials_store {
+            if let Err(e) = store.remove(self.user.as_deref()) {
+                warn!("Cannot remove credentials from cache: {}", e)
             }
         }
     }
@@ -367,9 +653,48 @@ impl Cache {
         }
     }
 
-    pub fn file_path(&self, file: FileId) -> Option<PathBuf> {
+    /// Returns the Spotify Connect state saved by
+    /// [`save_connect_state`](Self::save_connect_state).
+    pub fn connect_state(&self) -> Option<State> {
+        let location = self.connect_state_location.as_ref()?;
+
+        let read = || -> Result<State, Error> {
+            let data = fs::read(location)?;
+            Ok(State::parse_from_bytes(&data)?)
+        };
+
+        match read() {
+            Ok(state) => Some(state),
+            Err(e) => {
+                if e.kind != ErrorKind::NotFound {
+                    warn!("Error reading Spotify Connect state from cache: {}", e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Saves `state` to be restored after a restart. The file is replaced as
+    /// a whole, so a crash while saving leaves the previous state in place.
+    pub fn save_connect_state(&self, state: &State) {
+        if let Some(ref location) = self.connect_state_location {
+            let result = state
+                .write_to_bytes()
+                .map_err(Error::from)
+                .and_then(|data| {
+                    let temp_location = location.with_extension("tmp");
+                    fs::write(&temp_location, data)?;
+                    Ok(fs::rename(temp_location, location)?)
+                });
+            if let Err(e) = result {
+                warn!("Cannot save Spotify Connect state to cache: {}", e);
+            }
+        }
+    }
+
+    fn path_in(location: Option<&PathBuf>, file: FileId) -> Option<PathBuf> {
         match file.to_base16() {
-            Ok(name) => self.audio_location.as_ref().map(|location| {
+            Ok(name) => location.map(|location| {
                 let mut path = location.join(&name[0..2]);
                 path.push(&name[2..]);
                 path
@@ -381,8 +706,20 @@ impl Cache {
         }
     }
 
+    pub fn file_path(&self, file: FileId) -> Option<PathBuf> {
+        Self::path_in(self.audio_location.as_ref(), file)
+    }
+
+    pub fn download_path(&self, file: FileId) -> Option<PathBuf> {
+        Self::path_in(self.downloads_location.as_ref(), file)
+    }
+
     pub fn file(&self, file: FileId) -> Option<File> {
-        let path = self.file_path(file)?;
+        let path = match self.file_path(file) {
+            Some(path) => path,
+            None => return self.downloaded_file(file),
+        };
+
         match File::open(&path) {
             Ok(file) => {
                 if let Some(limiter) = self.size_limiter.as_deref() {
@@ -396,6 +733,19 @@ impl Cache {
                 if e.kind() != io::ErrorKind::NotFound {
                     warn!("Error reading file from cache: {}", e)
                 }
+                self.downloaded_file(file)
+            }
+        }
+    }
+
+    fn downloaded_file(&self, file: FileId) -> Option<File> {
+        let path = self.download_path(file)?;
+        match File::open(path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Error reading downloaded file: {}", e)
+                }
                 None
             }
         }
@@ -419,6 +769,34 @@ impl Cache {
         Err(CacheError::Path.into())
     }
 
+    /// Returns the size of the cached audio files in bytes.
+    pub fn audio_usage(&self) -> Result<u64, Error> {
+        let location = self.audio_location.as_ref().ok_or(CacheError::Path)?;
+
+        match self.size_limiter.as_deref() {
+            Some(limiter) => Ok(limiter.usage()),
+            None => {
+                let mut limiter = SizeLimiter::new(u64::MAX);
+                FsSizeLimiter::init_dir(&mut limiter, location);
+                Ok(limiter.in_use)
+            }
+        }
+    }
+
+    /// Removes all cached audio files. Downloads are kept.
+    pub fn clear_audio(&self) -> Result<(), Error> {
+        let location = self.audio_location.as_ref().ok_or(CacheError::Path)?;
+
+        match self.size_limiter.as_deref() {
+            Some(limiter) => limiter.clear(),
+            None => {
+                let mut limiter = SizeLimiter::new(u64::MAX);
+                FsSizeLimiter::init_dir(&mut limiter, location);
+                FsSizeLimiter::remove_files(limiter.clear())
+            }
+        }
+    }
+
     pub fn remove_file(&self, file: FileId) -> Result<(), Error> {
         let path = self.file_path(file).ok_or(CacheError::Path)?;
 
@@ -434,7 +812,6 @@ impl Cache {
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::time::Duration;
 
     fn ordered_time(v: u64) -> SystemTime {
         SystemTime::UNIX_EPOCH + Duration::from_secs(v)
@@ -481,4 +858,82 @@ mod test {
         assert!(limiter.remove(Path::new("c")));
         assert!(!limiter.exceeds_limit());
     }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "librespot-cache-test-{}-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_access_times() {
+        let dir = temp_dir("access-times");
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::write(&a, [0; 100]).unwrap();
+        fs::write(&b, [0; 100]).unwrap();
+
+        // Access times in the future win over the ones of the file system.
+        let limiter = FsSizeLimiter::new(&dir, 1000).unwrap();
+        assert!(limiter
+            .limiter
+            .lock()
+            .update(&a, ordered_time(4_000_000_000)));
+        assert!(limiter
+            .limiter
+            .lock()
+            .update(&b, ordered_time(4_000_000_001)));
+        limiter.save_access_times();
+
+        let limiter = FsSizeLimiter::new(&dir, 1000).unwrap();
+        assert_eq!(
+            limiter.limiter.lock().accessed(&a),
+            Some(ordered_time(4_000_000_000))
+        );
+        assert_eq!(
+            limiter.limiter.lock().accessed(&b),
+            Some(ordered_time(4_000_000_001))
+        );
+
+        // Dropping saves them as well.
+        assert!(limiter
+            .limiter
+            .lock()
+            .update(&a, ordered_time(4_000_000_002)));
+        drop(limiter);
+
+        let limiter = FsSizeLimiter::new(&dir, 1000).unwrap();
+        assert_eq!(
+            limiter.limiter.lock().accessed(&a),
+            Some(ordered_time(4_000_000_002))
+        );
+        drop(limiter);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_connect_state() {
+        let dir = temp_dir("connect-state");
+        let cache = Cache::new(None, Some(&dir), None, None).unwrap();
+        assert!(cache.connect_state().is_none());
+
+        let mut state = State::new();
+        state.set_context_uri("spotify:album:79dL7FLiJFOO0EoehUHQBv".into());
+        state.set_playing_track_index(2);
+        state.set_position_ms(12345);
+        state.set_shuffle(true);
+        cache.save_connect_state(&state);
+        assert_eq!(cache.connect_state(), Some(state.clone()));
+
+        state.set_playing_track_index(3);
+        cache.save_connect_state(&state);
+        assert_eq!(cache.connect_state(), Some(state));
+        assert!(!dir.join("connect-state.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -79,20 +79,30 @@ impl CdnUrl {
     }
 
     pub fn try_get_url(&self) -> Result<&str, Error> {
+        self.try_get_urls().map(|urls| urls[0])
+    }
+
+    /// Returns all URLs that did not expire, in the order they were resolved.
+    pub fn try_get_urls(&self) -> Result<Vec<&str>, Error> {
         if self.urls.is_empty() {
             return Err(CdnUrlError::Unresolved.into());
         }
 
         let now = Date::now_utc();
-        let url = self.urls.iter().find(|url| match url.1 {
-            Some(expiry) => now < expiry,
-            None => true,
-        });
+        let urls: Vec<&str> = self
+            .urls
+            .iter()
+            .filter(|url| match url.1 {
+                Some(expiry) => now < expiry,
+                None => true,
+            })
+            .map(|url| url.0.as_str())
+            .collect();
 
-        if let Some(url) = url {
-            Ok(&url.0)
-        } else {
+        if urls.is_empty() {
             Err(CdnUrlError::Expired.into())
+        } else {
+            Ok(urls)
         }
     }
 }
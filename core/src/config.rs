@@ -2,6 +2,8 @@ use std::{fmt, path::PathBuf, str::FromStr};
 
 use url::Url;
 
+use crate::{apresolve::ApResolveData, tls::TlsConfig};
+
 pub(crate) const KEYMASTER_CLIENT_ID: &str = "65b708073fc0480ea92a077233ca87bd";
 pub(crate) const ANDROID_CLIENT_ID: &str = "9a8d2f0ce77a4e248bb71fefcb557637";
 pub(crate) const IOS_CLIENT_ID: &str = "58bd3c95768941ea9eb4350aaa033eb3";
@@ -10,10 +12,20 @@ pub(crate) const IOS_CLIENT_ID: &str = "58bd3c95768941ea9eb4350aaa033eb3";
 pub struct SessionConfig {
     pub client_id: String,
     pub device_id: String,
+    // An HTTP(S) or SOCKS5 proxy for all connections. With `socks5h://` host
+    // names are resolved by the proxy, with `socks5://` locally.
     pub proxy: Option<Url>,
     pub ap_port: Option<u16>,
+    // Addresses to use instead of resolving them, for networks where that is
+    // blocked or slow. Kinds of endpoints left empty fall back to defaults.
+    pub access_points: Option<ApResolveData>,
+    // Root certificates for HTTPS and websocket connections.
+    pub tls: TlsConfig,
     pub tmp_dir: PathBuf,
     pub autoplay: Option<bool>,
+    // Language for titles, descriptions and genres in metadata, as a language
+    // tag like `de` or `pt-BR`. Spotify falls back to English.
+    pub locale: Option<String>,
 }
 
 impl SessionConfig {
@@ -31,8 +43,11 @@ impl SessionConfig {
             device_id,
             proxy: None,
             ap_port: None,
+            access_points: None,
+            tls: TlsConfig::default(),
             tmp_dir: std::env::temp_dir(),
             autoplay: None,
+            locale: None,
         }
     }
 }
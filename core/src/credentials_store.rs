@@ -0,0 +1,216 @@
+//! Where the reusable credentials of a device are kept, see
+//! [`Cache::with_credentials_store`](crate::cache::Cache::with_credentials_store).
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{authentication::Credentials, Error};
+
+pub(crate) const USERS_DIR: &str = "users";
+
+const CREDENTIALS_FILE: &str = "credentials.json";
+
+/// Stores reusable credentials.
+///
+/// `user` is `None` for the credentials of the device itself, or the name of a
+/// user that keeps their own, see [`Cache::for_user`](crate::cache::Cache::for_user).
+pub trait CredentialsStore: Send + Sync {
+    fn load(&self, user: Option<&str>) -> Result<Option<Credentials>, Error>;
+    fn save(&self, user: Option<&str>, credentials: &Credentials) -> Result<(), Error>;
+    fn remove(&self, user: Option<&str>) -> Result<(), Error>;
+
+    /// The users with stored credentials. Stores that cannot list their
+    /// entries return none.
+    fn users(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Keeps credentials as plain JSON in `credentials.json`, and those of users
+/// in `users/<user>/credentials.json`.
+pub struct FileCredentialsStore {
+    dir: PathBuf,
+}
+
+impl FileCredentialsStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn dir(&self, user: Option<&str>) -> PathBuf {
+        match user {
+            Some(user) => self.dir.join(USERS_DIR).join(user),
+            None => self.dir.clone(),
+        }
+    }
+}
+
+impl CredentialsStore for FileCredentialsStore {
+    fn load(&self, user: Option<&str>) -> Result<Option<Credentials>, Error> {
+        match fs::read_to_string(self.dir(user).join(CREDENTIALS_FILE)) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            // The file was probably not written before.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, user: Option<&str>, credentials: &Credentials) -> Result<(), Error> {
+        let dir = self.dir(user);
+        fs::create_dir_all(&dir)?;
+
+        let data = serde_json::to_string(credentials)?;
+        let mut file = File::create(dir.join(CREDENTIALS_FILE))?;
+        write!(file, "{data}")?;
+        Ok(())
+    }
+
+    fn remove(&self, user: Option<&str>) -> Result<(), Error> {
+        match fs::remove_file(self.dir(user).join(CREDENTIALS_FILE)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn users(&self) -> Vec<String> {
+        let entries = match fs::read_dir(self.dir.join(USERS_DIR)) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut users: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.path().join(CREDENTIALS_FILE).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        users.sort();
+        users
+    }
+}
+
+/// Keeps credentials in the keyring of the OS: the Keychain on macOS, the
+/// Credential Manager on Windows and the Secret Service on Linux.
+#[cfg(feature = "with-keyring")]
+pub struct KeyringCredentialsStore {
+    service: String,
+}
+
+#[cfg(feature = "with-keyring")]
+impl KeyringCredentialsStore {
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+        }
+    }
+
+    fn entry(&self, user: Option<&str>) -> Result<keyring::Entry, Error> {
+        let account = match user {
+            Some(user) => format!("user:{user}"),
+            None => String::from("device"),
+        };
+        keyring::Entry::new(&self.service, &account).map_err(Error::unavailable)
+    }
+}
+
+#[cfg(feature = "with-keyring")]
+impl CredentialsStore for KeyringCredentialsStore {
+    fn load(&self, user: Option<&str>) -> Result<Option<Credentials>, Error> {
+        match self.entry(user)?.get_password() {
+            Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(Error::unavailable(e)),
+        }
+    }
+
+    fn save(&self, user: Option<&str>, credentials: &Credentials) -> Result<(), Error> {
+        let data = serde_json::to_string(credentials)?;
+        self.entry(user)?
+            .set_password(&data)
+            .map_err(Error::unavailable)
+    }
+
+    fn remove(&self, user: Option<&str>) -> Result<(), Error> {
+        match self.entry(user)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::unavailable(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "librespot-credentials-test-{}-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_load_save_remove() {
+        let dir = temp_dir("load-save-remove");
+        let store = FileCredentialsStore::new(&dir);
+        let credentials = Credentials::with_password("device", "secret");
+
+        assert_eq!(store.load(None).unwrap(), None);
+        store.save(None, &credentials).unwrap();
+        assert_eq!(store.load(None).unwrap(), Some(credentials.clone()));
+        assert!(dir.join(CREDENTIALS_FILE).is_file());
+
+        let user = Credentials::with_password("alice", "secret");
+        store.save(Some("alice"), &user).unwrap();
+        assert_eq!(store.load(Some("alice")).unwrap(), Some(user));
+        assert_eq!(store.load(None).unwrap(), Some(credentials));
+
+        store.remove(None).unwrap();
+        assert_eq!(store.load(None).unwrap(), None);
+        assert!(store.load(Some("alice")).unwrap().is_some());
+
+        // Removing what isn't there is fine.
+        store.remove(None).unwrap();
+        store.remove(Some("bob")).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_users() {
+        let dir = temp_dir("users");
+        let store = FileCredentialsStore::new(&dir);
+        assert!(store.users().is_empty());
+
+        let credentials = Credentials::with_password("user", "secret");
+        store.save(None, &credentials).unwrap();
+        store.save(Some("bob"), &credentials).unwrap();
+        store.save(Some("alice"), &credentials).unwrap();
+
+        // A user directory without credentials doesn't count.
+        fs::create_dir_all(dir.join(USERS_DIR).join("carol")).unwrap();
+
+        assert_eq!(store.users(), ["alice", "bob"]);
+
+        store.remove(Some("bob")).unwrap();
+        assert_eq!(store.users(), ["alice"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let dir = temp_dir("invalid");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(CREDENTIALS_FILE), "not json").unwrap();
+
+        let store = FileCredentialsStore::new(&dir);
+        assert!(store.load(None).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
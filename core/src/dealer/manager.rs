@@ -0,0 +1,148 @@
+use std::{path::PathBuf, time::Duration};
+
+use thiserror::Error;
+use url::Url;
+
+use super::{Builder, Dealer, RequestHandler, Subscription};
+
+use crate::{session::SessionWeak, Error};
+
+#[derive(Debug, Error)]
+pub enum DealerManagerError {
+    #[error("the dealer has already been started")]
+    AlreadyStarted,
+    #[error("the dealer has been closed")]
+    Closed,
+}
+
+impl From<DealerManagerError> for Error {
+    fn from(err: DealerManagerError) -> Self {
+        Error::failed_precondition(err)
+    }
+}
+
+component! {
+    DealerManager : DealerManagerInner {
+        builder: Option<Builder> = Some(Builder::new()),
+        dealer: Option<Dealer> = None,
+    }
+}
+
+impl DealerManager {
+    async fn get_url(session: SessionWeak) -> Result<Url, Error> {
+        let session = session.try_upgrade().ok_or(DealerManagerError::Closed)?;
+
+        let (host, port) = session.apresolver().resolve("dealer").await?;
+        let token = session.login5().auth_token().await?;
+        let url = format!("wss://{host}:{port}/?access_token={}", token.access_token);
+
+        Ok(Url::parse(&url)?)
+    }
+
+    /// Registers a handler for requests sent to the given URI. Handlers can be
+    /// added both before and after the dealer was started.
+    pub fn add_handler(&self, uri: &str, handler: impl RequestHandler) -> Result<(), Error> {
+        self.lock(|inner| {
+            if let Some(dealer) = inner.dealer.as_ref() {
+                dealer.add_handler(uri, handler)
+            } else if let Some(builder) = inner.builder.as_mut() {
+                builder.add_handler(uri, handler)
+            } else {
+                Err(DealerManagerError::Closed.into())
+            }
+        })
+    }
+
+    /// Subscribes to all messages pushed to one of the given URI prefixes. The
+    /// returned stream yields the raw messages; see [`Message::payload_bytes`].
+    ///
+    /// [`Message::payload_bytes`]: super::protocol::Message::payload_bytes
+    pub fn subscribe(&self, uris: &[&str]) -> Result<Subscription, Error> {
+        self.lock(|inner| {
+            if let Some(dealer) = inner.dealer.as_ref() {
+                dealer.subscribe(uris)
+            } else if let Some(builder) = inner.builder.as_mut() {
+                builder.subscribe(uris)
+            } else {
+                Err(DealerManagerError::Closed.into())
+            }
+        })
+    }
+
+    /// Configures how often the websocket is pinged and how long to wait for
+    /// the pong. Lower values detect dead connections sooner, e.g. on cellular
+    /// links. Must be called before [`start`](Self::start).
+    pub fn set_keepalive(&self, interval: Duration, timeout: Duration) -> Result<(), Error> {
+        self.lock(|inner| {
+            if inner.dealer.is_some() {
+                return Err(DealerManagerError::AlreadyStarted.into());
+            }
+
+            let builder = inner.builder.as_mut().ok_or(DealerManagerError::Closed)?;
+            builder.ping_interval(interval).ping_timeout(timeout);
+
+            Ok(())
+        })
+    }
+
+    /// Records all dealer traffic to `path` for debugging, see
+    /// [`Builder::record_to`]. Must be called before [`start`](Self::start).
+    pub fn record_to(&self, path: impl Into<PathBuf>) -> Result<(), Error> {
+        self.lock(|inner| {
+            if inner.dealer.is_some() {
+                return Err(DealerManagerError::AlreadyStarted.into());
+            }
+
+            let builder = inner.builder.as_mut().ok_or(DealerManagerError::Closed)?;
+            builder.record_to(path);
+
+            Ok(())
+        })
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.lock(|inner| inner.dealer.is_some())
+    }
+
+    /// Whether the dealer websocket is currently connected and alive.
+    pub fn is_connected(&self) -> bool {
+        self.lock(|inner| inner.dealer.as_ref().is_some_and(Dealer::is_connected))
+    }
+
+    /// Connects to the dealer in the background, reconnecting whenever the
+    /// connection drops. Does nothing if the dealer is already running.
+    pub fn start(&self) -> Result<(), Error> {
+        let session = (self.0).0.clone();
+        let proxy = self.session().config().proxy.clone();
+        let metrics = self.session().metrics();
+        let tls_config = self.session().config().tls.clone();
+
+        self.lock(|inner| {
+            if inner.dealer.is_some() {
+                return Ok(());
+            }
+
+            let mut builder = inner.builder.take().ok_or(DealerManagerError::Closed)?;
+            if let Some(metrics) = metrics {
+                builder.metrics(metrics);
+            }
+            builder.tls_config(tls_config);
+
+            let get_url = move || Self::get_url(session.clone());
+            inner.dealer = Some(builder.launch_in_background(get_url, proxy));
+
+            Ok(())
+        })
+    }
+
+    pub async fn close(&self) {
+        let dealer = self.lock(|inner| {
+            inner.builder = None;
+            inner.dealer.take()
+        });
+
+        if let Some(dealer) = dealer {
+            dealer.close().await;
+        }
+    }
+}
@@ -1,8 +1,11 @@
+mod manager;
 mod maps;
 pub mod protocol;
+mod recorder;
 
 use std::{
-    iter,
+    io, iter,
+    path::PathBuf,
     pin::Pin,
     sync::{
         atomic::{self, AtomicBool},
@@ -30,9 +33,15 @@ use url::Url;
 
 use self::maps::*;
 use self::protocol::*;
+use self::recorder::Recorder;
+
+pub use self::manager::{DealerManager, DealerManagerError};
+pub use self::recorder::{read_recording, Direction, RecordedFrame};
 
 use crate::{
+    metrics::Metrics,
     socket,
+    tls::{self, TlsConfig},
     util::{keep_flushing, CancelOnDrop, TimeoutOnDrop},
     Error,
 };
@@ -43,10 +52,11 @@ type WsResult<T> = Result<T, tungstenite::Error>;
 
 const WEBSOCKET_CLOSE_TIMEOUT: Duration = Duration::from_secs(3);
 
-const PING_INTERVAL: Duration = Duration::from_secs(30);
-const PING_TIMEOUT: Duration = Duration::from_secs(3);
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(3);
 
-const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+const RECONNECT_INTERVAL_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_INTERVAL_MAX: Duration = Duration::from_secs(60);
 
 pub struct Response {
     pub success: bool,
@@ -224,21 +234,35 @@ fn subscribe(
     Ok(Subscription(rx))
 }
 
-#[derive(Default)]
 pub struct Builder {
     message_handlers: SubscriberMap<MessageHandler>,
     request_handlers: HandlerMap<Box<dyn RequestHandler>>,
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    record_path: Option<PathBuf>,
+    metrics: Option<Arc<dyn Metrics>>,
+    tls_config: TlsConfig,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            message_handlers: SubscriberMap::default(),
+            request_handlers: HandlerMap::default(),
+            ping_interval: DEFAULT_PING_INTERVAL,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
+            record_path: None,
+            metrics: None,
+            tls_config: TlsConfig::default(),
+        }
+    }
 }
 
 macro_rules! create_dealer {
     ($builder:expr, $shared:ident -> $body:expr) => {
         match $builder {
             builder => {
-                let shared = Arc::new(DealerShared {
-                    message_handlers: Mutex::new(builder.message_handlers),
-                    request_handlers: Mutex::new(builder.request_handlers),
-                    notify_drop: Semaphore::new(0),
-                });
+                let shared = Arc::new(DealerShared::new(builder));
 
                 let handle = {
                     let $shared = Arc::clone(&shared);
@@ -267,22 +291,79 @@ impl Builder {
         subscribe(&mut self.message_handlers, uris)
     }
 
+    /// Sets how often the websocket is pinged to check whether it is still alive.
+    pub fn ping_interval(&mut self, interval: Duration) -> &mut Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// Sets how long to wait for a pong before the connection is considered dead.
+    pub fn ping_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.ping_timeout = timeout;
+        self
+    }
+
+    /// Writes all frames sent and received to `path` as JSON lines, with
+    /// tokens redacted. Meant for debugging; see [`Builder::replay`].
+    pub fn record_to(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.record_path = Some(path.into());
+        self
+    }
+
+    /// Sets the root certificates to trust.
+    pub fn tls_config(&mut self, tls_config: TlsConfig) -> &mut Self {
+        self.tls_config = tls_config;
+        self
+    }
+
+    /// Reports every message and request received to `metrics`.
+    pub fn metrics(&mut self, metrics: Arc<dyn Metrics>) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Feeds the received frames of a recording into the handlers and
+    /// subscriptions of this builder, without connecting to Spotify. Fails on
+    /// the first frame that cannot be parsed.
+    pub fn replay(self, frames: impl IntoIterator<Item = RecordedFrame>) -> Result<(), Error> {
+        let shared = DealerShared::new(self);
+
+        // Replies to requests are only logged.
+        let (send_tx, mut send_rx) = mpsc::unbounded_channel();
+
+        for frame in frames {
+            if frame.direction != Direction::Received {
+                continue;
+            }
+
+            let message: MessageOrRequest = serde_json::from_value(frame.frame)?;
+            shared.dispatch(message, &send_tx);
+
+            while let Ok(reply) = send_rx.try_recv() {
+                debug!("Replay handler replied: {}", reply);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn launch_in_background<Fut, F>(self, get_url: F, proxy: Option<Url>) -> Dealer
     where
-        Fut: Future<Output = Url> + Send + 'static,
+        Fut: Future<Output = Result<Url, Error>> + Send + 'static,
         F: (FnMut() -> Fut) + Send + 'static,
     {
         create_dealer!(self, shared -> run(shared, None, get_url, proxy))
     }
 
-    pub async fn launch<Fut, F>(self, mut get_url: F, proxy: Option<Url>) -> WsResult<Dealer>
+    pub async fn launch<Fut, F>(self, mut get_url: F, proxy: Option<Url>) -> Result<Dealer, Error>
     where
-        Fut: Future<Output = Url> + Send + 'static,
+        Fut: Future<Output = Result<Url, Error>> + Send + 'static,
         F: (FnMut() -> Fut) + Send + 'static,
     {
+        // Try to connect.
+        let url = get_url().await?;
+
         let dealer = create_dealer!(self, shared -> {
-            // Try to connect.
-            let url = get_url().await;
             let tasks = connect(&url, proxy.as_ref(), &shared).await?;
 
             // If a connection is established, continue in a background task.
@@ -293,16 +374,56 @@ impl Builder {
     }
 }
 
+impl Drop for Dealer {
+    fn drop(&mut self) {
+        // Stop reconnecting when the dealer is dropped without being closed.
+        self.shared.notify_drop.close();
+    }
+}
+
 struct DealerShared {
     message_handlers: Mutex<SubscriberMap<MessageHandler>>,
     request_handlers: Mutex<HandlerMap<Box<dyn RequestHandler>>>,
 
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    connected: AtomicBool,
+    recorder: Option<Recorder>,
+    metrics: Option<Arc<dyn Metrics>>,
+    tls_config: TlsConfig,
+
     // Semaphore with 0 permits. By closing this semaphore, we indicate
     // that the actual Dealer struct has been dropped.
     notify_drop: Semaphore,
 }
 
 impl DealerShared {
+    fn new(builder: Builder) -> Self {
+        let recorder = builder.record_path.and_then(|path| {
+            Recorder::create(&path)
+                .map_err(|e| warn!("Unable to record dealer frames to {:?}: {}", path, e))
+                .ok()
+        });
+
+        Self {
+            message_handlers: Mutex::new(builder.message_handlers),
+            request_handlers: Mutex::new(builder.request_handlers),
+            ping_interval: builder.ping_interval,
+            ping_timeout: builder.ping_timeout,
+            connected: AtomicBool::new(false),
+            recorder,
+            metrics: builder.metrics,
+            tls_config: builder.tls_config,
+            notify_drop: Semaphore::new(0),
+        }
+    }
+
+    fn record(&self, direction: Direction, text: &str) {
+        if let Some(recorder) = self.recorder.as_ref() {
+            recorder.record(direction, text);
+        }
+    }
+
     fn dispatch_message(&self, msg: Message) {
         if let Some(split) = split_uri(&msg.uri) {
             self.message_handlers
@@ -338,6 +459,15 @@ impl DealerShared {
     }
 
     fn dispatch(&self, m: MessageOrRequest, send_tx: &mpsc::UnboundedSender<WsMessage>) {
+        let uri = match &m {
+            MessageOrRequest::Message(m) => &m.uri,
+            MessageOrRequest::Request(r) => &r.message_ident,
+        };
+        let _span = tracing::debug_span!("dealer_message", uri = uri.as_str()).entered();
+        if let Some(metrics) = &self.metrics {
+            metrics.dealer_message(uri);
+        }
+
         match m {
             MessageOrRequest::Message(m) => self.dispatch_message(m),
             MessageOrRequest::Request(r) => self.dispatch_request(r, send_tx),
@@ -376,6 +506,11 @@ impl Dealer {
         subscribe(&mut self.shared.message_handlers.lock(), uris)
     }
 
+    /// Whether the websocket is currently connected and answering pings.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(atomic::Ordering::Acquire)
+    }
+
     pub async fn close(mut self) {
         debug!("closing dealer");
 
@@ -409,10 +544,20 @@ async fn connect(
 
     let stream = socket::connect(host, port, proxy).await?;
 
-    let (mut ws_tx, ws_rx) = tokio_tungstenite::client_async_tls(address.as_str(), stream)
-        .await?
-        .0
-        .split();
+    let connector = tls::websocket_connector(&shared.tls_config)
+        .map_err(|e| WsError::Io(io::Error::other(e)))?;
+
+    let (mut ws_tx, ws_rx) = tokio_tungstenite::client_async_tls_with_config(
+        address.as_str(),
+        stream,
+        None,
+        Some(connector),
+    )
+    .await?
+    .0
+    .split();
+
+    shared.connected.store(true, atomic::Ordering::Release);
 
     let (send_tx, mut send_rx) = mpsc::unbounded_channel::<WsMessage>();
 
@@ -434,6 +579,10 @@ async fn connect(
                                 break Ok(close_frame);
                             }
 
+                            if let WsMessage::Text(ref text) = msg {
+                                shared.record(Direction::Sent, text);
+                            }
+
                             if let Err(e) = ws_tx.feed(msg).await  {
                                 break Err(e);
                             }
@@ -482,10 +631,13 @@ async fn connect(
             loop {
                 match ws_rx.next().await {
                     Some(Ok(msg)) => match msg {
-                        WsMessage::Text(t) => match serde_json::from_str(&t) {
-                            Ok(m) => shared.dispatch(m, &send_tx),
-                            Err(e) => info!("Received invalid message: {}", e),
-                        },
+                        WsMessage::Text(t) => {
+                            shared.record(Direction::Received, &t);
+                            match serde_json::from_str(&t) {
+                                Ok(m) => shared.dispatch(m, &send_tx),
+                                Err(e) => info!("Received invalid message: {}", e),
+                            }
+                        }
                         WsMessage::Binary(_) => {
                             info!("Received invalid binary message");
                         }
@@ -511,7 +663,7 @@ async fn connect(
         let ping_task = async {
             use tokio::time::{interval, sleep};
 
-            let mut timer = interval(PING_INTERVAL);
+            let mut timer = interval(shared.ping_interval);
 
             loop {
                 timer.tick().await;
@@ -524,7 +676,7 @@ async fn connect(
 
                 debug!("Sent ping");
 
-                sleep(PING_TIMEOUT).await;
+                sleep(shared.ping_timeout).await;
 
                 if !pong_received.load(atomic::Ordering::SeqCst) {
                     // No response
@@ -541,6 +693,8 @@ async fn connect(
             () = receive_task => ()
         }
 
+        shared.connected.store(false, atomic::Ordering::Release);
+
         // Try to take send_task down with us, in case it's still alive.
         let _ = send_tx.send(WsMessage::Close(None));
 
@@ -557,7 +711,7 @@ async fn run<F, Fut>(
     mut get_url: F,
     proxy: Option<Url>,
 ) where
-    Fut: Future<Output = Url> + Send + 'static,
+    Fut: Future<Output = Result<Url, Error>> + Send + 'static,
     F: (FnMut() -> Fut) + Send + 'static,
 {
     let init_task = |t| Some(TimeoutOnDrop::new(t, WEBSOCKET_CLOSE_TIMEOUT));
@@ -568,6 +722,9 @@ async fn run<F, Fut>(
         (None, None)
     };
 
+    let mut reconnect_interval = RECONNECT_INTERVAL_MIN;
+
+    // Handlers and subscriptions live in `shared`, so they carry over to every new connection.
     while !shared.is_closed() {
         match &mut tasks {
             (Some(t0), Some(t1)) => {
@@ -595,11 +752,29 @@ async fn run<F, Fut>(
                     e = get_url() => e
                 };
 
-                match connect(&url, proxy.as_ref(), &shared).await {
-                    Ok((s, r)) => tasks = (init_task(s), init_task(r)),
+                let result = match url {
+                    Ok(url) => connect(&url, proxy.as_ref(), &shared)
+                        .await
+                        .map_err(Error::from),
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok((s, r)) => {
+                        reconnect_interval = RECONNECT_INTERVAL_MIN;
+                        tasks = (init_task(s), init_task(r))
+                    }
                     Err(e) => {
-                        error!("Error while connecting: {}", e);
-                        tokio::time::sleep(RECONNECT_INTERVAL).await;
+                        error!(
+                            "Error while connecting: {}, retrying in {} seconds",
+                            e,
+                            reconnect_interval.as_secs()
+                        );
+                        select! {
+                            () = shared.closed() => break,
+                            () = tokio::time::sleep(reconnect_interval) => (),
+                        }
+                        reconnect_interval = (reconnect_interval * 2).min(RECONNECT_INTERVAL_MAX);
                     }
                 }
             }
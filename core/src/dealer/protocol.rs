@@ -1,6 +1,31 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io::Read};
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::engine::Engine as _;
+use flate2::read::GzDecoder;
 use serde::Deserialize;
+use thiserror::Error;
+
+use crate::Error;
+
+const TRANSFER_ENCODING_HEADER: &str = "Transfer-Encoding";
+
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("payload is not a string")]
+    InvalidPayload,
+    #[error("unsupported transfer encoding: {0}")]
+    UnsupportedEncoding(String),
+}
+
+impl From<ProtocolError> for Error {
+    fn from(err: ProtocolError) -> Self {
+        match err {
+            ProtocolError::InvalidPayload => Error::invalid_argument(err),
+            ProtocolError::UnsupportedEncoding(_) => Error::unimplemented(err),
+        }
+    }
+}
 
 pub type JsonValue = serde_json::Value;
 pub type JsonObject = serde_json::Map<String, JsonValue>;
@@ -31,9 +56,114 @@ pub struct Message {
     pub uri: String,
 }
 
+impl Message {
+    /// Decodes the base64 encoded payloads, decompressing them when the push
+    /// was sent with a gzip transfer encoding.
+    pub fn payload_bytes(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let encoding = self
+            .headers
+            .get(TRANSFER_ENCODING_HEADER)
+            .map(String::as_str);
+
+        self.payloads
+            .iter()
+            .map(|payload| {
+                let payload = payload.as_str().ok_or(ProtocolError::InvalidPayload)?;
+                let data = BASE64.decode(payload)?;
+
+                match encoding {
+                    None => Ok(data),
+                    Some("gzip") => {
+                        let mut decompressed = Vec::new();
+                        GzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+                        Ok(decompressed)
+                    }
+                    Some(encoding) => {
+                        Err(ProtocolError::UnsupportedEncoding(encoding.to_owned()).into())
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub(super) enum MessageOrRequest {
     Message(Message),
     Request(Request),
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+    use crate::error::ErrorKind;
+
+    fn message(encoding: Option<&str>, payloads: Vec<JsonValue>) -> Message {
+        Message {
+            headers: encoding
+                .map(|encoding| (TRANSFER_ENCODING_HEADER.to_owned(), encoding.to_owned()))
+                .into_iter()
+                .collect(),
+            method: None,
+            payloads,
+            uri: "hm://connect-state/v1/cluster".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_plain_payloads() {
+        let message = message(
+            None,
+            vec![
+                BASE64.encode(b"first").into(),
+                BASE64.encode(b"second").into(),
+            ],
+        );
+        assert_eq!(
+            message.payload_bytes().unwrap(),
+            vec![b"first".to_vec(), b"second".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_gzip_payload() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let message = message(Some("gzip"), vec![BASE64.encode(compressed).into()]);
+        assert_eq!(
+            message.payload_bytes().unwrap(),
+            vec![b"compressed".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_invalid_payloads() {
+        let not_a_string = message(None, vec![JsonValue::from(1)]);
+        assert_eq!(
+            not_a_string.payload_bytes().unwrap_err().kind,
+            ErrorKind::InvalidArgument
+        );
+
+        let not_base64 = message(None, vec!["not base64!".into()]);
+        assert!(not_base64.payload_bytes().is_err());
+
+        let not_gzip = message(Some("gzip"), vec![BASE64.encode(b"plain").into()]);
+        assert!(not_gzip.payload_bytes().is_err());
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        let message = message(Some("br"), vec![BASE64.encode(b"data").into()]);
+        assert_eq!(
+            message.payload_bytes().unwrap_err().kind,
+            ErrorKind::Unimplemented
+        );
+    }
+}
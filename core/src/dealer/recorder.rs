@@ -0,0 +1,206 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::protocol::JsonValue;
+
+use crate::Error;
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+/// A single websocket frame as written by a dealer recording, one per line.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub direction: Direction,
+    pub timestamp_ms: u64,
+    pub frame: JsonValue,
+}
+
+// Frames are written on a thread of their own, so recording never blocks the
+// dealer tasks on disk I/O.
+pub(super) struct Recorder {
+    tx: Option<mpsc::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub(super) fn create(path: &Path) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        // Even redacted, the frames say a lot about the account.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let file = options.open(path)?;
+        let (tx, rx) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("dealer-recorder".to_owned())
+            .spawn(move || Self::write_lines(BufWriter::new(file), rx))?;
+
+        Ok(Self {
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    fn write_lines(mut writer: BufWriter<File>, rx: mpsc::Receiver<String>) {
+        // Flushes whenever the queue runs empty, so the recording is complete
+        // up to the last frame even if librespot is killed.
+        while let Ok(line) = rx.recv() {
+            let result = writeln!(writer, "{line}")
+                .and_then(|_| {
+                    rx.try_iter()
+                        .try_for_each(|line| writeln!(writer, "{line}"))
+                })
+                .and_then(|_| writer.flush());
+
+            if let Err(e) = result {
+                warn!("Unable to record dealer frames: {}", e);
+                break;
+            }
+        }
+    }
+
+    pub(super) fn record(&self, direction: Direction, text: &str) {
+        // Keep frames that are not valid JSON as they are, they're the
+        // interesting ones when reproducing parser errors.
+        let mut frame =
+            serde_json::from_str(text).unwrap_or_else(|_| JsonValue::String(text.to_owned()));
+        redact(&mut frame);
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let record = RecordedFrame {
+            direction,
+            timestamp_ms,
+            frame,
+        };
+
+        match serde_json::to_string(&record) {
+            // Only fails once the writer thread gave up, which it has said why.
+            Ok(line) => {
+                if let Some(tx) = self.tx.as_ref() {
+                    let _ = tx.send(line);
+                }
+            }
+            Err(e) => warn!("Unable to record dealer frame: {}", e),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Lets the writer thread finish the queue and end.
+        self.tx.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    // The connection id is what pushes to this device are addressed with.
+    let key = key.to_lowercase().replace('_', "-");
+    key.contains("token") || key == "authorization" || key.ends_with("connection-id")
+}
+
+fn redact(value: &mut JsonValue) {
+    match value {
+        JsonValue::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive(key) {
+                    *value = JsonValue::String(REDACTED.to_owned());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        JsonValue::Array(values) => values.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+/// Reads a recording created with [`Builder::record_to`](super::Builder::record_to).
+pub fn read_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedFrame>, Error> {
+    let reader = BufReader::new(File::open(path)?);
+
+    reader
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let mut frame = serde_json::json!({
+            "headers": { "Authorization": "Bearer foo", "Spotify-Connection-Id": "bar" },
+            "payloads": [{ "access_token": "baz", "uri": "spotify:track:qux" }],
+        });
+
+        redact(&mut frame);
+
+        assert_eq!(frame["headers"]["Authorization"], REDACTED);
+        assert_eq!(frame["headers"]["Spotify-Connection-Id"], REDACTED);
+        assert_eq!(frame["payloads"][0]["access_token"], REDACTED);
+        assert_eq!(frame["payloads"][0]["uri"], "spotify:track:qux");
+    }
+
+    #[test]
+    fn test_recording() {
+        let path = std::env::temp_dir().join(format!(
+            "librespot-dealer-recording-{}.jsonl",
+            std::process::id()
+        ));
+
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(Direction::Sent, r#"{"type":"ping"}"#);
+        recorder.record(
+            Direction::Received,
+            r#"{"type":"message","headers":{"Spotify-Connection-Id":"foo"}}"#,
+        );
+        recorder.record(Direction::Received, "not json");
+        drop(recorder);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let frames = read_recording(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].direction, Direction::Sent);
+        assert_eq!(frames[0].frame["type"], "ping");
+        assert_eq!(frames[1].direction, Direction::Received);
+        assert_eq!(
+            frames[1].frame["headers"]["Spotify-Connection-Id"],
+            REDACTED
+        );
+        assert_eq!(frames[2].frame, "not json");
+    }
+}
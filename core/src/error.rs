@@ -17,6 +17,7 @@ use thiserror::Error;
 use tokio::sync::{
     mpsc::error::SendError, oneshot::error::RecvError, AcquireError, TryAcquireError,
 };
+use tokio_tungstenite::tungstenite::Error as WebsocketError;
 use url::ParseError;
 
 use librespot_oauth::OAuthError;
@@ -376,6 +377,19 @@ impl From<hyper_util::client::legacy::Error> for Error {
     }
 }
 
+impl From<WebsocketError> for Error {
+    fn from(err: WebsocketError) -> Self {
+        match err {
+            WebsocketError::Io(e) => e.into(),
+            WebsocketError::ConnectionClosed | WebsocketError::AlreadyClosed => {
+                Self::new(ErrorKind::Aborted, err)
+            }
+            WebsocketError::Url(_) => Self::new(ErrorKind::InvalidArgument, err),
+            _ => Self::new(ErrorKind::Unknown, err),
+        }
+    }
+}
+
 impl From<time::error::Parse> for Error {
     fn from(err: time::error::Parse) -> Self {
         Self::new(ErrorKind::FailedPrecondition, err)
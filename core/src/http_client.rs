@@ -1,11 +1,15 @@
 use std::{
     collections::HashMap,
     env::consts::OS,
+    task::{Context, Poll},
     time::{Duration, Instant},
 };
 
 use bytes::Bytes;
-use futures_util::{future::IntoStream, FutureExt};
+use futures_util::{
+    future::{BoxFuture, IntoStream},
+    FutureExt, TryFutureExt,
+};
 use governor::{
     clock::MonotonicClock, middleware::NoOpMiddleware, state::InMemoryState, Quota, RateLimiter,
 };
@@ -13,20 +17,24 @@ use http::{header::HeaderValue, Uri};
 use http_body_util::{BodyExt, Full};
 use hyper::{body::Incoming, header::USER_AGENT, HeaderMap, Request, Response, StatusCode};
 use hyper_proxy2::{Intercept, Proxy, ProxyConnector};
-use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
 use hyper_util::{
     client::legacy::{connect::HttpConnector, Client, ResponseFuture},
-    rt::TokioExecutor,
+    rt::{TokioExecutor, TokioIo, TokioTimer},
 };
 use nonzero_ext::nonzero;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use rand::Rng;
 use sysinfo::System;
 use thiserror::Error;
+use tokio::net::TcpStream;
+use tower_service::Service;
 use url::Url;
 
 use crate::{
     date::Date,
+    socket,
+    tls::{self, HttpsConnector, TlsConfig},
     version::{spotify_version, FALLBACK_USER_AGENT, VERSION_STRING},
     Error,
 };
@@ -38,6 +46,21 @@ pub const RATE_LIMIT_INTERVAL: Duration = Duration::from_secs(30);
 pub const RATE_LIMIT_MAX_WAIT: Duration = Duration::from_secs(10);
 pub const RATE_LIMIT_CALLS_PER_INTERVAL: u32 = 300;
 
+// Retrying after HTTP/429 gives up after this many tries in total.
+const RATE_LIMIT_MAX_TRIES: usize = 5;
+const RATE_LIMIT_BACKOFF_MIN: Duration = Duration::from_millis(500);
+
+/// Add this to the extensions of a request to fail right away when rate
+/// limited, rather than waiting to retry. Meant for latency-sensitive calls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoRetry;
+
+// Keep connections, and with HTTP/2 their multiplexed streams, around between
+// tracks, so that starting the next one does not need new TCP and TLS
+// handshakes. Pings stop idle connections from being dropped by middleboxes.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const HTTP2_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Error)]
 pub enum HttpClientError {
     #[error("Response status code: {0}")]
@@ -90,11 +113,54 @@ impl From<HttpClientError> for Error {
     }
 }
 
-type HyperClient = Client<ProxyConnector<HttpsConnector<HttpConnector>>, Full<bytes::Bytes>>;
+type HyperClient = Client<ProxyConnector<HttpsConnector<TcpConnector>>, Full<bytes::Bytes>>;
+
+// Opens the TCP connections below TLS, either directly or through a SOCKS5
+// proxy. HTTP proxies are handled by the `ProxyConnector` on top instead.
+#[derive(Clone)]
+enum TcpConnector {
+    Direct(HttpConnector),
+    Socks5(Url),
+}
+
+impl Service<Uri> for TcpConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self {
+            Self::Direct(connector) => connector.poll_ready(cx).map_err(Into::into),
+            Self::Socks5(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        match self {
+            Self::Direct(connector) => connector.call(uri).map_err(Into::into).boxed(),
+            Self::Socks5(proxy_url) => {
+                let proxy_url = proxy_url.clone();
+                async move {
+                    let host = uri.host().ok_or("URI has no host")?;
+                    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+                        Some("http") => 80,
+                        _ => 443,
+                    });
+
+                    let stream = socket::connect(host, port, Some(&proxy_url)).await?;
+                    stream.set_nodelay(true)?;
+                    Ok::<_, Self::Error>(TokioIo::new(stream))
+                }
+                .boxed()
+            }
+        }
+    }
+}
 
 pub struct HttpClient {
     user_agent: HeaderValue,
     proxy_url: Option<Url>,
+    tls_config: TlsConfig,
     hyper_client: OnceCell<HyperClient>,
 
     // while the DashMap variant is more performant, our level of concurrency
@@ -141,37 +207,65 @@ impl HttpClient {
         Self {
             user_agent,
             proxy_url: proxy_url.cloned(),
+            tls_config: TlsConfig::default(),
             hyper_client: OnceCell::new(),
             rate_limiter,
         }
     }
 
-    fn try_create_hyper_client(proxy_url: Option<&Url>) -> Result<HyperClient, Error> {
+    /// Sets the root certificates to trust. Only has an effect before the
+    /// first request.
+    pub fn with_tls_config(mut self, tls_config: TlsConfig) -> Self {
+        self.tls_config = tls_config;
+        self
+    }
+
+    fn try_create_hyper_client(
+        proxy_url: Option<&Url>,
+        tls_config: &TlsConfig,
+    ) -> Result<HyperClient, Error> {
+        let tcp_connector = match proxy_url {
+            Some(proxy_url) if socket::is_socks5(proxy_url) => {
+                TcpConnector::Socks5(proxy_url.clone())
+            }
+            _ => {
+                let mut http_connector = HttpConnector::new();
+                http_connector.enforce_http(false);
+                http_connector.set_nodelay(true);
+                http_connector.set_keepalive(Some(HTTP2_KEEP_ALIVE_INTERVAL));
+                TcpConnector::Direct(http_connector)
+            }
+        };
+
         // configuring TLS is expensive and should be done once per process
-        let https_connector = HttpsConnectorBuilder::new()
-            .with_native_roots()?
-            .https_or_http()
-            .enable_http1()
-            .enable_http2()
-            .build();
-
-        // When not using a proxy a dummy proxy is configured that will not intercept any traffic.
-        // This prevents needing to carry the Client Connector generics through the whole project
+        let https_connector = tls::https_connector(tls_config, tcp_connector)?;
+
+        // When not using an HTTP proxy a dummy proxy is configured that will not intercept any
+        // traffic. This prevents needing to carry the Client Connector generics through the whole
+        // project.
         let proxy = match &proxy_url {
-            Some(proxy_url) => Proxy::new(Intercept::All, proxy_url.to_string().parse()?),
-            None => Proxy::new(Intercept::None, Uri::from_static("0.0.0.0")),
+            Some(proxy_url) if !socket::is_socks5(proxy_url) => {
+                Proxy::new(Intercept::All, proxy_url.to_string().parse()?)
+            }
+            _ => Proxy::new(Intercept::None, Uri::from_static("0.0.0.0")),
         };
         let proxy_connector = ProxyConnector::from_proxy(https_connector, proxy)?;
 
         let client = Client::builder(TokioExecutor::new())
+            .timer(TokioTimer::new())
+            .pool_timer(TokioTimer::new())
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
             .http2_adaptive_window(true)
+            .http2_keep_alive_interval(HTTP2_KEEP_ALIVE_INTERVAL)
+            .http2_keep_alive_while_idle(true)
             .build(proxy_connector);
         Ok(client)
     }
 
     fn hyper_client(&self) -> Result<&HyperClient, Error> {
-        self.hyper_client
-            .get_or_try_init(|| Self::try_create_hyper_client(self.proxy_url.as_ref()))
+        self.hyper_client.get_or_try_init(|| {
+            Self::try_create_hyper_client(self.proxy_url.as_ref(), &self.tls_config)
+        })
     }
 
     pub async fn request(&self, req: Request<Bytes>) -> Result<Response<Incoming>, Error> {
@@ -181,8 +275,12 @@ impl HttpClient {
         // As correct as that may be technically, we now need all this boilerplate to clone it
         // ourselves, as any `Request` is moved in the loop.
         let (parts, body_as_bytes) = req.into_parts();
+        let retry = parts.extensions.get::<NoRetry>().is_none();
+        let mut tries = 0;
 
         loop {
+            tries += 1;
+
             let mut req = Request::builder()
                 .method(parts.method.clone())
                 .uri(parts.uri.clone())
@@ -196,13 +294,13 @@ impl HttpClient {
             if let Ok(response) = &response {
                 let code = response.status();
 
-                if code == StatusCode::TOO_MANY_REQUESTS {
-                    if let Some(duration) = Self::get_retry_after(response.headers()) {
+                if code == StatusCode::TOO_MANY_REQUESTS && retry && tries < RATE_LIMIT_MAX_TRIES {
+                    if let Some(delay) = Self::rate_limit_delay(response.headers(), tries) {
                         warn!(
-                            "Rate limited by service, retrying in {} seconds...",
-                            duration.as_secs()
+                            "Rate limited by service, retrying in {} ms...",
+                            delay.as_millis()
                         );
-                        tokio::time::sleep(duration).await;
+                        tokio::time::sleep(delay).await;
                         continue;
                     }
                 }
@@ -217,6 +315,32 @@ impl HttpClient {
         }
     }
 
+    // How long to wait before the next try after being rate limited, or `None`
+    // when the service wants us to wait longer than we are willing to. Without
+    // a Retry-After the wait doubles with every try. Jitter keeps many clients
+    // from retrying at the same moment.
+    fn rate_limit_delay(headers: &HeaderMap<HeaderValue>, tries: usize) -> Option<Duration> {
+        let delay = match Self::get_retry_after(headers) {
+            Some(duration) => duration,
+            None if Self::has_retry_after(headers) => return None,
+            None => {
+                let exponent = u32::try_from(tries.saturating_sub(1)).unwrap_or(u32::MAX);
+                RATE_LIMIT_BACKOFF_MIN
+                    .saturating_mul(2u32.saturating_pow(exponent))
+                    .min(RATE_LIMIT_MAX_WAIT)
+            }
+        };
+
+        let jitter = rand::thread_rng().gen_range(0.0..=0.25);
+        Some(delay.mul_f64(1.0 + jitter))
+    }
+
+    fn has_retry_after(headers: &HeaderMap<HeaderValue>) -> bool {
+        ["X-RateLimit-Next", "Fastly-RateLimit-Reset", "Retry-After"]
+            .iter()
+            .any(|name| headers.contains_key(*name))
+    }
+
     pub async fn request_body(&self, req: Request<Bytes>) -> Result<Bytes, Error> {
         let response = self.request(req).await?;
         Ok(response.into_body().collect().await?.to_bytes())
@@ -298,3 +422,52 @@ impl HttpClient {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_jittered(delay: Option<Duration>, base: Duration) {
+        let delay = delay.unwrap();
+        assert!(
+            delay >= base && delay <= base.mul_f64(1.25),
+            "{delay:?} is not within 25% above {base:?}"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_backoff() {
+        let headers = HeaderMap::new();
+
+        for (tries, base_ms) in [(1, 500), (2, 1000), (3, 2000), (4, 4000), (5, 8000)] {
+            assert_jittered(
+                HttpClient::rate_limit_delay(&headers, tries),
+                Duration::from_millis(base_ms),
+            );
+        }
+
+        // The backoff is capped, however often we tried.
+        assert_jittered(
+            HttpClient::rate_limit_delay(&headers, 6),
+            RATE_LIMIT_MAX_WAIT,
+        );
+        assert_jittered(
+            HttpClient::rate_limit_delay(&headers, usize::MAX),
+            RATE_LIMIT_MAX_WAIT,
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Retry-After", HeaderValue::from_static("3"));
+        assert_jittered(
+            HttpClient::rate_limit_delay(&headers, 5),
+            Duration::from_secs(3),
+        );
+
+        // Waiting longer than we are willing to gives up instead of backing off.
+        headers.insert("Retry-After", HeaderValue::from_static("3600"));
+        assert_eq!(HttpClient::rate_limit_delay(&headers, 1), None);
+    }
+}
@@ -14,9 +14,9 @@ pub mod cdn_url;
 pub mod channel;
 pub mod config;
 mod connection;
+pub mod credentials_store;
 pub mod date;
-#[allow(dead_code)]
-mod dealer;
+pub mod dealer;
 #[doc(hidden)]
 pub mod diffie_hellman;
 pub mod error;
@@ -24,13 +24,16 @@ pub mod file_id;
 pub mod http_client;
 pub mod login5;
 pub mod mercury;
+pub mod metrics;
 pub mod packet;
 mod proxytunnel;
 pub mod session;
+pub mod session_manager;
 mod socket;
 #[allow(dead_code)]
 pub mod spclient;
 pub mod spotify_id;
+pub mod tls;
 pub mod token;
 #[doc(hidden)]
 pub mod util;
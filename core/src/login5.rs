@@ -1,5 +1,6 @@
+use crate::session::SessionWeak;
 use crate::spclient::CLIENT_TOKEN;
-use crate::token::Token;
+use crate::token::{AuthTokenProvider, Token};
 use crate::{util, Error, SessionConfig};
 use bytes::Bytes;
 use http::{header::ACCEPT, HeaderValue, Method, Request};
@@ -16,6 +17,7 @@ use librespot_protocol::{
 use protobuf::well_known_types::duration::Duration as ProtoDuration;
 use protobuf::{Message, MessageField};
 use std::env::consts::OS;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::time::sleep;
@@ -23,9 +25,15 @@ use tokio::time::sleep;
 const MAX_LOGIN_TRIES: u8 = 3;
 const LOGIN_TIMEOUT: Duration = Duration::from_secs(3);
 
+// Refresh the auth token this long before it expires, and retry this often
+// when refreshing fails.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+const REFRESH_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
 component! {
     Login5Manager : Login5ManagerInner {
         auth_token: Option<Token> = None,
+        token_provider: Option<Arc<dyn AuthTokenProvider>> = None,
     }
 }
 
@@ -159,6 +167,10 @@ impl Login5Manager {
     /// stored credentials generated with the keymaster client-id will not work, for example, with
     /// the android client-id.
     pub async fn auth_token(&self) -> Result<Token, Error> {
+        if let Some(provider) = self.lock(|inner| inner.token_provider.clone()) {
+            return provider.auth_token().await;
+        }
+
         let auth_data = self.session().auth_data();
         if auth_data.is_empty() {
             return Err(Login5Error::NoStoredCredentials.into());
@@ -199,6 +211,68 @@ impl Login5Manager {
         token.ok_or(Login5Error::NoStoredCredentials.into())
     }
 
+    /// Drops the cached auth token, so the next call to [`auth_token`](Self::auth_token)
+    /// requests a new one. Meant for when the token was rejected before it expired.
+    pub fn invalidate_auth_token(&self) {
+        let provider = self.lock(|inner| {
+            inner.auth_token = None;
+            inner.token_provider.clone()
+        });
+
+        if let Some(provider) = provider {
+            provider.invalidate();
+        }
+    }
+
+    pub(crate) fn token_provider(&self) -> Option<Arc<dyn AuthTokenProvider>> {
+        self.lock(|inner| inner.token_provider.clone())
+    }
+
+    /// Delegates getting and refreshing auth tokens to `provider`, which then
+    /// serves all spclient requests and dealer connections of this session.
+    pub fn set_token_provider(&self, provider: Arc<dyn AuthTokenProvider>) {
+        self.lock(|inner| {
+            inner.auth_token = None;
+            inner.token_provider = Some(provider);
+        });
+    }
+
+    // Returns how long the current token stays fresh.
+    async fn refresh_auth_token(&self) -> Result<Duration, Error> {
+        let mut token = self.auth_token().await?;
+        if token.time_to_expiry() <= REFRESH_MARGIN {
+            debug!("Auth token is about to expire, refreshing");
+            self.invalidate_auth_token();
+            token = self.auth_token().await?;
+        }
+
+        Ok(token
+            .time_to_expiry()
+            .saturating_sub(REFRESH_MARGIN)
+            .max(REFRESH_RETRY_INTERVAL))
+    }
+
+    /// Refreshes the auth token ahead of its expiry for as long as the session
+    /// lives, so that long running sessions never hit an expired token.
+    pub(crate) async fn refresh_task(session: SessionWeak) {
+        loop {
+            let delay = match session.try_upgrade() {
+                Some(session) if !session.is_invalid() => {
+                    match session.login5().refresh_auth_token().await {
+                        Ok(delay) => delay,
+                        Err(e) => {
+                            warn!("Unable to refresh auth token: {e}");
+                            REFRESH_RETRY_INTERVAL
+                        }
+                    }
+                }
+                _ => break,
+            };
+
+            sleep(delay).await;
+        }
+    }
+
     fn handle_challenges(
         login_request: &mut LoginRequest,
         message: LoginResponse,
@@ -0,0 +1,54 @@
+//! Hooks to export telemetry, e.g. to Prometheus, see [`Session::set_metrics`].
+//!
+//! The same operations are wrapped in spans of the `tracing` crate, which any
+//! `tracing` subscriber can pick up.
+//!
+//! [`Session::set_metrics`]: crate::Session::set_metrics
+
+use std::time::Duration;
+
+use http::Method;
+
+use crate::{Error, FileId};
+
+/// Called whenever one of the operations below completes. `error` is `None` if
+/// it succeeded. All methods do nothing by default, so implementations only
+/// need to override what they export.
+///
+/// The methods are called from async tasks and should return quickly, e.g. by
+/// updating counters and histograms.
+pub trait Metrics: Send + Sync {
+    /// A connection to the access point `host:port` was made and authenticated.
+    fn ap_connection(&self, host: &str, port: u16, duration: Duration, error: Option<&Error>) {
+        let _ = (host, port, duration, error);
+    }
+
+    /// A single attempt of an spclient request. `endpoint` is the path and
+    /// query, which includes IDs; map it to a route before using it as a label.
+    fn spclient_request(
+        &self,
+        method: &Method,
+        endpoint: &str,
+        duration: Duration,
+        error: Option<&Error>,
+    ) {
+        let _ = (method, endpoint, duration, error);
+    }
+
+    /// A message or request was received from the dealer, identified by its URI.
+    fn dealer_message(&self, uri: &str) {
+        let _ = uri;
+    }
+
+    /// A range of an audio file was fetched from the CDN. `bytes` is what was
+    /// received, even if the request failed later on.
+    fn audio_fetch(
+        &self,
+        file_id: FileId,
+        bytes: usize,
+        duration: Duration,
+        error: Option<&Error>,
+    ) {
+        let _ = (file_id, bytes, duration, error);
+    }
+}
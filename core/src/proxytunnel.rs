@@ -1,4 +1,4 @@
-use std::io;
+use std::{io, net::IpAddr};
 
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
@@ -53,3 +53,252 @@ pub async fn proxy_connect<T: AsyncRead + AsyncWrite + Unpin>(
         }
     }
 }
+
+// See RFC 1928 and RFC 1929.
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_AUTH_VERSION: u8 = 1;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_USERNAME_PASSWORD: u8 = 2;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+pub async fn socks5_connect<T: AsyncRead + AsyncWrite + Unpin>(
+    mut proxy_connection: T,
+    connect_host: &str,
+    connect_port: u16,
+    credentials: Option<(&str, &str)>,
+) -> io::Result<T> {
+    let method = if credentials.is_some() {
+        SOCKS_USERNAME_PASSWORD
+    } else {
+        SOCKS_NO_AUTH
+    };
+    proxy_connection
+        .write_all(&[SOCKS_VERSION, 1, method])
+        .await?;
+
+    let mut reply = [0u8; 2];
+    proxy_connection.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(socks_error("Malformed response from proxy"));
+    } else if reply[1] != method {
+        return Err(socks_error(
+            "Proxy does not accept the authentication method",
+        ));
+    }
+
+    if let Some((username, password)) = credentials {
+        let mut buffer = vec![SOCKS_AUTH_VERSION];
+        for field in [username, password] {
+            let len = u8::try_from(field.len())
+                .map_err(|_| socks_error("Proxy username or password is too long"))?;
+            buffer.push(len);
+            buffer.extend_from_slice(field.as_bytes());
+        }
+        proxy_connection.write_all(&buffer).await?;
+
+        proxy_connection.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(socks_error("Proxy rejected the username or password"));
+        }
+    }
+
+    let mut buffer = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+    match connect_host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            buffer.push(SOCKS_IPV4);
+            buffer.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            buffer.push(SOCKS_IPV6);
+            buffer.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(connect_host.len())
+                .map_err(|_| socks_error("Host name is too long"))?;
+            buffer.push(SOCKS_DOMAIN);
+            buffer.push(len);
+            buffer.extend_from_slice(connect_host.as_bytes());
+        }
+    }
+    buffer.extend_from_slice(&connect_port.to_be_bytes());
+    proxy_connection.write_all(&buffer).await?;
+
+    let mut header = [0u8; 4];
+    proxy_connection.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(socks_error("Malformed response from proxy"));
+    }
+    if header[1] != 0 {
+        let reason = match header[1] {
+            1 => "general failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown error",
+        };
+        return Err(socks_error(&format!(
+            "Proxy responded with {}: {reason}",
+            header[1]
+        )));
+    }
+
+    // Skip the address the proxy bound to, and its port.
+    let address_len = match header[3] {
+        SOCKS_IPV4 => 4,
+        SOCKS_IPV6 => 16,
+        SOCKS_DOMAIN => proxy_connection.read_u8().await? as usize,
+        _ => return Err(socks_error("Malformed response from proxy")),
+    };
+    let mut bound_address = vec![0u8; address_len + 2];
+    proxy_connection.read_exact(&mut bound_address).await?;
+
+    Ok(proxy_connection)
+}
+
+fn socks_error(msg: &str) -> io::Error {
+    io::Error::other(msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::io::{duplex, DuplexStream};
+
+    // Answers the CONNECT request like a proxy that bound to 10.0.0.1:1234.
+    async fn connect_reply(proxy: &mut DuplexStream, expected: &[u8]) {
+        let mut request = vec![0u8; expected.len()];
+        proxy.read_exact(&mut request).await.unwrap();
+        assert_eq!(request, expected);
+
+        proxy
+            .write_all(&[SOCKS_VERSION, 0, 0, SOCKS_IPV4, 10, 0, 0, 1, 0x04, 0xd2])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socks5_no_auth() {
+        let (client, mut proxy) = duplex(64);
+
+        let proxy = tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            proxy.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [SOCKS_VERSION, 1, SOCKS_NO_AUTH]);
+            proxy
+                .write_all(&[SOCKS_VERSION, SOCKS_NO_AUTH])
+                .await
+                .unwrap();
+
+            let mut expected = vec![SOCKS_VERSION, SOCKS_CONNECT, 0, SOCKS_DOMAIN, 11];
+            expected.extend_from_slice(b"example.com");
+            expected.extend_from_slice(&4070u16.to_be_bytes());
+            connect_reply(&mut proxy, &expected).await;
+            proxy
+        });
+
+        let mut client = socks5_connect(client, "example.com", 4070, None)
+            .await
+            .unwrap();
+
+        // The stream is handed over right after the reply.
+        let mut proxy = proxy.await.unwrap();
+        proxy.write_all(b"ap").await.unwrap();
+        let mut data = [0u8; 2];
+        client.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"ap");
+    }
+
+    #[tokio::test]
+    async fn test_socks5_username_password() {
+        let (client, mut proxy) = duplex(64);
+
+        let proxy = tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            proxy.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [SOCKS_VERSION, 1, SOCKS_USERNAME_PASSWORD]);
+            proxy
+                .write_all(&[SOCKS_VERSION, SOCKS_USERNAME_PASSWORD])
+                .await
+                .unwrap();
+
+            let mut auth = [0u8; 11];
+            proxy.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x04user\x04pass");
+            proxy.write_all(&[SOCKS_AUTH_VERSION, 0]).await.unwrap();
+
+            let mut expected = vec![SOCKS_VERSION, SOCKS_CONNECT, 0, SOCKS_IPV4, 192, 0, 2, 1];
+            expected.extend_from_slice(&443u16.to_be_bytes());
+            connect_reply(&mut proxy, &expected).await;
+        });
+
+        socks5_connect(client, "192.0.2.1", 443, Some(("user", "pass")))
+            .await
+            .unwrap();
+        proxy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socks5_rejected() {
+        // Wrong credentials.
+        let (client, mut proxy) = duplex(64);
+        tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            proxy.read_exact(&mut greeting).await.unwrap();
+            proxy
+                .write_all(&[SOCKS_VERSION, SOCKS_USERNAME_PASSWORD])
+                .await
+                .unwrap();
+            let mut auth = [0u8; 11];
+            proxy.read_exact(&mut auth).await.unwrap();
+            proxy.write_all(&[SOCKS_AUTH_VERSION, 1]).await.unwrap();
+        });
+        let e = socks5_connect(client, "example.com", 443, Some(("user", "pass")))
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Proxy rejected the username or password");
+
+        // No acceptable authentication method.
+        let (client, mut proxy) = duplex(64);
+        tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            proxy.read_exact(&mut greeting).await.unwrap();
+            proxy.write_all(&[SOCKS_VERSION, 0xff]).await.unwrap();
+        });
+        let e = socks5_connect(client, "example.com", 443, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Proxy does not accept the authentication method"
+        );
+
+        // The connection itself is refused.
+        let (client, mut proxy) = duplex(64);
+        tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            proxy.read_exact(&mut greeting).await.unwrap();
+            proxy
+                .write_all(&[SOCKS_VERSION, SOCKS_NO_AUTH])
+                .await
+                .unwrap();
+            let mut request = [0u8; 10];
+            proxy.read_exact(&mut request).await.unwrap();
+            proxy
+                .write_all(&[SOCKS_VERSION, 5, 0, SOCKS_IPV4])
+                .await
+                .unwrap();
+        });
+        let e = socks5_connect(client, "192.0.2.1", 443, None)
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Proxy responded with 5: connection refused");
+    }
+}
@@ -6,7 +6,7 @@ use std::{
     process::exit,
     sync::{Arc, Weak},
     task::{Context, Poll},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use byteorder::{BigEndian, ByteOrder};
@@ -25,6 +25,7 @@ use tokio::{
     time::{sleep, Duration as TokioDuration, Instant as TokioInstant, Sleep},
 };
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::Instrument;
 
 use crate::{
     apresolve::{ApResolver, SocketAddress},
@@ -34,9 +35,11 @@ use crate::{
     channel::ChannelManager,
     config::SessionConfig,
     connection::{self, AuthenticationError, Transport},
+    dealer::DealerManager,
     http_client::HttpClient,
     login5::Login5Manager,
     mercury::MercuryManager,
+    metrics::Metrics,
     packet::PacketType,
     protocol::keyexchange::ErrorCode,
     spclient::SpClient,
@@ -84,6 +87,7 @@ struct SessionData {
     client_model_name: String,
     connection_id: String,
     auth_data: Vec<u8>,
+    reusable_credentials: Option<Credentials>,
     time_delta: i64,
     invalid: bool,
     user_data: UserData,
@@ -99,11 +103,13 @@ struct SessionInternal {
     apresolver: OnceCell<ApResolver>,
     audio_key: OnceCell<AudioKeyManager>,
     channel: OnceCell<ChannelManager>,
+    dealer: OnceCell<DealerManager>,
     mercury: OnceCell<MercuryManager>,
     spclient: OnceCell<SpClient>,
     token_provider: OnceCell<TokenProvider>,
     login5: OnceCell<Login5Manager>,
     cache: Option<Arc<Cache>>,
+    metrics: RwLock<Option<Arc<dyn Metrics>>>,
 
     handle: tokio::runtime::Handle,
 }
@@ -115,14 +121,20 @@ struct SessionInternal {
 /// this structs interface directly or hand it to a
 /// `Player`.
 ///
-/// *Note*: [Session] instances cannot yet be reused once invalidated. After
-/// an unexpectedly closed connection, you'll need to create a new [Session].
+/// *Note*: [Session] instances cannot be reused once invalidated. After an
+/// unexpectedly closed connection, use [Session::recreate] to get a new
+/// [Session] and the credentials to connect it as the same user.
 #[derive(Clone)]
 pub struct Session(Arc<SessionInternal>);
 
 impl Session {
     pub fn new(config: SessionConfig, cache: Option<Cache>) -> Self {
-        let http_client = HttpClient::new(config.proxy.as_ref());
+        Self::with_shared_cache(config, cache.map(Arc::new))
+    }
+
+    fn with_shared_cache(config: SessionConfig, cache: Option<Arc<Cache>>) -> Self {
+        let http_client =
+            HttpClient::new(config.proxy.as_ref()).with_tls_config(config.tls.clone());
 
         debug!("new Session");
 
@@ -136,14 +148,16 @@ impl Session {
             data: RwLock::new(session_data),
             http_client,
             tx_connection: OnceCell::new(),
-            cache: cache.map(Arc::new),
+            cache,
             apresolver: OnceCell::new(),
             audio_key: OnceCell::new(),
             channel: OnceCell::new(),
+            dealer: OnceCell::new(),
             mercury: OnceCell::new(),
             spclient: OnceCell::new(),
             token_provider: OnceCell::new(),
             login5: OnceCell::new(),
+            metrics: RwLock::new(None),
             handle: tokio::runtime::Handle::current(),
         }))
     }
@@ -191,6 +205,39 @@ impl Session {
         Ok((reusable_credentials, transport))
     }
 
+    /// Creates a new, unconnected session with the same configuration and cache
+    /// as this one, e.g. after this one lost its connection. Returns it along
+    /// with its [`reusable_credentials`](Self::reusable_credentials).
+    pub fn recreate(&self) -> (Session, Option<Credentials>) {
+        self.recreate_with(self.0.cache.clone())
+    }
+
+    /// Like [`recreate`](Self::recreate), but with `cache` instead, e.g. the
+    /// one of the user that turned out to be logged in.
+    pub fn recreate_with_cache(&self, cache: Option<Cache>) -> (Session, Option<Credentials>) {
+        self.recreate_with(cache.map(Arc::new))
+    }
+
+    fn recreate_with(&self, cache: Option<Arc<Cache>>) -> (Session, Option<Credentials>) {
+        let session = Self::with_shared_cache(self.config().clone(), cache);
+
+        if let Some(provider) = self.login5().token_provider() {
+            session.login5().set_token_provider(provider);
+        }
+
+        if let Some(metrics) = self.metrics() {
+            session.set_metrics(metrics);
+        }
+
+        (session, self.reusable_credentials())
+    }
+
+    /// The credentials to connect as the same user again, without asking for a
+    /// password or token, or `None` if this session never connected.
+    pub fn reusable_credentials(&self) -> Option<Credentials> {
+        self.0.data.read().reusable_credentials.clone()
+    }
+
     pub async fn connect(
         &self,
         credentials: Credentials,
@@ -202,7 +249,17 @@ impl Session {
         let (reusable_credentials, transport) = loop {
             let ap = self.apresolver().resolve("accesspoint").await?;
             info!("Connecting to AP \"{}:{}\"", ap.0, ap.1);
-            match self.connect_inner(&ap, credentials.clone()).await {
+
+            let started = Instant::now();
+            let result = self
+                .connect_inner(&ap, credentials.clone())
+                .instrument(tracing::info_span!("ap_connection", host = %ap.0, port = ap.1))
+                .await;
+            if let Some(metrics) = self.metrics() {
+                metrics.ap_connection(&ap.0, ap.1, started.elapsed(), result.as_ref().err());
+            }
+
+            match result {
                 Ok(ct) => break ct,
                 Err(e) => {
                     num_ap_tries += 1;
@@ -234,6 +291,7 @@ impl Session {
         info!("Authenticated as '{username}' !");
         self.set_username(username);
         self.set_auth_data(&reusable_credentials.auth_data);
+        self.0.data.write().reusable_credentials = Some(reusable_credentials.clone());
         if let Some(cache) = self.cache() {
             if store_credentials {
                 let cred_changed = cache
@@ -271,6 +329,11 @@ impl Session {
         });
 
         tokio::spawn(DispatchTask::new(self.weak(), stream));
+        tokio::spawn(Login5Manager::refresh_task(self.weak()));
+
+        if let Some(locale) = &self.config().locale {
+            self.send_preferred_locale(locale)?;
+        }
 
         Ok(())
     }
@@ -293,6 +356,12 @@ impl Session {
             .get_or_init(|| ChannelManager::new(self.weak()))
     }
 
+    pub fn dealer(&self) -> &DealerManager {
+        self.0
+            .dealer
+            .get_or_init(|| DealerManager::new(self.weak()))
+    }
+
     pub fn http_client(&self) -> &HttpClient {
         &self.0.http_client
     }
@@ -358,6 +427,25 @@ impl Session {
         }
     }
 
+    // Makes Mercury responses localized, like the `Accept-Language` header of
+    // spclient requests.
+    fn send_preferred_locale(&self, locale: &str) -> Result<(), Error> {
+        let mut data = vec![0x00, 0x00, 0x10, 0x00, 0x02];
+        data.extend_from_slice(b"preferred-locale");
+        data.extend_from_slice(locale.as_bytes());
+        self.send_packet(PacketType::PreferredLocale, data)
+    }
+
+    /// Reports telemetry of this session to `metrics`. Set it before
+    /// [`connect`](Self::connect) to include the access point connection.
+    pub fn set_metrics(&self, metrics: Arc<dyn Metrics>) {
+        *self.0.metrics.write() = Some(metrics);
+    }
+
+    pub fn metrics(&self) -> Option<Arc<dyn Metrics>> {
+        self.0.metrics.read().clone()
+    }
+
     pub fn cache(&self) -> Option<&Arc<Cache>> {
         self.0.cache.as_ref()
     }
@@ -487,6 +575,10 @@ impl Session {
         self.0.data.write().invalid = true;
         self.mercury().shutdown();
         self.channel().shutdown();
+
+        if let Some(dealer) = self.0.dealer.get().cloned() {
+            self.spawn(async move { dealer.close().await });
+        }
     }
 
     pub fn is_invalid(&self) -> bool {
@@ -498,7 +590,7 @@ impl Session {
 pub struct SessionWeak(Weak<SessionInternal>);
 
 impl SessionWeak {
-    fn try_upgrade(&self) -> Option<Session> {
+    pub(crate) fn try_upgrade(&self) -> Option<Session> {
         self.0.upgrade().map(Session)
     }
 
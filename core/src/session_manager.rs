@@ -0,0 +1,186 @@
+//! Keeps sessions of several users around, e.g. for family devices where users
+//! take turns.
+
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use tokio::sync::watch;
+
+use crate::{authentication::Credentials, cache::Cache, Error, Session, SessionConfig};
+
+#[derive(Debug, Error)]
+pub enum SessionManagerError {
+    #[error("no session for user {0}")]
+    UnknownUser(String),
+    #[error("the session of user {0} never connected")]
+    NeverConnected(String),
+}
+
+impl From<SessionManagerError> for Error {
+    fn from(err: SessionManagerError) -> Self {
+        match err {
+            SessionManagerError::UnknownUser(_) => Error::not_found(err),
+            SessionManagerError::NeverConnected(_) => Error::failed_precondition(err),
+        }
+    }
+}
+
+/// Holds an authenticated session per user, one of which is active.
+///
+/// All sessions share the audio cache, while credentials, volume and the
+/// Spotify Connect state are kept per canonical username, see
+/// [`Cache::for_user`].
+pub struct SessionManager {
+    config: SessionConfig,
+    cache: Option<Cache>,
+    sessions: Mutex<HashMap<String, Session>>,
+    active: watch::Sender<Option<String>>,
+}
+
+impl SessionManager {
+    pub fn new(config: SessionConfig, cache: Option<Cache>) -> Self {
+        Self {
+            config,
+            cache,
+            sessions: Mutex::new(HashMap::new()),
+            active: watch::channel(None).0,
+        }
+    }
+
+    /// Connects a session with `credentials` and adds it under the canonical
+    /// username, replacing an earlier session of the same user. The first
+    /// session added becomes the active one.
+    ///
+    /// The username in `credentials` may be an email address, so the session
+    /// only gets the cache of the user from [`activate`](Self::activate), once
+    /// the canonical username is known.
+    pub async fn connect(&self, credentials: Credentials) -> Result<Session, Error> {
+        let session = Session::new(self.config.clone(), self.cache.clone());
+        session.connect(credentials, false).await?;
+
+        let username = session.username();
+        if let Some(cache) = &self.cache {
+            if let Some(reusable_credentials) = session.reusable_credentials() {
+                cache
+                    .for_user(&username)?
+                    .save_credentials(&reusable_credentials);
+            }
+        }
+
+        self.insert(username.clone(), session.clone());
+        self.active.send_if_modified(|active| {
+            if active.is_none() {
+                *active = Some(username);
+                true
+            } else {
+                false
+            }
+        });
+
+        Ok(session)
+    }
+
+    /// Connects the session of a user whose credentials were stored by an
+    /// earlier [`connect`](Self::connect).
+    pub async fn connect_stored(&self, username: &str) -> Result<Session, Error> {
+        let credentials = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.for_user(username))
+            .transpose()?
+            .and_then(|cache| cache.credentials())
+            .ok_or_else(|| SessionManagerError::UnknownUser(username.to_owned()))?;
+
+        self.connect(credentials).await
+    }
+
+    /// Users with stored credentials, whether they are connected or not.
+    pub fn stored_users(&self) -> Vec<String> {
+        self.cache.as_ref().map(Cache::users).unwrap_or_default()
+    }
+
+    /// Users with a session.
+    pub fn users(&self) -> Vec<String> {
+        let mut users: Vec<String> = self.sessions.lock().keys().cloned().collect();
+        users.sort();
+        users
+    }
+
+    pub fn session(&self, username: &str) -> Option<Session> {
+        self.sessions.lock().get(username).cloned()
+    }
+
+    pub fn active_user(&self) -> Option<String> {
+        self.active.borrow().clone()
+    }
+
+    pub fn active_session(&self) -> Option<Session> {
+        self.active_user()
+            .and_then(|username| self.session(&username))
+    }
+
+    /// Makes `username` the active user, and returns a new, unconnected session
+    /// for them along with the credentials to connect it. This is what `Spirc`
+    /// needs, as it connects its session itself. The new session replaces the
+    /// user's old one, which is shut down.
+    pub fn activate(&self, username: &str) -> Result<(Session, Credentials), Error> {
+        let old_session = self
+            .session(username)
+            .ok_or_else(|| SessionManagerError::UnknownUser(username.to_owned()))?;
+
+        let user_cache = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.for_user(username))
+            .transpose()?;
+        let (session, credentials) = old_session.recreate_with_cache(user_cache);
+        let credentials =
+            credentials.ok_or_else(|| SessionManagerError::NeverConnected(username.to_owned()))?;
+
+        self.insert(username.to_owned(), session.clone());
+        self.active.send_replace(Some(username.to_owned()));
+
+        Ok((session, credentials))
+    }
+
+    /// Notifies about changes of the active user.
+    pub fn subscribe_active(&self) -> watch::Receiver<Option<String>> {
+        self.active.subscribe()
+    }
+
+    /// Shuts down and forgets the session of `username`. With `forget_credentials`
+    /// their stored credentials are removed as well.
+    pub fn remove(&self, username: &str, forget_credentials: bool) -> Result<(), Error> {
+        if let Some(session) = self.sessions.lock().remove(username) {
+            if !session.is_invalid() {
+                session.shutdown();
+            }
+        }
+
+        self.active.send_if_modified(|active| {
+            if active.as_deref() == Some(username) {
+                *active = None;
+                true
+            } else {
+                false
+            }
+        });
+
+        if forget_credentials {
+            if let Some(cache) = &self.cache {
+                cache.for_user(username)?.remove_credentials();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(&self, username: String, session: Session) {
+        if let Some(old_session) = self.sessions.lock().insert(username, session) {
+            if !old_session.is_invalid() {
+                old_session.shutdown();
+            }
+        }
+    }
+}
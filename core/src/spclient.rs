@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     env::consts::OS,
     fmt::Write,
     time::{Duration, Instant},
@@ -9,29 +10,40 @@ use data_encoding::HEXUPPER_PERMISSIVE;
 use futures_util::future::IntoStream;
 use http::header::HeaderValue;
 use hyper::{
-    header::{HeaderName, ACCEPT, AUTHORIZATION, CONTENT_TYPE, RANGE},
-    HeaderMap, Method, Request,
+    body::Incoming,
+    header::{HeaderName, ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_TYPE, RANGE},
+    HeaderMap, Method, Request, Response,
 };
 use hyper_util::client::legacy::ResponseFuture;
 use protobuf::{Enum, Message, MessageFull};
 use rand::RngCore;
 use sysinfo::System;
 use thiserror::Error;
+use tracing::Instrument;
+use url::Url;
 
 use crate::{
     apresolve::SocketAddress,
-    cdn_url::CdnUrl,
+    cdn_url::{CdnUrl, CdnUrlError},
     config::SessionConfig,
     error::ErrorKind,
+    http_client::{HttpClientError, NoRetry},
     protocol::{
-        canvaz::EntityCanvazRequest,
+        canvaz::{entity_canvaz_request::Entity as EntityCanvazRequestEntity, EntityCanvazRequest},
         clienttoken_http::{
             ChallengeAnswer, ChallengeType, ClientTokenRequest, ClientTokenRequestType,
             ClientTokenResponse, ClientTokenResponseType,
         },
+        collection2v2::{PageRequest, WriteRequest},
         connect::PutStateRequest,
-        extended_metadata::BatchedEntityRequest,
+        extended_metadata::{
+            BatchedEntityRequest, BatchedExtensionResponse, EntityRequest, ExtensionQuery,
+        },
+        extension_kind::ExtensionKind,
+        played_state::PlayedStateItems,
+        playlist4_external::ListChanges,
     },
+    spotify_id::SpotifyItemType,
     token::Token,
     util,
     version::spotify_semantic_version,
@@ -43,6 +55,93 @@ component! {
         accesspoint: Option<SocketAddress> = None,
         strategy: RequestStrategy = RequestStrategy::default(),
         client_token: Option<Token> = None,
+        cdn_health: HashMap<String, CdnHostHealth> = HashMap::new(),
+        metadata_cache: HashMap<SpotifyId, CachedMetadata> = HashMap::new(),
+    }
+}
+
+/// How many entities [`SpClient::prefetch_metadata`] requests at once.
+pub const EXTENDED_METADATA_BATCH_SIZE: usize = 500;
+
+// Beyond this many unexpired entities, which is a few MB of metadata, the
+// ones fetched first are evicted.
+const METADATA_CACHE_SIZE: usize = 10_000;
+
+// Metadata fetched in batches, kept until the first request for it, or until
+// it expires.
+struct CachedMetadata {
+    data: Bytes,
+    fetched_at: Instant,
+    expires_at: Instant,
+}
+
+// Keeps the entities of an extended metadata response that came back fine,
+// and drops the expired ones and those over the size limit.
+fn cache_metadata(
+    cache: &mut HashMap<SpotifyId, CachedMetadata>,
+    response: &BatchedExtensionResponse,
+    now: Instant,
+) {
+    cache.retain(|_, cached| cached.expires_at > now);
+
+    for data in response
+        .extended_metadata
+        .iter()
+        .flat_map(|array| array.extension_data.iter())
+    {
+        let status_code = data.header.status_code;
+        if (status_code != 0 && status_code != 200) || data.extension_data.value.is_empty() {
+            continue;
+        }
+
+        let id = match SpotifyId::from_uri(&data.entity_uri) {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+
+        let ttl = Duration::from_secs(data.header.cache_ttl_in_seconds.max(0) as u64);
+        cache.insert(
+            id,
+            CachedMetadata {
+                data: Bytes::copy_from_slice(&data.extension_data.value),
+                fetched_at: now,
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    if cache.len() > METADATA_CACHE_SIZE {
+        let mut fetched: Vec<_> = cache
+            .iter()
+            .map(|(id, cached)| (cached.fetched_at, *id))
+            .collect();
+        fetched.sort_unstable_by_key(|(fetched_at, _)| *fetched_at);
+
+        let excess = cache.len() - METADATA_CACHE_SIZE;
+        for (_, id) in fetched.into_iter().take(excess) {
+            cache.remove(&id);
+        }
+    }
+}
+
+// How long a CDN host that failed is only tried after the others.
+const CDN_FAILURE_PENALTY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Copy, Debug, Default)]
+struct CdnHostHealth {
+    // Smoothed time until the response of the last requests arrived.
+    response_time: Option<Duration>,
+    failed_at: Option<Instant>,
+}
+
+impl CdnHostHealth {
+    fn host(url: &str) -> Option<String> {
+        Url::parse(url).ok()?.host_str().map(str::to_owned)
+    }
+
+    fn has_failed_recently(&self) -> bool {
+        self.failed_at
+            .is_some_and(|failed_at| failed_at.elapsed() < CDN_FAILURE_PENALTY)
     }
 }
 
@@ -387,8 +486,35 @@ impl SpClient {
         endpoint: &str,
         headers: Option<HeaderMap>,
         body: Option<&str>,
+    ) -> SpClientResult {
+        self.request_with_retry(method, endpoint, headers, body, true)
+            .await
+    }
+
+    /// Like [`request`](Self::request), but does not retry after network errors
+    /// and fails right away when rate limited. Meant for latency-sensitive calls,
+    /// where a late answer is as good as none.
+    pub async fn request_without_retry(
+        &self,
+        method: &Method,
+        endpoint: &str,
+        headers: Option<HeaderMap>,
+        body: Option<&str>,
+    ) -> SpClientResult {
+        self.request_with_retry(method, endpoint, headers, body, false)
+            .await
+    }
+
+    async fn request_with_retry(
+        &self,
+        method: &Method,
+        endpoint: &str,
+        headers: Option<HeaderMap>,
+        body: Option<&str>,
+        retry: bool,
     ) -> SpClientResult {
         let mut tries: usize = 0;
+        let mut token_refreshed = false;
         let mut last_response;
 
         let body = body.unwrap_or_default();
@@ -430,6 +556,10 @@ impl SpClient {
             // Reconnection logic: keep getting (cached) tokens because they might have expired.
             let token = self.session().login5().auth_token().await?;
 
+            if !retry {
+                request.extensions_mut().insert(NoRetry);
+            }
+
             let headers_mut = request.headers_mut();
             if let Some(ref hdrs) = headers {
                 *headers_mut = hdrs.clone();
@@ -439,6 +569,12 @@ impl SpClient {
                 HeaderValue::from_str(&format!("{} {}", token.token_type, token.access_token,))?,
             );
 
+            if let Some(locale) = &self.session().config().locale {
+                if !headers_mut.contains_key(ACCEPT_LANGUAGE) {
+                    headers_mut.insert(ACCEPT_LANGUAGE, HeaderValue::from_str(locale)?);
+                }
+            }
+
             match self.client_token().await {
                 Ok(client_token) => {
                     let _ = headers_mut.insert(CLIENT_TOKEN, HeaderValue::from_str(&client_token)?);
@@ -449,12 +585,46 @@ impl SpClient {
                 }
             }
 
-            last_response = self.session().http_client().request_body(request).await;
+            let started = Instant::now();
+            last_response = self
+                .session()
+                .http_client()
+                .request_body(request)
+                .instrument(tracing::debug_span!(
+                    "spclient_request",
+                    %method,
+                    endpoint,
+                    tries
+                ))
+                .await;
+            if let Some(metrics) = self.session().metrics() {
+                metrics.spclient_request(
+                    method,
+                    endpoint,
+                    started.elapsed(),
+                    last_response.as_ref().err(),
+                );
+            }
 
             if last_response.is_ok() {
                 return last_response;
             }
 
+            // The token may have been revoked before it expired. Retry once with a new one,
+            // whatever the request strategy.
+            if let Err(ref e) = last_response {
+                if e.kind == ErrorKind::Unauthenticated && !token_refreshed {
+                    debug!("Auth token was rejected, retrying with a new one");
+                    self.session().login5().invalidate_auth_token();
+                    token_refreshed = true;
+                    continue;
+                }
+            }
+
+            if !retry {
+                break;
+            }
+
             // Break before the reconnection logic below, so that the current access point
             // is retained when max_tries == 1. Leave it up to the caller when to flush.
             if let RequestStrategy::TryTimes(max_tries) = self.lock(|inner| inner.strategy) {
@@ -498,6 +668,10 @@ impl SpClient {
     }
 
     pub async fn get_metadata(&self, scope: &str, id: &SpotifyId) -> SpClientResult {
+        if let Some(data) = self.take_cached_metadata(id) {
+            return Ok(data);
+        }
+
         let endpoint = format!("/metadata/4/{}/{}", scope, id.to_base16()?);
         self.request(&Method::GET, &endpoint, None, None).await
     }
@@ -522,6 +696,11 @@ impl SpClient {
         self.get_metadata("show", show_id).await
     }
 
+    // Audiobooks are shows, with their chapters as episodes.
+    pub async fn get_audiobook_metadata(&self, audiobook_id: &SpotifyId) -> SpClientResult {
+        self.get_metadata("show", audiobook_id).await
+    }
+
     pub async fn get_lyrics(&self, track_id: &SpotifyId) -> SpClientResult {
         let endpoint = format!("/color-lyrics/v2/track/{}", track_id.to_base62()?);
 
@@ -550,6 +729,53 @@ impl SpClient {
         self.request(&Method::GET, &endpoint, None, None).await
     }
 
+    pub async fn get_rootlist(&self, from: usize, length: Option<usize>) -> SpClientResult {
+        let length = length.unwrap_or(120);
+        let endpoint = format!(
+            "/playlist/v2/user/{}/rootlist?decorate=revision,attributes,length,owner&from={from}&length={length}",
+            self.session().username()
+        );
+
+        self.request(&Method::GET, &endpoint, None, None).await
+    }
+
+    pub async fn post_playlist_changes(
+        &self,
+        playlist_id: &SpotifyId,
+        changes: &ListChanges,
+    ) -> SpClientResult {
+        let endpoint = format!("/playlist/v2/playlist/{}/changes", playlist_id.to_base62()?);
+
+        self.request_with_protobuf(&Method::POST, &endpoint, None, changes)
+            .await
+    }
+
+    // Known sets: collection (saved tracks and albums), artist (followed
+    // artists), show (followed shows), ban (hidden tracks).
+    pub async fn get_collection_page(
+        &self,
+        set: &str,
+        pagination_token: &str,
+        limit: usize,
+    ) -> SpClientResult {
+        let endpoint = "/collection/v2/paging";
+
+        let mut request = PageRequest::new();
+        request.username = self.session().username();
+        request.set = set.to_owned();
+        request.pagination_token = pagination_token.to_owned();
+        request.limit = limit as i32;
+
+        self.request_with_protobuf(&Method::POST, endpoint, None, &request)
+            .await
+    }
+
+    pub async fn post_collection_write(&self, request: &WriteRequest) -> SpClientResult {
+        let endpoint = "/collection/v2/write";
+        self.request_with_protobuf(&Method::POST, endpoint, None, request)
+            .await
+    }
+
     pub async fn get_user_profile(
         &self,
         username: &str,
@@ -639,6 +865,21 @@ impl SpClient {
             .await
     }
 
+    pub async fn get_search(&self, query: &str, offset: usize, limit: usize) -> SpClientResult {
+        // The query is part of the path, where spaces are not encoded as `+`.
+        let query = form_urlencoded::byte_serialize(query.as_bytes())
+            .collect::<String>()
+            .replace('+', "%20");
+
+        let endpoint = format!(
+            "/searchview/km/v4/search/{query}?entityVersion=2&offset={offset}&limit={limit}&catalogue=premium&username={}",
+            self.session().username()
+        );
+
+        self.request_as_json(&Method::GET, &endpoint, None, None)
+            .await
+    }
+
     pub async fn get_next_page(&self, next_page_uri: &str) -> SpClientResult {
         let endpoint = next_page_uri.trim_start_matches("hm:/");
         self.request_as_json(&Method::GET, endpoint, None, None)
@@ -655,12 +896,94 @@ impl SpClient {
             .await
     }
 
+    pub async fn get_canvas(&self, track_id: &SpotifyId) -> SpClientResult {
+        let mut entity = EntityCanvazRequestEntity::new();
+        entity.entity_uri = track_id.to_uri()?;
+
+        let mut request = EntityCanvazRequest::new();
+        request.entities.push(entity);
+
+        self.get_canvases(request).await
+    }
+
     pub async fn get_extended_metadata(&self, request: BatchedEntityRequest) -> SpClientResult {
         let endpoint = "/extended-metadata/v0/extended-metadata";
         self.request_with_protobuf(&Method::POST, endpoint, None, &request)
             .await
     }
 
+    pub async fn get_resume_points(&self, episode_ids: &[SpotifyId]) -> SpClientResult {
+        let uris = episode_ids
+            .iter()
+            .map(|id| id.to_uri())
+            .collect::<Result<Vec<_>, _>>()?
+            .join(",");
+        let uris = form_urlencoded::byte_serialize(uris.as_bytes()).collect::<String>();
+
+        let endpoint = format!("/resume-points/v1/played-state?uris={uris}");
+        self.request(&Method::GET, &endpoint, None, None).await
+    }
+
+    pub async fn put_resume_points(&self, items: &PlayedStateItems) -> SpClientResult {
+        let endpoint = "/resume-points/v1/played-state";
+        self.request_with_protobuf(&Method::PUT, endpoint, None, items)
+            .await
+    }
+
+    /// Fetches the metadata of tracks, episodes, albums, artists and shows
+    /// with one extended metadata request per [`EXTENDED_METADATA_BATCH_SIZE`]
+    /// of them. The next [`get_metadata`](Self::get_metadata) for each of
+    /// them is answered from memory, which is much faster than requesting
+    /// them one by one, e.g. for the tracks of a big playlist. Other IDs are
+    /// skipped.
+    pub async fn prefetch_metadata(&self, ids: &[SpotifyId]) -> Result<(), Error> {
+        let mut uris = Vec::with_capacity(ids.len());
+        for id in ids {
+            let kind = match id.item_type {
+                SpotifyItemType::Track => ExtensionKind::TRACK_V4,
+                SpotifyItemType::Episode => ExtensionKind::EPISODE_V4,
+                SpotifyItemType::Album => ExtensionKind::ALBUM_V4,
+                SpotifyItemType::Artist => ExtensionKind::ARTIST_V4,
+                SpotifyItemType::Show | SpotifyItemType::Audiobook => ExtensionKind::SHOW_V4,
+                _ => continue,
+            };
+            uris.push((id.to_uri()?, kind));
+        }
+
+        for batch in uris.chunks(EXTENDED_METADATA_BATCH_SIZE) {
+            let mut request = BatchedEntityRequest::new();
+            request.header.mut_or_insert_default().country = self.session().country();
+            // Other accounts can't log in, see `Session::check_catalogue`.
+            request.header.mut_or_insert_default().catalogue = "premium".to_owned();
+
+            for (uri, kind) in batch {
+                let mut query = ExtensionQuery::new();
+                query.extension_kind = (*kind).into();
+
+                let mut entity = EntityRequest::new();
+                entity.entity_uri = uri.clone();
+                entity.query.push(query);
+
+                request.entity_request.push(entity);
+            }
+
+            let response = self.get_extended_metadata(request).await?;
+            let response = BatchedExtensionResponse::parse_from_bytes(&response)?;
+
+            let now = Instant::now();
+            self.lock(|inner| cache_metadata(&mut inner.metadata_cache, &response, now));
+        }
+
+        Ok(())
+    }
+
+    fn take_cached_metadata(&self, id: &SpotifyId) -> Option<Bytes> {
+        self.lock(|inner| {
+            let cached = inner.metadata_cache.remove(id)?;
+            (cached.expires_at > Instant::now()).then_some(cached.data)
+        })
+    }
+
     pub async fn get_audio_storage(&self, file_id: &FileId) -> SpClientResult {
         let endpoint = format!(
             "/storage-resolve/files/audio/interactive/{}",
@@ -669,13 +992,7 @@ impl SpClient {
         self.request(&Method::GET, &endpoint, None, None).await
     }
 
-    pub fn stream_from_cdn(
-        &self,
-        cdn_url: &CdnUrl,
-        offset: usize,
-        length: usize,
-    ) -> Result<IntoStream<ResponseFuture>, Error> {
-        let url = cdn_url.try_get_url()?;
+    fn cdn_range_request(url: &str, offset: usize, length: usize) -> Result<Request<Bytes>, Error> {
         let req = Request::builder()
             .method(&Method::GET)
             .uri(url)
@@ -685,11 +1002,93 @@ impl SpClient {
             )
             .body(Bytes::new())?;
 
+        Ok(req)
+    }
+
+    /// Returns the URLs of a CDN file, hosts that did not fail recently first
+    /// and faster hosts before slower ones.
+    fn ranked_cdn_urls<'a>(&self, cdn_url: &'a CdnUrl) -> Result<Vec<&'a str>, Error> {
+        let mut urls = cdn_url.try_get_urls()?;
+
+        self.lock(|inner| {
+            urls.sort_by_key(|url| {
+                let health = CdnHostHealth::host(url)
+                    .and_then(|host| inner.cdn_health.get(&host).copied())
+                    .unwrap_or_default();
+                (
+                    health.has_failed_recently(),
+                    health.response_time.unwrap_or_default(),
+                )
+            })
+        });
+
+        Ok(urls)
+    }
+
+    fn update_cdn_health<F: FnOnce(&mut CdnHostHealth)>(&self, url: &str, f: F) {
+        if let Some(host) = CdnHostHealth::host(url) {
+            self.lock(|inner| f(inner.cdn_health.entry(host).or_default()));
+        }
+    }
+
+    pub fn stream_from_cdn(
+        &self,
+        cdn_url: &CdnUrl,
+        offset: usize,
+        length: usize,
+    ) -> Result<IntoStream<ResponseFuture>, Error> {
+        let url = self.ranked_cdn_urls(cdn_url)?[0];
+        let req = Self::cdn_range_request(url, offset, length)?;
+
         let stream = self.session().http_client().request_stream(req)?;
 
         Ok(stream)
     }
 
+    /// Requests a range of a CDN file. When a host cannot be reached or has a
+    /// server error, the other hosts of the file are tried. The health and
+    /// speed of every host is remembered to rank them for later requests.
+    pub async fn request_from_cdn(
+        &self,
+        cdn_url: &CdnUrl,
+        offset: usize,
+        length: usize,
+    ) -> Result<Response<Incoming>, Error> {
+        let mut last_error = None;
+
+        for url in self.ranked_cdn_urls(cdn_url)? {
+            let req = Self::cdn_range_request(url, offset, length)?;
+            let request_time = Instant::now();
+
+            let error: Error = match self.session().http_client().request_fut(req)?.await {
+                Ok(response) if !response.status().is_server_error() => {
+                    let elapsed = request_time.elapsed();
+                    self.update_cdn_health(url, |health| {
+                        health.failed_at = None;
+                        health.response_time = Some(match health.response_time {
+                            Some(response_time) => (response_time * 3 + elapsed) / 4,
+                            None => elapsed,
+                        });
+                    });
+
+                    return Ok(response);
+                }
+                Ok(response) => HttpClientError::StatusCode(response.status()).into(),
+                Err(e) => e.into(),
+            };
+
+            warn!(
+                "CDN host {} failed, trying the next one: {}",
+                CdnHostHealth::host(url).unwrap_or_default(),
+                error
+            );
+            self.update_cdn_health(url, |health| health.failed_at = Some(Instant::now()));
+            last_error = Some(error);
+        }
+
+        Err(last_error.unwrap_or_else(|| CdnUrlError::Unresolved.into()))
+    }
+
     pub async fn request_url(&self, url: &str) -> SpClientResult {
         let request = Request::builder()
             .method(&Method::GET)
@@ -741,3 +1140,65 @@ impl SpClient {
         self.request_url(&url).await
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::protocol::{
+        entity_extension_data::EntityExtensionData, extended_metadata::EntityExtensionDataArray,
+    };
+
+    fn extension_data(uri: &str, status_code: i32, value: &[u8]) -> EntityExtensionData {
+        let mut data = EntityExtensionData::new();
+        data.entity_uri = uri.to_owned();
+        data.header.mut_or_insert_default().status_code = status_code;
+        data.header.mut_or_insert_default().cache_ttl_in_seconds = 3600;
+        data.extension_data.mut_or_insert_default().value = value.to_vec();
+        data
+    }
+
+    #[test]
+    fn test_cache_metadata() {
+        let track = "spotify:track:4uLU6hMCjMI75M1A2tKUQC";
+        let episode = "spotify:episode:0hHMsdfw3mDbqYPwHmOmJD";
+        let failed = "spotify:track:6rqhFgbbKwnb9MLmUQDhG6";
+
+        let mut array = EntityExtensionDataArray::new();
+        array
+            .extension_data
+            .push(extension_data(track, 200, b"track"));
+        array
+            .extension_data
+            .push(extension_data(episode, 0, b"episode"));
+        array
+            .extension_data
+            .push(extension_data(failed, 404, b"failed"));
+        array
+            .extension_data
+            .push(extension_data("spotify:foo:bar", 200, b"foo"));
+
+        let mut response = BatchedExtensionResponse::new();
+        response.extended_metadata.push(array);
+
+        // Go through the wire format like a real response.
+        let bytes = response.write_to_bytes().unwrap();
+        let response = BatchedExtensionResponse::parse_from_bytes(&bytes).unwrap();
+
+        let now = Instant::now();
+        let mut cache = HashMap::new();
+        cache_metadata(&mut cache, &response, now);
+
+        assert_eq!(cache.len(), 2);
+        let cached = &cache[&SpotifyId::from_uri(track).unwrap()];
+        assert_eq!(&cached.data[..], b"track");
+        assert_eq!(cached.expires_at, now + Duration::from_secs(3600));
+        let cached = &cache[&SpotifyId::from_uri(episode).unwrap()];
+        assert_eq!(&cached.data[..], b"episode");
+
+        // Expired entries are dropped with the next response.
+        let later = now + Duration::from_secs(3601);
+        cache_metadata(&mut cache, &BatchedExtensionResponse::new(), later);
+        assert!(cache.is_empty());
+    }
+}
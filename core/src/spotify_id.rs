@@ -13,6 +13,7 @@ pub use crate::FileId;
 pub enum SpotifyItemType {
     Album,
     Artist,
+    Audiobook,
     Episode,
     Playlist,
     Show,
@@ -26,6 +27,7 @@ impl From<&str> for SpotifyItemType {
         match v {
             "album" => Self::Album,
             "artist" => Self::Artist,
+            "audiobook" => Self::Audiobook,
             "episode" => Self::Episode,
             "playlist" => Self::Playlist,
             "show" => Self::Show,
@@ -41,6 +43,7 @@ impl From<SpotifyItemType> for &str {
         match item_type {
             SpotifyItemType::Album => "album",
             SpotifyItemType::Artist => "artist",
+            SpotifyItemType::Audiobook => "audiobook",
             SpotifyItemType::Episode => "episode",
             SpotifyItemType::Playlist => "playlist",
             SpotifyItemType::Show => "show",
@@ -0,0 +1,172 @@
+//! The TLS backend used by HTTPS and websocket connections, chosen at build
+//! time: `rustls-tls-native-roots` (the default), `rustls-tls-webpki-roots`
+//! for static builds without a certificate store, or `native-tls` for the TLS
+//! library of the OS. If `native-tls` is enabled along with a rustls feature,
+//! it takes precedence.
+
+use std::{fs, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::Error;
+
+#[cfg(not(any(
+    feature = "native-tls",
+    feature = "rustls-tls-native-roots",
+    feature = "rustls-tls-webpki-roots"
+)))]
+compile_error!(
+    "Enable one of the `native-tls`, `rustls-tls-native-roots` or `rustls-tls-webpki-roots` features"
+);
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("no certificates found in {0:?}")]
+    NoCertificates(PathBuf),
+    #[error("no root certificates available")]
+    NoRoots,
+}
+
+impl From<TlsError> for Error {
+    fn from(err: TlsError) -> Self {
+        match err {
+            TlsError::NoCertificates(_) => Error::invalid_argument(err),
+            TlsError::NoRoots => Error::failed_precondition(err),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    // PEM files with root certificates to trust in addition to the usual ones,
+    // e.g. that of a TLS-intercepting proxy.
+    pub extra_root_certificates: Vec<PathBuf>,
+    // Trust only `extra_root_certificates`.
+    pub disable_built_in_roots: bool,
+}
+
+impl TlsConfig {
+    fn read_extra_root_certificates(&self) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+        self.extra_root_certificates
+            .iter()
+            .map(|path| Ok((path.clone(), fs::read(path)?)))
+            .collect()
+    }
+}
+
+#[cfg(feature = "native-tls")]
+mod backend {
+    use super::*;
+
+    pub type HttpsConnector<T> = hyper_tls::HttpsConnector<T>;
+
+    fn builder(config: &TlsConfig) -> Result<native_tls::TlsConnectorBuilder, Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.disable_built_in_roots(config.disable_built_in_roots);
+
+        for (path, pem) in config.read_extra_root_certificates()? {
+            let certificate = native_tls::Certificate::from_pem(&pem)
+                .map_err(|_| TlsError::NoCertificates(path))?;
+            builder.add_root_certificate(certificate);
+        }
+
+        Ok(builder)
+    }
+
+    pub fn https_connector<T>(
+        config: &TlsConfig,
+        connector: T,
+    ) -> Result<HttpsConnector<T>, Error> {
+        let tls_connector = builder(config)?
+            .request_alpns(&["h2", "http/1.1"])
+            .build()
+            .map_err(Error::internal)?;
+
+        let mut https_connector =
+            hyper_tls::HttpsConnector::from((connector, tls_connector.into()));
+        https_connector.https_only(false);
+        Ok(https_connector)
+    }
+
+    pub fn websocket_connector(config: &TlsConfig) -> Result<tokio_tungstenite::Connector, Error> {
+        let tls_connector = builder(config)?.build().map_err(Error::internal)?;
+        Ok(tokio_tungstenite::Connector::NativeTls(tls_connector))
+    }
+}
+
+#[cfg(all(
+    not(feature = "native-tls"),
+    any(
+        feature = "rustls-tls-native-roots",
+        feature = "rustls-tls-webpki-roots"
+    )
+))]
+mod backend {
+    use std::sync::Arc;
+
+    use hyper_rustls::HttpsConnectorBuilder;
+    use rustls::{ClientConfig, RootCertStore};
+
+    use super::*;
+
+    pub type HttpsConnector<T> = hyper_rustls::HttpsConnector<T>;
+
+    fn root_store(config: &TlsConfig) -> Result<RootCertStore, Error> {
+        let mut roots = RootCertStore::empty();
+
+        if !config.disable_built_in_roots {
+            #[cfg(feature = "rustls-tls-native-roots")]
+            {
+                let native_certs = rustls_native_certs::load_native_certs();
+                for e in native_certs.errors {
+                    warn!("Unable to load a native root certificate: {}", e);
+                }
+                roots.add_parsable_certificates(native_certs.certs);
+            }
+
+            #[cfg(feature = "rustls-tls-webpki-roots")]
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+
+        for (path, pem) in config.read_extra_root_certificates()? {
+            let certificates =
+                rustls_pemfile::certs(&mut pem.as_slice()).collect::<Result<Vec<_>, _>>()?;
+            if certificates.is_empty() {
+                return Err(TlsError::NoCertificates(path).into());
+            }
+            roots.add_parsable_certificates(certificates);
+        }
+
+        if roots.is_empty() {
+            return Err(TlsError::NoRoots.into());
+        }
+
+        Ok(roots)
+    }
+
+    fn client_config(config: &TlsConfig) -> Result<ClientConfig, Error> {
+        Ok(ClientConfig::builder()
+            .with_root_certificates(root_store(config)?)
+            .with_no_client_auth())
+    }
+
+    pub fn https_connector<T>(
+        config: &TlsConfig,
+        connector: T,
+    ) -> Result<HttpsConnector<T>, Error> {
+        Ok(HttpsConnectorBuilder::new()
+            .with_tls_config(client_config(config)?)
+            .https_or_http()
+            .enable_http1()
+            .enable_http2()
+            .wrap_connector(connector))
+    }
+
+    pub fn websocket_connector(config: &TlsConfig) -> Result<tokio_tungstenite::Connector, Error> {
+        Ok(tokio_tungstenite::Connector::Rustls(Arc::new(
+            client_config(config)?,
+        )))
+    }
+}
+
+pub(crate) use backend::{https_connector, websocket_connector, HttpsConnector};
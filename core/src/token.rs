@@ -10,6 +10,7 @@
 
 use std::time::{Duration, Instant};
 
+use futures_util::future::BoxFuture;
 use serde::Deserialize;
 use thiserror::Error;
 
@@ -42,6 +43,19 @@ pub struct Token {
     pub timestamp: Instant,
 }
 
+/// A source of auth tokens outside of librespot, for integrations that obtain
+/// their tokens out-of-band, e.g. a server that runs the OAuth flow for many
+/// users. See [`Login5Manager::set_token_provider`](crate::login5::Login5Manager::set_token_provider).
+pub trait AuthTokenProvider: Send + Sync {
+    /// Returns a token that stays valid for at least a few more seconds. This is
+    /// called for every request, so implementations should cache their tokens.
+    fn auth_token(&self) -> BoxFuture<'_, Result<Token, Error>>;
+
+    /// Called when the token returned last was rejected, so that the next call
+    /// to `auth_token` should return a new one.
+    fn invalidate(&self) {}
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct TokenData {
@@ -113,6 +127,10 @@ impl Token {
         self.timestamp + (self.expires_in.saturating_sub(Self::EXPIRY_THRESHOLD)) < Instant::now()
     }
 
+    pub fn time_to_expiry(&self) -> Duration {
+        (self.timestamp + self.expires_in).saturating_duration_since(Instant::now())
+    }
+
     pub fn in_scope(&self, scope: &str) -> bool {
         for s in &self.scopes {
             if *s == scope {
@@ -131,3 +149,43 @@ impl Token {
         true
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(expires_in: Duration) -> Token {
+        Token {
+            access_token: String::new(),
+            expires_in,
+            token_type: "Bearer".to_string(),
+            scopes: vec![],
+            timestamp: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_time_to_expiry() {
+        let hour = Duration::from_secs(3600);
+        let time_to_expiry = token(hour).time_to_expiry();
+        assert!(time_to_expiry <= hour && time_to_expiry > hour - Duration::from_secs(60));
+
+        // Expired tokens don't go negative.
+        assert_eq!(token(Duration::ZERO).time_to_expiry(), Duration::ZERO);
+
+        let mut expired = token(Duration::from_secs(1));
+        expired.timestamp -= Duration::from_millis(1500);
+        assert_eq!(expired.time_to_expiry(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_expiry_threshold() {
+        // Tokens count as expired a little before they are, but are still
+        // usable until then.
+        let almost_expired = token(Token::EXPIRY_THRESHOLD / 2);
+        assert!(almost_expired.is_expired());
+        assert!(almost_expired.time_to_expiry() > Duration::ZERO);
+
+        assert!(!token(Duration::from_secs(3600)).is_expired());
+    }
+}
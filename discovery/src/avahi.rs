@@ -0,0 +1,167 @@
+//! Registers the service with the Avahi daemon over D-Bus, for systems where
+//! Avahi already owns the mDNS port.
+
+use std::convert::Infallible;
+
+use futures_util::StreamExt;
+use log::{debug, error, warn};
+use tokio::sync::oneshot;
+
+use crate::{DiscoveryEvent, DiscoveryEventSender};
+
+const IF_UNSPEC: i32 = -1;
+const PROTO_UNSPEC: i32 = -1;
+
+const ENTRY_GROUP_ESTABLISHED: i32 = 2;
+const ENTRY_GROUP_COLLISION: i32 = 3;
+const ENTRY_GROUP_FAILURE: i32 = 4;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Avahi.Server",
+    default_service = "org.freedesktop.Avahi",
+    default_path = "/",
+    gen_blocking = false
+)]
+trait Server {
+    fn entry_group_new(&self) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+
+    fn get_alternative_service_name(&self, name: &str) -> zbus::Result<String>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Avahi.EntryGroup",
+    default_service = "org.freedesktop.Avahi",
+    gen_blocking = false
+)]
+trait EntryGroup {
+    #[allow(clippy::too_many_arguments)]
+    fn add_service(
+        &self,
+        interface: i32,
+        protocol: i32,
+        flags: u32,
+        name: &str,
+        type_: &str,
+        domain: &str,
+        host: &str,
+        port: u16,
+        txt: &[&[u8]],
+    ) -> zbus::Result<()>;
+
+    fn commit(&self) -> zbus::Result<()>;
+
+    fn free(&self) -> zbus::Result<()>;
+
+    fn reset(&self) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn state_changed(&self, state: i32, error: &str) -> zbus::Result<()>;
+}
+
+/// The service stays registered until this is dropped.
+pub struct AvahiService {
+    _shutdown_tx: oneshot::Sender<Infallible>,
+}
+
+impl AvahiService {
+    /// Registers the service in the background. Without `interfaces`, it is
+    /// announced on all of them. Name collisions are resolved like Avahi
+    /// does itself, by appending a number.
+    pub fn register(
+        name: String,
+        service_type: &'static str,
+        port: u16,
+        txt: &[&str],
+        interfaces: Vec<u32>,
+        events: DiscoveryEventSender,
+    ) -> Self {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let txt: Vec<Vec<u8>> = txt
+            .iter()
+            .map(|record| record.as_bytes().to_vec())
+            .collect();
+
+        tokio::spawn(async move {
+            if let Err(e) = Self::run(name, service_type, port, txt, interfaces, shutdown_rx).await
+            {
+                error!("Registering with Avahi failed: {e}");
+                events.send(DiscoveryEvent::Error {
+                    reason: format!("Registering with Avahi failed: {e}"),
+                });
+            }
+        });
+
+        Self {
+            _shutdown_tx: shutdown_tx,
+        }
+    }
+
+    async fn run(
+        mut name: String,
+        service_type: &str,
+        port: u16,
+        txt: Vec<Vec<u8>>,
+        interfaces: Vec<u32>,
+        shutdown_rx: oneshot::Receiver<Infallible>,
+    ) -> zbus::Result<()> {
+        let connection = zbus::Connection::system().await?;
+        let server = ServerProxy::new(&connection).await?;
+        let entry_group = EntryGroupProxy::builder(&connection)
+            .path(server.entry_group_new().await?)?
+            .build()
+            .await?;
+        let mut state_changes = entry_group.receive_state_changed().await?;
+
+        let txt: Vec<&[u8]> = txt.iter().map(Vec::as_slice).collect();
+        let interfaces: Vec<i32> = if interfaces.is_empty() {
+            vec![IF_UNSPEC]
+        } else {
+            interfaces.into_iter().map(|index| index as i32).collect()
+        };
+
+        let mut shutdown_rx = std::pin::pin!(shutdown_rx);
+        loop {
+            for &interface in &interfaces {
+                entry_group
+                    .add_service(
+                        interface,
+                        PROTO_UNSPEC,
+                        0,
+                        &name,
+                        service_type,
+                        "",
+                        "",
+                        port,
+                        &txt,
+                    )
+                    .await?;
+            }
+            entry_group.commit().await?;
+
+            loop {
+                tokio::select! {
+                    Some(signal) = state_changes.next() => {
+                        let args = signal.args()?;
+                        match *args.state() {
+                            ENTRY_GROUP_ESTABLISHED => debug!("Registered \"{name}\" with Avahi"),
+                            ENTRY_GROUP_COLLISION => break,
+                            ENTRY_GROUP_FAILURE => {
+                                return Err(zbus::Error::Failure(args.error().to_string()));
+                            }
+                            _ => (),
+                        }
+                    }
+                    _ = &mut shutdown_rx => {
+                        debug!("Unregistering \"{name}\" from Avahi");
+                        return entry_group.free().await;
+                    }
+                }
+            }
+
+            let alternative_name = server.get_alternative_service_name(&name).await?;
+            warn!("Name \"{name}\" is taken on the network, using \"{alternative_name}\"");
+            name = alternative_name;
+            entry_group.reset().await?;
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+
+pub type DiscoveryEventChannel = mpsc::UnboundedReceiver<DiscoveryEvent>;
+
+/// What happens while this device is discoverable, e.g. to show the pairing
+/// status in a user interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryEvent {
+    /// The device is announced on the network, and clients can connect on `port`.
+    Announced { name: String, port: u16 },
+    /// A client selected this device and sent the credentials of `username`.
+    UserConnected { username: String },
+    /// The credentials of `username` were decrypted and are yielded by the
+    /// stream. Whether logging in with them succeeds is up to the session.
+    CredentialsReceived { username: String },
+    /// The credentials of `username` could not be decrypted.
+    AuthenticationFailed { username: String, reason: String },
+    /// Announcing the device or answering a client failed.
+    Error { reason: String },
+}
+
+#[derive(Default)]
+struct EventSenderInner {
+    senders: Vec<mpsc::UnboundedSender<DiscoveryEvent>>,
+    announced: Option<DiscoveryEvent>,
+}
+
+/// Sends [`DiscoveryEvent`]s to every channel of a
+/// [`Discovery`](crate::Discovery).
+#[derive(Clone, Default)]
+pub struct DiscoveryEventSender(Arc<Mutex<EventSenderInner>>);
+
+impl DiscoveryEventSender {
+    pub fn send(&self, event: DiscoveryEvent) {
+        if let Ok(mut inner) = self.0.lock() {
+            if matches!(event, DiscoveryEvent::Announced { .. }) {
+                inner.announced = Some(event.clone());
+            }
+
+            // Drop senders whose receiver is gone.
+            inner
+                .senders
+                .retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+
+    // Channels that are added after the device was announced start with the
+    // announcement, so they do not miss it.
+    pub(crate) fn channel(&self) -> DiscoveryEventChannel {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if let Ok(mut inner) = self.0.lock() {
+            if let Some(announced) = &inner.announced {
+                let _ = sender.send(announced.clone());
+            }
+            inner.senders.push(sender);
+        }
+        receiver
+    }
+}
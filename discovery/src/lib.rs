@@ -6,20 +6,30 @@
 //!
 //! This library uses mDNS and DNS-SD so that other devices can find it,
 //! and spawns an http server to answer requests of Spotify clients.
+//! The service is announced by one of the [`zeroconf_backend::BACKENDS`],
+//! which is chosen at runtime.
 
+#[cfg(feature = "with-avahi")]
+mod avahi;
+mod event;
 mod server;
+pub mod zeroconf_backend;
 
 use std::{
     borrow::Cow,
     io,
+    net::IpAddr,
     pin::Pin,
     task::{Context, Poll},
 };
 
 use futures_core::Stream;
+use log::debug;
 use thiserror::Error;
 
+pub use self::event::{DiscoveryEvent, DiscoveryEventChannel, DiscoveryEventSender};
 use self::server::DiscoveryServer;
+use self::zeroconf_backend::{ServiceBuilder, ServiceHandle};
 
 pub use crate::core::Error;
 use librespot_core as core;
@@ -36,11 +46,8 @@ pub use crate::core::config::DeviceType;
 /// is selected in the list of available devices, it yields [`Credentials`].
 pub struct Discovery {
     server: DiscoveryServer,
-
-    #[cfg(not(feature = "with-dns-sd"))]
-    _svc: libmdns::Service,
-    #[cfg(feature = "with-dns-sd")]
-    _svc: dns_sd::DNSService,
+    events: DiscoveryEventSender,
+    _svc: ServiceHandle,
 }
 
 /// A builder for [`Discovery`].
@@ -48,6 +55,8 @@ pub struct Builder {
     server_config: server::Config,
     port: u16,
     zeroconf_ip: Vec<std::net::IpAddr>,
+    zeroconf_interfaces: Vec<String>,
+    zeroconf_backend: ServiceBuilder,
 }
 
 /// Errors that can occur while setting up a [`Discovery`] instance.
@@ -61,6 +70,10 @@ pub enum DiscoveryError {
     HmacError(Vec<u8>),
     #[error("Setting up the HTTP server failed: {0}")]
     HttpServerError(#[from] hyper::Error),
+    #[error("No address found for network interface {0}")]
+    InterfaceError(String),
+    #[error("Setting up mdns-sd failed: {0}")]
+    MdnsSdError(#[from] mdns_sd::Error),
     #[error("Missing params for key {0}")]
     ParamsError(&'static str),
 }
@@ -72,6 +85,8 @@ impl From<DiscoveryError> for Error {
             DiscoveryError::DnsSdError(_) => Error::unavailable(err),
             DiscoveryError::HmacError(_) => Error::invalid_argument(err),
             DiscoveryError::HttpServerError(_) => Error::unavailable(err),
+            DiscoveryError::InterfaceError(_) => Error::unavailable(err),
+            DiscoveryError::MdnsSdError(_) => Error::unavailable(err),
             DiscoveryError::ParamsError(_) => Error::invalid_argument(err),
         }
     }
@@ -90,6 +105,8 @@ impl Builder {
             },
             port: 0,
             zeroconf_ip: vec![],
+            zeroconf_interfaces: vec![],
+            zeroconf_backend: zeroconf_backend::BACKENDS[0].1,
         }
     }
 
@@ -117,6 +134,20 @@ impl Builder {
         self
     }
 
+    /// Set the network interfaces, like `eth0`, on which it should listen to incoming connections.
+    /// Their addresses are looked up on launch and added to those of [`Builder::zeroconf_ip`].
+    pub fn zeroconf_interfaces(mut self, zeroconf_interfaces: Vec<String>) -> Self {
+        self.zeroconf_interfaces = zeroconf_interfaces;
+        self
+    }
+
+    /// Sets how the service is announced, see [`zeroconf_backend::find`]. The default is the
+    /// first of the [`zeroconf_backend::BACKENDS`].
+    pub fn zeroconf_backend(mut self, zeroconf_backend: ServiceBuilder) -> Self {
+        self.zeroconf_backend = zeroconf_backend;
+        self
+    }
+
     /// Sets the port on which it should listen to incoming connections.
     /// The default value `0` means any port.
     pub fn port(mut self, port: u16) -> Self {
@@ -127,46 +158,51 @@ impl Builder {
     /// Sets up the [`Discovery`] instance.
     ///
     /// # Errors
-    /// If setting up the mdns service or creating the server fails, this function returns an error.
+    /// If setting up the mdns service or creating the server fails, or a network interface
+    /// has no address, this function returns an error.
     pub fn launch(self) -> Result<Discovery, Error> {
         let mut port = self.port;
         let name = self.server_config.name.clone().into_owned();
-        let server = DiscoveryServer::new(self.server_config, &mut port)?;
-        let _zeroconf_ip = self.zeroconf_ip;
-        let svc;
-
-        #[cfg(feature = "with-dns-sd")]
-        {
-            svc = dns_sd::DNSService::register(
-                Some(name.as_ref()),
-                "_spotify-connect._tcp",
-                None,
-                None,
-                port,
-                &["VERSION=1.0", "CPath=/"],
-            )?;
-        }
-
-        #[cfg(not(feature = "with-dns-sd"))]
-        {
-            let _svc = if !_zeroconf_ip.is_empty() {
-                libmdns::Responder::spawn_with_ip_list(
-                    &tokio::runtime::Handle::current(),
-                    _zeroconf_ip,
-                )?
-            } else {
-                libmdns::Responder::spawn(&tokio::runtime::Handle::current())?
-            };
-            svc = _svc.register(
-                "_spotify-connect._tcp".to_owned(),
-                name,
-                port,
-                &["VERSION=1.0", "CPath=/"],
-            );
+        let mut zeroconf_ip = self.zeroconf_ip;
+        for interface in &self.zeroconf_interfaces {
+            for ip in interface_addresses(interface)? {
+                if !zeroconf_ip.contains(&ip) {
+                    zeroconf_ip.push(ip);
+                }
+            }
         }
+        let events = DiscoveryEventSender::default();
+        let server =
+            DiscoveryServer::new(self.server_config, &zeroconf_ip, &mut port, events.clone())?;
+        let svc = (self.zeroconf_backend)(name.clone(), port, zeroconf_ip, events.clone())?;
+        events.send(DiscoveryEvent::Announced { name, port });
+
+        Ok(Discovery {
+            server,
+            events,
+            _svc: svc,
+        })
+    }
+}
 
-        Ok(Discovery { server, _svc: svc })
+// IPv6 link-local addresses are left out, they cannot be bound to without a scope ID.
+fn interface_addresses(interface: &str) -> Result<Vec<IpAddr>, DiscoveryError> {
+    let addresses: Vec<IpAddr> = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|iface| iface.name == interface)
+        .map(|iface| iface.ip())
+        .filter(|ip| match ip {
+            IpAddr::V4(_) => true,
+            IpAddr::V6(ip) => (ip.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .collect();
+
+    if addresses.is_empty() {
+        return Err(DiscoveryError::InterfaceError(interface.to_owned()));
     }
+
+    debug!("Using addresses {addresses:?} of network interface {interface}");
+    Ok(addresses)
 }
 
 impl Discovery {
@@ -179,6 +215,12 @@ impl Discovery {
     pub fn new<T: Into<String>>(device_id: T, client_id: T) -> Result<Self, Error> {
         Self::builder(device_id, client_id).launch()
     }
+
+    /// Returns a channel of the [`DiscoveryEvent`]s from now on. It starts with the
+    /// [`DiscoveryEvent::Announced`] event.
+    pub fn get_event_channel(&self) -> DiscoveryEventChannel {
+        self.events.channel()
+    }
 }
 
 impl Stream for Discovery {
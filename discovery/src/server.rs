@@ -2,7 +2,8 @@ use std::{
     borrow::Cow,
     collections::BTreeMap,
     convert::Infallible,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     pin::Pin,
     sync::{Arc, Mutex},
     task::{Context, Poll},
@@ -13,7 +14,7 @@ use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::engine::Engine as _;
 use bytes::Bytes;
 use futures_core::Stream;
-use futures_util::{FutureExt, TryFutureExt};
+use futures_util::{future::select_all, FutureExt, TryFutureExt};
 use hmac::{Hmac, Mac};
 use http_body_util::{BodyExt, Full};
 use hyper::{body::Incoming, Method, Request, Response, StatusCode};
@@ -22,9 +23,10 @@ use hyper_util::{rt::TokioIo, server::graceful::GracefulShutdown};
 use log::{debug, error, warn};
 use serde_json::json;
 use sha1::{Digest, Sha1};
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::sync::{mpsc, oneshot};
 
-use super::DiscoveryError;
+use super::{DiscoveryError, DiscoveryEvent, DiscoveryEventSender};
 
 use crate::{
     core::config::DeviceType,
@@ -48,10 +50,14 @@ struct RequestHandler {
     username: Mutex<Option<String>>,
     keys: DhLocalKeys,
     tx: mpsc::UnboundedSender<Credentials>,
+    events: DiscoveryEventSender,
 }
 
 impl RequestHandler {
-    fn new(config: Config) -> (Self, mpsc::UnboundedReceiver<Credentials>) {
+    fn new(
+        config: Config,
+        events: DiscoveryEventSender,
+    ) -> (Self, mpsc::UnboundedReceiver<Credentials>) {
         let (tx, rx) = mpsc::unbounded_channel();
 
         let discovery = Self {
@@ -59,6 +65,7 @@ impl RequestHandler {
             username: Mutex::new(None),
             keys: DhLocalKeys::random(&mut rand::thread_rng()),
             tx,
+            events,
         };
 
         (discovery, rx)
@@ -133,6 +140,10 @@ impl RequestHandler {
             .ok_or(DiscoveryError::ParamsError(username_key))?
             .as_ref();
 
+        self.events.send(DiscoveryEvent::UserConnected {
+            username: username.to_owned(),
+        });
+
         let blob_key = "blob";
         let encrypted_blob = params
             .get(blob_key)
@@ -179,6 +190,10 @@ impl RequestHandler {
         h.update(encrypted);
         if h.verify_slice(cksum).is_err() {
             warn!("Login error for user {:?}: MAC mismatch", username);
+            self.events.send(DiscoveryEvent::AuthenticationFailed {
+                username: username.to_owned(),
+                reason: "MAC mismatch".to_owned(),
+            });
             let result = json!({
                 "status": 102,
                 "spotifyError": 1,
@@ -210,6 +225,10 @@ impl RequestHandler {
             }
         }
 
+        self.events.send(DiscoveryEvent::CredentialsReceived {
+            username: username.to_owned(),
+        });
+
         let result = json!({
             "status": 101,
             "spotifyError": 0,
@@ -252,7 +271,18 @@ impl RequestHandler {
 
         Ok(Ok(match (parts.method, action) {
             (Method::GET, Some("getInfo")) => self.handle_get_info(),
-            (Method::POST, Some("addUser")) => self.handle_add_user(&params)?,
+            (Method::POST, Some("addUser")) => match self.handle_add_user(&params) {
+                Ok(response) => response,
+                Err(e) => {
+                    if let Some(username) = params.get("userName") {
+                        self.events.send(DiscoveryEvent::AuthenticationFailed {
+                            username: username.to_string(),
+                            reason: e.to_string(),
+                        });
+                    }
+                    return Err(e);
+                }
+            },
             _ => self.not_found(),
         }))
     }
@@ -264,34 +294,52 @@ pub struct DiscoveryServer {
 }
 
 impl DiscoveryServer {
-    pub fn new(config: Config, port: &mut u16) -> Result<Self, Error> {
-        let (discovery, cred_rx) = RequestHandler::new(config);
-        let address = if cfg!(windows) {
-            SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), *port)
+    /// Listens on all `addresses`, or on all interfaces if there are none.
+    pub fn new(
+        config: Config,
+        addresses: &[IpAddr],
+        port: &mut u16,
+        events: DiscoveryEventSender,
+    ) -> Result<Self, Error> {
+        let (discovery, cred_rx) = RequestHandler::new(config, events);
+        // Without addresses, a dual stack socket is used, or an IPv4 one if IPv6 is unavailable.
+        let any_address = addresses.is_empty();
+        let addresses = if any_address {
+            vec![Ipv6Addr::UNSPECIFIED.into(), Ipv4Addr::UNSPECIFIED.into()]
         } else {
-            // this creates a dual stack socket on non-windows systems
-            SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), *port)
+            addresses.to_vec()
         };
 
         let (close_tx, close_rx) = oneshot::channel();
 
-        let listener = match TcpListener::bind(address) {
-            Ok(listener) => listener,
-            Err(e) => {
-                warn!("Discovery server failed to start: {e}");
-                return Err(e.into());
+        // All listeners share the port of the first one, which is the one that is announced.
+        let mut listeners = Vec::with_capacity(addresses.len());
+        let mut last_error = None;
+        for ip in addresses {
+            if any_address && !listeners.is_empty() {
+                break;
             }
-        };
 
-        listener.set_nonblocking(true)?;
-        let listener = tokio::net::TcpListener::from_std(listener)?;
-
-        match listener.local_addr() {
-            Ok(addr) => {
-                *port = addr.port();
-                debug!("Zeroconf server listening on 0.0.0.0:{}", *port);
+            match Self::bind(SocketAddr::new(ip, *port)) {
+                Ok(listener) => {
+                    let addr = listener.local_addr()?;
+                    *port = addr.port();
+                    debug!("Zeroconf server listening on {addr}");
+                    listeners.push(listener);
+                }
+                Err(e) if any_address => {
+                    debug!("Discovery server failed to listen on {ip}: {e}");
+                    last_error = Some(e);
+                }
+                Err(e) => {
+                    warn!("Discovery server failed to listen on {ip}: {e}");
+                    last_error = Some(e);
+                }
             }
-            Err(e) => {
+        }
+
+        if listeners.is_empty() {
+            if let Some(e) = last_error {
                 warn!("Discovery server failed to start: {e}");
                 return Err(e.into());
             }
@@ -304,8 +352,11 @@ impl DiscoveryServer {
             let graceful = GracefulShutdown::new();
             let mut close_rx = std::pin::pin!(close_rx);
             loop {
+                let accept =
+                    select_all(listeners.iter().map(|listener| Box::pin(listener.accept())));
+
                 tokio::select! {
-                    Ok((stream, _)) = listener.accept() => {
+                    (Ok((stream, _)), _, _) = accept => {
                         let io = TokioIo::new(stream);
                         let discovery = discovery.clone();
 
@@ -341,6 +392,25 @@ impl DiscoveryServer {
             _close_tx: close_tx,
         })
     }
+
+    fn bind(address: SocketAddr) -> io::Result<tokio::net::TcpListener> {
+        let socket = Socket::new(
+            Domain::for_address(address),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        if address.is_ipv6() {
+            // Windows and systems with `net.ipv6.bindv6only` set default to IPv6 only,
+            // which would make the unspecified address miss IPv4 clients.
+            socket.set_only_v6(!address.ip().is_unspecified())?;
+        }
+        #[cfg(not(windows))]
+        socket.set_reuse_address(true)?;
+        socket.bind(&address.into())?;
+        socket.listen(128)?;
+        socket.set_nonblocking(true)?;
+        tokio::net::TcpListener::from_std(socket.into())
+    }
 }
 
 impl Stream for DiscoveryServer {
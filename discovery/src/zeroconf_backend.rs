@@ -0,0 +1,190 @@
+//! The implementations that announce the discovery service on the network.
+//!
+//! All of them announce the same service type and TXT record, so Spotify
+//! clients cannot tell them apart.
+
+use std::net::IpAddr;
+
+use log::debug;
+
+#[cfg(feature = "with-avahi")]
+use crate::avahi::AvahiService;
+use crate::{core::Error, DiscoveryError, DiscoveryEventSender};
+
+pub const SERVICE_TYPE: &str = "_spotify-connect._tcp";
+pub const TXT_RECORD: &[&str] = &["VERSION=1.0", "CPath=/"];
+
+/// Announces a service with the given name and port. Without addresses, it is
+/// announced on all interfaces. Errors after launch are sent as events.
+pub type ServiceBuilder =
+    fn(String, u16, Vec<IpAddr>, DiscoveryEventSender) -> Result<ServiceHandle, Error>;
+
+/// Keeps the service announced until it is dropped.
+pub struct ServiceHandle {
+    _svc: Service,
+}
+
+// Only held so that the service stays announced.
+#[allow(dead_code)]
+enum Service {
+    Libmdns(libmdns::Service),
+    MdnsSd(MdnsSdService),
+    #[cfg(feature = "with-avahi")]
+    Avahi(AvahiService),
+    #[cfg(feature = "with-dns-sd")]
+    DnsSd(dns_sd::DNSService),
+}
+
+pub const BACKENDS: &[(&str, ServiceBuilder)] = &[
+    #[cfg(feature = "with-dns-sd")]
+    ("dns-sd", launch_dns_sd), // default goes first
+    #[cfg(feature = "with-avahi")]
+    ("avahi", launch_avahi),
+    ("libmdns", launch_libmdns),
+    ("mdns-sd", launch_mdns_sd),
+];
+
+pub fn find(name: Option<String>) -> Option<ServiceBuilder> {
+    if let Some(name) = name {
+        BACKENDS
+            .iter()
+            .find(|backend| name == backend.0)
+            .map(|backend| backend.1)
+    } else {
+        BACKENDS.first().map(|backend| backend.1)
+    }
+}
+
+fn launch_libmdns(
+    name: String,
+    port: u16,
+    zeroconf_ip: Vec<IpAddr>,
+    _events: DiscoveryEventSender,
+) -> Result<ServiceHandle, Error> {
+    let responder = if !zeroconf_ip.is_empty() {
+        libmdns::Responder::spawn_with_ip_list(&tokio::runtime::Handle::current(), zeroconf_ip)?
+    } else {
+        libmdns::Responder::spawn(&tokio::runtime::Handle::current())?
+    };
+    let svc = responder.register(SERVICE_TYPE.to_owned(), name, port, TXT_RECORD);
+
+    Ok(ServiceHandle {
+        _svc: Service::Libmdns(svc),
+    })
+}
+
+struct MdnsSdService {
+    daemon: mdns_sd::ServiceDaemon,
+    fullname: String,
+}
+
+impl Drop for MdnsSdService {
+    fn drop(&mut self) {
+        if let Err(e) = self.daemon.unregister(&self.fullname) {
+            debug!("Unable to unregister {}: {e}", self.fullname);
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+fn launch_mdns_sd(
+    name: String,
+    port: u16,
+    zeroconf_ip: Vec<IpAddr>,
+    _events: DiscoveryEventSender,
+) -> Result<ServiceHandle, Error> {
+    let daemon = mdns_sd::ServiceDaemon::new().map_err(DiscoveryError::from)?;
+    if !zeroconf_ip.is_empty() {
+        daemon
+            .disable_interface(mdns_sd::IfKind::All)
+            .map_err(DiscoveryError::from)?;
+        for ip in zeroconf_ip {
+            daemon
+                .enable_interface(mdns_sd::IfKind::Addr(ip))
+                .map_err(DiscoveryError::from)?;
+        }
+    }
+
+    // The host name only has to be unique in the local network, the addresses
+    // behind it are those of the enabled interfaces.
+    let host_name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let properties: Vec<(&str, &str)> = TXT_RECORD
+        .iter()
+        .filter_map(|record| record.split_once('='))
+        .collect();
+
+    let info = mdns_sd::ServiceInfo::new(
+        &format!("{SERVICE_TYPE}.local."),
+        &name,
+        &format!("{host_name}.local."),
+        "",
+        port,
+        &properties[..],
+    )
+    .map_err(DiscoveryError::from)?
+    .enable_addr_auto();
+    let fullname = info.get_fullname().to_owned();
+    daemon.register(info).map_err(DiscoveryError::from)?;
+
+    Ok(ServiceHandle {
+        _svc: Service::MdnsSd(MdnsSdService { daemon, fullname }),
+    })
+}
+
+#[cfg(feature = "with-avahi")]
+fn launch_avahi(
+    name: String,
+    port: u16,
+    zeroconf_ip: Vec<IpAddr>,
+    events: DiscoveryEventSender,
+) -> Result<ServiceHandle, Error> {
+    let svc = AvahiService::register(
+        name,
+        SERVICE_TYPE,
+        port,
+        TXT_RECORD,
+        interface_indices(&zeroconf_ip)?,
+        events,
+    );
+
+    Ok(ServiceHandle {
+        _svc: Service::Avahi(svc),
+    })
+}
+
+#[cfg(feature = "with-avahi")]
+fn interface_indices(addresses: &[IpAddr]) -> Result<Vec<u32>, DiscoveryError> {
+    let mut indices = Vec::new();
+    for iface in if_addrs::get_if_addrs()? {
+        if let Some(index) = iface.index {
+            if addresses.contains(&iface.ip()) && !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+    }
+    Ok(indices)
+}
+
+#[cfg(feature = "with-dns-sd")]
+fn launch_dns_sd(
+    name: String,
+    port: u16,
+    zeroconf_ip: Vec<IpAddr>,
+    _events: DiscoveryEventSender,
+) -> Result<ServiceHandle, Error> {
+    if !zeroconf_ip.is_empty() {
+        log::warn!(
+            "DNS-SD announces on all interfaces, only the HTTP server is bound to the given ones"
+        );
+    }
+
+    let svc =
+        dns_sd::DNSService::register(Some(&name), SERVICE_TYPE, None, None, port, TXT_RECORD)?;
+
+    Ok(ServiceHandle {
+        _svc: Service::DnsSd(svc),
+    })
+}
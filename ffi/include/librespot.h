@@ -0,0 +1,106 @@
+/*
+ * C interface of librespot, see ffi/src for the documentation of every
+ * function. Objects are freed in the reverse order of their creation: Spirc,
+ * then the player, then the session.
+ *
+ * To play on request, connect the session with librespot_session_connect or
+ * librespot_session_connect_cached. To be a Spotify Connect device instead,
+ * pass the unconnected session to librespot_spirc_new, which logs it in.
+ */
+
+#ifndef LIBRESPOT_H
+#define LIBRESPOT_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct LibrespotSession LibrespotSession;
+typedef struct LibrespotPlayer LibrespotPlayer;
+typedef struct LibrespotSpirc LibrespotSpirc;
+
+typedef enum LibrespotResult {
+    LIBRESPOT_OK = 0,
+    LIBRESPOT_INVALID_ARGUMENT = 1,
+    LIBRESPOT_FAILED = 2,
+} LibrespotResult;
+
+typedef enum LibrespotEventKind {
+    LIBRESPOT_EVENT_STOPPED = 0,
+    LIBRESPOT_EVENT_LOADING = 1,
+    LIBRESPOT_EVENT_PLAYING = 2,
+    LIBRESPOT_EVENT_PAUSED = 3,
+    LIBRESPOT_EVENT_SEEKED = 4,
+    LIBRESPOT_EVENT_END_OF_TRACK = 5,
+    LIBRESPOT_EVENT_UNAVAILABLE = 6,
+    LIBRESPOT_EVENT_TRACK_CHANGED = 7,
+    LIBRESPOT_EVENT_VOLUME_CHANGED = 8,
+    LIBRESPOT_EVENT_SHUFFLE_CHANGED = 9,
+    LIBRESPOT_EVENT_REPEAT_CHANGED = 10,
+    LIBRESPOT_EVENT_SESSION_CONNECTED = 11,
+    LIBRESPOT_EVENT_SESSION_DISCONNECTED = 12,
+} LibrespotEventKind;
+
+/* Strings are NULL when they do not apply, and only valid during the callback. */
+typedef struct LibrespotEvent {
+    LibrespotEventKind kind;
+    const char *track_uri;
+    uint32_t position_ms;
+    uint32_t duration_ms;
+    const char *title;
+    const char *artist;
+    const char *album;
+    const char *cover_url;
+    uint16_t volume;
+    bool enabled;
+    const char *user_name;
+} LibrespotEvent;
+
+typedef void (*LibrespotEventCallback)(const LibrespotEvent *event, void *user_data);
+
+/* The message of the last error on this thread, or NULL. */
+const char *librespot_last_error(void);
+
+LibrespotSession *librespot_session_new(const char *cache_dir);
+LibrespotResult librespot_session_connect(LibrespotSession *session, const char *access_token);
+LibrespotResult librespot_session_connect_cached(LibrespotSession *session);
+void librespot_session_free(LibrespotSession *session);
+
+LibrespotPlayer *librespot_player_new(LibrespotSession *session, const char *backend,
+                                      const char *device);
+LibrespotResult librespot_player_set_event_callback(LibrespotPlayer *player,
+                                                    LibrespotEventCallback callback,
+                                                    void *user_data);
+LibrespotResult librespot_player_load(LibrespotPlayer *player, const char *uri,
+                                      bool start_playing, uint32_t position_ms);
+LibrespotResult librespot_player_play(LibrespotPlayer *player);
+LibrespotResult librespot_player_pause(LibrespotPlayer *player);
+LibrespotResult librespot_player_stop(LibrespotPlayer *player);
+LibrespotResult librespot_player_seek(LibrespotPlayer *player, uint32_t position_ms);
+void librespot_player_free(LibrespotPlayer *player);
+
+LibrespotSpirc *librespot_spirc_new(LibrespotSession *session, LibrespotPlayer *player,
+                                    const char *name, const char *access_token);
+LibrespotResult librespot_spirc_play(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_pause(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_play_pause(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_next(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_prev(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_volume_up(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_volume_down(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_set_volume(LibrespotSpirc *spirc, uint16_t volume);
+LibrespotResult librespot_spirc_set_position_ms(LibrespotSpirc *spirc, uint32_t position_ms);
+LibrespotResult librespot_spirc_shuffle(LibrespotSpirc *spirc, bool shuffle);
+LibrespotResult librespot_spirc_repeat(LibrespotSpirc *spirc, bool repeat);
+LibrespotResult librespot_spirc_activate(LibrespotSpirc *spirc);
+LibrespotResult librespot_spirc_disconnect(LibrespotSpirc *spirc);
+void librespot_spirc_free(LibrespotSpirc *spirc);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LIBRESPOT_H */
@@ -0,0 +1,201 @@
+use std::{
+    ffi::{c_char, c_void, CString},
+    ptr,
+};
+
+use librespot_playback::player::PlayerEvent;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibrespotEventKind {
+    Stopped = 0,
+    Loading = 1,
+    Playing = 2,
+    Paused = 3,
+    Seeked = 4,
+    EndOfTrack = 5,
+    Unavailable = 6,
+    TrackChanged = 7,
+    VolumeChanged = 8,
+    ShuffleChanged = 9,
+    RepeatChanged = 10,
+    SessionConnected = 11,
+    SessionDisconnected = 12,
+}
+
+/// A player event. Strings are null when they do not apply to the kind of
+/// event, and are only valid during the callback.
+#[repr(C)]
+pub struct LibrespotEvent {
+    pub kind: LibrespotEventKind,
+    pub track_uri: *const c_char,
+    pub position_ms: u32,
+    /// `TrackChanged` only.
+    pub duration_ms: u32,
+    pub title: *const c_char,
+    /// The artists, separated by ", ".
+    pub artist: *const c_char,
+    pub album: *const c_char,
+    pub cover_url: *const c_char,
+    /// `VolumeChanged` only, 0 to 65535.
+    pub volume: u16,
+    /// Whether shuffle or repeat is on, for `ShuffleChanged` and `RepeatChanged`.
+    pub enabled: bool,
+    /// `SessionConnected` and `SessionDisconnected` only.
+    pub user_name: *const c_char,
+}
+
+pub type LibrespotEventCallback =
+    extern "C" fn(event: *const LibrespotEvent, user_data: *mut c_void);
+
+// The strings that the pointers of an event point into.
+#[derive(Default)]
+struct EventStrings {
+    track_uri: Option<CString>,
+    title: Option<CString>,
+    artist: Option<CString>,
+    album: Option<CString>,
+    cover_url: Option<CString>,
+    user_name: Option<CString>,
+}
+
+fn c_string(s: impl Into<Vec<u8>>) -> Option<CString> {
+    CString::new(s).ok()
+}
+
+fn as_ptr(s: &Option<CString>) -> *const c_char {
+    s.as_ref().map_or(ptr::null(), |s| s.as_ptr())
+}
+
+pub(crate) struct EventCallback {
+    callback: LibrespotEventCallback,
+    user_data: *mut c_void,
+}
+
+// The caller promises that the callback can be called from any thread with
+// its user data, see `librespot_player_set_event_callback`.
+unsafe impl Send for EventCallback {}
+
+impl EventCallback {
+    pub(crate) fn new(callback: LibrespotEventCallback, user_data: *mut c_void) -> Self {
+        Self {
+            callback,
+            user_data,
+        }
+    }
+
+    pub(crate) fn call(&self, event: PlayerEvent) {
+        use PlayerEvent::*;
+
+        let mut strings = EventStrings::default();
+        let mut position_ms = 0;
+        let mut duration_ms = 0;
+        let mut volume = 0;
+        let mut enabled = false;
+
+        let kind = match event {
+            Stopped { track_id, .. } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                LibrespotEventKind::Stopped
+            }
+            Loading {
+                track_id,
+                position_ms: position,
+                ..
+            } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                position_ms = position;
+                LibrespotEventKind::Loading
+            }
+            Playing {
+                track_id,
+                position_ms: position,
+                ..
+            } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                position_ms = position;
+                LibrespotEventKind::Playing
+            }
+            Paused {
+                track_id,
+                position_ms: position,
+                ..
+            } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                position_ms = position;
+                LibrespotEventKind::Paused
+            }
+            Seeked {
+                track_id,
+                position_ms: position,
+                ..
+            } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                position_ms = position;
+                LibrespotEventKind::Seeked
+            }
+            EndOfTrack { track_id, .. } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                LibrespotEventKind::EndOfTrack
+            }
+            Unavailable { track_id, .. } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                LibrespotEventKind::Unavailable
+            }
+            TrackMetadata {
+                track_id,
+                title,
+                artists,
+                album,
+                duration_ms: duration,
+                cover_url,
+                ..
+            } => {
+                strings.track_uri = track_id.to_uri().ok().and_then(c_string);
+                strings.title = c_string(title);
+                strings.artist = c_string(artists.join(", "));
+                strings.album = album.and_then(c_string);
+                strings.cover_url = cover_url.and_then(c_string);
+                duration_ms = duration;
+                LibrespotEventKind::TrackChanged
+            }
+            VolumeChanged { volume: new_volume } => {
+                volume = new_volume;
+                LibrespotEventKind::VolumeChanged
+            }
+            ShuffleChanged { shuffle } => {
+                enabled = shuffle;
+                LibrespotEventKind::ShuffleChanged
+            }
+            RepeatChanged { repeat } => {
+                enabled = repeat;
+                LibrespotEventKind::RepeatChanged
+            }
+            SessionConnected { user_name, .. } => {
+                strings.user_name = c_string(user_name);
+                LibrespotEventKind::SessionConnected
+            }
+            SessionDisconnected { user_name, .. } => {
+                strings.user_name = c_string(user_name);
+                LibrespotEventKind::SessionDisconnected
+            }
+            _ => return,
+        };
+
+        let event = LibrespotEvent {
+            kind,
+            track_uri: as_ptr(&strings.track_uri),
+            position_ms,
+            duration_ms,
+            title: as_ptr(&strings.title),
+            artist: as_ptr(&strings.artist),
+            album: as_ptr(&strings.album),
+            cover_url: as_ptr(&strings.cover_url),
+            volume,
+            enabled,
+            user_name: as_ptr(&strings.user_name),
+        };
+
+        (self.callback)(&event, self.user_data);
+    }
+}
@@ -0,0 +1,210 @@
+//! A C interface to embed librespot in applications that are not written in
+//! Rust. See `include/librespot.h` for the declarations.
+//!
+//! All objects are created and freed through this interface. Functions that
+//! can fail return a [`LibrespotResult`], and [`librespot_last_error`]
+//! describes the last failure on the calling thread.
+
+#[macro_use]
+extern crate log;
+
+// Returns the error of a `Result<_, LibrespotResult>` from the function.
+macro_rules! ffi_try {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) => return e,
+        }
+    };
+}
+
+mod event;
+mod player;
+mod session;
+mod spirc;
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    ptr,
+};
+
+pub use event::{LibrespotEvent, LibrespotEventCallback, LibrespotEventKind};
+pub use player::LibrespotPlayer;
+pub use session::LibrespotSession;
+pub use spirc::LibrespotSpirc;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibrespotResult {
+    Ok = 0,
+    InvalidArgument = 1,
+    Failed = 2,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: impl ToString) {
+    let error = error.to_string();
+    error!("{error}");
+    let error = CString::new(error).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error));
+}
+
+fn result<E: ToString>(result: Result<(), E>) -> LibrespotResult {
+    match result {
+        Ok(()) => LibrespotResult::Ok,
+        Err(e) => {
+            set_last_error(e);
+            LibrespotResult::Failed
+        }
+    }
+}
+
+/// Dereferences an object that was created by this interface.
+///
+/// # Safety
+/// `ptr` must be null or point to a live object.
+unsafe fn object<'a, T>(ptr: *mut T, name: &str) -> Result<&'a T, LibrespotResult> {
+    ptr.as_ref().ok_or_else(|| {
+        set_last_error(format!("`{name}` must not be null"));
+        LibrespotResult::InvalidArgument
+    })
+}
+
+/// Reads an optional string, `None` for a null pointer.
+///
+/// # Safety
+/// `s` must be null or a valid, nul-terminated string.
+unsafe fn opt_str(s: *const c_char) -> Result<Option<String>, LibrespotResult> {
+    if s.is_null() {
+        return Ok(None);
+    }
+
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Ok(Some(s.to_owned())),
+        Err(e) => {
+            set_last_error(format!("Invalid string: {e}"));
+            Err(LibrespotResult::InvalidArgument)
+        }
+    }
+}
+
+/// Reads a required string.
+///
+/// # Safety
+/// `s` must be null or a valid, nul-terminated string.
+unsafe fn req_str(s: *const c_char, name: &str) -> Result<String, LibrespotResult> {
+    opt_str(s)?.ok_or_else(|| {
+        set_last_error(format!("`{name}` must not be null"));
+        LibrespotResult::InvalidArgument
+    })
+}
+
+/// The message of the last error on this thread, or null. It stays valid until
+/// the next call on this thread.
+#[no_mangle]
+pub extern "C" fn librespot_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use player::librespot_player_free;
+    use session::{librespot_session_connect, librespot_session_free};
+    use spirc::{librespot_spirc_free, librespot_spirc_new, librespot_spirc_play};
+
+    fn last_error() -> String {
+        let error = librespot_last_error();
+        assert!(!error.is_null());
+        unsafe { CStr::from_ptr(error) }
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[test]
+    fn test_object() {
+        let mut value = 1;
+        assert_eq!(unsafe { object(&mut value, "value") }, Ok(&1));
+
+        let null: *mut i32 = ptr::null_mut();
+        assert_eq!(
+            unsafe { object(null, "value") },
+            Err(LibrespotResult::InvalidArgument)
+        );
+        assert_eq!(last_error(), "`value` must not be null");
+    }
+
+    #[test]
+    fn test_strings() {
+        let s = CString::new("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap();
+        assert_eq!(
+            unsafe { opt_str(s.as_ptr()) },
+            Ok(Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_owned()))
+        );
+        assert_eq!(
+            unsafe { req_str(s.as_ptr(), "uri") }.as_deref(),
+            Ok("spotify:track:4uLU6hMCjMI75M1A2tKUQC")
+        );
+
+        assert_eq!(unsafe { opt_str(ptr::null()) }, Ok(None));
+        assert_eq!(
+            unsafe { req_str(ptr::null(), "uri") },
+            Err(LibrespotResult::InvalidArgument)
+        );
+        assert_eq!(last_error(), "`uri` must not be null");
+
+        let invalid = CString::new(vec![0x66, 0xff, 0x6f]).unwrap();
+        assert_eq!(
+            unsafe { opt_str(invalid.as_ptr()) },
+            Err(LibrespotResult::InvalidArgument)
+        );
+        assert!(last_error().starts_with("Invalid string"));
+        assert_eq!(
+            unsafe { req_str(invalid.as_ptr(), "uri") },
+            Err(LibrespotResult::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_result() {
+        assert_eq!(result(Ok::<_, String>(())), LibrespotResult::Ok);
+        assert_eq!(result(Err("no device")), LibrespotResult::Failed);
+        assert_eq!(last_error(), "no device");
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            assert!(librespot_spirc_new(
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null()
+            )
+            .is_null());
+            assert_eq!(
+                librespot_spirc_play(ptr::null_mut()),
+                LibrespotResult::InvalidArgument
+            );
+            assert_eq!(
+                librespot_session_connect(ptr::null_mut(), ptr::null()),
+                LibrespotResult::InvalidArgument
+            );
+
+            // Freeing null is a no-op.
+            librespot_spirc_free(ptr::null_mut());
+            librespot_player_free(ptr::null_mut());
+            librespot_session_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    ffi::{c_char, c_void},
+    sync::{Arc, Mutex},
+};
+
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use librespot_core::SpotifyId;
+use librespot_playback::{
+    audio_backend,
+    config::{AudioFormat, PlayerConfig},
+    mixer::{self, Mixer, MixerConfig},
+    player::Player,
+};
+
+use crate::{
+    event::{EventCallback, LibrespotEventCallback},
+    object, opt_str, req_str, set_last_error, LibrespotResult, LibrespotSession,
+};
+
+pub struct LibrespotPlayer {
+    pub(crate) runtime: Arc<Runtime>,
+    pub(crate) player: Arc<Player>,
+    pub(crate) mixer: Arc<dyn Mixer>,
+    event_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Creates a player of the session with a software mixer. Without a `backend`
+/// the default audio backend is used, and without a `device` its default
+/// device. Returns null on failure.
+///
+/// # Safety
+/// `session` must come from `librespot_session_new`. `backend` and `device`
+/// must be null or valid, nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_new(
+    session: *mut LibrespotSession,
+    backend: *const c_char,
+    device: *const c_char,
+) -> *mut LibrespotPlayer {
+    let session = match object(session, "session") {
+        Ok(session) => session,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let (backend_name, device) = match (opt_str(backend), opt_str(device)) {
+        (Ok(backend_name), Ok(device)) => (backend_name, device),
+        _ => return std::ptr::null_mut(),
+    };
+
+    let backend = match audio_backend::find(backend_name.clone()) {
+        Some(backend) => backend,
+        None => {
+            set_last_error(format!(
+                "Unknown audio backend: {}",
+                backend_name.unwrap_or_default()
+            ));
+            return std::ptr::null_mut();
+        }
+    };
+    let mixer = match mixer::find(None) {
+        Some(mixer) => mixer(MixerConfig::default()),
+        None => {
+            set_last_error("No mixer available");
+            return std::ptr::null_mut();
+        }
+    };
+
+    let player = {
+        let _guard = session.runtime.enter();
+        Player::new(
+            PlayerConfig::default(),
+            session.session.clone(),
+            mixer.get_soft_volume(),
+            move || backend(device, AudioFormat::default()),
+        )
+    };
+
+    Box::into_raw(Box::new(LibrespotPlayer {
+        runtime: session.runtime.clone(),
+        player,
+        mixer,
+        event_task: Mutex::new(None),
+    }))
+}
+
+/// Calls `callback` with every player event, or stops calling the previous
+/// callback if it is null. The callback is called from a runtime thread, and
+/// must neither block nor call functions of this interface that block.
+///
+/// # Safety
+/// `player` must come from [`librespot_player_new`]. `user_data` must stay
+/// valid, and be usable from other threads, until the callback is replaced or
+/// the player is freed.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_set_event_callback(
+    player: *mut LibrespotPlayer,
+    callback: Option<LibrespotEventCallback>,
+    user_data: *mut c_void,
+) -> LibrespotResult {
+    let player = ffi_try!(object(player, "player"));
+    let mut event_task = match player.event_task.lock() {
+        Ok(event_task) => event_task,
+        Err(_) => {
+            set_last_error("Event callback lock poisoned");
+            return LibrespotResult::Failed;
+        }
+    };
+
+    if let Some(task) = event_task.take() {
+        task.abort();
+    }
+
+    if let Some(callback) = callback {
+        let callback = EventCallback::new(callback, user_data);
+        let mut events = player.player.get_player_event_channel();
+        *event_task = Some(player.runtime.spawn(async move {
+            while let Some(event) = events.recv().await {
+                callback.call(event);
+            }
+        }));
+    }
+
+    LibrespotResult::Ok
+}
+
+/// Plays a track or episode without Spotify Connect.
+///
+/// # Safety
+/// `player` must come from [`librespot_player_new`], and `uri` must be a
+/// valid, nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_load(
+    player: *mut LibrespotPlayer,
+    uri: *const c_char,
+    start_playing: bool,
+    position_ms: u32,
+) -> LibrespotResult {
+    let player = ffi_try!(object(player, "player"));
+    let uri = ffi_try!(req_str(uri, "uri"));
+    match SpotifyId::from_uri(&uri) {
+        Ok(track_id) => {
+            player.player.load(track_id, start_playing, position_ms);
+            LibrespotResult::Ok
+        }
+        Err(e) => {
+            set_last_error(format!("Invalid URI {uri}: {e}"));
+            LibrespotResult::InvalidArgument
+        }
+    }
+}
+
+/// # Safety
+/// `player` must come from [`librespot_player_new`].
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_play(player: *mut LibrespotPlayer) -> LibrespotResult {
+    ffi_try!(object(player, "player")).player.play();
+    LibrespotResult::Ok
+}
+
+/// # Safety
+/// `player` must come from [`librespot_player_new`].
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_pause(player: *mut LibrespotPlayer) -> LibrespotResult {
+    ffi_try!(object(player, "player")).player.pause();
+    LibrespotResult::Ok
+}
+
+/// # Safety
+/// `player` must come from [`librespot_player_new`].
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_stop(player: *mut LibrespotPlayer) -> LibrespotResult {
+    ffi_try!(object(player, "player")).player.stop();
+    LibrespotResult::Ok
+}
+
+/// # Safety
+/// `player` must come from [`librespot_player_new`].
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_seek(
+    player: *mut LibrespotPlayer,
+    position_ms: u32,
+) -> LibrespotResult {
+    ffi_try!(object(player, "player")).player.seek(position_ms);
+    LibrespotResult::Ok
+}
+
+/// Stops the player and frees it.
+///
+/// # Safety
+/// `player` must be null or come from [`librespot_player_new`], and must not
+/// be used afterwards. A Spirc instance that uses it must be freed first.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_player_free(player: *mut LibrespotPlayer) {
+    if !player.is_null() {
+        let player = Box::from_raw(player);
+        if let Ok(Some(task)) = player.event_task.lock().map(|mut task| task.take()) {
+            task.abort();
+        }
+        player.player.stop();
+    }
+}
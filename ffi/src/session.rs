@@ -0,0 +1,118 @@
+use std::{ffi::c_char, path::PathBuf, sync::Arc};
+
+use tokio::runtime::Runtime;
+
+use librespot_core::{authentication::Credentials, cache::Cache, Session, SessionConfig};
+
+use crate::{object, opt_str, req_str, result, set_last_error, LibrespotResult};
+
+/// A session with its own runtime, which the players and Spirc instances of
+/// the session run on.
+pub struct LibrespotSession {
+    pub(crate) runtime: Arc<Runtime>,
+    pub(crate) session: Session,
+}
+
+/// Creates a session. With a `cache_dir`, credentials, volume and audio files
+/// are cached there. Returns null on failure.
+///
+/// # Safety
+/// `cache_dir` must be null or a valid, nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_session_new(cache_dir: *const c_char) -> *mut LibrespotSession {
+    let cache_dir = match opt_str(cache_dir) {
+        Ok(cache_dir) => cache_dir.map(PathBuf::from),
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    let cache = match cache_dir {
+        Some(dir) => match Cache::new(Some(&dir), Some(&dir), Some(&dir.join("files")), None) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                set_last_error(format!("Unable to open cache: {e}"));
+                return std::ptr::null_mut();
+            }
+        },
+        None => None,
+    };
+
+    let runtime = match Runtime::new() {
+        Ok(runtime) => Arc::new(runtime),
+        Err(e) => {
+            set_last_error(format!("Unable to start runtime: {e}"));
+            return std::ptr::null_mut();
+        }
+    };
+
+    let session = {
+        let _guard = runtime.enter();
+        Session::new(SessionConfig::default(), cache)
+    };
+
+    Box::into_raw(Box::new(LibrespotSession { runtime, session }))
+}
+
+/// Logs in with an OAuth access token. Blocks until the session is connected.
+/// Not needed for Spotify Connect, which logs the session in itself.
+///
+/// # Safety
+/// `session` must come from [`librespot_session_new`], and `access_token`
+/// must be a valid, nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_session_connect(
+    session: *mut LibrespotSession,
+    access_token: *const c_char,
+) -> LibrespotResult {
+    let session = ffi_try!(object(session, "session"));
+    let credentials =
+        Credentials::with_access_token(ffi_try!(req_str(access_token, "access_token")));
+
+    result(
+        session
+            .runtime
+            .block_on(session.session.connect(credentials, true)),
+    )
+}
+
+/// Logs in with the credentials that a previous session stored in the cache.
+/// Blocks until the session is connected.
+///
+/// # Safety
+/// `session` must come from [`librespot_session_new`].
+#[no_mangle]
+pub unsafe extern "C" fn librespot_session_connect_cached(
+    session: *mut LibrespotSession,
+) -> LibrespotResult {
+    let session = ffi_try!(object(session, "session"));
+    let credentials = match session
+        .session
+        .cache()
+        .and_then(|cache| cache.credentials())
+    {
+        Some(credentials) => credentials,
+        None => {
+            set_last_error("No cached credentials");
+            return LibrespotResult::Failed;
+        }
+    };
+
+    result(
+        session
+            .runtime
+            .block_on(session.session.connect(credentials, true)),
+    )
+}
+
+/// Disconnects and frees the session. Players and Spirc instances of it keep
+/// the runtime alive until they are freed.
+///
+/// # Safety
+/// `session` must be null or come from [`librespot_session_new`], and must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_session_free(session: *mut LibrespotSession) {
+    if !session.is_null() {
+        let session = Box::from_raw(session);
+        session.session.shutdown();
+    }
+}
@@ -0,0 +1,151 @@
+use std::{ffi::c_char, sync::Arc};
+
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use librespot_connect::{config::ConnectConfig, spirc::Spirc};
+use librespot_core::authentication::Credentials;
+
+use crate::{
+    object, opt_str, result, set_last_error, LibrespotPlayer, LibrespotResult, LibrespotSession,
+};
+
+/// Makes the player controllable with Spotify Connect.
+pub struct LibrespotSpirc {
+    runtime: Arc<Runtime>,
+    spirc: Spirc,
+    task: JoinHandle<()>,
+}
+
+/// Logs the session in and creates a Spotify Connect device named `name` that
+/// plays on `player`. Without an `access_token`, the credentials that a
+/// previous session stored in the cache are used. Blocks until the device is
+/// connected, and returns null on failure.
+///
+/// Spotify Connect logs the session in itself, so it must not have been
+/// connected with `librespot_session_connect` before.
+///
+/// # Safety
+/// `session` and `player` must come from `librespot_session_new` and
+/// `librespot_player_new`. `name` and `access_token` must be null or valid,
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_spirc_new(
+    session: *mut LibrespotSession,
+    player: *mut LibrespotPlayer,
+    name: *const c_char,
+    access_token: *const c_char,
+) -> *mut LibrespotSpirc {
+    let (session, player) = match (object(session, "session"), object(player, "player")) {
+        (Ok(session), Ok(player)) => (session, player),
+        _ => return std::ptr::null_mut(),
+    };
+    let (name, access_token) = match (opt_str(name), opt_str(access_token)) {
+        (Ok(name), Ok(access_token)) => (name, access_token),
+        _ => return std::ptr::null_mut(),
+    };
+
+    if !session.session.username().is_empty() {
+        set_last_error("`session` must not be connected, Spotify Connect connects it");
+        return std::ptr::null_mut();
+    }
+
+    let credentials = match access_token {
+        Some(access_token) => Credentials::with_access_token(access_token),
+        None => match session
+            .session
+            .cache()
+            .and_then(|cache| cache.credentials())
+        {
+            Some(credentials) => credentials,
+            None => {
+                set_last_error("No access token and no cached credentials");
+                return std::ptr::null_mut();
+            }
+        },
+    };
+
+    let mut config = ConnectConfig::default();
+    if let Some(name) = name {
+        config.name = name;
+    }
+
+    let spirc = session.runtime.block_on(Spirc::new(
+        config,
+        session.session.clone(),
+        credentials,
+        player.player.clone(),
+        player.mixer.clone(),
+    ));
+
+    match spirc {
+        Ok((spirc, task)) => Box::into_raw(Box::new(LibrespotSpirc {
+            runtime: session.runtime.clone(),
+            spirc,
+            task: session.runtime.spawn(task),
+        })),
+        Err(e) => {
+            set_last_error(format!("Unable to start Spotify Connect: {e}"));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+macro_rules! spirc_command {
+    ($(#[$meta:meta])* $name:ident => $command:ident($($arg:ident: $ty:ty),*)) => {
+        $(#[$meta])*
+        ///
+        /// # Safety
+        /// `spirc` must come from [`librespot_spirc_new`].
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(
+            spirc: *mut LibrespotSpirc
+            $(, $arg: $ty)*
+        ) -> LibrespotResult {
+            result(ffi_try!(object(spirc, "spirc")).spirc.$command($($arg),*))
+        }
+    };
+}
+
+spirc_command!(librespot_spirc_play => play());
+spirc_command!(librespot_spirc_pause => pause());
+spirc_command!(librespot_spirc_play_pause => play_pause());
+spirc_command!(librespot_spirc_next => next());
+spirc_command!(librespot_spirc_prev => prev());
+spirc_command!(librespot_spirc_volume_up => volume_up());
+spirc_command!(librespot_spirc_volume_down => volume_down());
+spirc_command!(
+    /// Sets the volume, 0 to 65535.
+    librespot_spirc_set_volume => set_volume(volume: u16)
+);
+spirc_command!(librespot_spirc_set_position_ms => set_position_ms(position_ms: u32));
+spirc_command!(librespot_spirc_shuffle => shuffle(shuffle: bool));
+spirc_command!(librespot_spirc_repeat => repeat(repeat: bool));
+spirc_command!(
+    /// Makes this the active device of the user.
+    librespot_spirc_activate => activate()
+);
+spirc_command!(
+    /// Stops playback and makes this device inactive.
+    librespot_spirc_disconnect => disconnect()
+);
+
+/// Shuts the device down and frees it. Blocks until it is shut down.
+///
+/// # Safety
+/// `spirc` must be null or come from [`librespot_spirc_new`], and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn librespot_spirc_free(spirc: *mut LibrespotSpirc) {
+    if !spirc.is_null() {
+        let LibrespotSpirc {
+            runtime,
+            spirc,
+            task,
+        } = *Box::from_raw(spirc);
+        if let Err(e) = spirc.shutdown() {
+            warn!("Unable to shut Spotify Connect down: {e}");
+            task.abort();
+        }
+        let _ = runtime.block_on(task);
+    }
+}
@@ -154,11 +154,16 @@ impl AudioItem {
 
                 let covers = get_covers(episode.covers, image_url);
 
-                let availability = available_for_user(
-                    &session.user_data(),
-                    &episode.availability,
-                    &episode.restrictions,
-                );
+                let availability = if episode.is_audiobook_chapter && episode.audio.is_empty() {
+                    // Spotify leaves out the files of chapters the user has no access to.
+                    Err(UnavailabilityReason::AudiobookNotOwned)
+                } else {
+                    available_for_user(
+                        &session.user_data(),
+                        &episode.availability,
+                        &episode.restrictions,
+                    )
+                };
 
                 let unique_fields = UniqueFields::Episode {
                     description: episode.description,
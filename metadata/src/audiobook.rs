@@ -0,0 +1,74 @@
+use std::fmt::Debug;
+
+use crate::{
+    availability::Availabilities, copyright::Copyrights, episode::Episodes, error::MetadataError,
+    image::Images, restriction::Restrictions, show::ShowConsumptionOrder, Metadata, RequestResult,
+};
+
+use librespot_core::{spotify_id::SpotifyItemType, Error, Session, SpotifyId};
+
+use librespot_protocol as protocol;
+
+/// Audiobooks are shows with their chapters as episodes, so their ID can be
+/// either a `spotify:audiobook:` or a `spotify:show:` one. Chapters can only be
+/// played after buying the audiobook or with a plan that includes it.
+#[derive(Debug, Clone)]
+pub struct Audiobook {
+    pub id: SpotifyId,
+    pub name: String,
+    pub description: String,
+    pub publisher: String,
+    pub language: String,
+    pub is_explicit: bool,
+    pub covers: Images,
+    pub chapters: Episodes,
+    pub copyrights: Copyrights,
+    pub restrictions: Restrictions,
+    pub keywords: Vec<String>,
+    pub consumption_order: ShowConsumptionOrder,
+    pub availability: Availabilities,
+}
+
+#[async_trait]
+impl Metadata for Audiobook {
+    type Message = protocol::metadata::Show;
+
+    async fn request(session: &Session, audiobook_id: &SpotifyId) -> RequestResult {
+        session
+            .spclient()
+            .get_audiobook_metadata(audiobook_id)
+            .await
+    }
+
+    fn parse(msg: &Self::Message, _: &SpotifyId) -> Result<Self, Error> {
+        Self::try_from(msg)
+    }
+}
+
+impl TryFrom<&<Self as Metadata>::Message> for Audiobook {
+    type Error = librespot_core::Error;
+    fn try_from(audiobook: &<Self as Metadata>::Message) -> Result<Self, Self::Error> {
+        if !audiobook.is_audiobook() {
+            return Err(Error::invalid_argument(MetadataError::NotAudiobook));
+        }
+
+        let mut id: SpotifyId = audiobook.try_into()?;
+        id.item_type = SpotifyItemType::Audiobook;
+
+        Ok(Self {
+            id,
+            name: audiobook.name().to_owned(),
+            description: audiobook.description().to_owned(),
+            publisher: audiobook.publisher().to_owned(),
+            language: audiobook.language().to_owned(),
+            is_explicit: audiobook.explicit(),
+            covers: audiobook.cover_image.image.as_slice().into(),
+            chapters: audiobook.episode.as_slice().try_into()?,
+            copyrights: audiobook.copyright.as_slice().into(),
+            restrictions: audiobook.restriction.as_slice().into(),
+            keywords: audiobook.keyword.to_vec(),
+            consumption_order: audiobook.consumption_order(),
+            availability: audiobook.availability.as_slice().try_into()?,
+        })
+    }
+}
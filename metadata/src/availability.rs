@@ -35,6 +35,8 @@ pub enum UnavailabilityReason {
     NoData,
     #[error("whitelist present and country not on it")]
     NotWhitelisted,
+    #[error("audiobook chapters can only be played after buying the audiobook or with a plan that includes it")]
+    AudiobookNotOwned,
 }
 
 impl TryFrom<&AvailabilityMessage> for Availability {
@@ -0,0 +1,94 @@
+use protobuf::Message;
+
+use librespot_core::{Error, Session, SpotifyId};
+
+use librespot_protocol as protocol;
+use protocol::canvaz::entity_canvaz_request::Entity as CanvasEntityMessage;
+use protocol::canvaz::entity_canvaz_response::Canvaz as CanvasMessage;
+use protocol::canvaz::Artist as CanvasArtistMessage;
+use protocol::canvaz::EntityCanvazRequest as CanvasRequestMessage;
+use protocol::canvaz::EntityCanvazResponse as CanvasResponseMessage;
+pub use protocol::canvaz_meta::Type as CanvasType;
+
+#[derive(Debug, Clone)]
+pub struct CanvasArtist {
+    pub uri: String,
+    pub name: String,
+    pub avatar_url: String,
+}
+
+/// The short video loop or image that Spotify apps show instead of the cover
+/// of a track. Most tracks have none.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    pub id: String,
+    pub url: String,
+    pub file_id: String,
+    pub canvas_type: CanvasType,
+    pub entity_uri: String,
+    pub artist: CanvasArtist,
+    pub is_explicit: bool,
+    pub uploaded_by: String,
+    pub canvas_uri: String,
+}
+
+impl Canvas {
+    pub async fn get(session: &Session, track_id: &SpotifyId) -> Result<Option<Self>, Error> {
+        let response = session.spclient().get_canvas(track_id).await?;
+        let mut canvases = Self::parse(&response)?;
+        Ok(canvases.pop())
+    }
+
+    /// The canvases of several tracks. Tracks without one are left out.
+    pub async fn get_many(session: &Session, track_ids: &[SpotifyId]) -> Result<Vec<Self>, Error> {
+        if track_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut request = CanvasRequestMessage::new();
+        for track_id in track_ids {
+            let mut entity = CanvasEntityMessage::new();
+            entity.entity_uri = track_id.to_uri()?;
+            request.entities.push(entity);
+        }
+
+        let response = session.spclient().get_canvases(request).await?;
+        Self::parse(&response)
+    }
+
+    fn parse(response: &[u8]) -> Result<Vec<Self>, Error> {
+        let msg = CanvasResponseMessage::parse_from_bytes(response)?;
+        trace!("Received canvases: {:#?}", msg);
+        Ok(msg.canvases.iter().map(Self::from).collect())
+    }
+
+    pub fn is_video(&self) -> bool {
+        !matches!(self.canvas_type, CanvasType::IMAGE | CanvasType::GIF)
+    }
+}
+
+impl From<&CanvasMessage> for Canvas {
+    fn from(canvas: &CanvasMessage) -> Self {
+        Self {
+            id: canvas.id.clone(),
+            url: canvas.url.clone(),
+            file_id: canvas.file_id.clone(),
+            canvas_type: canvas.type_.enum_value_or_default(),
+            entity_uri: canvas.entity_uri.clone(),
+            artist: canvas.artist.get_or_default().into(),
+            is_explicit: canvas.explicit,
+            uploaded_by: canvas.uploaded_by.clone(),
+            canvas_uri: canvas.canvas_uri.clone(),
+        }
+    }
+}
+
+impl From<&CanvasArtistMessage> for CanvasArtist {
+    fn from(artist: &CanvasArtistMessage) -> Self {
+        Self {
+            uri: artist.uri.clone(),
+            name: artist.name.clone(),
+            avatar_url: artist.avatar.clone(),
+        }
+    }
+}
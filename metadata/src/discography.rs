@@ -0,0 +1,189 @@
+use librespot_core::{Error, Session, SpotifyId};
+
+use crate::{album::Album, artist::Artist, track::Track, Metadata};
+
+// The albums of a page are requested at once, see `Metadata::get_many`.
+pub const DISCOGRAPHY_MAX_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReleaseGroup {
+    Albums,
+    Singles,
+    Compilations,
+    AppearsOn,
+}
+
+impl ReleaseGroup {
+    pub const ALL: [Self; 4] = [
+        Self::Albums,
+        Self::Singles,
+        Self::Compilations,
+        Self::AppearsOn,
+    ];
+}
+
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub group: ReleaseGroup,
+    pub album: Album,
+}
+
+impl Release {
+    /// The URI to play the release as a context.
+    pub fn context_uri(&self) -> Result<String, Error> {
+        self.album.id.to_uri()
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = &SpotifyId> {
+        self.album.tracks()
+    }
+}
+
+/// One page of the releases of an artist, in the order of `groups` and only
+/// the current variant of each. Releases that are not available in `market`
+/// are left out, so a page can hold fewer than `limit` of them.
+#[derive(Debug, Clone)]
+pub struct Discography {
+    pub artist_id: SpotifyId,
+    pub groups: Vec<ReleaseGroup>,
+    pub market: String,
+    pub offset: usize,
+    pub limit: usize,
+    // Releases in `groups`, in any market.
+    pub total: usize,
+    pub releases: Vec<Release>,
+}
+
+impl Discography {
+    /// Lists the releases of an artist. Without a `market`, the country of the
+    /// user is used. `limit` is capped at [`DISCOGRAPHY_MAX_LIMIT`].
+    pub async fn get(
+        session: &Session,
+        artist_id: &SpotifyId,
+        groups: &[ReleaseGroup],
+        market: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Self, Error> {
+        let artist = Artist::get(session, artist_id).await?;
+        Self::from_artist(session, &artist, groups, market, offset, limit).await
+    }
+
+    /// Like [`Discography::get`], for an artist that was requested already.
+    pub async fn from_artist(
+        session: &Session,
+        artist: &Artist,
+        groups: &[ReleaseGroup],
+        market: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Self, Error> {
+        let limit = limit.clamp(1, DISCOGRAPHY_MAX_LIMIT);
+        let market = market
+            .map(str::to_owned)
+            .unwrap_or_else(|| session.user_data().country);
+
+        let ids: Vec<(ReleaseGroup, SpotifyId)> = groups
+            .iter()
+            .flat_map(|&group| {
+                let albums = match group {
+                    ReleaseGroup::Albums => &artist.albums,
+                    ReleaseGroup::Singles => &artist.singles,
+                    ReleaseGroup::Compilations => &artist.compilations,
+                    ReleaseGroup::AppearsOn => &artist.appears_on_albums,
+                };
+                albums.current_releases().map(move |&id| (group, id))
+            })
+            .collect();
+
+        let page: Vec<(ReleaseGroup, SpotifyId)> =
+            ids.iter().skip(offset).take(limit).copied().collect();
+        let page_ids: Vec<SpotifyId> = page.iter().map(|&(_, id)| id).collect();
+        let albums = Album::get_many(session, &page_ids).await?;
+
+        let mut releases = Vec::with_capacity(limit);
+        for ((group, id), album) in page.into_iter().zip(albums) {
+            if album.restrictions.allows_country(&market) {
+                releases.push(Release { group, album });
+            } else {
+                debug!(
+                    "Leaving out <{}>, which is not available in {}",
+                    id.to_uri().unwrap_or_default(),
+                    market
+                );
+            }
+        }
+
+        Ok(Self {
+            artist_id: artist.id,
+            groups: groups.to_vec(),
+            market,
+            offset,
+            limit,
+            total: ids.len(),
+            releases,
+        })
+    }
+
+    pub fn has_next_page(&self) -> bool {
+        self.total > self.offset + self.limit
+    }
+
+    pub async fn next_page(&self, session: &Session) -> Result<Option<Self>, Error> {
+        if !self.has_next_page() {
+            return Ok(None);
+        }
+
+        Self::get(
+            session,
+            &self.artist_id,
+            &self.groups,
+            Some(&self.market),
+            self.offset + self.limit,
+            self.limit,
+        )
+        .await
+        .map(Some)
+    }
+}
+
+/// The most popular tracks of an artist in a market. They play as the context
+/// of the artist.
+#[derive(Debug, Clone)]
+pub struct ArtistTopTracks {
+    pub artist_id: SpotifyId,
+    pub market: String,
+    pub tracks: Vec<Track>,
+}
+
+impl ArtistTopTracks {
+    /// Without a `market`, the country of the user is used. Tracks that are not
+    /// available there are left out.
+    pub async fn get(
+        session: &Session,
+        artist_id: &SpotifyId,
+        market: Option<&str>,
+    ) -> Result<Self, Error> {
+        let artist = Artist::get(session, artist_id).await?;
+        let market = market
+            .map(str::to_owned)
+            .unwrap_or_else(|| session.user_data().country);
+
+        let mut tracks = Track::get_many(session, &artist.top_tracks.for_country(&market)).await?;
+        tracks.retain(|track| track.restrictions.allows_country(&market));
+
+        Ok(Self {
+            artist_id: artist.id,
+            market,
+            tracks,
+        })
+    }
+
+    pub fn context_uri(&self) -> Result<String, Error> {
+        self.artist_id.to_uri()
+    }
+
+    pub fn track_ids(&self) -> impl Iterator<Item = &SpotifyId> {
+        self.tracks.iter().map(|track| &track.id)
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
     fmt::Debug,
     ops::{Deref, DerefMut},
+    time::Duration,
 };
 
+use protobuf::Message;
+
 use crate::{
     audio::file::AudioFiles,
     availability::Availabilities,
@@ -10,7 +13,8 @@ use crate::{
     image::Images,
     request::RequestResult,
     restriction::Restrictions,
-    util::{impl_deref_wrapped, impl_try_from_repeated},
+    resume_point::ResumePoint,
+    util::{impl_deref_wrapped, impl_from_repeated, impl_try_from_repeated},
     video::VideoFiles,
     Metadata,
 };
@@ -18,7 +22,14 @@ use crate::{
 use librespot_core::{date::Date, Error, Session, SpotifyId};
 
 use librespot_protocol as protocol;
+use protocol::extended_metadata::{
+    BatchedEntityRequest, BatchedExtensionResponse, EntityRequest, ExtensionQuery,
+};
+use protocol::extension_kind::ExtensionKind;
 pub use protocol::metadata::episode::EpisodeType;
+use protocol::podcast_segments::PlaybackSegment as ChapterMessage;
+use protocol::podcast_segments::PodcastSegments as ChaptersMessage;
+pub use protocol::podcast_segments::SegmentType as ChapterType;
 
 #[derive(Debug, Clone)]
 pub struct Episode {
@@ -53,6 +64,72 @@ pub struct Episodes(pub Vec<SpotifyId>);
 
 impl_deref_wrapped!(Episodes, Vec<SpotifyId>);
 
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub title: String,
+    pub subtitle: String,
+    pub chapter_type: ChapterType,
+    pub start: Duration,
+    pub end: Duration,
+    pub image_url: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chapters(pub Vec<Chapter>);
+
+impl_deref_wrapped!(Chapters, Vec<Chapter>);
+
+impl Chapters {
+    /// The chapter playing at `position`, if any.
+    pub fn at(&self, position: Duration) -> Option<&Chapter> {
+        self.iter()
+            .find(|chapter| chapter.start <= position && position < chapter.end)
+    }
+}
+
+impl Episode {
+    /// Chapters are not part of the episode metadata but one of its
+    /// extensions. Most episodes have none.
+    pub async fn chapters(&self, session: &Session) -> Result<Chapters, Error> {
+        let uri = self.id.to_uri()?;
+
+        let mut query = ExtensionQuery::new();
+        query.extension_kind = ExtensionKind::PODCAST_SEGMENTS.into();
+
+        let mut entity = EntityRequest::new();
+        entity.entity_uri = uri.clone();
+        entity.query.push(query);
+
+        let mut request = BatchedEntityRequest::new();
+        request.entity_request.push(entity);
+
+        let response = session.spclient().get_extended_metadata(request).await?;
+        let response = BatchedExtensionResponse::parse_from_bytes(&response)?;
+
+        let data = response
+            .extended_metadata
+            .iter()
+            .filter(|array| {
+                array.extension_kind.enum_value() == Ok(ExtensionKind::PODCAST_SEGMENTS)
+            })
+            .flat_map(|array| array.extension_data.iter())
+            .find(|data| data.entity_uri == uri);
+
+        match data {
+            Some(data) => {
+                let chapters = ChaptersMessage::parse_from_bytes(&data.extension_data.value)?;
+                Ok(chapters.playback_segments.as_slice().into())
+            }
+            None => Ok(Chapters::default()),
+        }
+    }
+
+    /// Where the user left off listening to this episode, on any device.
+    pub async fn resume_point(&self, session: &Session) -> Result<Option<ResumePoint>, Error> {
+        ResumePoint::get(session, &self.id).await
+    }
+}
+
 #[async_trait]
 impl Metadata for Episode {
     type Message = protocol::metadata::Episode;
@@ -99,3 +176,18 @@ impl TryFrom<&<Self as Metadata>::Message> for Episode {
 }
 
 impl_try_from_repeated!(<Episode as Metadata>::Message, Episodes);
+
+impl From<&ChapterMessage> for Chapter {
+    fn from(chapter: &ChapterMessage) -> Self {
+        Self {
+            title: chapter.title.clone(),
+            subtitle: chapter.subtitle.clone(),
+            chapter_type: chapter.type_.enum_value_or_default(),
+            start: Duration::from_millis(chapter.start_ms.max(0) as u64),
+            end: Duration::from_millis(chapter.stop_ms.max(0) as u64),
+            image_url: chapter.image_url.clone(),
+        }
+    }
+}
+
+impl_from_repeated!(ChapterMessage, Chapters);
@@ -11,4 +11,6 @@ pub enum MetadataError {
     InvalidDuration(i32),
     #[error("track is marked as explicit, which client setting forbids")]
     ExplicitContentFiltered,
+    #[error("show is not an audiobook")]
+    NotAudiobook,
 }
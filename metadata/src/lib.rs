@@ -11,18 +11,24 @@ use librespot_core::{Error, Session, SpotifyId};
 pub mod album;
 pub mod artist;
 pub mod audio;
+pub mod audiobook;
 pub mod availability;
+pub mod canvas;
 pub mod content_rating;
 pub mod copyright;
+pub mod discography;
 pub mod episode;
 pub mod error;
 pub mod external_id;
 pub mod image;
+pub mod library;
 pub mod lyrics;
 pub mod playlist;
 mod request;
 pub mod restriction;
+pub mod resume_point;
 pub mod sale_period;
+pub mod search;
 pub mod show;
 pub mod track;
 mod util;
@@ -33,9 +39,15 @@ use request::RequestResult;
 
 pub use album::Album;
 pub use artist::Artist;
+pub use audiobook::Audiobook;
+pub use canvas::Canvas;
+pub use discography::{ArtistTopTracks, Discography};
 pub use episode::Episode;
+pub use library::Library;
 pub use lyrics::Lyrics;
 pub use playlist::Playlist;
+pub use resume_point::ResumePoint;
+pub use search::SearchResults;
 pub use show::Show;
 pub use track::Track;
 
@@ -54,5 +66,22 @@ pub trait Metadata: Send + Sized + 'static {
         Self::parse(&msg, id)
     }
 
+    // Request metadata structs for many IDs, in batches. Without batches,
+    // they are requested one by one.
+    async fn get_many(session: &Session, ids: &[SpotifyId]) -> Result<Vec<Self>, Error> {
+        if let Err(e) = session.spclient().prefetch_metadata(ids).await {
+            warn!(
+                "Unable to prefetch metadata, requesting it one by one: {}",
+                e
+            );
+        }
+
+        let mut items = Vec::with_capacity(ids.len());
+        for id in ids {
+            items.push(Self::get(session, id).await?);
+        }
+        Ok(items)
+    }
+
     fn parse(msg: &Self::Message, _: &SpotifyId) -> Result<Self, Error>;
 }
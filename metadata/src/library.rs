@@ -0,0 +1,240 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::Stream;
+use protobuf::Message;
+
+use librespot_core::{
+    date::Date,
+    dealer::{protocol::Message as DealerMessage, Subscription},
+    spotify_id::SpotifyItemType,
+    Error, Session, SpotifyId,
+};
+
+use librespot_protocol as protocol;
+use protocol::collection2v2::CollectionItem as LibraryItemMessage;
+use protocol::collection2v2::PageResponse as LibraryPageMessage;
+use protocol::collection2v2::PubSubUpdate as LibraryUpdateMessage;
+use protocol::collection2v2::WriteRequest as LibraryWriteMessage;
+
+const PAGE_LIMIT: usize = 300;
+
+/// The parts of the library of a user, as Spotify stores them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LibrarySet {
+    // Saved tracks and albums.
+    Collection,
+    // Followed artists.
+    Artists,
+    // Followed shows.
+    Shows,
+}
+
+impl LibrarySet {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Collection => "collection",
+            Self::Artists => "artist",
+            Self::Shows => "show",
+        }
+    }
+
+    fn from_name(set: &str) -> Option<Self> {
+        match set {
+            "collection" => Some(Self::Collection),
+            "artist" => Some(Self::Artists),
+            "show" => Some(Self::Shows),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LibraryItem {
+    pub id: SpotifyId,
+    pub added_at: Date,
+    pub is_removed: bool,
+}
+
+impl TryFrom<&LibraryItemMessage> for LibraryItem {
+    type Error = Error;
+    fn try_from(item: &LibraryItemMessage) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: SpotifyId::from_uri(&item.uri)?,
+            added_at: Date::from_timestamp_ms(item.added_at as i64 * 1000)?,
+            is_removed: item.is_removed,
+        })
+    }
+}
+
+/// Items that were added to or removed from a library set, possibly by
+/// another device.
+#[derive(Debug, Clone)]
+pub struct LibraryUpdate {
+    pub set: LibrarySet,
+    pub items: Vec<LibraryItem>,
+}
+
+/// Reads and changes the library of the user of a session.
+pub struct Library;
+
+impl Library {
+    /// All items of `set`, most recently added first.
+    pub async fn items(session: &Session, set: LibrarySet) -> Result<Vec<LibraryItem>, Error> {
+        let mut items = Vec::new();
+        let mut pagination_token = String::new();
+
+        loop {
+            let response = session
+                .spclient()
+                .get_collection_page(set.as_str(), &pagination_token, PAGE_LIMIT)
+                .await?;
+            let page = LibraryPageMessage::parse_from_bytes(&response)?;
+
+            for item in &page.items {
+                match LibraryItem::try_from(item) {
+                    Ok(item) => items.push(item),
+                    // e.g. local files
+                    Err(e) => debug!("Skipping library item <{}>: {}", item.uri, e),
+                }
+            }
+
+            if page.next_page_token.is_empty() {
+                break;
+            }
+            pagination_token = page.next_page_token;
+        }
+
+        Ok(items)
+    }
+
+    pub async fn saved_tracks(session: &Session) -> Result<Vec<LibraryItem>, Error> {
+        Self::items_of_type(session, LibrarySet::Collection, SpotifyItemType::Track).await
+    }
+
+    pub async fn saved_albums(session: &Session) -> Result<Vec<LibraryItem>, Error> {
+        Self::items_of_type(session, LibrarySet::Collection, SpotifyItemType::Album).await
+    }
+
+    pub async fn followed_artists(session: &Session) -> Result<Vec<LibraryItem>, Error> {
+        Self::items(session, LibrarySet::Artists).await
+    }
+
+    /// Saves tracks and albums, e.g. to like the current track.
+    pub async fn save(session: &Session, ids: &[SpotifyId]) -> Result<(), Error> {
+        Self::write(session, LibrarySet::Collection, ids, false).await
+    }
+
+    pub async fn unsave(session: &Session, ids: &[SpotifyId]) -> Result<(), Error> {
+        Self::write(session, LibrarySet::Collection, ids, true).await
+    }
+
+    pub async fn follow(session: &Session, artist_ids: &[SpotifyId]) -> Result<(), Error> {
+        Self::write(session, LibrarySet::Artists, artist_ids, false).await
+    }
+
+    pub async fn unfollow(session: &Session, artist_ids: &[SpotifyId]) -> Result<(), Error> {
+        Self::write(session, LibrarySet::Artists, artist_ids, true).await
+    }
+
+    /// Notifies about changes to the library, including those made by other
+    /// devices. Requires the dealer of the session to be started.
+    pub fn subscribe(session: &Session) -> Result<LibraryUpdates, Error> {
+        let subscription = session.dealer().subscribe(&["hm://collection/"])?;
+        Ok(LibraryUpdates {
+            subscription,
+            pending: VecDeque::new(),
+        })
+    }
+
+    async fn items_of_type(
+        session: &Session,
+        set: LibrarySet,
+        item_type: SpotifyItemType,
+    ) -> Result<Vec<LibraryItem>, Error> {
+        let mut items = Self::items(session, set).await?;
+        items.retain(|item| item.id.item_type == item_type);
+        Ok(items)
+    }
+
+    async fn write(
+        session: &Session,
+        set: LibrarySet,
+        ids: &[SpotifyId],
+        is_removed: bool,
+    ) -> Result<(), Error> {
+        let added_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i32)
+            .unwrap_or_default();
+
+        let mut request = LibraryWriteMessage::new();
+        request.username = session.username();
+        request.set = set.as_str().to_owned();
+        for id in ids {
+            let mut item = LibraryItemMessage::new();
+            item.uri = id.to_uri()?;
+            item.added_at = added_at;
+            item.is_removed = is_removed;
+            request.items.push(item);
+        }
+
+        session.spclient().post_collection_write(&request).await?;
+        Ok(())
+    }
+}
+
+/// A stream of [`LibraryUpdate`]s, see [`Library::subscribe`]. Pushes that
+/// cannot be parsed are skipped.
+pub struct LibraryUpdates {
+    subscription: Subscription,
+    // A push may carry several updates.
+    pending: VecDeque<LibraryUpdate>,
+}
+
+impl LibraryUpdates {
+    fn parse(message: &DealerMessage) -> Result<Vec<LibraryUpdate>, Error> {
+        let mut updates = Vec::new();
+
+        for payload in message.payload_bytes()? {
+            let update = LibraryUpdateMessage::parse_from_bytes(&payload)?;
+            if let Some(set) = LibrarySet::from_name(&update.set) {
+                let items = update
+                    .items
+                    .iter()
+                    .filter_map(|item| LibraryItem::try_from(item).ok())
+                    .collect();
+                updates.push(LibraryUpdate { set, items });
+            }
+        }
+
+        Ok(updates)
+    }
+}
+
+impl Stream for LibraryUpdates {
+    type Item = LibraryUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(update) = self.pending.pop_front() {
+                return Poll::Ready(Some(update));
+            }
+
+            let message = match Pin::new(&mut self.subscription).poll_next(cx) {
+                Poll::Ready(Some(message)) => message,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match Self::parse(&message) {
+                Ok(updates) => self.pending.extend(updates),
+                Err(e) => warn!("Unable to parse library update <{}>: {}", message.uri, e),
+            }
+        }
+    }
+}
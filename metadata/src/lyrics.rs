@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 
 use librespot_core::{Error, FileId, Session, SpotifyId};
@@ -18,6 +20,20 @@ impl Lyrics {
         let lyrics = spclient.get_lyrics_for_image(id, image_id).await?;
         Self::try_from(&lyrics)
     }
+
+    /// The line being sung at `position` of the track, for highlighting it.
+    /// Always `None` for unsynced lyrics.
+    pub fn line_at(&self, position: Duration) -> Option<&Line> {
+        if self.lyrics.sync_type == SyncType::Unsynced {
+            return None;
+        }
+
+        self.lyrics
+            .lines
+            .iter()
+            .take_while(|line| line.start_time().is_some_and(|start| start <= position))
+            .last()
+    }
 }
 
 impl TryFrom<&Bytes> for Lyrics {
@@ -44,6 +60,13 @@ pub struct Colors {
     pub text: i32,
 }
 
+impl Colors {
+    /// Formats one of the colors, which are signed ARGB values, as `#rrggbb`.
+    pub fn to_hex(color: i32) -> String {
+        format!("#{:06x}", color as u32 & 0x00ff_ffff)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LyricsInner {
@@ -64,6 +87,9 @@ pub struct LyricsInner {
 pub enum SyncType {
     Unsynced,
     LineSynced,
+    SyllableSynced,
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
@@ -74,3 +100,102 @@ pub struct Line {
     pub words: String,
     // TODO: 'syllables' array
 }
+
+impl Line {
+    pub fn start_time(&self) -> Option<Duration> {
+        self.start_time_ms.parse().ok().map(Duration::from_millis)
+    }
+
+    // Often zero, the end of a line being the start of the next one.
+    pub fn end_time(&self) -> Option<Duration> {
+        self.end_time_ms
+            .parse()
+            .ok()
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(sync_type: &str) -> Lyrics {
+        let json = format!(
+            r#"{{
+                "colors": {{ "background": -9211021, "highlightText": -1, "text": -16777216 }},
+                "hasVocalRemoval": false,
+                "lyrics": {{
+                    "isDenseTypeface": false,
+                    "isRtlLanguage": false,
+                    "language": "en",
+                    "lines": [
+                        {{ "startTimeMs": "1000", "endTimeMs": "0", "words": "First", "syllables": [] }},
+                        {{ "startTimeMs": "4500", "endTimeMs": "6000", "words": "Second", "syllables": [] }},
+                        {{ "startTimeMs": "8000", "endTimeMs": "0", "words": "Third", "syllables": [] }}
+                    ],
+                    "provider": "MusixMatch",
+                    "providerDisplayName": "Musixmatch",
+                    "providerLyricsId": "123",
+                    "syncLyricsUri": "",
+                    "syncType": "{sync_type}"
+                }}
+            }}"#
+        );
+        Lyrics::try_from(&Bytes::from(json)).unwrap()
+    }
+
+    #[test]
+    fn test_line_times() {
+        let lyrics = parse("LINE_SYNCED");
+        let lines = &lyrics.lyrics.lines;
+
+        assert_eq!(lines[0].start_time(), Some(Duration::from_millis(1000)));
+        assert_eq!(lines[0].end_time(), None);
+        assert_eq!(lines[1].end_time(), Some(Duration::from_millis(6000)));
+
+        let line = Line {
+            start_time_ms: "soon".to_owned(),
+            end_time_ms: String::new(),
+            words: String::new(),
+        };
+        assert_eq!(line.start_time(), None);
+        assert_eq!(line.end_time(), None);
+    }
+
+    #[test]
+    fn test_line_at() {
+        let lyrics = parse("LINE_SYNCED");
+        let words_at = |ms| {
+            lyrics
+                .line_at(Duration::from_millis(ms))
+                .map(|line| line.words.as_str())
+        };
+
+        assert_eq!(words_at(0), None);
+        assert_eq!(words_at(1000), Some("First"));
+        assert_eq!(words_at(4499), Some("First"));
+        assert_eq!(words_at(4500), Some("Second"));
+        assert_eq!(words_at(60_000), Some("Third"));
+
+        assert_eq!(parse("UNSYNCED").line_at(Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn test_sync_type() {
+        assert_eq!(
+            parse("SYLLABLE_SYNCED").lyrics.sync_type,
+            SyncType::SyllableSynced
+        );
+        assert_eq!(parse("WORD_SYNCED").lyrics.sync_type, SyncType::Unknown);
+    }
+
+    #[test]
+    fn test_colors_to_hex() {
+        let colors = parse("LINE_SYNCED").colors;
+
+        assert_eq!(Colors::to_hex(colors.background), "#737373");
+        assert_eq!(Colors::to_hex(colors.highlight_text), "#ffffff");
+        assert_eq!(Colors::to_hex(colors.text), "#000000");
+    }
+}
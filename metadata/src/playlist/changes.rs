@@ -0,0 +1,130 @@
+use protobuf::Message;
+
+use crate::playlist::{list::SelectedListContent, Playlist};
+
+use librespot_core::{Error, Session, SpotifyId};
+
+use librespot_protocol as protocol;
+use protocol::playlist4_external::op::Kind as PlaylistOperationKind;
+use protocol::playlist4_external::Add as PlaylistAddMessage;
+use protocol::playlist4_external::Delta as PlaylistDeltaMessage;
+use protocol::playlist4_external::Item as PlaylistItemMessage;
+use protocol::playlist4_external::ListChanges as PlaylistChangesMessage;
+use protocol::playlist4_external::Mov as PlaylistMoveMessage;
+use protocol::playlist4_external::Op as PlaylistOperationMessage;
+use protocol::playlist4_external::Rem as PlaylistRemoveMessage;
+use protocol::playlist4_external::SelectedListContent as SelectedListContentMessage;
+
+/// Changes to a playlist, built on top of the revision it was fetched at and
+/// submitted at once. Indexes refer to the playlist as it is after the
+/// changes before.
+///
+/// ```ignore
+/// let playlist = Playlist::get(&session, &playlist_id).await?;
+/// playlist
+///     .changes()
+///     .add(&[track_id], None)?
+///     .submit(&session)
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct PlaylistChanges {
+    playlist_id: SpotifyId,
+    base_revision: Vec<u8>,
+    operations: Vec<PlaylistOperationMessage>,
+}
+
+impl PlaylistChanges {
+    pub fn new(playlist: &Playlist) -> Self {
+        Self {
+            playlist_id: playlist.id.inner_id,
+            base_revision: playlist.revision.clone(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Inserts `items` at `index`, or appends them if `None`.
+    pub fn add(mut self, items: &[SpotifyId], index: Option<i32>) -> Result<Self, Error> {
+        let mut add = PlaylistAddMessage::new();
+        add.items = Self::items(items)?;
+        match index {
+            Some(index) => add.set_from_index(index),
+            None => add.set_add_last(true),
+        }
+
+        let mut operation = PlaylistOperationMessage::new();
+        operation.set_kind(PlaylistOperationKind::ADD);
+        operation.add = Some(add).into();
+        self.operations.push(operation);
+
+        Ok(self)
+    }
+
+    /// Removes `items`, which are expected at `index` and onwards. Spotify
+    /// rejects the change if the playlist does not contain them there.
+    pub fn remove(mut self, items: &[SpotifyId], index: i32) -> Result<Self, Error> {
+        let mut rem = PlaylistRemoveMessage::new();
+        rem.set_from_index(index);
+        rem.set_length(items.len() as i32);
+        rem.items = Self::items(items)?;
+
+        let mut operation = PlaylistOperationMessage::new();
+        operation.set_kind(PlaylistOperationKind::REM);
+        operation.rem = Some(rem).into();
+        self.operations.push(operation);
+
+        Ok(self)
+    }
+
+    /// Moves `length` items from `from_index` to before the item that is at
+    /// `to_index` before the move.
+    pub fn reorder(mut self, from_index: i32, length: i32, to_index: i32) -> Self {
+        let mut mov = PlaylistMoveMessage::new();
+        mov.set_from_index(from_index);
+        mov.set_length(length);
+        mov.set_to_index(to_index);
+
+        let mut operation = PlaylistOperationMessage::new();
+        operation.set_kind(PlaylistOperationKind::MOV);
+        operation.mov = Some(mov).into();
+        self.operations.push(operation);
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Submits the changes, and returns the revision of the playlist after
+    /// them. Fails if the playlist was changed elsewhere in a conflicting way
+    /// since it was fetched.
+    pub async fn submit(self, session: &Session) -> Result<SelectedListContent, Error> {
+        let mut delta = PlaylistDeltaMessage::new();
+        delta.set_base_version(self.base_revision.clone());
+        delta.ops = self.operations;
+
+        let mut changes = PlaylistChangesMessage::new();
+        changes.set_base_revision(self.base_revision);
+        changes.deltas.push(delta);
+        changes.set_want_resulting_revisions(true);
+
+        let response = session
+            .spclient()
+            .post_playlist_changes(&self.playlist_id, &changes)
+            .await?;
+        let msg = SelectedListContentMessage::parse_from_bytes(&response)?;
+        SelectedListContent::try_from(&msg)
+    }
+
+    fn items(items: &[SpotifyId]) -> Result<Vec<PlaylistItemMessage>, Error> {
+        items
+            .iter()
+            .map(|id| {
+                let mut item = PlaylistItemMessage::new();
+                item.set_uri(id.to_uri()?);
+                Ok(item)
+            })
+            .collect()
+    }
+}
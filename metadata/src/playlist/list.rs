@@ -10,8 +10,8 @@ use crate::{
 };
 
 use super::{
-    attribute::PlaylistAttributes, diff::PlaylistDiff, item::PlaylistItemList,
-    permission::Capabilities,
+    attribute::PlaylistAttributes, changes::PlaylistChanges, diff::PlaylistDiff,
+    item::PlaylistItemList, permission::Capabilities,
 };
 
 use librespot_core::{
@@ -90,6 +90,11 @@ impl Playlist {
     pub fn name(&self) -> &str {
         &self.attributes.name
     }
+
+    /// Starts changes to this playlist, at the revision it was fetched at.
+    pub fn changes(&self) -> PlaylistChanges {
+        PlaylistChanges::new(self)
+    }
 }
 
 #[async_trait]
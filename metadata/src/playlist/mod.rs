@@ -1,10 +1,14 @@
 pub mod annotation;
 pub mod attribute;
+pub mod changes;
 pub mod diff;
 pub mod item;
 pub mod list;
 pub mod operation;
 pub mod permission;
+pub mod rootlist;
 
 pub use annotation::PlaylistAnnotation;
+pub use changes::PlaylistChanges;
 pub use list::Playlist;
+pub use rootlist::{Rootlist, RootlistSync};
@@ -0,0 +1,186 @@
+use futures_util::StreamExt;
+use protobuf::Message;
+use tokio::sync::watch;
+
+use librespot_core::{Error, Session, SpotifyId};
+
+use librespot_protocol as protocol;
+use protocol::playlist4_external::SelectedListContent as RootlistMessage;
+
+const PAGE_LENGTH: usize = 120;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootlistPlaylist {
+    pub id: SpotifyId,
+    pub name: String,
+    pub length: i32,
+    pub owner_username: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootlistEntry {
+    Playlist(RootlistPlaylist),
+    Folder {
+        id: String,
+        name: String,
+        entries: Vec<RootlistEntry>,
+    },
+}
+
+/// The playlists of the user as shown in the sidebar of Spotify clients,
+/// including folders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rootlist {
+    pub revision: Vec<u8>,
+    pub entries: Vec<RootlistEntry>,
+}
+
+impl Rootlist {
+    pub async fn get(session: &Session) -> Result<Self, Error> {
+        let mut revision;
+        let mut uris = Vec::new();
+        let mut playlists = Vec::new();
+
+        loop {
+            let response = session
+                .spclient()
+                .get_rootlist(uris.len(), Some(PAGE_LENGTH))
+                .await?;
+            let msg = RootlistMessage::parse_from_bytes(&response)?;
+            trace!("Received rootlist: {:#?}", msg);
+
+            revision = msg.revision().to_vec();
+
+            let contents = msg.contents.get_or_default();
+            for (i, item) in contents.items.iter().enumerate() {
+                let meta_item = contents.meta_items.get(i);
+                uris.push(item.uri().to_owned());
+                playlists.push(meta_item.map(|meta_item| {
+                    (
+                        meta_item.attributes.name().to_owned(),
+                        meta_item.length(),
+                        meta_item.owner_username().to_owned(),
+                    )
+                }));
+            }
+
+            if !contents.truncated() || contents.items.is_empty() {
+                break;
+            }
+        }
+
+        let mut items = uris.iter().zip(playlists);
+        Ok(Self {
+            revision,
+            entries: Self::parse_entries(&mut items),
+        })
+    }
+
+    /// Keeps a copy of the rootlist up to date with the changes pushed by the
+    /// dealer, including those of other devices. Requires the dealer of the
+    /// session to be started.
+    pub async fn sync(session: &Session) -> Result<RootlistSync, Error> {
+        let uri = format!("hm://playlist/v2/user/{}/rootlist", session.username());
+        let mut subscription = session.dealer().subscribe(&[uri.as_str()])?;
+
+        let (tx, rx) = watch::channel(Self::get(session).await?);
+
+        let session_clone = session.clone();
+        session.spawn(async move {
+            while subscription.next().await.is_some() {
+                if tx.is_closed() {
+                    break;
+                }
+
+                match Self::get(&session_clone).await {
+                    Ok(rootlist) => {
+                        tx.send_if_modified(|current| {
+                            let modified = *current != rootlist;
+                            *current = rootlist;
+                            modified
+                        });
+                    }
+                    Err(e) => warn!("Unable to update rootlist: {}", e),
+                }
+            }
+        });
+
+        Ok(RootlistSync(rx))
+    }
+
+    /// All playlists, including those in folders.
+    pub fn playlists(&self) -> Vec<&RootlistPlaylist> {
+        fn collect<'a>(entries: &'a [RootlistEntry], playlists: &mut Vec<&'a RootlistPlaylist>) {
+            for entry in entries {
+                match entry {
+                    RootlistEntry::Playlist(playlist) => playlists.push(playlist),
+                    RootlistEntry::Folder { entries, .. } => collect(entries, playlists),
+                }
+            }
+        }
+
+        let mut playlists = Vec::new();
+        collect(&self.entries, &mut playlists);
+        playlists
+    }
+
+    // Folders are delimited by `spotify:start-group:<id>:<name>` and
+    // `spotify:end-group:<id>` items.
+    fn parse_entries<'a>(
+        items: &mut impl Iterator<Item = (&'a String, Option<(String, i32, String)>)>,
+    ) -> Vec<RootlistEntry> {
+        let mut entries = Vec::new();
+
+        while let Some((uri, meta)) = items.next() {
+            if let Some(group) = uri.strip_prefix("spotify:start-group:") {
+                let (id, name) = group.split_once(':').unwrap_or((group, ""));
+                let name = form_urlencoded::parse(name.as_bytes())
+                    .map(|(name, _)| name.into_owned())
+                    .next()
+                    .unwrap_or_default();
+
+                entries.push(RootlistEntry::Folder {
+                    id: id.to_owned(),
+                    name,
+                    entries: Self::parse_entries(items),
+                });
+            } else if uri.starts_with("spotify:end-group:") {
+                break;
+            } else {
+                match SpotifyId::from_uri(uri) {
+                    Ok(id) => {
+                        let (name, length, owner_username) = meta.unwrap_or_default();
+                        entries.push(RootlistEntry::Playlist(RootlistPlaylist {
+                            id,
+                            name,
+                            length,
+                            owner_username,
+                        }));
+                    }
+                    Err(e) => debug!("Skipping rootlist item <{}>: {}", uri, e),
+                }
+            }
+        }
+
+        entries
+    }
+}
+
+/// A copy of the rootlist that is kept up to date, see [`Rootlist::sync`].
+/// Updates stop once every clone of it is dropped.
+#[derive(Debug, Clone)]
+pub struct RootlistSync(watch::Receiver<Rootlist>);
+
+impl RootlistSync {
+    /// The rootlist as of the last change.
+    pub fn rootlist(&self) -> Rootlist {
+        self.0.borrow().clone()
+    }
+
+    /// Waits for the rootlist to change, and returns it. Returns `None` once
+    /// the dealer connection is closed.
+    pub async fn changed(&mut self) -> Option<Rootlist> {
+        self.0.changed().await.ok()?;
+        Some(self.0.borrow_and_update().clone())
+    }
+}
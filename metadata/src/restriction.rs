@@ -38,6 +38,28 @@ impl Restriction {
             .map(|country_code| country_code.to_owned())
             .collect()
     }
+
+    pub fn allows_country(&self, country: &str) -> bool {
+        let allowed = self
+            .countries_allowed
+            .as_ref()
+            .map_or(true, |allowed| allowed.iter().any(|c| c == country));
+        let forbidden = self
+            .countries_forbidden
+            .as_ref()
+            .is_some_and(|forbidden| forbidden.iter().any(|c| c == country));
+        allowed && !forbidden
+    }
+}
+
+impl Restrictions {
+    /// Whether the item is available in `country` in any catalogue.
+    pub fn allows_country(&self, country: &str) -> bool {
+        self.is_empty()
+            || self
+                .iter()
+                .any(|restriction| restriction.allows_country(country))
+    }
 }
 
 impl From<&RestrictionMessage> for Restriction {
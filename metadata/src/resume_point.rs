@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use protobuf::Message;
+
+use librespot_core::{date::Date, Error, Session, SpotifyId};
+
+use librespot_protocol as protocol;
+use protocol::played_state::PlayedStateItem as ResumePointMessage;
+use protocol::played_state::PlayedStateItems as ResumePointsMessage;
+
+/// Where the user left off listening to an episode. Spotify clients share
+/// these, so playback of an episode continues where it stopped on any device.
+#[derive(Debug, Clone)]
+pub struct ResumePoint {
+    pub episode_id: SpotifyId,
+    pub position: Duration,
+    pub fully_played: bool,
+    pub last_played_at: Option<Date>,
+}
+
+impl ResumePoint {
+    pub fn new(episode_id: SpotifyId, position: Duration, fully_played: bool) -> Self {
+        Self {
+            episode_id,
+            position,
+            fully_played,
+            last_played_at: Some(Date::now_utc()),
+        }
+    }
+
+    /// The resume point of an episode, or `None` if it was never played.
+    pub async fn get(session: &Session, episode_id: &SpotifyId) -> Result<Option<Self>, Error> {
+        let mut resume_points = Self::get_many(session, &[*episode_id]).await?;
+        Ok(resume_points.pop())
+    }
+
+    /// The resume points of several episodes. Episodes that were never played
+    /// are left out.
+    pub async fn get_many(
+        session: &Session,
+        episode_ids: &[SpotifyId],
+    ) -> Result<Vec<Self>, Error> {
+        if episode_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = session.spclient().get_resume_points(episode_ids).await?;
+        let msg = ResumePointsMessage::parse_from_bytes(&response)?;
+        trace!("Received resume points: {:#?}", msg);
+
+        msg.item.iter().map(TryFrom::try_from).collect()
+    }
+
+    pub async fn save(&self, session: &Session) -> Result<(), Error> {
+        Self::save_many(session, std::slice::from_ref(self)).await
+    }
+
+    pub async fn save_many(session: &Session, resume_points: &[Self]) -> Result<(), Error> {
+        let mut msg = ResumePointsMessage::new();
+        for resume_point in resume_points {
+            msg.item.push(resume_point.try_into()?);
+        }
+
+        session.spclient().put_resume_points(&msg).await?;
+        Ok(())
+    }
+}
+
+impl TryFrom<&ResumePointMessage> for ResumePoint {
+    type Error = Error;
+    fn try_from(item: &ResumePointMessage) -> Result<Self, Self::Error> {
+        let last_played_at = match item.last_played_at {
+            Some(seconds) => Some(Date::from_timestamp_ms(seconds as i64 * 1000)?),
+            None => None,
+        };
+
+        Ok(Self {
+            episode_id: SpotifyId::from_uri(item.episode_uri())?,
+            position: Duration::from_millis(item.resume_point().max(0) as u64),
+            fully_played: item.has_been_fully_played(),
+            last_played_at,
+        })
+    }
+}
+
+impl TryFrom<&ResumePoint> for ResumePointMessage {
+    type Error = Error;
+    fn try_from(resume_point: &ResumePoint) -> Result<Self, Self::Error> {
+        let mut item = Self::new();
+        item.set_episode_uri(resume_point.episode_id.to_uri()?);
+        item.set_resume_point(resume_point.position.as_millis().min(i32::MAX as u128) as i32);
+        item.set_has_been_fully_played(resume_point.fully_played);
+        if let Some(date) = &resume_point.last_played_at {
+            item.set_last_played_at((date.as_timestamp_ms() / 1000) as i32);
+        }
+        Ok(item)
+    }
+}
@@ -0,0 +1,155 @@
+use bytes::Bytes;
+use serde::Deserialize;
+
+use librespot_core::{Error, Session};
+
+// Spotify returns up to 50 hits per kind and page.
+pub const SEARCH_MAX_LIMIT: usize = 50;
+
+/// One page of search results. Every kind of item is paged separately, with
+/// the same `offset` and `limit`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SearchResults {
+    #[serde(skip)]
+    pub query: String,
+    #[serde(skip)]
+    pub offset: usize,
+    #[serde(skip)]
+    pub limit: usize,
+    pub tracks: SearchPage<TrackHit>,
+    pub albums: SearchPage<AlbumHit>,
+    pub artists: SearchPage<ArtistHit>,
+    pub playlists: SearchPage<PlaylistHit>,
+    pub shows: SearchPage<ShowHit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SearchPage<T> {
+    pub hits: Vec<T>,
+    pub total: usize,
+}
+
+impl<T> Default for SearchPage<T> {
+    fn default() -> Self {
+        Self {
+            hits: Vec::new(),
+            total: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SearchEntity {
+    pub name: String,
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TrackHit {
+    pub name: String,
+    pub uri: String,
+    pub image: String,
+    // In milliseconds.
+    pub duration: u32,
+    pub explicit: bool,
+    pub album: SearchEntity,
+    pub artists: Vec<SearchEntity>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AlbumHit {
+    pub name: String,
+    pub uri: String,
+    pub image: String,
+    pub artists: Vec<SearchEntity>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ArtistHit {
+    pub name: String,
+    pub uri: String,
+    pub image: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlaylistHit {
+    pub name: String,
+    pub uri: String,
+    pub image: String,
+    pub followers_count: u64,
+    pub author: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ShowHit {
+    pub name: String,
+    pub uri: String,
+    pub image: String,
+    pub publisher: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    results: SearchResults,
+}
+
+impl SearchResults {
+    /// Searches the catalogue for tracks, albums, artists, playlists and shows
+    /// matching `query`. `limit` is capped at [`SEARCH_MAX_LIMIT`].
+    pub async fn get(
+        session: &Session,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Self, Error> {
+        let limit = limit.clamp(1, SEARCH_MAX_LIMIT);
+        let response = session.spclient().get_search(query, offset, limit).await?;
+
+        let mut results = Self::try_from(&response)?;
+        results.query = query.to_owned();
+        results.offset = offset;
+        results.limit = limit;
+        Ok(results)
+    }
+
+    /// Whether any kind of item has more results after this page.
+    pub fn has_next_page(&self) -> bool {
+        let end = self.offset + self.limit;
+        [
+            self.tracks.total,
+            self.albums.total,
+            self.artists.total,
+            self.playlists.total,
+            self.shows.total,
+        ]
+        .into_iter()
+        .any(|total| total > end)
+    }
+
+    pub async fn next_page(&self, session: &Session) -> Result<Option<Self>, Error> {
+        if !self.has_next_page() {
+            return Ok(None);
+        }
+
+        Self::get(session, &self.query, self.offset + self.limit, self.limit)
+            .await
+            .map(Some)
+    }
+}
+
+impl TryFrom<&Bytes> for SearchResults {
+    type Error = Error;
+
+    fn try_from(response: &Bytes) -> Result<Self, Self::Error> {
+        let response: SearchResponse = serde_json::from_slice(response)?;
+        Ok(response.results)
+    }
+}
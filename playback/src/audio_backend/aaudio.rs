@@ -0,0 +1,173 @@
+use super::{Open, Sink, SinkAsBytes, SinkError, SinkResult};
+use crate::config::AudioFormat;
+use crate::convert::Converter;
+use crate::decoder::AudioPacket;
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+use ndk::audio::{
+    AudioDirection, AudioError, AudioFormat as AAudioFormat, AudioPerformanceMode,
+    AudioSharingMode, AudioStream, AudioStreamBuilder, AudioStreamState,
+};
+use std::time::Duration;
+use thiserror::Error;
+
+// Blocking writes return early after this long, so a stalled stream
+// doesn't hang the player thread forever.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How long stopping may take to play out what was already written.
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Error)]
+enum AAudioError {
+    #[error("<AAudioSink> Invalid Device {0:?}, expected a numeric device id")]
+    InvalidDevice(String),
+
+    #[error("<AAudioSink> Failed to Open Stream, {0}")]
+    ConnectionRefused(AudioError),
+
+    #[error("<AAudioSink> Failed to Change Stream State, {0}")]
+    StateChange(AudioError),
+
+    #[error("<AAudioSink>")]
+    NotConnected,
+
+    #[error("<AAudioSink> Write Timed Out")]
+    WriteTimeout,
+
+    #[error("<AAudioSink> {0}")]
+    OnWrite(AudioError),
+}
+
+impl From<AAudioError> for SinkError {
+    fn from(e: AAudioError) -> SinkError {
+        use AAudioError::*;
+        let es = e.to_string();
+        match e {
+            InvalidDevice(_) => SinkError::InvalidParams(es),
+            ConnectionRefused(_) => SinkError::ConnectionRefused(es),
+            StateChange(_) => SinkError::StateChange(es),
+            NotConnected => SinkError::NotConnected(es),
+            WriteTimeout | OnWrite(_) => SinkError::OnWrite(es),
+        }
+    }
+}
+
+pub struct AAudioSink {
+    stream: Option<AudioStream>,
+    device: Option<String>,
+    format: AudioFormat,
+}
+
+impl Open for AAudioSink {
+    fn open(device: Option<String>, format: AudioFormat) -> Self {
+        let actual_format = match format {
+            // Integer formats other than S16 need API level 31.
+            AudioFormat::F32 | AudioFormat::S16 => format,
+            _ => {
+                warn!("AAudio currently does not support {format:?} output");
+                AudioFormat::F32
+            }
+        };
+
+        info!("Using AAudioSink with format: {actual_format:?}");
+
+        Self {
+            stream: None,
+            device,
+            format: actual_format,
+        }
+    }
+}
+
+impl Sink for AAudioSink {
+    fn start(&mut self) -> SinkResult<()> {
+        if self.stream.is_none() {
+            let aaudio_format = match self.format {
+                AudioFormat::F32 => AAudioFormat::PCM_Float,
+                AudioFormat::S16 => AAudioFormat::PCM_I16,
+                _ => unreachable!(),
+            };
+
+            let mut builder = AudioStreamBuilder::new()
+                .map_err(AAudioError::ConnectionRefused)?
+                .direction(AudioDirection::Output)
+                .format(aaudio_format)
+                .channel_count(NUM_CHANNELS as i32)
+                .sample_rate(SAMPLE_RATE as i32)
+                .performance_mode(AudioPerformanceMode::LowLatency)
+                .sharing_mode(AudioSharingMode::Shared);
+
+            // Without a device id AAudio follows the default route,
+            // e.g. when headphones or a bluetooth speaker get connected.
+            if let Some(device) = self.device.as_deref() {
+                let device_id = device
+                    .parse()
+                    .map_err(|_| AAudioError::InvalidDevice(device.to_string()))?;
+                builder = builder.device_id(device_id);
+            }
+
+            let stream = builder
+                .open_stream()
+                .map_err(AAudioError::ConnectionRefused)?;
+
+            stream.request_start().map_err(AAudioError::StateChange)?;
+
+            self.stream = Some(stream);
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        let stream = self.stream.take().ok_or(AAudioError::NotConnected)?;
+
+        // Stopping lets AAudio play out what was already written, but closing
+        // the stream discards it, so it is only dropped once it stopped.
+        stream.request_stop().map_err(AAudioError::StateChange)?;
+        if let Err(e) =
+            stream.wait_for_state_change(AudioStreamState::Stopping, STOP_TIMEOUT.as_nanos() as i64)
+        {
+            warn!("<AAudioSink> Stream did not stop in time, {e}");
+        }
+
+        Ok(())
+    }
+
+    sink_as_bytes!();
+}
+
+impl SinkAsBytes for AAudioSink {
+    fn write_bytes(&mut self, data: &[u8]) -> SinkResult<()> {
+        let stream = self.stream.as_ref().ok_or(AAudioError::NotConnected)?;
+
+        let frame_size = self.format.size() * NUM_CHANNELS as usize;
+        let mut remaining = data;
+
+        while !remaining.is_empty() {
+            let num_frames = (remaining.len() / frame_size) as i32;
+
+            // SAFETY: `remaining` holds at least `num_frames` frames in the
+            // format the stream was opened with.
+            let written = unsafe {
+                stream.write(
+                    remaining.as_ptr().cast(),
+                    num_frames,
+                    WRITE_TIMEOUT.as_nanos() as i64,
+                )
+            }
+            .map_err(AAudioError::OnWrite)?;
+
+            if written == 0 {
+                return Err(AAudioError::WriteTimeout.into());
+            }
+
+            remaining = &remaining[written as usize * frame_size..];
+        }
+
+        Ok(())
+    }
+}
+
+impl AAudioSink {
+    pub const NAME: &'static str = "aaudio";
+}
@@ -0,0 +1,466 @@
+use super::{Open, Sink, SinkError, SinkResult};
+use crate::config::AudioFormat;
+use crate::convert::{i24, Converter};
+use crate::decoder::AudioPacket;
+use crate::metadata::audio::{AudioItem, UniqueFields};
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use thiserror::Error;
+use zerocopy::AsBytes;
+
+// Writes every track to its own file in the given directory. Decoded audio is
+// written as WAV with the track metadata in a LIST INFO chunk, passthrough
+// audio as the raw Ogg stream.
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+// Offset of the RIFF chunk size, which is only known once the track has ended.
+const RIFF_SIZE_OFFSET: u64 = 4;
+
+#[derive(Debug, Error)]
+enum FileError {
+    #[error("<FileSink> {0}")]
+    OnWrite(io::Error),
+
+    #[error("<FileSink> File {file:?} Can Not be Created, {e}")]
+    OpenFailure { file: PathBuf, e: io::Error },
+
+    #[error("<FileSink> Failed to Finalize {file:?}, {e}")]
+    FinalizeFailure { file: PathBuf, e: io::Error },
+}
+
+impl From<FileError> for SinkError {
+    fn from(e: FileError) -> SinkError {
+        use FileError::*;
+        let es = e.to_string();
+        match e {
+            OnWrite(_) | FinalizeFailure { .. } => SinkError::OnWrite(es),
+            OpenFailure { .. } => SinkError::ConnectionRefused(es),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tags {
+    title: String,
+    artist: String,
+    album: String,
+    track_number: Option<u32>,
+}
+
+impl Tags {
+    fn from_audio_item(audio_item: &AudioItem) -> Self {
+        match &audio_item.unique_fields {
+            UniqueFields::Track {
+                artists,
+                album,
+                number,
+                ..
+            } => Self {
+                title: audio_item.name.clone(),
+                artist: artists
+                    .iter()
+                    .map(|artist| artist.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                album: album.clone(),
+                track_number: Some(*number),
+            },
+            UniqueFields::Episode { show_name, .. } => Self {
+                title: audio_item.name.clone(),
+                artist: show_name.clone(),
+                album: show_name.clone(),
+                track_number: None,
+            },
+        }
+    }
+
+    fn file_stem(&self) -> String {
+        let stem = match (self.artist.is_empty(), self.title.is_empty()) {
+            (_, true) => "untitled".to_string(),
+            (true, false) => self.title.clone(),
+            (false, false) => format!("{} - {}", self.artist, self.title),
+        };
+
+        stem.chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect()
+    }
+
+    fn info_chunk(&self) -> Vec<u8> {
+        let track_number = self.track_number.map(|n| n.to_string()).unwrap_or_default();
+
+        let mut chunk = b"INFO".to_vec();
+        for (id, value) in [
+            (b"INAM", &self.title),
+            (b"IART", &self.artist),
+            (b"IPRD", &self.album),
+            (b"ITRK", &track_number),
+        ] {
+            if value.is_empty() {
+                continue;
+            }
+
+            // NUL terminated and padded to an even length.
+            let len = value.len() + 1;
+            chunk.extend_from_slice(id);
+            chunk.extend_from_slice(&(len as u32).to_le_bytes());
+            chunk.extend_from_slice(value.as_bytes());
+            chunk.push(0);
+            if len % 2 == 1 {
+                chunk.push(0);
+            }
+        }
+
+        chunk
+    }
+}
+
+struct TrackFile {
+    path: PathBuf,
+    output: BufWriter<File>,
+    // Where the size of the data chunk goes, None for raw files.
+    data_size_offset: Option<u64>,
+    data_size: u64,
+}
+
+impl TrackFile {
+    fn create(path: PathBuf, header: Option<(AudioFormat, &Tags)>) -> Result<Self, FileError> {
+        let file = File::create(&path).map_err(|e| FileError::OpenFailure {
+            file: path.clone(),
+            e,
+        })?;
+
+        let mut track_file = Self {
+            path,
+            output: BufWriter::new(file),
+            data_size_offset: None,
+            data_size: 0,
+        };
+
+        if let Some((format, tags)) = header {
+            let offset = track_file
+                .write_header(format, tags)
+                .map_err(FileError::OnWrite)?;
+            track_file.data_size_offset = Some(offset);
+        }
+
+        Ok(track_file)
+    }
+
+    fn write_header(&mut self, format: AudioFormat, tags: &Tags) -> io::Result<u64> {
+        let format_tag = match format {
+            AudioFormat::F64 | AudioFormat::F32 => WAVE_FORMAT_IEEE_FLOAT,
+            _ => WAVE_FORMAT_PCM,
+        };
+        let bytes_per_sample = format.size() as u16;
+        let block_align = bytes_per_sample * NUM_CHANNELS as u16;
+        let info = tags.info_chunk();
+
+        let output = &mut self.output;
+
+        output.write_all(b"RIFF")?;
+        output.write_all(&0u32.to_le_bytes())?;
+        output.write_all(b"WAVE")?;
+
+        output.write_all(b"fmt ")?;
+        output.write_all(&16u32.to_le_bytes())?;
+        output.write_all(&format_tag.to_le_bytes())?;
+        output.write_all(&(NUM_CHANNELS as u16).to_le_bytes())?;
+        output.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        output.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        output.write_all(&block_align.to_le_bytes())?;
+        output.write_all(&(bytes_per_sample * 8).to_le_bytes())?;
+
+        output.write_all(b"LIST")?;
+        output.write_all(&(info.len() as u32).to_le_bytes())?;
+        output.write_all(&info)?;
+
+        output.write_all(b"data")?;
+        let data_size_offset = output.stream_position()?;
+        output.write_all(&0u32.to_le_bytes())?;
+
+        Ok(data_size_offset)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), FileError> {
+        self.output.write_all(data).map_err(FileError::OnWrite)?;
+        self.data_size += data.len() as u64;
+        Ok(())
+    }
+
+    // Brings the header up to date, so the file is valid even if we get killed.
+    fn flush(&mut self) -> Result<(), FileError> {
+        self.update_header()
+            .and_then(|_| self.output.flush())
+            .map_err(|e| FileError::FinalizeFailure {
+                file: self.path.clone(),
+                e,
+            })
+    }
+
+    fn update_header(&mut self) -> io::Result<()> {
+        if let Some(data_size_offset) = self.data_size_offset {
+            let end = self.output.stream_position()?;
+            let riff_size = end - RIFF_SIZE_OFFSET - 4;
+
+            self.output.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+            self.output.write_all(&(riff_size as u32).to_le_bytes())?;
+            self.output.seek(SeekFrom::Start(data_size_offset))?;
+            self.output
+                .write_all(&(self.data_size as u32).to_le_bytes())?;
+            self.output.seek(SeekFrom::Start(end))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct FileSink {
+    directory: PathBuf,
+    format: AudioFormat,
+    tags: Tags,
+    output: Option<TrackFile>,
+}
+
+impl Open for FileSink {
+    fn open(directory: Option<String>, format: AudioFormat) -> Self {
+        if let Some("?") = directory.as_deref() {
+            println!("\nUsage:\n\nOutput each track to a file in the current directory:\n\n\t--backend file\n\nOutput each track to a file in a directory:\n\n\t--backend file --device {{directory}}\n");
+            exit(0);
+        }
+
+        // WAV expects 24 bit samples packed or left-justified, not in the low
+        // bits of an i32.
+        let actual_format = match format {
+            AudioFormat::S24 => {
+                warn!("FileSink currently does not support S24 output");
+                AudioFormat::S24_3
+            }
+            _ => format,
+        };
+
+        info!("Using FileSink with format: {actual_format:?}");
+
+        Self {
+            directory: directory.map(PathBuf::from).unwrap_or_default(),
+            format: actual_format,
+            tags: Tags::default(),
+            output: None,
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn stop(&mut self) -> SinkResult<()> {
+        // Stopping also happens when pausing, so keep writing to the same file.
+        if let Some(output) = self.output.as_mut() {
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        if self.output.is_none() {
+            let raw = matches!(packet, AudioPacket::Raw(_));
+            self.output = Some(self.create_file(raw)?);
+        }
+
+        match packet {
+            AudioPacket::Samples(samples) => match self.format {
+                AudioFormat::F64 => self.write_bytes(samples.as_bytes()),
+                AudioFormat::F32 => {
+                    let samples_f32: &[f32] = &converter.f64_to_f32(&samples);
+                    self.write_bytes(samples_f32.as_bytes())
+                }
+                AudioFormat::S32 => {
+                    let samples_s32: &[i32] = &converter.f64_to_s32(&samples);
+                    self.write_bytes(samples_s32.as_bytes())
+                }
+                AudioFormat::S24 | AudioFormat::S24_3 => {
+                    let samples_s24_3: &[i24] = &converter.f64_to_s24_3(&samples);
+                    self.write_bytes(samples_s24_3.as_bytes())
+                }
+                AudioFormat::S16 => {
+                    let samples_s16: &[i16] = &converter.f64_to_s16(&samples);
+                    self.write_bytes(samples_s16.as_bytes())
+                }
+            },
+            AudioPacket::Raw(samples) => self.write_bytes(&samples),
+        }
+    }
+
+    fn track_changed(&mut self, audio_item: &AudioItem) -> SinkResult<()> {
+        // The next file is created with the first packet of the new track.
+        self.tags = Tags::from_audio_item(audio_item);
+
+        if let Some(mut output) = self.output.take() {
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        if let Some(mut output) = self.output.take() {
+            if let Err(e) = output.flush() {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+impl FileSink {
+    pub const NAME: &'static str = "file";
+
+    fn write_bytes(&mut self, data: &[u8]) -> SinkResult<()> {
+        if let Some(output) = self.output.as_mut() {
+            output.write(data)?;
+        }
+
+        Ok(())
+    }
+
+    fn create_file(&self, raw: bool) -> Result<TrackFile, FileError> {
+        let extension = if raw { "ogg" } else { "wav" };
+        let path = unique_path(&self.directory, &self.tags.file_stem(), extension);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| FileError::OpenFailure {
+                file: path.clone(),
+                e,
+            })?;
+        }
+
+        info!("Writing track to {:?}", path);
+
+        let header = (!raw).then_some((self.format, &self.tags));
+        TrackFile::create(path, header)
+    }
+}
+
+// Don't overwrite earlier recordings of the same track.
+fn unique_path(directory: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut path = directory.join(format!("{stem}.{extension}"));
+    let mut n = 1;
+
+    while path.exists() {
+        path = directory.join(format!("{stem} ({n}).{extension}"));
+        n += 1;
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags() -> Tags {
+        Tags {
+            title: "Song/Title".to_string(),
+            artist: "Artist".to_string(),
+            album: "An Album".to_string(),
+            track_number: Some(7),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("librespot-file-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_info_chunk() {
+        let chunk = tags().info_chunk();
+
+        let mut expected = b"INFO".to_vec();
+        expected.extend_from_slice(b"INAM\x0b\0\0\0Song/Title\0\0");
+        expected.extend_from_slice(b"IART\x07\0\0\0Artist\0\0");
+        expected.extend_from_slice(b"IPRD\x09\0\0\0An Album\0\0");
+        expected.extend_from_slice(b"ITRK\x02\0\0\x007\0");
+        assert_eq!(chunk, expected);
+
+        // Empty tags are left out.
+        assert_eq!(Tags::default().info_chunk(), b"INFO");
+    }
+
+    #[test]
+    fn test_file_stem() {
+        assert_eq!(tags().file_stem(), "Artist - Song_Title");
+        assert_eq!(Tags::default().file_stem(), "untitled");
+    }
+
+    #[test]
+    fn test_write_header() {
+        let directory = temp_dir("header");
+        let path = directory.join("track.wav");
+
+        let mut file = TrackFile::create(path.clone(), Some((AudioFormat::S16, &tags()))).unwrap();
+        file.write(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        file.flush().unwrap();
+        drop(file);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        assert_eq!(&bytes[20..22], &WAVE_FORMAT_PCM.to_le_bytes());
+        assert_eq!(&bytes[22..24], &2u16.to_le_bytes());
+        assert_eq!(u32_at(&bytes, 24), 44100);
+        assert_eq!(u32_at(&bytes, 28), 44100 * 4);
+        assert_eq!(&bytes[32..34], &4u16.to_le_bytes());
+        assert_eq!(&bytes[34..36], &16u16.to_le_bytes());
+
+        let info = tags().info_chunk();
+        assert_eq!(&bytes[36..40], b"LIST");
+        assert_eq!(u32_at(&bytes, 40) as usize, info.len());
+        assert_eq!(&bytes[44..44 + info.len()], &info[..]);
+
+        let data = 44 + info.len();
+        assert_eq!(&bytes[data..data + 4], b"data");
+        assert_eq!(u32_at(&bytes, data + 4), 8);
+        assert_eq!(&bytes[data + 8..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let directory = temp_dir("unique");
+
+        let first = unique_path(&directory, "track", "wav");
+        assert_eq!(first, directory.join("track.wav"));
+        fs::write(&first, b"").unwrap();
+
+        let second = unique_path(&directory, "track", "wav");
+        assert_eq!(second, directory.join("track (1).wav"));
+        fs::write(&second, b"").unwrap();
+
+        assert_eq!(
+            unique_path(&directory, "track", "wav"),
+            directory.join("track (2).wav")
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
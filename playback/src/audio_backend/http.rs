@@ -0,0 +1,596 @@
+use super::pacer::Pacer;
+use super::{Open, Sink, SinkError, SinkResult};
+use crate::config::AudioFormat;
+use crate::convert::{i24, Converter};
+use crate::decoder::AudioPacket;
+use crate::metadata::audio::{AudioItem, UniqueFields};
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+use parking_lot::Mutex;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use thiserror::Error;
+use zerocopy::AsBytes;
+
+// Serves the output over HTTP to any number of clients. Decoded audio is sent
+// as an endless WAV stream, passthrough audio as the Ogg stream. Clients that
+// ask for it with `Icy-MetaData: 1` get the current track as Icecast (ICY)
+// in-band metadata.
+//
+// Every client has its own thread, so reading its request and writing to it
+// never holds up the player or the other clients.
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8000";
+
+// Audio bytes between ICY metadata blocks.
+const ICY_METAINT: usize = 16000;
+
+// How far ahead of real time we send, clients buffer at least this much.
+const LEAD: Duration = Duration::from_secs(1);
+
+// Clients that can't keep up are dropped instead of stalling everyone.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Packets queued for a client before it counts as not keeping up, a few
+// seconds of audio.
+const CLIENT_QUEUE: usize = 64;
+
+// How often the accept thread checks whether the sink is gone.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Error)]
+enum HttpError {
+    #[error("<HttpSink> Failed to Listen on {address}, {e}")]
+    ConnectionRefused { address: String, e: io::Error },
+}
+
+impl From<HttpError> for SinkError {
+    fn from(e: HttpError) -> SinkError {
+        use HttpError::*;
+        let es = e.to_string();
+        match e {
+            ConnectionRefused { .. } => SinkError::ConnectionRefused(es),
+        }
+    }
+}
+
+enum Message {
+    // The response headers and what a stream has to start with.
+    Start {
+        content_type: &'static str,
+        header: Arc<[u8]>,
+    },
+    Data {
+        data: Arc<[u8]>,
+        title: Option<Arc<str>>,
+    },
+}
+
+struct ClientHandle {
+    tx: SyncSender<Message>,
+    started: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    clients: Mutex<Vec<ClientHandle>>,
+    stopped: AtomicBool,
+}
+
+struct Client<W> {
+    writer: W,
+    icy: bool,
+    bytes_until_metadata: usize,
+    title_sent: Option<String>,
+}
+
+impl<W: Write> Client<W> {
+    fn new(writer: W, icy: bool) -> Self {
+        Self {
+            writer,
+            icy,
+            bytes_until_metadata: ICY_METAINT,
+            title_sent: None,
+        }
+    }
+
+    fn send_headers(&mut self, content_type: &str, header: &[u8]) -> io::Result<()> {
+        self.writer
+            .write_all(response_headers(content_type, self.icy).as_bytes())?;
+        self.write_body(header, None)
+    }
+
+    fn write_body(&mut self, mut data: &[u8], title: Option<&str>) -> io::Result<()> {
+        if !self.icy {
+            return self.writer.write_all(data);
+        }
+
+        while !data.is_empty() {
+            let len = self.bytes_until_metadata.min(data.len());
+            self.writer.write_all(&data[..len])?;
+            data = &data[len..];
+
+            self.bytes_until_metadata -= len;
+            if self.bytes_until_metadata == 0 {
+                self.write_metadata(title)?;
+                self.bytes_until_metadata = ICY_METAINT;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_metadata(&mut self, title: Option<&str>) -> io::Result<()> {
+        // Only send the title when it changed, an empty block otherwise.
+        match title {
+            Some(title) if self.title_sent.as_deref() != Some(title) => {
+                self.writer.write_all(&icy_metadata(title))?;
+                self.title_sent = Some(title.to_string());
+                Ok(())
+            }
+            _ => self.writer.write_all(&[0]),
+        }
+    }
+}
+
+// Reads the request and returns whether the client wants ICY metadata. We
+// serve the same stream whatever the path, only the headers matter.
+fn read_request(reader: impl BufRead) -> io::Result<bool> {
+    let mut icy = false;
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("icy-metadata") && value.trim() == "1" {
+                icy = true;
+            }
+        }
+    }
+
+    Ok(icy)
+}
+
+fn response_headers(content_type: &str, icy: bool) -> String {
+    let mut headers = format!(
+        "HTTP/1.0 200 OK\r\nContent-Type: {content_type}\r\nCache-Control: no-cache\r\nicy-name: librespot\r\n"
+    );
+    if icy {
+        headers.push_str(&format!("icy-metaint: {ICY_METAINT}\r\n"));
+    }
+    headers.push_str("\r\n");
+    headers
+}
+
+fn icy_metadata(title: &str) -> Vec<u8> {
+    let mut block = format!("StreamTitle='{}';", title.replace('\'', "’")).into_bytes();
+
+    // The length is sent in units of 16 bytes, in front of the block.
+    let len = block.len().div_ceil(16).min(u8::MAX as usize);
+    block.resize(len * 16, 0);
+    block.insert(0, len as u8);
+    block
+}
+
+fn wav_header(format: AudioFormat) -> Vec<u8> {
+    // Sizes are unknown, so they're set to the maximum like most streaming
+    // servers do.
+    let format_tag: u16 = match format {
+        AudioFormat::F64 | AudioFormat::F32 => 3,
+        _ => 1,
+    };
+    let bytes_per_sample = format.size() as u16;
+    let block_align = bytes_per_sample * NUM_CHANNELS as u16;
+
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&format_tag.to_le_bytes());
+    header.extend_from_slice(&(NUM_CHANNELS as u16).to_le_bytes());
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&(bytes_per_sample * 8).to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
+
+// The header pages of the current Ogg stream, which clients that join
+// mid-stream need before any audio. The passthrough decoder writes them
+// whenever it (re)starts a stream, in front of its first audio page.
+#[derive(Default)]
+struct OggHeaders {
+    pages: Vec<u8>,
+}
+
+impl OggHeaders {
+    // Picks up the headers from data that is split at page boundaries, and
+    // returns whether the data starts a new stream.
+    fn update(&mut self, mut data: &[u8]) -> bool {
+        let mut started = false;
+
+        while let Some(len) = ogg_page_len(data) {
+            let page = &data[..len];
+            let bos = page[5] & 0x02 != 0;
+            let granule_position = u64::from_le_bytes(page[6..14].try_into().unwrap_or_default());
+
+            if bos {
+                self.pages.clear();
+                started = true;
+            }
+            if started && granule_position == 0 {
+                self.pages.extend_from_slice(page);
+            }
+
+            data = &data[len..];
+        }
+
+        started
+    }
+}
+
+fn ogg_page_len(data: &[u8]) -> Option<usize> {
+    if data.len() < 27 || &data[..4] != b"OggS" {
+        return None;
+    }
+
+    let segments = data[26] as usize;
+    let body: usize = data
+        .get(27..27 + segments)?
+        .iter()
+        .map(|&len| len as usize)
+        .sum();
+    let len = 27 + segments + body;
+    (len <= data.len()).then_some(len)
+}
+
+pub struct HttpSink {
+    address: String,
+    shared: Arc<Shared>,
+    accept_thread: Option<JoinHandle<()>>,
+    format: AudioFormat,
+    wav_header: Arc<[u8]>,
+    title: Option<Arc<str>>,
+    ogg_headers: OggHeaders,
+    pacer: Pacer,
+}
+
+impl Open for HttpSink {
+    fn open(address: Option<String>, format: AudioFormat) -> Self {
+        if let Some("?") = address.as_deref() {
+            println!("\nUsage:\n\nServe on port 8000 of localhost:\n\n\t--backend http\n\nServe on a specific address, e.g. 0.0.0.0:8000 for all interfaces:\n\n\t--backend http --device {{ip}}:{{port}}\n");
+            exit(0);
+        }
+
+        // WAV expects 24 bit samples packed or left-justified, not in the low
+        // bits of an i32.
+        let actual_format = match format {
+            AudioFormat::S24 => {
+                warn!("HttpSink currently does not support S24 output");
+                AudioFormat::S24_3
+            }
+            _ => format,
+        };
+
+        let address = address.unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+
+        info!("Using HttpSink on {address} with format: {actual_format:?}");
+
+        Self {
+            address,
+            shared: Arc::default(),
+            accept_thread: None,
+            format: actual_format,
+            wav_header: wav_header(actual_format).into(),
+            title: None,
+            ogg_headers: OggHeaders::default(),
+            pacer: Pacer::new(LEAD),
+        }
+    }
+}
+
+impl Sink for HttpSink {
+    fn start(&mut self) -> SinkResult<()> {
+        if self.accept_thread.is_none() {
+            self.accept_thread = Some(self.listen()?);
+        }
+
+        self.pacer.reset();
+
+        Ok(())
+    }
+
+    fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        match packet {
+            AudioPacket::Samples(samples) => {
+                self.pacer.wait(samples.len() / NUM_CHANNELS as usize);
+
+                match self.format {
+                    AudioFormat::F64 => self.write_bytes(samples.as_bytes(), false),
+                    AudioFormat::F32 => {
+                        let samples_f32: &[f32] = &converter.f64_to_f32(&samples);
+                        self.write_bytes(samples_f32.as_bytes(), false)
+                    }
+                    AudioFormat::S32 => {
+                        let samples_s32: &[i32] = &converter.f64_to_s32(&samples);
+                        self.write_bytes(samples_s32.as_bytes(), false)
+                    }
+                    AudioFormat::S24 | AudioFormat::S24_3 => {
+                        let samples_s24_3: &[i24] = &converter.f64_to_s24_3(&samples);
+                        self.write_bytes(samples_s24_3.as_bytes(), false)
+                    }
+                    AudioFormat::S16 => {
+                        let samples_s16: &[i16] = &converter.f64_to_s16(&samples);
+                        self.write_bytes(samples_s16.as_bytes(), false)
+                    }
+                }
+            }
+            // Passthrough audio is only paced by the clients reading it.
+            AudioPacket::Raw(samples) => self.write_bytes(&samples, true),
+        }
+    }
+
+    fn track_changed(&mut self, audio_item: &AudioItem) -> SinkResult<()> {
+        let artist = match &audio_item.unique_fields {
+            UniqueFields::Track { artists, .. } => artists
+                .iter()
+                .map(|artist| artist.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            UniqueFields::Episode { show_name, .. } => show_name.clone(),
+        };
+
+        self.title = Some(format!("{artist} - {}", audio_item.name).into());
+
+        Ok(())
+    }
+}
+
+impl Drop for HttpSink {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let _ = accept_thread.join();
+        }
+
+        // Client threads end once their queue is drained.
+        self.shared.clients.lock().clear();
+    }
+}
+
+impl HttpSink {
+    pub const NAME: &'static str = "http";
+
+    fn listen(&self) -> SinkResult<JoinHandle<()>> {
+        let listener = TcpListener::bind(&self.address)
+            .and_then(|listener| {
+                // Polled, so the thread notices when the sink is dropped.
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .map_err(|e| HttpError::ConnectionRefused {
+                address: self.address.clone(),
+                e,
+            })?;
+
+        let shared = Arc::clone(&self.shared);
+        let accept_thread = thread::spawn(move || {
+            while !shared.stopped.load(Ordering::Acquire) {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        debug!("HTTP client connected from {peer}");
+                        let shared = Arc::clone(&shared);
+                        thread::spawn(move || Self::serve_client(stream, &shared));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL)
+                    }
+                    Err(e) => warn!("Unable to accept HTTP client: {e}"),
+                }
+            }
+        });
+
+        Ok(accept_thread)
+    }
+
+    fn serve_client(stream: TcpStream, shared: &Shared) {
+        let icy = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(READ_TIMEOUT)))
+            .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+            .and_then(|_| read_request(BufReader::new(&stream)));
+        let icy = match icy {
+            Ok(icy) => icy,
+            Err(e) => {
+                warn!("Unable to accept HTTP client: {e}");
+                return;
+            }
+        };
+
+        let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+        {
+            // Checked under the lock, else the sender could outlive the sink.
+            let mut clients = shared.clients.lock();
+            if shared.stopped.load(Ordering::Acquire) {
+                return;
+            }
+            clients.push(ClientHandle { tx, started: false });
+        }
+
+        if let Err(e) = Self::write_client(Client::new(stream, icy), rx) {
+            debug!("Dropping HTTP client: {e}");
+        }
+    }
+
+    fn write_client(mut client: Client<TcpStream>, rx: Receiver<Message>) -> io::Result<()> {
+        for message in rx {
+            match message {
+                Message::Start {
+                    content_type,
+                    header,
+                } => client.send_headers(content_type, &header)?,
+                Message::Data { data, title } => client.write_body(&data, title.as_deref())?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, data: &[u8], raw: bool) -> SinkResult<()> {
+        let (content_type, header): (_, Arc<[u8]>) = if raw {
+            // Data that starts a stream already has its headers.
+            let started = self.ogg_headers.update(data);
+            let header = if started {
+                &[][..]
+            } else {
+                &self.ogg_headers.pages[..]
+            };
+            ("audio/ogg", header.into())
+        } else {
+            ("audio/wav", Arc::clone(&self.wav_header))
+        };
+        let data: Arc<[u8]> = data.into();
+
+        // Only queues the data, the client threads do the writing.
+        self.shared.clients.lock().retain_mut(|client| {
+            if !client.started {
+                client.started = true;
+                let start = Message::Start {
+                    content_type,
+                    header: Arc::clone(&header),
+                };
+                if client.tx.try_send(start).is_err() {
+                    return false;
+                }
+            }
+
+            let message = Message::Data {
+                data: Arc::clone(&data),
+                title: self.title.clone(),
+            };
+            match client.tx.try_send(message) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    debug!("Dropping HTTP client that can't keep up");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // An Ogg page with one segment as its body.
+    fn ogg_page(header_type: u8, granule_position: u64, body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(header_type);
+        page.extend_from_slice(&granule_position.to_le_bytes());
+        page.extend_from_slice(&[0; 12]);
+        page.push(1);
+        page.push(body.len() as u8);
+        page.extend_from_slice(body);
+        page
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let header = wav_header(AudioFormat::S16);
+        assert_eq!(header.len(), 44);
+        assert_eq!(&header[..4], b"RIFF");
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(&header[20..22], &1u16.to_le_bytes());
+        assert_eq!(&header[22..24], &2u16.to_le_bytes());
+        assert_eq!(&header[24..28], &44100u32.to_le_bytes());
+        assert_eq!(&header[28..32], &(44100u32 * 4).to_le_bytes());
+        assert_eq!(&header[32..34], &4u16.to_le_bytes());
+        assert_eq!(&header[34..36], &16u16.to_le_bytes());
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(&header[40..], &u32::MAX.to_le_bytes());
+
+        let header = wav_header(AudioFormat::F32);
+        assert_eq!(&header[20..22], &3u16.to_le_bytes());
+        assert_eq!(&header[34..36], &32u16.to_le_bytes());
+    }
+
+    #[test]
+    fn test_request() {
+        let request = b"GET /stream HTTP/1.1\r\nHost: localhost\r\nIcy-MetaData: 1\r\n\r\n";
+        assert!(read_request(&request[..]).unwrap());
+
+        let request = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert!(!read_request(&request[..]).unwrap());
+
+        let headers = response_headers("audio/wav", true);
+        assert!(headers.starts_with("HTTP/1.0 200 OK\r\nContent-Type: audio/wav\r\n"));
+        assert!(headers.ends_with("icy-metaint: 16000\r\n\r\n"));
+        assert!(!response_headers("audio/ogg", false).contains("icy-metaint"));
+    }
+
+    #[test]
+    fn test_icy_metadata() {
+        let block = icy_metadata("Artist - It's a Title");
+        assert_eq!(block[0], 3);
+        assert_eq!(block.len(), 1 + 3 * 16);
+        assert!(block[1..].starts_with("StreamTitle='Artist - It’s a Title';".as_bytes()));
+        assert!(block.ends_with(&[0]));
+
+        let mut client = Client::new(Vec::new(), true);
+        let audio = vec![1; ICY_METAINT + 10];
+        client.write_body(&audio, Some("Title")).unwrap();
+        client.write_body(&audio, Some("Title")).unwrap();
+
+        // The title is only sent once, an empty block follows.
+        let written = client.writer;
+        let title_block = icy_metadata("Title");
+        let second = ICY_METAINT + title_block.len();
+        assert_eq!(&written[ICY_METAINT..second], &title_block[..]);
+        assert_eq!(written[second + ICY_METAINT], 0);
+        assert_eq!(written.len(), 2 * audio.len() + title_block.len() + 1);
+
+        let mut client = Client::new(Vec::new(), false);
+        client.write_body(&audio, Some("Title")).unwrap();
+        assert_eq!(client.writer, audio);
+    }
+
+    #[test]
+    fn test_ogg_headers() {
+        let ident = ogg_page(0x02, 0, b"ident");
+        let setup = ogg_page(0, 0, b"setup");
+        let audio = ogg_page(0, 1024, b"audio");
+
+        let mut headers = OggHeaders::default();
+        let start = [ident.clone(), setup.clone(), audio.clone()].concat();
+        assert!(headers.update(&start));
+        assert_eq!(headers.pages, [ident, setup].concat());
+
+        let headers_before = headers.pages.clone();
+        assert!(!headers.update(&audio));
+        assert_eq!(headers.pages, headers_before);
+
+        // A seek restarts the stream with new headers.
+        let ident = ogg_page(0x02, 0, b"ident2");
+        assert!(headers.update(&[ident.clone(), audio].concat()));
+        assert_eq!(headers.pages, ident);
+
+        assert_eq!(ogg_page_len(&start[..10]), None);
+        assert_eq!(ogg_page_len(b"not an ogg page at all, no no no"), None);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::config::AudioFormat;
 use crate::convert::Converter;
 use crate::decoder::AudioPacket;
+use crate::metadata::audio::AudioItem;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -31,6 +32,10 @@ pub trait Sink {
         Ok(())
     }
     fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()>;
+    // Called when a new track starts, before its first packet is written.
+    fn track_changed(&mut self, _audio_item: &AudioItem) -> SinkResult<()> {
+        Ok(())
+    }
 }
 
 pub type SinkBuilder = fn(Option<String>, AudioFormat) -> Box<dyn Sink>;
@@ -114,6 +119,23 @@ mod sdl;
 #[cfg(feature = "sdl-backend")]
 use self::sdl::SdlSink;
 
+// AAudio only exists on Android, the feature does nothing elsewhere.
+#[cfg(all(feature = "aaudio-backend", target_os = "android"))]
+mod aaudio;
+#[cfg(all(feature = "aaudio-backend", target_os = "android"))]
+use self::aaudio::AAudioSink;
+
+mod file;
+use self::file::FileSink;
+
+mod pacer;
+
+mod http;
+use self::http::HttpSink;
+
+mod network;
+use self::network::NetworkSink;
+
 mod pipe;
 use self::pipe::StdoutSink;
 
@@ -137,7 +159,12 @@ pub const BACKENDS: &[(&str, SinkBuilder)] = &[
     ("rodiojack", rodio::mk_rodiojack),
     #[cfg(feature = "sdl-backend")]
     (SdlSink::NAME, mk_sink::<SdlSink>),
+    #[cfg(all(feature = "aaudio-backend", target_os = "android"))]
+    (AAudioSink::NAME, mk_sink::<AAudioSink>),
     (StdoutSink::NAME, mk_sink::<StdoutSink>),
+    (FileSink::NAME, mk_sink::<FileSink>),
+    (NetworkSink::NAME, mk_sink::<NetworkSink>),
+    (HttpSink::NAME, mk_sink::<HttpSink>),
     (SubprocessSink::NAME, mk_sink::<SubprocessSink>),
 ];
 
@@ -0,0 +1,331 @@
+use super::pacer::Pacer;
+use super::{Open, Sink, SinkAsBytes, SinkError, SinkResult};
+use crate::config::AudioFormat;
+use crate::convert::Converter;
+use crate::decoder::AudioPacket;
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::process::exit;
+use std::time::Duration;
+use thiserror::Error;
+
+// Streams interleaved PCM to a receiver over TCP or UDP. Every stream starts
+// with a header describing the format, for UDP every datagram does:
+//
+//   magic      4 bytes  "LSPC"
+//   version    u8       1
+//   format     u8       see `format_code`
+//   channels   u8
+//   reserved   u8
+//   rate       u32 LE   samples per second per channel
+//   sequence   u32 LE   datagram counter, always 0 over TCP
+//
+// All other bytes are samples in native byte order, split at frame boundaries.
+
+const MAGIC: &[u8; 4] = b"LSPC";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 16;
+
+// Stays below the usual Ethernet MTU to avoid fragmentation.
+const MAX_DATAGRAM_SIZE: usize = 1472;
+
+// UDP has no flow control, so we send in real time with this much lead for
+// the receiver to buffer. TCP is paced by the receiver.
+const UDP_LEAD: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+enum NetworkError {
+    #[error("<NetworkSink> Invalid Device {0:?}, expected tcp://host:port or udp://host:port")]
+    InvalidDevice(String),
+
+    #[error("<NetworkSink> Failed to Connect to {address}, {e}")]
+    ConnectionRefused { address: String, e: io::Error },
+
+    #[error("<NetworkSink>")]
+    NotConnected,
+
+    #[error("<NetworkSink> {0}")]
+    OnWrite(io::Error),
+}
+
+impl From<NetworkError> for SinkError {
+    fn from(e: NetworkError) -> SinkError {
+        use NetworkError::*;
+        let es = e.to_string();
+        match e {
+            InvalidDevice(_) => SinkError::InvalidParams(es),
+            ConnectionRefused { .. } => SinkError::ConnectionRefused(es),
+            NotConnected => SinkError::NotConnected(es),
+            OnWrite(_) => SinkError::OnWrite(es),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Udp { socket: UdpSocket, sequence: u32 },
+}
+
+pub struct NetworkSink {
+    protocol: Protocol,
+    address: String,
+    connection: Option<Connection>,
+    format: AudioFormat,
+    pacer: Pacer,
+}
+
+impl Open for NetworkSink {
+    fn open(device: Option<String>, format: AudioFormat) -> Self {
+        let device = device.unwrap_or_default();
+
+        if device == "?" {
+            println!("\nUsage:\n\nStream over TCP:\n\n\t--backend network --device tcp://{{host}}:{{port}}\n\nStream over UDP:\n\n\t--backend network --device udp://{{host}}:{{port}}\n");
+            exit(0);
+        }
+
+        let (protocol, address) = match device.split_once("://") {
+            Some(("tcp", address)) => (Protocol::Tcp, address),
+            Some(("udp", address)) => (Protocol::Udp, address),
+            _ => {
+                error!("{}", NetworkError::InvalidDevice(device));
+                exit(1);
+            }
+        };
+
+        info!("Using NetworkSink ({protocol:?} to {address}) with format: {format:?}");
+
+        Self {
+            protocol,
+            address: address.to_string(),
+            connection: None,
+            format,
+            pacer: Pacer::new(UDP_LEAD),
+        }
+    }
+}
+
+impl Sink for NetworkSink {
+    fn start(&mut self) -> SinkResult<()> {
+        if self.connection.is_none() {
+            let connection = self
+                .connect()
+                .map_err(|e| NetworkError::ConnectionRefused {
+                    address: self.address.clone(),
+                    e,
+                })?;
+
+            self.connection = Some(connection);
+            self.pacer.reset();
+
+            if self.protocol == Protocol::Tcp {
+                let header = self.header(0);
+                self.write_tcp(&header)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        // Receivers see the connection close and get a new header on start.
+        if let Some(Connection::Tcp(mut stream)) = self.connection.take() {
+            stream.flush().map_err(NetworkError::OnWrite)?;
+        }
+
+        Ok(())
+    }
+
+    sink_as_bytes!();
+}
+
+impl SinkAsBytes for NetworkSink {
+    fn write_bytes(&mut self, data: &[u8]) -> SinkResult<()> {
+        match self.protocol {
+            Protocol::Tcp => self.write_tcp(data),
+            Protocol::Udp => self.write_udp(data),
+        }
+    }
+}
+
+impl NetworkSink {
+    pub const NAME: &'static str = "network";
+
+    fn connect(&self) -> io::Result<Connection> {
+        match self.protocol {
+            Protocol::Tcp => {
+                let stream = TcpStream::connect(&self.address)?;
+                stream.set_nodelay(true)?;
+                Ok(Connection::Tcp(stream))
+            }
+            Protocol::Udp => {
+                let target = self
+                    .address
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+                let bind_address = if target.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+
+                let socket = UdpSocket::bind(bind_address)?;
+                socket.connect(target)?;
+                Ok(Connection::Udp {
+                    socket,
+                    sequence: 0,
+                })
+            }
+        }
+    }
+
+    fn format_code(&self) -> u8 {
+        match self.format {
+            AudioFormat::F64 => 0,
+            AudioFormat::F32 => 1,
+            AudioFormat::S32 => 2,
+            AudioFormat::S24 => 3,
+            AudioFormat::S24_3 => 4,
+            AudioFormat::S16 => 5,
+        }
+    }
+
+    fn header(&self, sequence: u32) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[5] = self.format_code();
+        header[6] = NUM_CHANNELS;
+        header[8..12].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+        header[12..16].copy_from_slice(&sequence.to_le_bytes());
+        header
+    }
+
+    fn write_tcp(&mut self, data: &[u8]) -> SinkResult<()> {
+        match self.connection.as_mut() {
+            Some(Connection::Tcp(stream)) => {
+                stream.write_all(data).map_err(NetworkError::OnWrite)?;
+                Ok(())
+            }
+            _ => Err(NetworkError::NotConnected.into()),
+        }
+    }
+
+    fn write_udp(&mut self, data: &[u8]) -> SinkResult<()> {
+        let frame_size = self.format.size() * NUM_CHANNELS as usize;
+        let max_payload = (MAX_DATAGRAM_SIZE - HEADER_SIZE) / frame_size * frame_size;
+
+        let mut datagram = Vec::with_capacity(MAX_DATAGRAM_SIZE);
+
+        for payload in data.chunks(max_payload) {
+            let sequence = match self.connection.as_mut() {
+                Some(Connection::Udp { sequence, .. }) => {
+                    let current = *sequence;
+                    *sequence = sequence.wrapping_add(1);
+                    current
+                }
+                _ => return Err(NetworkError::NotConnected.into()),
+            };
+
+            self.pacer.wait(payload.len() / frame_size);
+
+            datagram.clear();
+            datagram.extend_from_slice(&self.header(sequence));
+            datagram.extend_from_slice(payload);
+
+            if let Some(Connection::Udp { socket, .. }) = self.connection.as_ref() {
+                // Lost datagrams are the receiver's problem, but a receiver
+                // that isn't listening (yet) shouldn't stop playback.
+                match socket.send(&datagram) {
+                    Ok(_) => (),
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+                    Err(e) => return Err(NetworkError::OnWrite(e).into()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sink(protocol: Protocol, address: String, format: AudioFormat) -> NetworkSink {
+        NetworkSink {
+            protocol,
+            address,
+            connection: None,
+            format,
+            pacer: Pacer::new(UDP_LEAD),
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let sink = sink(Protocol::Tcp, String::new(), AudioFormat::S16);
+        let header = sink.header(0x0102_0304);
+
+        assert_eq!(&header[0..4], b"LSPC");
+        assert_eq!(header[4], VERSION);
+        assert_eq!(header[5], 5);
+        assert_eq!(header[6], 2);
+        assert_eq!(header[7], 0);
+        assert_eq!(&header[8..12], &44100u32.to_le_bytes());
+        assert_eq!(&header[12..16], &[4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_format_code() {
+        let codes: Vec<u8> = [
+            AudioFormat::F64,
+            AudioFormat::F32,
+            AudioFormat::S32,
+            AudioFormat::S24,
+            AudioFormat::S24_3,
+            AudioFormat::S16,
+        ]
+        .into_iter()
+        .map(|format| sink(Protocol::Tcp, String::new(), format).format_code())
+        .collect();
+
+        assert_eq!(codes, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_udp_datagrams() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = receiver.local_addr().unwrap().to_string();
+
+        let mut sink = sink(Protocol::Udp, address, AudioFormat::S24_3);
+        sink.start().unwrap();
+
+        // More than fits into one datagram, a frame is 6 bytes.
+        let data: Vec<u8> = (0..334 * 6).map(|i| i as u8).collect();
+        sink.write_bytes(&data).unwrap();
+
+        let mut received = Vec::new();
+        let mut datagram = [0; MAX_DATAGRAM_SIZE];
+        for sequence in 0..2u32 {
+            let len = receiver.recv(&mut datagram).unwrap();
+            assert!(len <= MAX_DATAGRAM_SIZE);
+            assert_eq!(datagram[..HEADER_SIZE], sink.header(sequence));
+            assert_eq!((len - HEADER_SIZE) % 6, 0);
+            received.extend_from_slice(&datagram[HEADER_SIZE..len]);
+        }
+
+        assert_eq!(received, data);
+    }
+}
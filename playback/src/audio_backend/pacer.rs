@@ -0,0 +1,42 @@
+use crate::SAMPLE_RATE;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Sinks without a sound card (or a reader) behind them would take packets as
+// fast as they can be decoded. This keeps them at most `lead` ahead of real
+// time instead.
+pub struct Pacer {
+    lead: Duration,
+    start: Option<Instant>,
+    written: Duration,
+}
+
+impl Pacer {
+    pub fn new(lead: Duration) -> Self {
+        Self {
+            lead,
+            start: None,
+            written: Duration::ZERO,
+        }
+    }
+
+    // Call when the output (re)starts, e.g. after a pause.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.written = Duration::ZERO;
+    }
+
+    // Blocks until `frames` more frames may be written.
+    pub fn wait(&mut self, frames: usize) {
+        let start = *self.start.get_or_insert_with(Instant::now);
+
+        if let Some(ahead) = (start + self.written).checked_duration_since(Instant::now()) {
+            if let Some(sleep) = ahead.checked_sub(self.lead) {
+                thread::sleep(sleep);
+            }
+        }
+
+        self.written += Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64);
+    }
+}
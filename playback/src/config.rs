@@ -1,7 +1,10 @@
-use std::{mem, str::FromStr, time::Duration};
+use std::{fmt, mem, str::FromStr, time::Duration};
+
+use thiserror::Error;
 
 pub use crate::dither::{mk_ditherer, DithererBuilder, TriangularDitherer};
-use crate::{convert::i24, player::duration_to_coefficient};
+pub use crate::equalizer::EqBand;
+use crate::{audio::AudioFetchParams, convert::i24, player::duration_to_coefficient};
 
 #[derive(Clone, Copy, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub enum Bitrate {
@@ -28,6 +31,17 @@ impl Default for Bitrate {
     }
 }
 
+impl fmt::Display for Bitrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kbps = match self {
+            Self::Bitrate96 => 96,
+            Self::Bitrate160 => 160,
+            Self::Bitrate320 => 320,
+        };
+        write!(f, "{kbps}")
+    }
+}
+
 #[derive(Clone, Copy, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub enum AudioFormat {
     F64,
@@ -121,11 +135,66 @@ impl Default for NormalisationMethod {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum PlayerConfigError {
+    #[error("bit-perfect playback is incompatible with {0}")]
+    BitPerfect(&'static str),
+    #[error("the download chunk size must be a power of 2 of at least 64 kB")]
+    DownloadChunkSize,
+}
+
 #[derive(Clone)]
 pub struct PlayerConfig {
     pub bitrate: Bitrate,
     pub gapless: bool,
+
+    // Lower the bitrate of the following tracks after running out of data,
+    // and raise it again when the network keeps up. `bitrate` is the highest
+    // bitrate used.
+    pub adaptive_bitrate: bool,
+
     pub passthrough: bool,
+    pub crossfade: Duration,
+
+    // Length of the volume ramps when playback resumes, pauses or seeks.
+    pub fade: Duration,
+
+    // After running out of data, playback pauses until this much audio is
+    // downloaded again. Zero disables rebuffering: playback resumes on every
+    // bit of data that arrives.
+    pub rebuffer_threshold: Duration,
+
+    // How much audio is downloaded before playback starts and kept ahead of
+    // the read position during playback, and how many ping times worth of
+    // requests may be in flight. See `AudioFetchParams` for details. Low
+    // memory devices want these small, flaky networks large.
+    pub read_ahead_before_playback: Duration,
+    pub read_ahead_during_playback: Duration,
+    pub prefetch_threshold_factor: f32,
+
+    // The smallest request made for audio data, and how many requests may be
+    // in flight at once. More parallelism helps on high latency links, less
+    // keeps memory use down.
+    pub download_chunk_size: usize,
+    pub concurrent_downloads: usize,
+
+    // How often to send `PlayerEvent::Stats` while playing, if at all.
+    pub stats_interval: Option<Duration>,
+
+    // How long the sink has to stay closed after playback pauses or stops
+    // before `PlayerEvent::Idle` is sent, e.g. to power an amplifier down.
+    pub idle_timeout: Duration,
+
+    // Skip digital silence at the start and end of tracks.
+    pub trim_silence: bool,
+
+    // Start episodes loaded without a position where the user left off, on
+    // this or another device, and save the progress through them.
+    pub resume_points: bool,
+
+    // When set, samples reach the sink as decoded: no volume, normalisation,
+    // equalizer, crossfade, fades or dithering.
+    pub bit_perfect: bool,
 
     pub normalisation: bool,
     pub normalisation_type: NormalisationType,
@@ -136,6 +205,8 @@ pub struct PlayerConfig {
     pub normalisation_release_cf: f64,
     pub normalisation_knee_db: f64,
 
+    pub equalizer: Vec<EqBand>,
+
     // pass function pointers so they can be lazily instantiated *after* spawning a thread
     // (thereby circumventing Send bounds that they might not satisfy)
     pub ditherer: Option<DithererBuilder>,
@@ -143,9 +214,12 @@ pub struct PlayerConfig {
 
 impl Default for PlayerConfig {
     fn default() -> Self {
+        let fetch_params = AudioFetchParams::default();
+
         Self {
             bitrate: Bitrate::default(),
             gapless: true,
+            adaptive_bitrate: false,
             normalisation: false,
             normalisation_type: NormalisationType::default(),
             normalisation_method: NormalisationMethod::default(),
@@ -154,12 +228,54 @@ impl Default for PlayerConfig {
             normalisation_attack_cf: duration_to_coefficient(Duration::from_millis(5)),
             normalisation_release_cf: duration_to_coefficient(Duration::from_millis(100)),
             normalisation_knee_db: 5.0,
+            equalizer: Vec::new(),
             passthrough: false,
+            crossfade: Duration::ZERO,
+            fade: Duration::ZERO,
+            rebuffer_threshold: Duration::ZERO,
+            read_ahead_before_playback: fetch_params.read_ahead_before_playback,
+            read_ahead_during_playback: fetch_params.read_ahead_during_playback,
+            prefetch_threshold_factor: fetch_params.prefetch_threshold_factor,
+            download_chunk_size: fetch_params.minimum_download_size,
+            concurrent_downloads: fetch_params.concurrent_requests,
+            stats_interval: None,
+            idle_timeout: Duration::ZERO,
+            trim_silence: false,
+            resume_points: true,
+            bit_perfect: false,
             ditherer: Some(mk_ditherer::<TriangularDitherer>),
         }
     }
 }
 
+impl PlayerConfig {
+    pub fn validate(&self) -> Result<(), PlayerConfigError> {
+        if !self.download_chunk_size.is_power_of_two() || self.download_chunk_size <= 32 * 1024 {
+            return Err(PlayerConfigError::DownloadChunkSize);
+        }
+
+        if self.bit_perfect {
+            if self.normalisation {
+                return Err(PlayerConfigError::BitPerfect("volume normalisation"));
+            }
+            if !self.equalizer.is_empty() {
+                return Err(PlayerConfigError::BitPerfect("the equalizer"));
+            }
+            if !self.crossfade.is_zero() {
+                return Err(PlayerConfigError::BitPerfect("crossfading"));
+            }
+            if !self.fade.is_zero() {
+                return Err(PlayerConfigError::BitPerfect("fading"));
+            }
+            if self.ditherer.is_some() {
+                return Err(PlayerConfigError::BitPerfect("dithering"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 // fields are intended for volume control range in dB
 #[derive(Clone, Copy, Debug)]
 pub enum VolumeCtrl {
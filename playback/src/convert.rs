@@ -59,7 +59,12 @@ impl Converter {
         // Casting float to integer rounds towards zero by default, i.e. it
         // truncates, and that generates larger error than rounding to nearest.
         match self.ditherer.as_mut() {
-            Some(d) => (sample * factor + d.noise()).round(),
+            Some(d) => {
+                let dithered = sample * factor + d.noise();
+                let int_value = dithered.round();
+                d.feedback(int_value - dithered);
+                int_value
+            }
             None => (sample * factor).round(),
         }
     }
@@ -1,6 +1,7 @@
 // Passthrough decoder for librespot
 use std::{
     io::{Read, Seek},
+    mem,
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -38,6 +39,10 @@ pub struct PassthroughDecoder<R: Read + Seek> {
     eos: bool,
     bos: bool,
     ofsgp_page: u64,
+    // Granule position where the audio of the next page we output starts.
+    start_gp_page: u64,
+    // Whether we skipped ahead since the last packet, i.e. seeked.
+    skipped: bool,
     stream_serial: u32,
     ident: Vec<u8>,
     comment: Vec<u8>,
@@ -73,6 +78,8 @@ impl<R: Read + Seek> PassthroughDecoder<R> {
             rdr,
             wtr: PacketWriter::new(Vec::new()),
             ofsgp_page: 0,
+            start_gp_page: 0,
+            skipped: false,
             stream_serial,
             ident,
             comment,
@@ -122,9 +129,13 @@ impl<R: Read + Seek> AudioDecoder for PassthroughDecoder<R> {
                     .read_packet()
                     .map_err(|e| DecoderError::PassthroughDecoder(e.to_string()))?;
                 match pck {
+                    // The rest of this page is skipped, so playback resumes
+                    // exactly at its end, the start of the next page.
                     Some(pck) => {
                         let new_page = pck.absgp_page();
                         self.ofsgp_page = new_page;
+                        self.start_gp_page = new_page;
+                        self.skipped = true;
                         debug!("Seek to offset page {}", new_page);
                         let new_position_ms = Self::position_pcm_to_ms(new_page);
                         Ok(new_position_ms)
@@ -205,14 +216,17 @@ impl<R: Read + Seek> AudioDecoder for PassthroughDecoder<R> {
 
             let data = self.wtr.inner_mut();
 
+            // Data is only written at page boundaries. Its position is where
+            // the page starts, the granule position is where it ends.
             if !data.is_empty() {
-                let position_ms = Self::position_pcm_to_ms(pckgp_page);
+                let position_ms = Self::position_pcm_to_ms(self.start_gp_page);
                 let packet_position = AudioPacketPosition {
                     position_ms,
-                    skipped: false,
+                    skipped: mem::take(&mut self.skipped),
                 };
+                self.start_gp_page = pckgp_page;
 
-                let ogg_data = AudioPacket::Raw(std::mem::take(data));
+                let ogg_data = AudioPacket::Raw(mem::take(data));
 
                 return Ok(Some((packet_position, ogg_data)));
             }
@@ -23,6 +23,11 @@ use crate::NUM_CHANNELS;
 //    so unless you have a multibit / R2R DAC, or otherwise know what you are
 //    doing, this is not for you.
 //
+//  * On S16, noise shaped dithering moves the requantization noise out of
+//    the frequencies the ear is most sensitive to. Its total noise power is
+//    higher, but it is perceived as quieter and reveals more low-level detail.
+//    It is tuned for 44.1 kHz, which is all we output.
+//
 //  * Don't dither or shape noise on S32 or F32. On F32 it's not supported
 //    anyway (there are no integer conversions and so no rounding errors) and
 //    on S32 the noise level is so far down that it is simply inaudible even
@@ -34,6 +39,9 @@ pub trait Ditherer {
         Self: Sized;
     fn name(&self) -> &'static str;
     fn noise(&mut self) -> f64;
+    // Receives the requantization error of the sample that `noise()` was
+    // last added to, for ditherers that shape it.
+    fn feedback(&mut self, _error: f64) {}
 }
 
 impl fmt::Display for dyn Ditherer {
@@ -134,6 +142,55 @@ impl HighPassDitherer {
     pub const NAME: &'static str = "tpdf_hp";
 }
 
+// Error feedback filter after: Lipshitz, S.P., Vanderkooy, J., & Wannamaker,
+// R.A. (1991). Minimally Audible Noise Shaping. Journal of the Audio
+// Engineering Society, 39, 836-852. These are the 5-tap E-weighted
+// coefficients for 44.1 kHz.
+const NOISE_SHAPING_COEFFICIENTS: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+pub struct ShapedDitherer {
+    active_channel: usize,
+    previous_errors: [[f64; NOISE_SHAPING_COEFFICIENTS.len()]; NUM_CHANNELS as usize],
+    cached_rng: SmallRng,
+    distribution: Triangular<f64>,
+}
+
+impl Ditherer for ShapedDitherer {
+    fn new() -> Self {
+        Self {
+            active_channel: 0,
+            previous_errors: [[0.0; NOISE_SHAPING_COEFFICIENTS.len()]; NUM_CHANNELS as usize],
+            cached_rng: create_rng(),
+            distribution: Triangular::new(-1.0, 1.0, 0.0).unwrap(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn noise(&mut self) -> f64 {
+        let shaped_error: f64 = NOISE_SHAPING_COEFFICIENTS
+            .iter()
+            .zip(self.previous_errors[self.active_channel].iter())
+            .map(|(coefficient, error)| coefficient * error)
+            .sum();
+
+        self.distribution.sample(&mut self.cached_rng) - shaped_error
+    }
+
+    fn feedback(&mut self, error: f64) {
+        let previous_errors = &mut self.previous_errors[self.active_channel];
+        previous_errors.rotate_right(1);
+        previous_errors[0] = error;
+        self.active_channel ^= 1;
+    }
+}
+
+impl ShapedDitherer {
+    pub const NAME: &'static str = "shaped";
+}
+
 pub fn mk_ditherer<D: Ditherer + 'static>() -> Box<dyn Ditherer> {
     Box::new(D::new())
 }
@@ -145,6 +202,59 @@ pub fn find_ditherer(name: Option<String>) -> Option<DithererBuilder> {
         Some(TriangularDitherer::NAME) => Some(mk_ditherer::<TriangularDitherer>),
         Some(GaussianDitherer::NAME) => Some(mk_ditherer::<GaussianDitherer>),
         Some(HighPassDitherer::NAME) => Some(mk_ditherer::<HighPassDitherer>),
+        Some(ShapedDitherer::NAME) => Some(mk_ditherer::<ShapedDitherer>),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::convert::Converter;
+
+    #[test]
+    fn test_find_ditherer() {
+        let ditherer = find_ditherer(Some(ShapedDitherer::NAME.to_string())).unwrap()();
+        assert_eq!(ditherer.name(), ShapedDitherer::NAME);
+        assert!(find_ditherer(Some("unknown".to_string())).is_none());
+        assert!(find_ditherer(None).is_none());
+    }
+
+    #[test]
+    fn test_shaped_feedback_per_channel() {
+        let mut ditherer = ShapedDitherer::new();
+
+        // Without any error fed back yet, only the triangular noise remains.
+        assert!(ditherer.noise().abs() <= 1.0);
+        ditherer.feedback(10.0);
+
+        // The error of the left channel must not leak into the right one.
+        assert!(ditherer.noise().abs() <= 1.0);
+        ditherer.feedback(0.0);
+
+        let expected = -NOISE_SHAPING_COEFFICIENTS[0] * 10.0;
+        assert!((ditherer.noise() - expected).abs() <= 1.0);
+        ditherer.feedback(0.0);
+
+        // Older errors move on to the next coefficients.
+        ditherer.noise();
+        ditherer.feedback(0.0);
+        let expected = -NOISE_SHAPING_COEFFICIENTS[1] * 10.0;
+        assert!((ditherer.noise() - expected).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_shaped_is_stable_and_unbiased() {
+        let mut converter = Converter::new(find_ditherer(Some(ShapedDitherer::NAME.to_string())));
+
+        // A constant signal a fraction of an LSB above zero.
+        let sample = 0.3 / 32768.0;
+        let samples = vec![sample; 2 * 100_000];
+        let converted = converter.f64_to_s16(&samples);
+
+        assert!(converted.iter().all(|value| value.abs() < 32));
+        let mean =
+            converted.iter().map(|&value| value as f64).sum::<f64>() / converted.len() as f64;
+        assert!((mean - 0.3).abs() < 0.05);
+    }
+}
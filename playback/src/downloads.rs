@@ -0,0 +1,556 @@
+//! Downloads tracks for offline use.
+//!
+//! Downloaded audio files are kept in the downloads directory of the cache,
+//! where they are never evicted. The player finds them like any other cached
+//! file. Decryption keys are not stored, so playback still needs a session.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::{
+    audio::download_file,
+    config::Bitrate,
+    core::{spotify_id::SpotifyItemType, Error, FileId, Session, SpotifyId},
+    metadata::{
+        audio::{AudioItem, UniqueFields},
+        Metadata, Playlist,
+    },
+    player::preferred_formats,
+};
+
+const INDEX_FILE: &str = "index.json";
+
+/// Looks up the audio file of a track or episode to fetch for a bitrate.
+pub(crate) async fn resolve_file(
+    session: &Session,
+    track_id: SpotifyId,
+    bitrate: Bitrate,
+) -> Result<(AudioItem, FileId), Error> {
+    let uri = track_id.to_uri()?;
+
+    let audio_item = AudioItem::get_file(session, track_id).await?;
+    if let Err(e) = audio_item.availability {
+        warn!("<{}> is unavailable: {}", uri, e);
+        return Err(DownloadError::Unavailable(uri).into());
+    }
+
+    let file_id = preferred_formats(bitrate)
+        .iter()
+        .find_map(|format| audio_item.files.get(format).copied())
+        .ok_or(DownloadError::Unavailable(uri))?;
+
+    Ok((audio_item, file_id))
+}
+
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error("downloads location is not configured")]
+    Location,
+    #[error("<{0}> is not available in any supported format")]
+    Unavailable(String),
+    #[error("downloading <{uri}> would exceed the storage quota of {quota} bytes")]
+    Quota { uri: String, quota: u64 },
+}
+
+impl From<DownloadError> for Error {
+    fn from(err: DownloadError) -> Self {
+        match err {
+            DownloadError::Location => Error::failed_precondition(err),
+            DownloadError::Unavailable(_) => Error::unavailable(err),
+            DownloadError::Quota { .. } => Error::resource_exhausted(err),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DownloadConfig {
+    pub bitrate: Bitrate,
+    // The most disk space downloads may take up, in bytes.
+    pub quota: Option<u64>,
+}
+
+/// A track that was downloaded, or is being downloaded, with the metadata to
+/// show it without going online.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DownloadedTrack {
+    pub uri: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub duration_ms: u32,
+    pub cover_url: Option<String>,
+    pub size: u64,
+    pub complete: bool,
+    file_id: String,
+}
+
+impl DownloadedTrack {
+    fn file_id(&self) -> Option<FileId> {
+        let bytes: Option<Vec<u8>> = (0..self.file_id.len())
+            .step_by(2)
+            .map(|i| {
+                self.file_id
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect();
+
+        bytes
+            .filter(|bytes| bytes.len() == 20)
+            .map(|bytes| FileId::from_raw(&bytes))
+    }
+}
+
+// The disk space taken up by downloads, not counting `except`.
+fn usage(index: &HashMap<String, DownloadedTrack>, except: Option<&str>) -> u64 {
+    index
+        .values()
+        .filter(|track| Some(track.uri.as_str()) != except)
+        .map(|track| track.size)
+        .sum()
+}
+
+// Whether `uri` still fits into the quota with `size` bytes, next to all other
+// downloads.
+fn check_quota(
+    index: &HashMap<String, DownloadedTrack>,
+    uri: &str,
+    size: u64,
+    quota: Option<u64>,
+) -> Result<(), DownloadError> {
+    match quota {
+        Some(quota) if usage(index, Some(uri)) + size > quota => Err(DownloadError::Quota {
+            uri: uri.to_owned(),
+            quota,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DownloadEvent {
+    Queued {
+        track_id: SpotifyId,
+    },
+    Progress {
+        track_id: SpotifyId,
+        downloaded: usize,
+        total: usize,
+    },
+    Completed {
+        track_id: SpotifyId,
+        size: u64,
+    },
+    Failed {
+        track_id: SpotifyId,
+        error: String,
+    },
+    Removed {
+        track_id: SpotifyId,
+    },
+}
+
+enum DownloadCommand {
+    Track(SpotifyId),
+    Playlist(SpotifyId),
+}
+
+struct DownloadShared {
+    session: Session,
+    config: DownloadConfig,
+    location: PathBuf,
+    index: Mutex<HashMap<String, DownloadedTrack>>,
+    event_senders: Mutex<Vec<mpsc::UnboundedSender<DownloadEvent>>>,
+}
+
+/// Downloads tracks one at a time in the background. Downloads that did not
+/// finish, also in an earlier run, are resumed.
+pub struct DownloadManager {
+    commands: mpsc::UnboundedSender<DownloadCommand>,
+    shared: Arc<DownloadShared>,
+}
+
+impl DownloadManager {
+    pub fn new(session: Session, config: DownloadConfig) -> Result<Self, Error> {
+        let location = session
+            .cache()
+            .and_then(|cache| cache.downloads_location())
+            .ok_or(DownloadError::Location)?
+            .to_owned();
+        fs::create_dir_all(&location)?;
+
+        let index = match DownloadShared::read_index(&location) {
+            Ok(index) => index,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Error reading downloads index: {}", e);
+                }
+                HashMap::new()
+            }
+        };
+
+        let unfinished: Vec<SpotifyId> = index
+            .values()
+            .filter(|track| !track.complete)
+            .filter_map(|track| SpotifyId::from_uri(&track.uri).ok())
+            .collect();
+
+        let shared = Arc::new(DownloadShared {
+            session: session.clone(),
+            config,
+            location,
+            index: Mutex::new(index),
+            event_senders: Mutex::new(Vec::new()),
+        });
+
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        session.spawn(DownloadShared::run(shared.clone(), commands_rx));
+
+        let manager = Self { commands, shared };
+        for track_id in unfinished {
+            manager.download_track(track_id);
+        }
+
+        Ok(manager)
+    }
+
+    pub fn download_track(&self, track_id: SpotifyId) {
+        self.shared.send_event(DownloadEvent::Queued { track_id });
+        let _ = self.commands.send(DownloadCommand::Track(track_id));
+    }
+
+    pub fn download_playlist(&self, playlist_id: SpotifyId) {
+        let _ = self.commands.send(DownloadCommand::Playlist(playlist_id));
+    }
+
+    /// Removes a download and its audio file.
+    pub fn remove(&self, track_id: SpotifyId) -> Result<(), Error> {
+        let uri = track_id.to_uri()?;
+        let track = match self.shared.index.lock().remove(&uri) {
+            Some(track) => track,
+            None => return Ok(()),
+        };
+
+        if let Some(path) = track
+            .file_id()
+            .and_then(|file_id| self.shared.path(file_id))
+        {
+            let path = if track.complete {
+                path
+            } else {
+                path.with_extension("part")
+            };
+
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+
+        self.shared.write_index()?;
+        self.shared.send_event(DownloadEvent::Removed { track_id });
+
+        Ok(())
+    }
+
+    pub fn downloads(&self) -> Vec<DownloadedTrack> {
+        self.shared.index.lock().values().cloned().collect()
+    }
+
+    /// The disk space taken up by downloads, in bytes.
+    pub fn usage(&self) -> u64 {
+        self.shared.usage(None)
+    }
+
+    pub fn get_event_channel(&self) -> mpsc::UnboundedReceiver<DownloadEvent> {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+        self.shared.event_senders.lock().push(event_sender);
+        event_receiver
+    }
+}
+
+impl DownloadShared {
+    async fn run(shared: Arc<Self>, mut commands: mpsc::UnboundedReceiver<DownloadCommand>) {
+        while let Some(command) = commands.recv().await {
+            match command {
+                DownloadCommand::Track(track_id) => shared.download_and_report(track_id).await,
+                DownloadCommand::Playlist(playlist_id) => {
+                    let playlist = match Playlist::get(&shared.session, &playlist_id).await {
+                        Ok(playlist) => playlist,
+                        Err(e) => {
+                            error!("Unable to load playlist for download: {}", e);
+                            continue;
+                        }
+                    };
+
+                    let track_ids: Vec<SpotifyId> = playlist.tracks().copied().collect();
+                    if let Err(e) = shared
+                        .session
+                        .spclient()
+                        .prefetch_metadata(&track_ids)
+                        .await
+                    {
+                        warn!("Unable to prefetch metadata of the playlist: {}", e);
+                    }
+
+                    for track_id in track_ids {
+                        if matches!(
+                            track_id.item_type,
+                            SpotifyItemType::Track | SpotifyItemType::Episode
+                        ) {
+                            shared.send_event(DownloadEvent::Queued { track_id });
+                            shared.download_and_report(track_id).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn download_and_report(&self, track_id: SpotifyId) {
+        if let Err(e) = self.download(track_id).await {
+            error!(
+                "Unable to download <{}>: {}",
+                track_id.to_uri().unwrap_or_default(),
+                e
+            );
+            self.send_event(DownloadEvent::Failed {
+                track_id,
+                error: e.to_string(),
+            });
+        }
+    }
+
+    async fn download(&self, track_id: SpotifyId) -> Result<(), Error> {
+        let uri = track_id.to_uri()?;
+        if self
+            .index
+            .lock()
+            .get(&uri)
+            .is_some_and(|track| track.complete)
+        {
+            debug!("<{}> is already downloaded", uri);
+            return Ok(());
+        }
+
+        let (audio_item, file_id) =
+            resolve_file(&self.session, track_id, self.config.bitrate).await?;
+
+        let path = self.path(file_id).ok_or(DownloadError::Location)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let part_path = path.with_extension("part");
+
+        self.index
+            .lock()
+            .insert(uri.clone(), Self::track_entry(&audio_item, file_id)?);
+        self.write_index()?;
+
+        info!(
+            "Downloading <{}> with Spotify URI <{}>",
+            audio_item.name, uri
+        );
+
+        let mut exceeds_quota = false;
+        let result = download_file(&self.session, file_id, &part_path, |downloaded, total| {
+            let quota_check =
+                check_quota(&self.index.lock(), &uri, total as u64, self.config.quota);
+            if let Err(e) = quota_check {
+                exceeds_quota = true;
+                return Err(e.into());
+            }
+
+            if let Some(track) = self.index.lock().get_mut(&uri) {
+                track.size = downloaded as u64;
+            }
+
+            self.send_event(DownloadEvent::Progress {
+                track_id,
+                downloaded,
+                total,
+            });
+
+            Ok(())
+        })
+        .await;
+
+        let size = match result {
+            Ok(size) => size as u64,
+            Err(e) => {
+                // Anything else is kept to resume from later on.
+                if exceeds_quota {
+                    let _ = fs::remove_file(&part_path);
+                    self.index.lock().remove(&uri);
+                    self.write_index()?;
+                }
+                return Err(e);
+            }
+        };
+
+        fs::rename(&part_path, &path)?;
+
+        if let Some(track) = self.index.lock().get_mut(&uri) {
+            track.size = size;
+            track.complete = true;
+        }
+        self.write_index()?;
+
+        self.send_event(DownloadEvent::Completed { track_id, size });
+
+        Ok(())
+    }
+
+    fn track_entry(audio_item: &AudioItem, file_id: FileId) -> Result<DownloadedTrack, Error> {
+        let (artists, album) = match &audio_item.unique_fields {
+            UniqueFields::Track { artists, album, .. } => (
+                artists.iter().map(|artist| artist.name.clone()).collect(),
+                album.clone(),
+            ),
+            UniqueFields::Episode { show_name, .. } => (Vec::new(), show_name.clone()),
+        };
+
+        let cover_url = audio_item
+            .covers
+            .iter()
+            .max_by_key(|cover| cover.width)
+            .map(|cover| cover.url.clone());
+
+        Ok(DownloadedTrack {
+            uri: audio_item.uri.clone(),
+            name: audio_item.name.clone(),
+            artists,
+            album: Some(album).filter(|album| !album.is_empty()),
+            duration_ms: audio_item.duration_ms,
+            cover_url,
+            size: 0,
+            complete: false,
+            file_id: file_id.to_base16()?,
+        })
+    }
+
+    fn path(&self, file_id: FileId) -> Option<PathBuf> {
+        self.session
+            .cache()
+            .and_then(|cache| cache.download_path(file_id))
+    }
+
+    fn usage(&self, except: Option<&str>) -> u64 {
+        usage(&self.index.lock(), except)
+    }
+
+    fn read_index(location: &Path) -> io::Result<HashMap<String, DownloadedTrack>> {
+        let contents = fs::read_to_string(location.join(INDEX_FILE))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn write_index(&self) -> Result<(), Error> {
+        Self::write_index_to(&self.location, &self.index.lock())
+    }
+
+    fn write_index_to(
+        location: &Path,
+        index: &HashMap<String, DownloadedTrack>,
+    ) -> Result<(), Error> {
+        let contents = serde_json::to_string(index)?;
+        fs::write(location.join(INDEX_FILE), contents)?;
+        Ok(())
+    }
+
+    fn send_event(&self, event: DownloadEvent) {
+        self.event_senders
+            .lock()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn track(uri: &str, size: u64, complete: bool) -> DownloadedTrack {
+        DownloadedTrack {
+            uri: uri.to_owned(),
+            name: "Name".to_owned(),
+            artists: vec!["Artist".to_owned()],
+            album: Some("Album".to_owned()),
+            duration_ms: 180_000,
+            cover_url: None,
+            size,
+            complete,
+            file_id: "00112233445566778899aabbccddeeff00112233".to_owned(),
+        }
+    }
+
+    fn index(tracks: &[DownloadedTrack]) -> HashMap<String, DownloadedTrack> {
+        tracks
+            .iter()
+            .map(|track| (track.uri.clone(), track.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_quota() {
+        let index = index(&[
+            track("spotify:track:a", 600, true),
+            track("spotify:track:b", 300, false),
+        ]);
+
+        assert_eq!(usage(&index, None), 900);
+        assert!(check_quota(&index, "spotify:track:c", 100, Some(1000)).is_ok());
+        assert!(matches!(
+            check_quota(&index, "spotify:track:c", 101, Some(1000)),
+            Err(DownloadError::Quota { quota: 1000, .. })
+        ));
+        assert!(check_quota(&index, "spotify:track:c", u64::MAX / 2, None).is_ok());
+
+        // What is already downloaded of the track itself doesn't count twice.
+        assert!(check_quota(&index, "spotify:track:b", 400, Some(1000)).is_ok());
+        assert!(check_quota(&index, "spotify:track:b", 401, Some(1000)).is_err());
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let location =
+            std::env::temp_dir().join(format!("librespot-downloads-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&location);
+        fs::create_dir_all(&location).unwrap();
+
+        let written = index(&[
+            track("spotify:track:a", 600, true),
+            track("spotify:episode:b", 300, false),
+        ]);
+        DownloadShared::write_index_to(&location, &written).unwrap();
+
+        let read = DownloadShared::read_index(&location).unwrap();
+        assert_eq!(read.len(), 2);
+        let track = &read["spotify:track:a"];
+        assert_eq!(track.size, 600);
+        assert!(track.complete);
+        assert_eq!(track.artists, ["Artist"]);
+        assert_eq!(track.album.as_deref(), Some("Album"));
+        assert!(!read["spotify:episode:b"].complete);
+        assert_eq!(track.file_id().unwrap().to_base16().unwrap(), track.file_id);
+
+        fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_file_id() {
+        let mut track = track("spotify:track:a", 0, false);
+        track.file_id = "0011".to_owned();
+        assert!(track.file_id().is_none());
+        track.file_id = "zz".repeat(20);
+        assert!(track.file_id().is_none());
+    }
+}
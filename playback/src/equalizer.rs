@@ -0,0 +1,220 @@
+use std::f64::consts::PI;
+
+use crate::{NUM_CHANNELS, SAMPLE_RATE};
+
+// A parametric equalizer made of peaking filters in series, one per band.
+// Each band is a biquad after: Bristow-Johnson, R. Cookbook formulae for audio
+// EQ biquad filter coefficients.
+//
+// The centre frequencies of the default bands are those of the common ISO
+// octave band graphic equalizers, so a list of gains is enough to configure it.
+
+pub const DEFAULT_BAND_FREQUENCIES: [f64; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+// One octave wide bands.
+pub const DEFAULT_BAND_Q: f64 = std::f64::consts::SQRT_2;
+
+// Gains beyond this are clamped, both ways.
+pub const MAX_GAIN_DB: f64 = 12.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBand {
+    pub frequency: f64,
+    pub gain_db: f64,
+    pub q: f64,
+}
+
+impl EqBand {
+    pub fn new(frequency: f64, gain_db: f64, q: f64) -> Self {
+        Self {
+            frequency,
+            gain_db,
+            q,
+        }
+    }
+
+    /// Creates the default octave bands with the given gains, in order of
+    /// [`DEFAULT_BAND_FREQUENCIES`]. Missing gains are left flat.
+    pub fn from_gains(gains_db: &[f64]) -> Vec<Self> {
+        DEFAULT_BAND_FREQUENCIES
+            .iter()
+            .zip(gains_db.iter().chain(std::iter::repeat(&0.0)))
+            .map(|(&frequency, &gain_db)| Self::new(frequency, gain_db, DEFAULT_BAND_Q))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    fn peaking(band: &EqBand) -> Self {
+        let a = 10.0_f64.powf(band.gain_db / 40.0);
+        let w0 = 2.0 * PI * band.frequency / SAMPLE_RATE as f64;
+        let alpha = w0.sin() / (2.0 * band.q);
+        let cos_w0 = w0.cos();
+
+        let a0 = 1.0 + alpha / a;
+
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: (-2.0 * cos_w0) / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+}
+
+// Transposed direct form II state, per channel.
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    z1: f64,
+    z2: f64,
+}
+
+struct Filter {
+    coefficients: Coefficients,
+    states: [State; NUM_CHANNELS as usize],
+}
+
+impl Filter {
+    fn process(&mut self, sample: f64, channel: usize) -> f64 {
+        let c = &self.coefficients;
+        let s = &mut self.states[channel];
+
+        let output = c.b0 * sample + s.z1;
+        s.z1 = c.b1 * sample - c.a1 * output + s.z2;
+        s.z2 = c.b2 * sample - c.a2 * output;
+
+        output
+    }
+}
+
+pub struct Equalizer {
+    filters: Vec<Filter>,
+}
+
+impl Equalizer {
+    pub fn new(bands: &[EqBand]) -> Self {
+        let nyquist = SAMPLE_RATE as f64 / 2.0;
+
+        let filters = bands
+            .iter()
+            .filter(|band| {
+                let valid = band.frequency > 0.0 && band.frequency < nyquist && band.q > 0.0;
+                if !valid {
+                    warn!("Ignoring invalid equalizer band: {:?}", band);
+                }
+
+                // Flat bands don't do anything, so spare the CPU cycles.
+                valid && band.gain_db != 0.0
+            })
+            .map(|band| {
+                let gain_db = band.gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+                if gain_db != band.gain_db {
+                    warn!("Clamping equalizer band gain to {gain_db} dB: {:?}", band);
+                }
+
+                Filter {
+                    coefficients: Coefficients::peaking(&EqBand { gain_db, ..*band }),
+                    states: Default::default(),
+                }
+            })
+            .collect();
+
+        Self { filters }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Filters interleaved samples in place.
+    pub fn process(&mut self, samples: &mut [f64]) {
+        for frame in samples.chunks_mut(NUM_CHANNELS as usize) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                for filter in self.filters.iter_mut() {
+                    *sample = filter.process(*sample, channel);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Feeds a stereo sine of the given frequency through the equalizer and
+    // returns the ratio of output to input peak amplitude once settled.
+    fn gain_at(equalizer: &mut Equalizer, frequency: f64) -> f64 {
+        let frames = SAMPLE_RATE as usize;
+        let mut samples: Vec<f64> = (0..frames)
+            .flat_map(|i| {
+                let sample = 0.25 * (2.0 * PI * frequency * i as f64 / SAMPLE_RATE as f64).sin();
+                [sample, sample]
+            })
+            .collect();
+        equalizer.process(&mut samples);
+
+        let settled = &samples[samples.len() / 2..];
+        settled.iter().fold(0.0_f64, |max, s| max.max(s.abs())) / 0.25
+    }
+
+    fn to_db(ratio: f64) -> f64 {
+        20.0 * ratio.log10()
+    }
+
+    #[test]
+    fn test_flat_is_identity() {
+        let mut equalizer = Equalizer::new(&EqBand::from_gains(&[0.0; 10]));
+        assert!(equalizer.is_flat());
+
+        let mut samples = vec![0.5, -0.5, 0.25, -0.25];
+        equalizer.process(&mut samples);
+        assert_eq!(samples, [0.5, -0.5, 0.25, -0.25]);
+    }
+
+    #[test]
+    fn test_band_gain_at_centre_frequency() {
+        let mut equalizer = Equalizer::new(&[EqBand::new(1000.0, 6.0, DEFAULT_BAND_Q)]);
+        assert!(!equalizer.is_flat());
+        assert!((to_db(gain_at(&mut equalizer, 1000.0)) - 6.0).abs() < 0.1);
+
+        let mut equalizer = Equalizer::new(&[EqBand::new(1000.0, -6.0, DEFAULT_BAND_Q)]);
+        assert!((to_db(gain_at(&mut equalizer, 1000.0)) + 6.0).abs() < 0.1);
+
+        // Far away from the band, the signal passes through unchanged.
+        let mut equalizer = Equalizer::new(&[EqBand::new(1000.0, 6.0, DEFAULT_BAND_Q)]);
+        assert!(to_db(gain_at(&mut equalizer, 16000.0)).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_gain_is_clamped() {
+        let mut equalizer = Equalizer::new(&[EqBand::new(1000.0, 40.0, DEFAULT_BAND_Q)]);
+        assert!((to_db(gain_at(&mut equalizer, 1000.0)) - MAX_GAIN_DB).abs() < 0.1);
+
+        let mut equalizer = Equalizer::new(&[EqBand::new(1000.0, -40.0, DEFAULT_BAND_Q)]);
+        assert!((to_db(gain_at(&mut equalizer, 1000.0)) + MAX_GAIN_DB).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_invalid_bands_are_ignored() {
+        let nyquist = SAMPLE_RATE as f64 / 2.0;
+        let equalizer = Equalizer::new(&[
+            EqBand::new(0.0, 6.0, DEFAULT_BAND_Q),
+            EqBand::new(nyquist, 6.0, DEFAULT_BAND_Q),
+            EqBand::new(1000.0, 6.0, 0.0),
+        ]);
+        assert!(equalizer.is_flat());
+    }
+}
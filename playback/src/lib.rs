@@ -10,10 +10,19 @@ pub mod config;
 pub mod convert;
 pub mod decoder;
 pub mod dither;
+pub mod downloads;
+pub mod equalizer;
 pub mod mixer;
 pub mod player;
+pub mod precache;
 
+/// The rate of all decoded audio and of every sink. There is no resampler in
+/// the pipeline; devices that need another rate rely on their backend (ALSA
+/// plug devices, PulseAudio, rodio or GStreamer) to convert it.
 pub const SAMPLE_RATE: u32 = 44100;
+/// All stages and sinks use interleaved stereo. Routing to other channels of
+/// a multichannel device is left to the sound server, e.g. an ALSA route PCM
+/// or a PipeWire remap sink.
 pub const NUM_CHANNELS: u8 = 2;
 pub const SAMPLES_PER_SECOND: u32 = SAMPLE_RATE * NUM_CHANNELS as u32;
 pub const PAGES_PER_MS: f64 = SAMPLE_RATE as f64 / 1000.0;
@@ -4,10 +4,14 @@ use super::mappings::{LogMapping, MappedCtrl, VolumeMapping};
 use super::{Mixer, MixerConfig, VolumeCtrl};
 
 use alsa::ctl::{ElemId, ElemIface};
-use alsa::mixer::{MilliBel, SelemChannelId, SelemId};
+use alsa::mixer::{MilliBel, Selem, SelemChannelId, SelemId};
 use alsa::{Ctl, Round};
 
 use std::ffi::CString;
+use std::process::exit;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
 
 #[derive(Clone)]
 #[allow(dead_code)]
@@ -22,6 +26,21 @@ pub struct AlsaMixer {
     has_switch: bool,
     is_softvol: bool,
     use_linear_in_db: bool,
+    // Reported while the control can't be reached, e.g. when the device was
+    // unplugged or its controls were re-enumerated.
+    last_volume: Arc<AtomicU16>,
+}
+
+#[derive(Debug, Error)]
+enum AlsaMixerError {
+    #[error("<AlsaMixer> Could not open mixer for device {device}, {e}")]
+    OpenMixer { device: String, e: alsa::Error },
+
+    #[error("<AlsaMixer> Could not find mixer control {control},{index}")]
+    NoControl { control: String, index: u32 },
+
+    #[error("<AlsaMixer> {0}")]
+    Alsa(#[from] alsa::Error),
 }
 
 // min_db cannot be depended on to be mute. Also note that contrary to
@@ -38,11 +57,47 @@ impl Mixer for AlsaMixer {
 
         let mut config = config; // clone
 
+        if config.control == "?" {
+            match list_usable_controls(&config.device) {
+                Ok(_) => exit(0),
+                Err(e) => {
+                    error!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+
         let mixer =
             alsa::mixer::Mixer::new(&config.device, false).expect("Could not open Alsa mixer");
-        let simple_element = mixer
-            .find_selem(&SelemId::new(&config.control, config.index))
-            .expect("Could not find Alsa mixer control");
+        let simple_element = match mixer.find_selem(&SelemId::new(&config.control, config.index)) {
+            Some(simple_element) => simple_element,
+            None => {
+                error!(
+                    "{}",
+                    AlsaMixerError::NoControl {
+                        control: config.control,
+                        index: config.index,
+                    }
+                );
+
+                match usable_controls(&config.device) {
+                    Ok(controls) if !controls.is_empty() => {
+                        let controls: Vec<String> = controls
+                            .iter()
+                            .map(|(control, index)| format!("{control},{index}"))
+                            .collect();
+                        error!("Usable mixer controls are: {}", controls.join(" "));
+                    }
+                    _ => error!("There are no usable mixer controls on this device"),
+                }
+
+                exit(1);
+            }
+        };
+
+        if !simple_element.has_playback_volume() {
+            warn!("Alsa mixer control has no playback volume, use `--alsa-mixer-control ?` to list usable controls");
+        }
 
         // Query capabilities
         let has_switch = simple_element.has_playback_switch();
@@ -161,29 +216,70 @@ impl Mixer for AlsaMixer {
             has_switch,
             is_softvol,
             use_linear_in_db,
+            last_volume: Arc::new(AtomicU16::new(0)),
         }
     }
 
     fn volume(&self) -> u16 {
-        let mixer =
-            alsa::mixer::Mixer::new(&self.config.device, false).expect("Could not open Alsa mixer");
+        match self.with_element(|simple_element| self.get_volume(simple_element)) {
+            Ok(volume) => {
+                self.last_volume.store(volume, Ordering::Relaxed);
+                volume
+            }
+            Err(e) => {
+                warn!("{}, reporting the last known volume", e);
+                self.last_volume.load(Ordering::Relaxed)
+            }
+        }
+    }
+
+    fn set_volume(&self, volume: u16) {
+        if let Err(e) =
+            self.with_element(|simple_element| self.set_element_volume(simple_element, volume))
+        {
+            error!("{}", e);
+        }
+
+        self.last_volume.store(volume, Ordering::Relaxed);
+    }
+}
+
+impl AlsaMixer {
+    pub const NAME: &'static str = "alsa";
+
+    // The mixer is opened again on every access, so that it keeps working
+    // when the device or its controls come and go.
+    fn with_element<T>(
+        &self,
+        f: impl FnOnce(&Selem) -> Result<T, AlsaMixerError>,
+    ) -> Result<T, AlsaMixerError> {
+        let mixer = alsa::mixer::Mixer::new(&self.config.device, false).map_err(|e| {
+            AlsaMixerError::OpenMixer {
+                device: self.config.device.clone(),
+                e,
+            }
+        })?;
         let simple_element = mixer
             .find_selem(&SelemId::new(&self.config.control, self.config.index))
-            .expect("Could not find Alsa mixer control");
+            .ok_or_else(|| AlsaMixerError::NoControl {
+                control: self.config.control.clone(),
+                index: self.config.index,
+            })?;
+
+        f(&simple_element)
+    }
 
-        if self.switched_off() {
-            return 0;
+    fn get_volume(&self, simple_element: &Selem) -> Result<u16, AlsaMixerError> {
+        if self.switched_off(simple_element) {
+            return Ok(0);
         }
 
         let mut mapped_volume = if self.is_softvol {
-            let raw_volume = simple_element
-                .get_playback_volume(SelemChannelId::mono())
-                .expect("Could not get raw Alsa volume");
-            raw_volume as f64 / self.range as f64 - self.min as f64
+            let raw_volume = simple_element.get_playback_volume(SelemChannelId::mono())?;
+            (raw_volume - self.min) as f64 / self.range as f64
         } else {
             let db_volume = simple_element
-                .get_playback_vol_db(SelemChannelId::mono())
-                .expect("Could not get Alsa dB volume")
+                .get_playback_vol_db(SelemChannelId::mono())?
                 .to_db() as f64;
 
             if self.use_linear_in_db {
@@ -201,27 +297,26 @@ impl Mixer for AlsaMixer {
             mapped_volume = LogMapping::linear_to_mapped(mapped_volume, self.db_range);
         }
 
-        self.config.volume_ctrl.as_unmapped(mapped_volume)
+        Ok(self.config.volume_ctrl.as_unmapped(mapped_volume))
     }
 
-    fn set_volume(&self, volume: u16) {
-        let mixer =
-            alsa::mixer::Mixer::new(&self.config.device, false).expect("Could not open Alsa mixer");
-        let simple_element = mixer
-            .find_selem(&SelemId::new(&self.config.control, self.config.index))
-            .expect("Could not find Alsa mixer control");
-
+    fn set_element_volume(
+        &self,
+        simple_element: &Selem,
+        volume: u16,
+    ) -> Result<(), AlsaMixerError> {
+        // Failing to switch is not fatal, the volume alone still works.
         if self.has_switch {
             if volume == 0 {
                 debug!("Disabling playback (setting mute) on Alsa");
-                simple_element
-                    .set_playback_switch_all(0)
-                    .expect("Could not disable playback (set mute) on Alsa");
-            } else if self.switched_off() {
+                if let Err(e) = simple_element.set_playback_switch_all(0) {
+                    warn!("Could not disable playback (set mute) on Alsa: {}", e);
+                }
+            } else if self.switched_off(simple_element) {
                 debug!("Enabling playback (unsetting mute) on Alsa");
-                simple_element
-                    .set_playback_switch_all(1)
-                    .expect("Could not enable playback (unset mute) on Alsa");
+                if let Err(e) = simple_element.set_playback_switch_all(1) {
+                    warn!("Could not enable playback (unset mute) on Alsa: {}", e);
+                }
             }
         }
 
@@ -240,10 +335,8 @@ impl Mixer for AlsaMixer {
         if self.is_softvol {
             let scaled_volume = (self.min as f64 + mapped_volume * self.range as f64) as i64;
             debug!("Setting Alsa raw volume to {}", scaled_volume);
-            simple_element
-                .set_playback_volume_all(scaled_volume)
-                .expect("Could not set Alsa raw volume");
-            return;
+            simple_element.set_playback_volume_all(scaled_volume)?;
+            return Ok(());
         }
 
         let db_volume = if self.use_linear_in_db {
@@ -256,26 +349,16 @@ impl Mixer for AlsaMixer {
         };
 
         debug!("Setting Alsa volume to {:.2} dB", db_volume);
-        simple_element
-            .set_playback_db_all(MilliBel::from_db(db_volume as f32), Round::Floor)
-            .expect("Could not set Alsa dB volume");
-    }
-}
+        simple_element.set_playback_db_all(MilliBel::from_db(db_volume as f32), Round::Floor)?;
 
-impl AlsaMixer {
-    pub const NAME: &'static str = "alsa";
+        Ok(())
+    }
 
-    fn switched_off(&self) -> bool {
+    fn switched_off(&self, simple_element: &Selem) -> bool {
         if !self.has_switch {
             return false;
         }
 
-        let mixer =
-            alsa::mixer::Mixer::new(&self.config.device, false).expect("Could not open Alsa mixer");
-        let simple_element = mixer
-            .find_selem(&SelemId::new(&self.config.control, self.config.index))
-            .expect("Could not find Alsa mixer control");
-
         simple_element
             .get_playback_switch(SelemChannelId::mono())
             .map(|playback| playback == 0)
@@ -286,3 +369,35 @@ impl AlsaMixer {
         self.is_softvol || self.use_linear_in_db
     }
 }
+
+/// Returns the name and index of every control on `device` that has a
+/// playback volume, and so can be used with `--alsa-mixer-control`.
+pub fn usable_controls(device: &str) -> Result<Vec<(String, u32)>, alsa::Error> {
+    let mixer = alsa::mixer::Mixer::new(device, false)?;
+
+    let controls = mixer
+        .iter()
+        .filter_map(Selem::new)
+        .filter(|simple_element| simple_element.has_playback_volume())
+        .filter_map(|simple_element| {
+            let id = simple_element.get_id();
+            let name = id.get_name().ok()?.to_string();
+            Some((name, id.get_index()))
+        })
+        .collect();
+
+    Ok(controls)
+}
+
+fn list_usable_controls(device: &str) -> Result<(), alsa::Error> {
+    println!("\n\n\tUsable alsa mixer control(s) on {device}:\n");
+    println!("\t------------------------------------------------------\n");
+
+    for (control, index) in usable_controls(device)? {
+        println!("\tControl:\n\n\t\t{control}\n");
+        println!("\tIndex:\n\n\t\t{index}\n");
+        println!("\t------------------------------------------------------\n");
+    }
+
+    Ok(())
+}
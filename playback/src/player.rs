@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt,
     future::Future,
     io::{self, Read, Seek, SeekFrom},
@@ -26,20 +26,34 @@ use tokio::sync::{mpsc, oneshot};
 use crate::{
     audio::{AudioDecrypt, AudioFetchParams, AudioFile, StreamLoaderController},
     audio_backend::Sink,
-    config::{Bitrate, NormalisationMethod, NormalisationType, PlayerConfig},
+    config::{Bitrate, NormalisationMethod, NormalisationType, PlayerConfig, PlayerConfigError},
     convert::Converter,
-    core::{util::SeqGenerator, Error, Session, SpotifyId},
+    core::{spotify_id::SpotifyItemType, util::SeqGenerator, Error, Session, SpotifyId},
     decoder::{AudioDecoder, AudioPacket, AudioPacketPosition, SymphoniaDecoder},
-    metadata::audio::{AudioFileFormat, AudioFiles, AudioItem},
+    equalizer::{EqBand, Equalizer},
+    metadata::{
+        audio::{AudioFileFormat, AudioFiles, AudioItem, UniqueFields},
+        ResumePoint,
+    },
     mixer::VolumeGetter,
 };
 
 #[cfg(feature = "passthrough-decoder")]
 use crate::decoder::PassthroughDecoder;
 
-use crate::SAMPLES_PER_SECOND;
+use crate::{NUM_CHANNELS, SAMPLES_PER_SECOND, SAMPLE_RATE};
 
 const PRELOAD_NEXT_TRACK_BEFORE_END_DURATION_MS: u32 = 30000;
+const REBUFFER_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const RESUME_POINT_INTERVAL: Duration = Duration::from_secs(30);
+
+// Anything below the least significant bit of 16 bit audio counts as silence.
+const SILENCE_THRESHOLD: f64 = 1.0 / 32768.0;
+
+// With adaptive bitrate, the network has to be this many times faster than the
+// next higher bitrate needs before it is used again.
+const BITRATE_RECOVERY_HEADROOM: usize = 4;
+
 pub const DB_VOLTAGE_RATIO: f64 = 20.0;
 pub const PCM_AT_0DBFS: f64 = 1.0;
 
@@ -77,11 +91,46 @@ struct PlayerInternal {
     volume_getter: Box<dyn VolumeGetter + Send>,
     event_senders: Vec<mpsc::UnboundedSender<PlayerEvent>>,
     converter: Converter,
+    equalizer: Equalizer,
 
     normalisation_integrator: f64,
     normalisation_peak: f64,
 
     auto_normalise_as_album: bool,
+    context_uri: Option<String>,
+
+    // Processed samples of the end of the previous track, mixed into the start
+    // of the next one. `crossfade_len` is non-zero while mixing is underway.
+    crossfade_tail: VecDeque<f64>,
+    crossfade_len: usize,
+
+    // Frames left to ramp up or down, non-zero while a fade is underway.
+    fade_in_remaining: usize,
+    fade_out_remaining: usize,
+
+    // Percentage of the rebuffer threshold downloaded, while rebuffering, and
+    // the timer to check it again.
+    rebuffering: Option<u8>,
+    rebuffer_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+
+    stats: PlaybackStats,
+    stats_sent: Option<Instant>,
+    resume_point_saved: Option<Instant>,
+
+    // Whether `PlaybackStarting` was sent without an `Idle` since, and the
+    // timer that sends `Idle`.
+    active: bool,
+    idle_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+
+    // Whether we are still skipping silence at the start of the track, and
+    // the frames of silence held back in case the track ends in it.
+    trimming_leading_silence: bool,
+    trailing_silence: usize,
+
+    // The bitrate to load tracks with, and whether we ran out of data during
+    // the current track.
+    bitrate: Bitrate,
+    stalled: bool,
 
     player_id: usize,
     play_request_id_generator: SeqGenerator<u64>,
@@ -107,6 +156,9 @@ enum PlayerCommand {
     SetSinkEventCallback(Option<SinkEventCallback>),
     EmitVolumeChangedEvent(u16),
     SetAutoNormaliseAsAlbum(bool),
+    SetContextUri(Option<String>),
+    SetEqualizer(Vec<EqBand>),
+    GetStats(oneshot::Sender<PlaybackStats>),
     EmitSessionDisconnectedEvent {
         connection_id: String,
         user_name: String,
@@ -125,6 +177,10 @@ enum PlayerCommand {
     EmitShuffleChangedEvent(bool),
     EmitRepeatChangedEvent(bool),
     EmitAutoPlayChangedEvent(bool),
+    EmitBackendMetadataChangedEvent {
+        key: String,
+        value: String,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -197,9 +253,34 @@ pub enum PlayerEvent {
         track_id: SpotifyId,
         position_ms: u32,
     },
+    // The player ran out of data and paused the sink until enough is downloaded
+    // again. Progress (0 to 100) is reported until playback resumes, which is
+    // announced by a Playing event.
+    Buffering {
+        play_request_id: u64,
+        track_id: SpotifyId,
+        position_ms: u32,
+        progress: u8,
+    },
+    // With adaptive bitrate, the following tracks are loaded with this bitrate.
+    BitrateChanged {
+        bitrate: Bitrate,
+    },
     TrackChanged {
         audio_item: Box<AudioItem>,
     },
+    // Sent right after TrackChanged with the metadata that displays and media
+    // controls need, so they don't have to dig through the AudioItem. For
+    // episodes, the show is reported as the album.
+    TrackMetadata {
+        track_id: SpotifyId,
+        title: String,
+        artists: Vec<String>,
+        album: Option<String>,
+        duration_ms: u32,
+        cover_url: Option<String>,
+        context_uri: Option<String>,
+    },
     SessionConnected {
         connection_id: String,
         user_name: String,
@@ -226,6 +307,40 @@ pub enum PlayerEvent {
     FilterExplicitContentChanged {
         filter: bool,
     },
+    // A remote client pushed metadata that is meant for the playback backend.
+    BackendMetadataChanged {
+        key: String,
+        value: String,
+    },
+    // Sent before the sink starts after the player was idle, and before the
+    // first playback. Each is followed by `Idle` once playback stops for long
+    // enough, so these can switch an amplifier on and off.
+    PlaybackStarting,
+    // The sink has been closed for `PlayerConfig::idle_timeout`.
+    Idle {
+        idle_timeout: Duration,
+    },
+    // Sent periodically while playing, if `PlayerConfig::stats_interval` is set.
+    Stats {
+        play_request_id: u64,
+        track_id: SpotifyId,
+        stats: PlaybackStats,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlaybackStats {
+    // The file that is playing and its nominal bitrate.
+    pub format: Option<AudioFileFormat>,
+    pub bitrate_kbps: u32,
+    // Audio that can be decoded without waiting for the network, and how much
+    // of the file was downloaded so far.
+    pub buffered_ms: u32,
+    pub bytes_fetched: usize,
+    // How often playback ran out of data since the player was created.
+    pub underruns: u32,
+    // Time spent decoding the current track.
+    pub decode_time: Duration,
 }
 
 impl PlayerEvent {
@@ -258,6 +373,12 @@ impl PlayerEvent {
             }
             | Seeked {
                 play_request_id, ..
+            }
+            | Buffering {
+                play_request_id, ..
+            }
+            | Stats {
+                play_request_id, ..
             } => Some(*play_request_id),
             _ => None,
         }
@@ -413,7 +534,7 @@ impl NormalisationData {
 
 impl Player {
     pub fn new<F>(
-        config: PlayerConfig,
+        mut config: PlayerConfig,
         session: Session,
         volume_getter: Box<dyn VolumeGetter + Send>,
         sink_builder: F,
@@ -423,6 +544,52 @@ impl Player {
     {
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
 
+        let default_params = AudioFetchParams::default();
+
+        // An invalid chunk size is checked first, so fixing it up reveals the
+        // bit-perfect conflicts, which are resolved below.
+        while let Err(e) = config.validate() {
+            match e {
+                PlayerConfigError::DownloadChunkSize => {
+                    warn!(
+                        "{}, using the default of {} kB",
+                        e,
+                        default_params.minimum_download_size / 1024
+                    );
+                    config.download_chunk_size = default_params.minimum_download_size;
+                }
+                PlayerConfigError::BitPerfect(_) => {
+                    warn!("{}, disabling it", e);
+                    break;
+                }
+            }
+        }
+
+        // The fetch parameters are shared by all audio files in the process,
+        // so only the first player gets to set them.
+        let fetch_params = AudioFetchParams {
+            minimum_download_size: config.download_chunk_size,
+            read_ahead_before_playback: config.read_ahead_before_playback,
+            read_ahead_during_playback: config.read_ahead_during_playback,
+            prefetch_threshold_factor: config.prefetch_threshold_factor,
+            download_timeout: Duration::from_secs(
+                (config.download_chunk_size / default_params.minimum_throughput) as u64,
+            ),
+            concurrent_requests: config.concurrent_downloads.max(1),
+            ..default_params
+        };
+        if AudioFetchParams::set(fetch_params).is_err() {
+            debug!("Audio fetch parameters were set before, keeping them");
+        }
+
+        if config.bit_perfect {
+            config.normalisation = false;
+            config.equalizer.clear();
+            config.crossfade = Duration::ZERO;
+            config.fade = Duration::ZERO;
+            config.ditherer = None;
+        }
+
         if config.normalisation {
             debug!("Normalisation Type: {:?}", config.normalisation_type);
             debug!(
@@ -454,6 +621,8 @@ impl Player {
             debug!("new Player [{}]", player_id);
 
             let converter = Converter::new(config.ditherer);
+            let equalizer = Equalizer::new(&config.equalizer);
+            let bitrate = config.bitrate;
 
             let internal = PlayerInternal {
                 session,
@@ -469,11 +638,35 @@ impl Player {
                 volume_getter,
                 event_senders: vec![],
                 converter,
+                equalizer,
 
                 normalisation_peak: 0.0,
                 normalisation_integrator: 0.0,
 
                 auto_normalise_as_album: false,
+                context_uri: None,
+
+                crossfade_tail: VecDeque::new(),
+                crossfade_len: 0,
+
+                fade_in_remaining: 0,
+                fade_out_remaining: 0,
+
+                rebuffering: None,
+                rebuffer_timer: None,
+
+                stats: PlaybackStats::default(),
+                stats_sent: None,
+                resume_point_saved: None,
+
+                active: false,
+                idle_timer: None,
+
+                trimming_leading_silence: false,
+                trailing_silence: 0,
+
+                bitrate,
+                stalled: false,
 
                 player_id,
                 play_request_id_generator: SeqGenerator::new(0),
@@ -546,6 +739,14 @@ impl Player {
         event_receiver
     }
 
+    /// Returns statistics about the current playback, or `None` if the player
+    /// has shut down.
+    pub async fn stats(&self) -> Option<PlaybackStats> {
+        let (stats_tx, stats_rx) = oneshot::channel();
+        self.command(PlayerCommand::GetStats(stats_tx));
+        stats_rx.await.ok()
+    }
+
     pub async fn await_end_of_track(&self) {
         let mut channel = self.get_player_event_channel();
         while let Some(event) = channel.recv().await {
@@ -570,6 +771,17 @@ impl Player {
         self.command(PlayerCommand::SetAutoNormaliseAsAlbum(setting));
     }
 
+    /// Sets the context (e.g. playlist or album) that upcoming tracks are
+    /// played from, as reported in `PlayerEvent::TrackMetadata`.
+    pub fn set_context_uri(&self, context_uri: Option<String>) {
+        self.command(PlayerCommand::SetContextUri(context_uri));
+    }
+
+    /// Replaces the equalizer bands. An empty list disables the equalizer.
+    pub fn set_equalizer(&self, bands: Vec<EqBand>) {
+        self.command(PlayerCommand::SetEqualizer(bands));
+    }
+
     pub fn emit_filter_explicit_content_changed_event(&self, filter: bool) {
         self.command(PlayerCommand::EmitFilterExplicitContentChangedEvent(filter));
     }
@@ -614,6 +826,10 @@ impl Player {
     pub fn emit_auto_play_changed_event(&self, auto_play: bool) {
         self.command(PlayerCommand::EmitAutoPlayChangedEvent(auto_play));
     }
+
+    pub fn emit_backend_metadata_changed_event(&self, key: String, value: String) {
+        self.command(PlayerCommand::EmitBackendMetadataChangedEvent { key, value });
+    }
 }
 
 impl Drop for Player {
@@ -634,6 +850,7 @@ struct PlayerLoadedTrackData {
     stream_loader_controller: StreamLoaderController,
     audio_item: AudioItem,
     bytes_per_second: usize,
+    format: AudioFileFormat,
     duration_ms: u32,
     stream_position_ms: u32,
     is_explicit: bool,
@@ -670,6 +887,7 @@ enum PlayerState {
         normalisation_factor: f64,
         stream_loader_controller: StreamLoaderController,
         bytes_per_second: usize,
+        format: AudioFileFormat,
         duration_ms: u32,
         stream_position_ms: u32,
         suggested_to_preload_next_track: bool,
@@ -684,6 +902,7 @@ enum PlayerState {
         normalisation_factor: f64,
         stream_loader_controller: StreamLoaderController,
         bytes_per_second: usize,
+        format: AudioFileFormat,
         duration_ms: u32,
         stream_position_ms: u32,
         reported_nominal_start_time: Option<Instant>,
@@ -750,6 +969,7 @@ impl PlayerState {
                 decoder,
                 duration_ms,
                 bytes_per_second,
+                format,
                 normalisation_data,
                 stream_loader_controller,
                 stream_position_ms,
@@ -766,6 +986,7 @@ impl PlayerState {
                         stream_loader_controller,
                         audio_item,
                         bytes_per_second,
+                        format,
                         duration_ms,
                         stream_position_ms,
                         is_explicit,
@@ -796,6 +1017,7 @@ impl PlayerState {
                 stream_loader_controller,
                 duration_ms,
                 bytes_per_second,
+                format,
                 stream_position_ms,
                 suggested_to_preload_next_track,
                 is_explicit,
@@ -810,6 +1032,7 @@ impl PlayerState {
                     stream_loader_controller,
                     duration_ms,
                     bytes_per_second,
+                    format,
                     stream_position_ms,
                     reported_nominal_start_time: Instant::now()
                         .checked_sub(Duration::from_millis(stream_position_ms as u64)),
@@ -841,6 +1064,7 @@ impl PlayerState {
                 stream_loader_controller,
                 duration_ms,
                 bytes_per_second,
+                format,
                 stream_position_ms,
                 suggested_to_preload_next_track,
                 is_explicit,
@@ -856,6 +1080,7 @@ impl PlayerState {
                     stream_loader_controller,
                     duration_ms,
                     bytes_per_second,
+                    format,
                     stream_position_ms,
                     suggested_to_preload_next_track,
                     is_explicit,
@@ -872,6 +1097,40 @@ impl PlayerState {
     }
 }
 
+// The formats to try for a bitrate, in order of preference.
+pub(crate) fn preferred_formats(bitrate: Bitrate) -> [AudioFileFormat; 7] {
+    // (Most) podcasts seem to support only 96 kbps Ogg Vorbis, so fall back to it
+    match bitrate {
+        Bitrate::Bitrate96 => [
+            AudioFileFormat::OGG_VORBIS_96,
+            AudioFileFormat::MP3_96,
+            AudioFileFormat::OGG_VORBIS_160,
+            AudioFileFormat::MP3_160,
+            AudioFileFormat::MP3_256,
+            AudioFileFormat::OGG_VORBIS_320,
+            AudioFileFormat::MP3_320,
+        ],
+        Bitrate::Bitrate160 => [
+            AudioFileFormat::OGG_VORBIS_160,
+            AudioFileFormat::MP3_160,
+            AudioFileFormat::OGG_VORBIS_96,
+            AudioFileFormat::MP3_96,
+            AudioFileFormat::MP3_256,
+            AudioFileFormat::OGG_VORBIS_320,
+            AudioFileFormat::MP3_320,
+        ],
+        Bitrate::Bitrate320 => [
+            AudioFileFormat::OGG_VORBIS_320,
+            AudioFileFormat::MP3_320,
+            AudioFileFormat::MP3_256,
+            AudioFileFormat::OGG_VORBIS_160,
+            AudioFileFormat::MP3_160,
+            AudioFileFormat::OGG_VORBIS_96,
+            AudioFileFormat::MP3_96,
+        ],
+    }
+}
+
 struct PlayerTrackLoader {
     session: Session,
     config: PlayerConfig,
@@ -901,7 +1160,7 @@ impl PlayerTrackLoader {
         }
     }
 
-    fn stream_data_rate(&self, format: AudioFileFormat) -> Option<usize> {
+    fn stream_data_rate(format: AudioFileFormat) -> Option<usize> {
         let kbps = match format {
             AudioFileFormat::OGG_VORBIS_96 => 12,
             AudioFileFormat::OGG_VORBIS_160 => 20,
@@ -926,6 +1185,23 @@ impl PlayerTrackLoader {
         Some(kbps * 1024)
     }
 
+    async fn resume_position_ms(&self, spotify_id: SpotifyId) -> u32 {
+        match ResumePoint::get(&self.session, &spotify_id).await {
+            Ok(Some(resume_point)) if !resume_point.fully_played => {
+                info!(
+                    "Resuming at {} ms, where it was left off",
+                    resume_point.position.as_millis()
+                );
+                resume_point.position.as_millis().min(u32::MAX as u128) as u32
+            }
+            Ok(_) => 0,
+            Err(e) => {
+                warn!("Unable to get resume point: {}", e);
+                0
+            }
+        }
+    }
+
     async fn load_track(
         &self,
         spotify_id: SpotifyId,
@@ -953,37 +1229,17 @@ impl PlayerTrackLoader {
             audio_item.name, audio_item.uri
         );
 
-        // (Most) podcasts seem to support only 96 kbps Ogg Vorbis, so fall back to it
-        let formats = match self.config.bitrate {
-            Bitrate::Bitrate96 => [
-                AudioFileFormat::OGG_VORBIS_96,
-                AudioFileFormat::MP3_96,
-                AudioFileFormat::OGG_VORBIS_160,
-                AudioFileFormat::MP3_160,
-                AudioFileFormat::MP3_256,
-                AudioFileFormat::OGG_VORBIS_320,
-                AudioFileFormat::MP3_320,
-            ],
-            Bitrate::Bitrate160 => [
-                AudioFileFormat::OGG_VORBIS_160,
-                AudioFileFormat::MP3_160,
-                AudioFileFormat::OGG_VORBIS_96,
-                AudioFileFormat::MP3_96,
-                AudioFileFormat::MP3_256,
-                AudioFileFormat::OGG_VORBIS_320,
-                AudioFileFormat::MP3_320,
-            ],
-            Bitrate::Bitrate320 => [
-                AudioFileFormat::OGG_VORBIS_320,
-                AudioFileFormat::MP3_320,
-                AudioFileFormat::MP3_256,
-                AudioFileFormat::OGG_VORBIS_160,
-                AudioFileFormat::MP3_160,
-                AudioFileFormat::OGG_VORBIS_96,
-                AudioFileFormat::MP3_96,
-            ],
+        let position_ms = if position_ms == 0
+            && self.config.resume_points
+            && spotify_id.item_type == SpotifyItemType::Episode
+        {
+            self.resume_position_ms(spotify_id).await
+        } else {
+            position_ms
         };
 
+        let formats = preferred_formats(self.config.bitrate);
+
         let (format, file_id) =
             match formats
                 .iter()
@@ -1001,7 +1257,7 @@ impl PlayerTrackLoader {
                 }
             };
 
-        let bytes_per_second = self.stream_data_rate(format)?;
+        let bytes_per_second = Self::stream_data_rate(format)?;
 
         // This is only a loop to be able to reload the file if an error occurred
         // while opening a cached file.
@@ -1149,6 +1405,7 @@ impl PlayerTrackLoader {
                 stream_loader_controller,
                 audio_item,
                 bytes_per_second,
+                format,
                 duration_ms,
                 stream_position_ms,
                 is_explicit,
@@ -1258,6 +1515,13 @@ impl Future for PlayerInternal {
                 }
             }
 
+            if self.state.is_playing() && !self.ensure_buffered(cx) {
+                if all_futures_completed_or_not_ready {
+                    return Poll::Pending;
+                }
+                continue;
+            }
+
             if self.state.is_playing() {
                 self.ensure_sink_running();
 
@@ -1266,12 +1530,17 @@ impl Future for PlayerInternal {
                     play_request_id,
                     ref mut decoder,
                     normalisation_factor,
+                    duration_ms,
                     ref mut stream_position_ms,
                     ref mut reported_nominal_start_time,
                     ..
                 } = self.state
                 {
-                    match decoder.next_packet() {
+                    let decode_start = Instant::now();
+                    let result = decoder.next_packet();
+                    let decode_time = decode_start.elapsed();
+
+                    match result {
                         Ok(result) => {
                             if let Some((ref packet_position, ref packet)) = result {
                                 let new_stream_position_ms = packet_position.position_ms;
@@ -1280,76 +1549,77 @@ impl Future for PlayerInternal {
                                     new_stream_position_ms,
                                 );
 
-                                if !passthrough {
-                                    match packet.samples() {
-                                        Ok(_) => {
-                                            let new_stream_position = Duration::from_millis(
-                                                new_stream_position_ms as u64,
-                                            );
-
-                                            let now = Instant::now();
-
-                                            // Only notify if we're skipped some packets *or* we are behind.
-                                            // If we're ahead it's probably due to a buffer of the backend
-                                            // and we're actually in time.
-                                            let notify_about_position =
-                                                match *reported_nominal_start_time {
-                                                    None => true,
-                                                    Some(reported_nominal_start_time) => {
-                                                        let mut notify = false;
-
-                                                        if packet_position.skipped {
-                                                            if let Some(ahead) = new_stream_position
-                                                                .checked_sub(Duration::from_millis(
-                                                                    expected_position_ms as u64,
-                                                                ))
-                                                            {
-                                                                notify |=
-                                                                    ahead >= Duration::from_secs(1)
-                                                            }
-                                                        }
-
-                                                        if let Some(lag) = now
-                                                            .checked_duration_since(
-                                                                reported_nominal_start_time,
-                                                            )
-                                                        {
-                                                            if let Some(lag) =
-                                                                lag.checked_sub(new_stream_position)
-                                                            {
-                                                                notify |=
-                                                                    lag >= Duration::from_secs(1)
-                                                            }
-                                                        }
-
-                                                        notify
-                                                    }
-                                                };
-
-                                            if notify_about_position {
-                                                *reported_nominal_start_time =
-                                                    now.checked_sub(new_stream_position);
-                                                self.send_event(PlayerEvent::PositionCorrection {
-                                                    play_request_id,
-                                                    track_id,
-                                                    position_ms: new_stream_position_ms,
-                                                });
-                                            }
-                                        }
+                                // Passthrough packets have no samples to check, but their
+                                // positions are just as good to keep Connect in sync.
+                                let is_valid = passthrough
+                                    || match packet.samples() {
+                                        Ok(_) => true,
                                         Err(e) => {
                                             error!("Skipping to next track, unable to decode samples for track <{:?}>: {:?}", track_id, e);
-                                            self.send_event(PlayerEvent::EndOfTrack {
-                                                track_id,
-                                                play_request_id,
-                                            })
+                                            false
                                         }
+                                    };
+
+                                if is_valid {
+                                    let new_stream_position =
+                                        Duration::from_millis(new_stream_position_ms as u64);
+
+                                    let now = Instant::now();
+
+                                    // Only notify if we're skipped some packets *or* we are behind.
+                                    // If we're ahead it's probably due to a buffer of the backend
+                                    // and we're actually in time.
+                                    let notify_about_position = match *reported_nominal_start_time {
+                                        None => true,
+                                        Some(reported_nominal_start_time) => {
+                                            let mut notify = false;
+
+                                            if packet_position.skipped {
+                                                if let Some(ahead) = new_stream_position
+                                                    .checked_sub(Duration::from_millis(
+                                                        expected_position_ms as u64,
+                                                    ))
+                                                {
+                                                    notify |= ahead >= Duration::from_secs(1)
+                                                }
+                                            }
+
+                                            if let Some(lag) = now
+                                                .checked_duration_since(reported_nominal_start_time)
+                                            {
+                                                if let Some(lag) =
+                                                    lag.checked_sub(new_stream_position)
+                                                {
+                                                    notify |= lag >= Duration::from_secs(1)
+                                                }
+                                            }
+
+                                            notify
+                                        }
+                                    };
+
+                                    if notify_about_position {
+                                        *reported_nominal_start_time =
+                                            now.checked_sub(new_stream_position);
+                                        self.send_event(PlayerEvent::PositionCorrection {
+                                            play_request_id,
+                                            track_id,
+                                            position_ms: new_stream_position_ms,
+                                        });
                                     }
+                                } else {
+                                    self.send_event(PlayerEvent::EndOfTrack {
+                                        track_id,
+                                        play_request_id,
+                                    });
                                 }
                             }
 
-                            self.handle_packet(result, normalisation_factor);
+                            self.stats.decode_time += decode_time;
+                            self.handle_packet(result, normalisation_factor, duration_ms);
                         }
                         Err(e) => {
+                            self.stats.decode_time += decode_time;
                             error!("Skipping to next track, unable to get next packet for track <{:?}>: {:?}", track_id, e);
                             self.send_event(PlayerEvent::EndOfTrack {
                                 track_id,
@@ -1395,6 +1665,51 @@ impl Future for PlayerInternal {
                 }
             }
 
+            if let Some(interval) = self.config.stats_interval {
+                if let PlayerState::Playing {
+                    track_id,
+                    play_request_id,
+                    ..
+                } = self.state
+                {
+                    if self
+                        .stats_sent
+                        .map_or(true, |sent| sent.elapsed() >= interval)
+                    {
+                        self.stats_sent = Some(Instant::now());
+                        let stats = self.current_stats();
+                        self.send_event(PlayerEvent::Stats {
+                            play_request_id,
+                            track_id,
+                            stats,
+                        });
+                    }
+                }
+            }
+
+            if let PlayerState::Playing {
+                track_id,
+                stream_position_ms,
+                ..
+            } = self.state
+            {
+                if self
+                    .resume_point_saved
+                    .map_or(true, |saved| saved.elapsed() >= RESUME_POINT_INTERVAL)
+                {
+                    self.save_resume_point(track_id, stream_position_ms, false);
+                }
+            }
+
+            if let Some(idle_timer) = self.idle_timer.as_mut() {
+                if idle_timer.as_mut().poll(cx).is_ready() {
+                    self.idle_timer = None;
+                    self.active = false;
+                    let idle_timeout = self.config.idle_timeout;
+                    self.send_event(PlayerEvent::Idle { idle_timeout });
+                }
+            }
+
             if (!self.state.is_playing()) && all_futures_completed_or_not_ready {
                 return Poll::Pending;
             }
@@ -1406,6 +1721,11 @@ impl PlayerInternal {
     fn ensure_sink_running(&mut self) {
         if self.sink_status != SinkStatus::Running {
             trace!("== Starting sink ==");
+            self.idle_timer = None;
+            if !self.active {
+                self.active = true;
+                self.send_event(PlayerEvent::PlaybackStarting);
+            }
             if let Some(callback) = &mut self.sink_event_callback {
                 callback(SinkStatus::Running);
             }
@@ -1413,6 +1733,7 @@ impl PlayerInternal {
                 Ok(()) => self.sink_status = SinkStatus::Running,
                 Err(e) => {
                     error!("{}", e);
+                    self.start_idle_timer();
                     self.handle_pause();
                 }
             }
@@ -1433,6 +1754,9 @@ impl PlayerInternal {
                         if let Some(callback) = &mut self.sink_event_callback {
                             callback(self.sink_status);
                         }
+                        if self.sink_status == SinkStatus::Closed {
+                            self.start_idle_timer();
+                        }
                     }
                     Err(e) => {
                         error!("{}", e);
@@ -1446,12 +1770,126 @@ impl PlayerInternal {
                     if let Some(callback) = &mut self.sink_event_callback {
                         callback(SinkStatus::Closed);
                     }
+                    self.start_idle_timer();
                 }
             }
             SinkStatus::Closed => (),
         }
     }
 
+    fn start_idle_timer(&mut self) {
+        if self.active {
+            self.idle_timer = Some(Box::pin(tokio::time::sleep(self.config.idle_timeout)));
+        }
+    }
+
+    // Returns whether there is data to decode. When the download can't keep up,
+    // the sink is paused until the rebuffer threshold is met, so playback
+    // doesn't stutter on every bit of data that trickles in. Meanwhile, the
+    // rebuffer timer wakes the player to check the progress again.
+    fn ensure_buffered(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.config.rebuffer_threshold.is_zero() {
+            return true;
+        }
+
+        let (track_id, play_request_id, stream_position_ms, target, buffered) = match self.state {
+            PlayerState::Playing {
+                track_id,
+                play_request_id,
+                stream_position_ms,
+                bytes_per_second,
+                ref stream_loader_controller,
+                ..
+            } => {
+                let target = (self.config.rebuffer_threshold.as_secs_f32()
+                    * bytes_per_second as f32) as usize;
+                let target = target.min(stream_loader_controller.remaining());
+
+                if self.rebuffering.is_none()
+                    && (target == 0 || stream_loader_controller.buffered() > 0)
+                {
+                    return true;
+                }
+
+                let buffered = stream_loader_controller.fetch_buffered(target);
+
+                (
+                    track_id,
+                    play_request_id,
+                    stream_position_ms,
+                    target,
+                    buffered,
+                )
+            }
+            _ => return true,
+        };
+
+        if self.rebuffering.is_none() {
+            warn!("Ran out of data, rebuffering <{:?}>", track_id);
+            self.stats.underruns += 1;
+            self.ensure_sink_stopped(true);
+
+            if self.config.adaptive_bitrate && !self.stalled {
+                self.stalled = true;
+                let lower = match self.bitrate {
+                    Bitrate::Bitrate320 => Some(Bitrate::Bitrate160),
+                    Bitrate::Bitrate160 => Some(Bitrate::Bitrate96),
+                    Bitrate::Bitrate96 => None,
+                };
+                if let Some(bitrate) = lower {
+                    self.set_bitrate(bitrate);
+                }
+            }
+        }
+
+        let progress = (buffered.min(target) * 100 / target.max(1)) as u8;
+
+        if self.rebuffering != Some(progress) {
+            self.rebuffering = Some(progress);
+            self.send_event(PlayerEvent::Buffering {
+                play_request_id,
+                track_id,
+                position_ms: stream_position_ms,
+                progress,
+            });
+        }
+
+        if buffered < target {
+            let timer = self
+                .rebuffer_timer
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(REBUFFER_PROGRESS_INTERVAL)));
+            if timer.as_mut().poll(cx).is_ready() {
+                timer
+                    .as_mut()
+                    .reset(tokio::time::Instant::now() + REBUFFER_PROGRESS_INTERVAL);
+                let _ = timer.as_mut().poll(cx);
+            }
+            return false;
+        }
+
+        debug!("Rebuffering <{:?}> done", track_id);
+        self.rebuffering = None;
+        self.rebuffer_timer = None;
+        self.fade_in_remaining = self.fade_frames();
+
+        if let PlayerState::Playing {
+            ref mut reported_nominal_start_time,
+            ..
+        } = self.state
+        {
+            *reported_nominal_start_time =
+                Instant::now().checked_sub(Duration::from_millis(stream_position_ms as u64));
+        }
+
+        self.send_event(PlayerEvent::Playing {
+            track_id,
+            play_request_id,
+            position_ms: stream_position_ms,
+        });
+
+        true
+    }
+
     fn handle_player_stop(&mut self) {
         match self.state {
             PlayerState::Playing {
@@ -1474,6 +1912,11 @@ impl PlayerInternal {
                 play_request_id,
                 ..
             } => {
+                if let PlayerState::EndOfTrack { .. } = self.state {
+                    // Nothing follows, so let the last track fade out.
+                    self.flush_crossfade_tail();
+                }
+
                 self.ensure_sink_stopped(false);
                 self.send_event(PlayerEvent::Stopped {
                     track_id,
@@ -1498,6 +1941,8 @@ impl PlayerInternal {
                 ..
             } => {
                 self.state.paused_to_playing();
+                self.fade_in_remaining = self.fade_frames();
+                self.rebuffering = None;
                 self.send_event(PlayerEvent::Playing {
                     track_id,
                     play_request_id,
@@ -1518,20 +1963,41 @@ impl PlayerInternal {
     fn handle_pause(&mut self) {
         match self.state {
             PlayerState::Paused { .. } => self.ensure_sink_stopped(false),
-            PlayerState::Playing {
-                track_id,
-                play_request_id,
-                stream_position_ms,
-                ..
-            } => {
-                self.state.playing_to_paused();
+            PlayerState::Playing { .. } => {
+                let trailing_silence = self.trailing_silence;
+                let fade_start_ms = self.fade_out();
 
-                self.ensure_sink_stopped(false);
-                self.send_event(PlayerEvent::Paused {
+                // The track may have ended or failed while fading out.
+                if let PlayerState::Playing {
                     track_id,
                     play_request_id,
-                    position_ms: stream_position_ms,
-                });
+                    ref mut decoder,
+                    ref mut stream_position_ms,
+                    ..
+                } = self.state
+                {
+                    // Resume with what was faded out rather than after it.
+                    if let Some(fade_start_ms) = fade_start_ms {
+                        match decoder.seek(fade_start_ms) {
+                            Ok(new_position_ms) => {
+                                *stream_position_ms = new_position_ms;
+                                // Silence held back while fading is decoded again.
+                                self.trailing_silence = trailing_silence;
+                            }
+                            Err(e) => warn!("Unable to seek back after fading out: {}", e),
+                        }
+                    }
+                    let stream_position_ms = *stream_position_ms;
+
+                    self.state.playing_to_paused();
+
+                    self.ensure_sink_stopped(false);
+                    self.send_event(PlayerEvent::Paused {
+                        track_id,
+                        play_request_id,
+                        position_ms: stream_position_ms,
+                    });
+                }
             }
             PlayerState::Loading {
                 ref mut start_playback,
@@ -1547,15 +2013,35 @@ impl PlayerInternal {
         &mut self,
         packet: Option<(AudioPacketPosition, AudioPacket)>,
         normalisation_factor: f64,
+        duration_ms: u32,
     ) {
         match packet {
-            Some((_, mut packet)) => {
+            Some((packet_position, mut packet)) => {
                 if !packet.is_empty() {
                     if let AudioPacket::Samples(ref mut data) = packet {
+                        if self.config.trim_silence {
+                            if let Some(frames) = self.trim_silence(data) {
+                                let trimmed_ms = (frames as u64 * 1000 / SAMPLE_RATE as u64) as u32;
+                                self.correct_position(packet_position.position_ms + trimmed_ms);
+                            }
+
+                            if data.is_empty() {
+                                return;
+                            }
+                        }
+
+                        if !self.equalizer.is_flat() {
+                            self.equalizer.process(data);
+                        }
+
                         // Get the volume for the packet.
-                        // In the case of hardware volume control this will
-                        // always be 1.0 (no change).
-                        let volume = self.volume_getter.attenuation_factor();
+                        // In the case of hardware volume control or bit-perfect
+                        // playback this will always be 1.0 (no change).
+                        let volume = if self.config.bit_perfect {
+                            1.0
+                        } else {
+                            self.volume_getter.attenuation_factor()
+                        };
 
                         // For the basic normalisation method, a normalisation factor of 1.0 indicates that
                         // there is nothing to normalise (all samples should pass unaltered). For the
@@ -1660,6 +2146,21 @@ impl PlayerInternal {
                                 *sample *= volume;
                             }
                         }
+
+                        self.apply_fades(data);
+                        self.mix_crossfade_tail(data);
+
+                        // Hold back the end of the track to mix it into the next one.
+                        let crossfade_ms = self.config.crossfade.as_millis() as u32;
+                        if crossfade_ms > 0
+                            && self.crossfade_len == 0
+                            && self.fade_out_remaining == 0
+                            && packet_position.position_ms.saturating_add(crossfade_ms)
+                                >= duration_ms
+                        {
+                            self.crossfade_tail.extend(data.drain(..));
+                            return;
+                        }
                     }
 
                     if let Err(e) = self.sink.write(packet, &mut self.converter) {
@@ -1670,6 +2171,11 @@ impl PlayerInternal {
             }
 
             None => {
+                if self.config.adaptive_bitrate {
+                    self.raise_bitrate();
+                }
+
+                self.crossfade_len = self.crossfade_tail.len();
                 self.state.playing_to_end_of_track();
                 if let PlayerState::EndOfTrack {
                     track_id,
@@ -1689,6 +2195,261 @@ impl PlayerInternal {
         }
     }
 
+    // Goes up a step when the track played without running out of data, and
+    // the network is fast enough for the next higher bitrate.
+    fn raise_bitrate(&mut self) {
+        let higher = match self.bitrate {
+            Bitrate::Bitrate96 => Bitrate::Bitrate160,
+            Bitrate::Bitrate160 | Bitrate::Bitrate320 => Bitrate::Bitrate320,
+        };
+
+        if self.stalled || higher > self.config.bitrate || higher == self.bitrate {
+            return;
+        }
+
+        let throughput = match self.state {
+            PlayerState::Playing {
+                ref stream_loader_controller,
+                ..
+            } => stream_loader_controller.throughput(),
+            _ => None,
+        };
+
+        let needed = preferred_formats(higher)
+            .iter()
+            .find_map(|&format| PlayerTrackLoader::stream_data_rate(format));
+
+        if let (Some(throughput), Some(needed)) = (throughput, needed) {
+            if throughput >= needed * BITRATE_RECOVERY_HEADROOM {
+                self.set_bitrate(higher);
+            }
+        }
+    }
+
+    fn set_bitrate(&mut self, bitrate: Bitrate) {
+        info!(
+            "Switching from {} to {} kbps for the following tracks",
+            self.bitrate, bitrate
+        );
+        self.bitrate = bitrate;
+        self.send_event(PlayerEvent::BitrateChanged { bitrate });
+    }
+
+    fn fade_frames(&self) -> usize {
+        if self.config.passthrough {
+            0
+        } else {
+            (self.config.fade.as_secs_f64() * SAMPLE_RATE as f64) as usize
+        }
+    }
+
+    fn apply_fades(&mut self, data: &mut Vec<f64>) {
+        if self.fade_in_remaining == 0 && self.fade_out_remaining == 0 {
+            return;
+        }
+
+        let fading_out = self.fade_out_remaining > 0;
+        let frames = apply_ramp(
+            data,
+            self.fade_frames(),
+            &mut self.fade_in_remaining,
+            &mut self.fade_out_remaining,
+        );
+
+        // Nothing after a fade out is meant to be heard. What's cut off here
+        // isn't lost: pausing seeks back to where the fade out started.
+        if fading_out {
+            data.truncate(frames * NUM_CHANNELS as usize);
+        }
+    }
+
+    // Plays on for the length of the fade while ramping down, so that pausing
+    // or seeking doesn't cut the audio off mid-waveform. Returns the position
+    // the fade out started at, if there was one.
+    fn fade_out(&mut self) -> Option<u32> {
+        let frames = self.fade_frames();
+        if frames == 0 || self.fade_out_remaining > 0 || self.sink_status != SinkStatus::Running {
+            return None;
+        }
+
+        self.fade_in_remaining = 0;
+        self.fade_out_remaining = frames;
+        let mut fade_start_ms = None;
+
+        while self.fade_out_remaining > 0 {
+            let (packet, normalisation_factor, duration_ms) = match self.state {
+                PlayerState::Playing {
+                    ref mut decoder,
+                    ref mut stream_position_ms,
+                    normalisation_factor,
+                    duration_ms,
+                    ..
+                } => match decoder.next_packet() {
+                    Ok(packet) => {
+                        if let Some((ref packet_position, _)) = packet {
+                            *stream_position_ms = packet_position.position_ms;
+                            fade_start_ms.get_or_insert(packet_position.position_ms);
+                        }
+                        (packet, normalisation_factor, duration_ms)
+                    }
+                    Err(e) => {
+                        warn!("Unable to fade out: {}", e);
+                        break;
+                    }
+                },
+                _ => break,
+            };
+
+            let end_of_track = packet.is_none();
+            self.handle_packet(packet, normalisation_factor, duration_ms);
+            if end_of_track {
+                break;
+            }
+        }
+
+        self.fade_out_remaining = 0;
+        fade_start_ms
+    }
+
+    // Drops silence at the start of the track and holds back silence until
+    // it's clear whether the track ends in it. Returns how many frames were
+    // dropped from this packet once the start of the track is trimmed.
+    fn trim_silence(&mut self, data: &mut Vec<f64>) -> Option<usize> {
+        let channels = NUM_CHANNELS as usize;
+        let is_silent = |frame: &[f64]| frame.iter().all(|sample| sample.abs() < SILENCE_THRESHOLD);
+        let mut trimmed_start = None;
+
+        if self.trimming_leading_silence {
+            match data.chunks(channels).position(|frame| !is_silent(frame)) {
+                Some(start) => {
+                    data.drain(..start * channels);
+                    self.trimming_leading_silence = false;
+                    trimmed_start = Some(start);
+                }
+                None => {
+                    data.clear();
+                    return None;
+                }
+            }
+        }
+
+        match data.chunks(channels).rposition(|frame| !is_silent(frame)) {
+            Some(end) => {
+                // The silence was part of the track after all.
+                self.flush_trailing_silence();
+
+                let frames = data.len() / channels;
+                data.truncate((end + 1) * channels);
+                self.trailing_silence = frames - end - 1;
+            }
+            None => {
+                self.trailing_silence += data.len() / channels;
+                data.clear();
+            }
+        }
+
+        trimmed_start
+    }
+
+    fn flush_trailing_silence(&mut self) {
+        // Written in chunks, as silence in the middle of a track can be long.
+        let chunk = SAMPLE_RATE as usize;
+
+        while self.trailing_silence > 0 {
+            let frames = self.trailing_silence.min(chunk);
+            self.trailing_silence -= frames;
+
+            let silence = vec![0.0; frames * NUM_CHANNELS as usize];
+            if let Err(e) = self
+                .sink
+                .write(AudioPacket::Samples(silence), &mut self.converter)
+            {
+                error!("{}", e);
+                self.trailing_silence = 0;
+            }
+        }
+    }
+
+    fn correct_position(&mut self, position_ms: u32) {
+        if let PlayerState::Playing {
+            track_id,
+            play_request_id,
+            ref mut reported_nominal_start_time,
+            ..
+        } = self.state
+        {
+            *reported_nominal_start_time =
+                Instant::now().checked_sub(Duration::from_millis(position_ms as u64));
+            self.send_event(PlayerEvent::PositionCorrection {
+                play_request_id,
+                track_id,
+                position_ms,
+            });
+        }
+    }
+
+    fn mix_crossfade_tail(&mut self, data: &mut [f64]) {
+        if self.crossfade_len == 0 {
+            return;
+        }
+
+        mix_crossfade(&mut self.crossfade_tail, self.crossfade_len, data);
+
+        if self.crossfade_tail.is_empty() {
+            self.crossfade_len = 0;
+        }
+    }
+
+    fn flush_crossfade_tail(&mut self) {
+        let len = self.crossfade_tail.len();
+        self.crossfade_len = 0;
+
+        if len == 0 || self.sink_status != SinkStatus::Running {
+            self.crossfade_tail.clear();
+            return;
+        }
+
+        let samples = fade_out_crossfade(&mut self.crossfade_tail);
+
+        if let Err(e) = self
+            .sink
+            .write(AudioPacket::Samples(samples), &mut self.converter)
+        {
+            error!("{}", e);
+        }
+    }
+
+    fn clear_crossfade_tail(&mut self) {
+        self.crossfade_tail.clear();
+        self.crossfade_len = 0;
+    }
+
+    fn track_metadata(&self, audio_item: &AudioItem) -> PlayerEvent {
+        let (artists, album) = match &audio_item.unique_fields {
+            UniqueFields::Track { artists, album, .. } => (
+                artists.iter().map(|artist| artist.name.clone()).collect(),
+                album.clone(),
+            ),
+            UniqueFields::Episode { show_name, .. } => (Vec::new(), show_name.clone()),
+        };
+
+        let cover_url = audio_item
+            .covers
+            .iter()
+            .max_by_key(|cover| cover.width)
+            .map(|cover| cover.url.clone());
+
+        PlayerEvent::TrackMetadata {
+            track_id: audio_item.track_id,
+            title: audio_item.name.clone(),
+            artists,
+            album: Some(album).filter(|album| !album.is_empty()),
+            duration_ms: audio_item.duration_ms,
+            cover_url,
+            context_uri: self.context_uri.clone(),
+        }
+    }
+
     fn start_playback(
         &mut self,
         track_id: SpotifyId,
@@ -1698,10 +2459,34 @@ impl PlayerInternal {
     ) {
         let audio_item = Box::new(loaded_track.audio_item.clone());
 
+        if let Err(e) = self.sink.track_changed(&audio_item) {
+            error!("{}", e);
+        }
+
+        let track_metadata = self.track_metadata(&audio_item);
         self.send_event(PlayerEvent::TrackChanged { audio_item });
+        self.send_event(track_metadata);
 
         let position_ms = loaded_track.stream_position_ms;
 
+        self.rebuffering = None;
+        self.stalled = false;
+
+        // Whatever silence the previous track ended in is dropped.
+        self.trimming_leading_silence = self.config.trim_silence && position_ms == 0;
+        self.trailing_silence = 0;
+
+        self.stats.format = Some(loaded_track.format);
+        self.stats.bitrate_kbps = (loaded_track.bytes_per_second * 8 / 1000) as u32;
+        self.stats.decode_time = Duration::ZERO;
+
+        // Starting mid-track would otherwise start mid-waveform.
+        self.fade_in_remaining = if position_ms > 0 {
+            self.fade_frames()
+        } else {
+            0
+        };
+
         let mut config = self.config.clone();
         if config.normalisation_type == NormalisationType::Auto {
             if self.auto_normalise_as_album {
@@ -1731,6 +2516,7 @@ impl PlayerInternal {
                 stream_loader_controller: loaded_track.stream_loader_controller,
                 duration_ms: loaded_track.duration_ms,
                 bytes_per_second: loaded_track.bytes_per_second,
+                format: loaded_track.format,
                 stream_position_ms: loaded_track.stream_position_ms,
                 reported_nominal_start_time: Instant::now()
                     .checked_sub(Duration::from_millis(position_ms as u64)),
@@ -1750,6 +2536,7 @@ impl PlayerInternal {
                 stream_loader_controller: loaded_track.stream_loader_controller,
                 duration_ms: loaded_track.duration_ms,
                 bytes_per_second: loaded_track.bytes_per_second,
+                format: loaded_track.format,
                 stream_position_ms: loaded_track.stream_position_ms,
                 suggested_to_preload_next_track: false,
                 is_explicit: loaded_track.is_explicit,
@@ -1775,6 +2562,11 @@ impl PlayerInternal {
 
         self.send_event(PlayerEvent::PlayRequestIdChanged { play_request_id });
 
+        // Only crossfade into the track that follows naturally, not on skips.
+        if !matches!(self.state, PlayerState::EndOfTrack { .. }) {
+            self.clear_crossfade_tail();
+        }
+
         if !self.config.gapless {
             self.ensure_sink_stopped(play);
         }
@@ -1848,6 +2640,7 @@ impl PlayerInternal {
                     audio_item,
                     stream_loader_controller,
                     bytes_per_second,
+                    format,
                     duration_ms,
                     normalisation_data,
                     is_explicit,
@@ -1859,6 +2652,7 @@ impl PlayerInternal {
                     audio_item,
                     stream_loader_controller,
                     bytes_per_second,
+                    format,
                     duration_ms,
                     normalisation_data,
                     is_explicit,
@@ -1871,6 +2665,7 @@ impl PlayerInternal {
                         stream_loader_controller,
                         audio_item,
                         bytes_per_second,
+                        format,
                         duration_ms,
                         stream_position_ms,
                         is_explicit,
@@ -2029,9 +2824,20 @@ impl PlayerInternal {
             );
         }
 
+        if self.state.is_playing() {
+            self.fade_out();
+        }
+
+        self.rebuffering = None;
+        self.trimming_leading_silence = false;
+        self.trailing_silence = 0;
+        self.clear_crossfade_tail();
+
         if let Some(decoder) = self.state.decoder() {
             match decoder.seek(position_ms) {
                 Ok(new_position_ms) => {
+                    self.fade_in_remaining = self.fade_frames();
+
                     if let PlayerState::Playing {
                         ref mut stream_position_ms,
                         track_id,
@@ -2063,13 +2869,16 @@ impl PlayerInternal {
         // ensure we have a bit of a buffer of downloaded data
         self.preload_data_before_playback()?;
 
+        // The decoder may not land exactly where we asked, e.g. passthrough
+        // can only seek to Ogg page boundaries.
         if let PlayerState::Playing {
             ref mut reported_nominal_start_time,
+            stream_position_ms,
             ..
         } = self.state
         {
             *reported_nominal_start_time =
-                Instant::now().checked_sub(Duration::from_millis(position_ms as u64));
+                Instant::now().checked_sub(Duration::from_millis(stream_position_ms as u64));
         }
 
         Ok(())
@@ -2116,6 +2925,10 @@ impl PlayerInternal {
                 self.send_event(PlayerEvent::AutoPlayChanged { auto_play })
             }
 
+            PlayerCommand::EmitBackendMetadataChangedEvent { key, value } => {
+                self.send_event(PlayerEvent::BackendMetadataChanged { key, value })
+            }
+
             PlayerCommand::EmitSessionClientChangedEvent {
                 client_id,
                 client_name,
@@ -2148,6 +2961,21 @@ impl PlayerInternal {
                 self.auto_normalise_as_album = setting
             }
 
+            PlayerCommand::SetContextUri(context_uri) => self.context_uri = context_uri,
+
+            PlayerCommand::SetEqualizer(_) if self.config.bit_perfect => {
+                warn!("Ignoring equalizer settings in bit-perfect mode");
+            }
+
+            PlayerCommand::SetEqualizer(bands) => {
+                self.equalizer = Equalizer::new(&bands);
+                self.config.equalizer = bands;
+            }
+
+            PlayerCommand::GetStats(stats_tx) => {
+                let _ = stats_tx.send(self.current_stats());
+            }
+
             PlayerCommand::EmitFilterExplicitContentChangedEvent(filter) => {
                 self.send_event(PlayerEvent::FilterExplicitContentChanged { filter });
 
@@ -2180,11 +3008,82 @@ impl PlayerInternal {
         Ok(())
     }
 
+    fn current_stats(&self) -> PlaybackStats {
+        let mut stats = self.stats.clone();
+
+        if let PlayerState::Playing {
+            bytes_per_second,
+            ref stream_loader_controller,
+            ..
+        }
+        | PlayerState::Paused {
+            bytes_per_second,
+            ref stream_loader_controller,
+            ..
+        } = self.state
+        {
+            let buffered = stream_loader_controller
+                .buffered()
+                .min(stream_loader_controller.remaining());
+            stats.buffered_ms = (buffered as u64 * 1000 / bytes_per_second.max(1) as u64) as u32;
+            stats.bytes_fetched = stream_loader_controller.downloaded();
+        }
+
+        stats
+    }
+
     fn send_event(&mut self, event: PlayerEvent) {
+        self.report_progress(&event);
         self.event_senders
             .retain(|sender| sender.send(event.clone()).is_ok());
     }
 
+    fn report_progress(&mut self, event: &PlayerEvent) {
+        match *event {
+            PlayerEvent::Paused {
+                track_id,
+                position_ms,
+                ..
+            }
+            | PlayerEvent::Seeked {
+                track_id,
+                position_ms,
+                ..
+            } => self.save_resume_point(track_id, position_ms, false),
+            PlayerEvent::Stopped { track_id, .. } => match self.state {
+                PlayerState::Playing {
+                    stream_position_ms, ..
+                }
+                | PlayerState::Paused {
+                    stream_position_ms, ..
+                } => self.save_resume_point(track_id, stream_position_ms, false),
+                _ => (),
+            },
+            PlayerEvent::EndOfTrack { track_id, .. } => self.save_resume_point(track_id, 0, true),
+            _ => (),
+        }
+    }
+
+    fn save_resume_point(&mut self, track_id: SpotifyId, position_ms: u32, fully_played: bool) {
+        if !self.config.resume_points || track_id.item_type != SpotifyItemType::Episode {
+            return;
+        }
+
+        self.resume_point_saved = Some(Instant::now());
+
+        let resume_point = ResumePoint::new(
+            track_id,
+            Duration::from_millis(position_ms as u64),
+            fully_played,
+        );
+        let session = self.session.clone();
+        self.session.spawn(async move {
+            if let Err(e) = resume_point.save(&session).await {
+                warn!("Unable to save resume point: {}", e);
+            }
+        });
+    }
+
     fn load_track(
         &mut self,
         spotify_id: SpotifyId,
@@ -2196,9 +3095,12 @@ impl PlayerInternal {
         // easily. Instead we spawn a thread to do the work and return a one-shot channel as the
         // future to work with.
 
+        let mut config = self.config.clone();
+        config.bitrate = self.bitrate;
+
         let loader = PlayerTrackLoader {
             session: self.session.clone(),
-            config: self.config.clone(),
+            config,
         };
 
         let (result_tx, result_rx) = oneshot::channel();
@@ -2246,6 +3148,71 @@ impl PlayerInternal {
     }
 }
 
+/// Ramps `data` up while `fade_in_remaining` and down while
+/// `fade_out_remaining` frames of a fade of `len` frames are left, counting
+/// both down. Returns how many frames are meant to be heard: a fade out ends
+/// in silence, so the frames after it are not.
+fn apply_ramp(
+    data: &mut [f64],
+    len: usize,
+    fade_in_remaining: &mut usize,
+    fade_out_remaining: &mut usize,
+) -> usize {
+    let len = len.max(1) as f64;
+    let fading_out = *fade_out_remaining > 0;
+    let mut frames = 0;
+
+    for frame in data.chunks_mut(NUM_CHANNELS as usize) {
+        if fading_out && *fade_out_remaining == 0 {
+            break;
+        }
+
+        let mut gain = 1.0;
+
+        if *fade_in_remaining > 0 {
+            gain *= 1.0 - *fade_in_remaining as f64 / len;
+            *fade_in_remaining -= 1;
+        }
+
+        if *fade_out_remaining > 0 {
+            gain *= *fade_out_remaining as f64 / len;
+            *fade_out_remaining -= 1;
+        }
+
+        for sample in frame.iter_mut() {
+            *sample *= gain;
+        }
+        frames += 1;
+    }
+
+    frames
+}
+
+/// Mixes the held back end of the previous track into `data`, fading it out
+/// while fading `data` in. `len` is the length of the tail when the crossfade
+/// started.
+fn mix_crossfade(tail: &mut VecDeque<f64>, len: usize, data: &mut [f64]) {
+    for sample in data.iter_mut() {
+        match tail.pop_front() {
+            Some(tail_sample) => {
+                let fade_in = 1.0 - tail.len() as f64 / len as f64;
+                *sample = *sample * fade_in + tail_sample * (1.0 - fade_in);
+            }
+            None => break,
+        }
+    }
+}
+
+/// Drains the held back end of a track with a fade out, for when there is no
+/// next track to mix it into.
+fn fade_out_crossfade(tail: &mut VecDeque<f64>) -> Vec<f64> {
+    let len = tail.len();
+    tail.drain(..)
+        .enumerate()
+        .map(|(i, sample)| sample * (1.0 - i as f64 / len as f64))
+        .collect()
+}
+
 impl Drop for PlayerInternal {
     fn drop(&mut self) {
         debug!("drop PlayerInternal[{}]", self.player_id);
@@ -2300,6 +3267,13 @@ impl fmt::Debug for PlayerCommand {
                 .debug_tuple("SetAutoNormaliseAsAlbum")
                 .field(&setting)
                 .finish(),
+            PlayerCommand::SetContextUri(context_uri) => {
+                f.debug_tuple("SetContextUri").field(&context_uri).finish()
+            }
+            PlayerCommand::SetEqualizer(bands) => {
+                f.debug_tuple("SetEqualizer").field(&bands).finish()
+            }
+            PlayerCommand::GetStats(_) => f.debug_tuple("GetStats").finish(),
             PlayerCommand::EmitFilterExplicitContentChangedEvent(filter) => f
                 .debug_tuple("EmitFilterExplicitContentChangedEvent")
                 .field(&filter)
@@ -2344,6 +3318,11 @@ impl fmt::Debug for PlayerCommand {
                 .debug_tuple("EmitAutoPlayChangedEvent")
                 .field(&auto_play)
                 .finish(),
+            PlayerCommand::EmitBackendMetadataChangedEvent { key, value } => f
+                .debug_tuple("EmitBackendMetadataChangedEvent")
+                .field(&key)
+                .field(&value)
+                .finish(),
         }
     }
 }
@@ -2452,3 +3431,67 @@ where
         Some(self.length)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mix_crossfade() {
+        let mut tail: VecDeque<f64> = vec![1.0; 4].into();
+        let mut data = vec![0.0; 6];
+
+        mix_crossfade(&mut tail, 4, &mut data[..2]);
+        assert_eq!(data[..2], [0.75, 0.5]);
+        assert_eq!(tail.len(), 2);
+
+        // The crossfade continues across packets and stops with the tail.
+        mix_crossfade(&mut tail, 4, &mut data[2..]);
+        assert_eq!(data, [0.75, 0.5, 0.25, 0.0, 0.0, 0.0]);
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn test_mix_crossfade_keeps_level() {
+        let mut tail: VecDeque<f64> = vec![0.5; 8].into();
+        let mut data = vec![0.5; 8];
+
+        mix_crossfade(&mut tail, 8, &mut data);
+        assert!(data.iter().all(|sample| (sample - 0.5).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_apply_ramp() {
+        // Two channels, the gain is the same for both samples of a frame.
+        let mut data = vec![1.0; 8];
+        let (mut fade_in, mut fade_out) = (4, 0);
+        assert_eq!(apply_ramp(&mut data, 4, &mut fade_in, &mut fade_out), 4);
+        assert_eq!(data, [0.0, 0.0, 0.25, 0.25, 0.5, 0.5, 0.75, 0.75]);
+        assert_eq!(fade_in, 0);
+
+        // A fade out continues across packets and silences what follows it.
+        let mut data = vec![1.0; 6];
+        let (mut fade_in, mut fade_out) = (0, 4);
+        assert_eq!(apply_ramp(&mut data, 4, &mut fade_in, &mut fade_out), 3);
+        assert_eq!(data, [1.0, 1.0, 0.75, 0.75, 0.5, 0.5]);
+
+        let mut data = vec![1.0; 6];
+        assert_eq!(apply_ramp(&mut data, 4, &mut fade_in, &mut fade_out), 1);
+        assert_eq!(data[..2], [0.25, 0.25]);
+        assert_eq!(fade_out, 0);
+
+        // Without a fade, everything is heard unchanged.
+        let mut data = vec![1.0; 4];
+        assert_eq!(apply_ramp(&mut data, 4, &mut fade_in, &mut fade_out), 2);
+        assert_eq!(data, [1.0; 4]);
+    }
+
+    #[test]
+    fn test_fade_out_crossfade() {
+        let mut tail: VecDeque<f64> = vec![1.0; 4].into();
+
+        assert_eq!(fade_out_crossfade(&mut tail), [1.0, 0.75, 0.5, 0.25]);
+        assert!(tail.is_empty());
+        assert!(fade_out_crossfade(&mut tail).is_empty());
+    }
+}
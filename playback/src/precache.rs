@@ -0,0 +1,220 @@
+//! Fills the audio cache with the tracks of a context ahead of time, e.g. to
+//! prepare for a drive without reception.
+
+use std::{fs, time::Duration};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::{
+    audio::download_file,
+    config::Bitrate,
+    core::{cache::CacheError, spotify_id::SpotifyItemType, Error, Session, SpotifyId},
+    downloads::resolve_file,
+    metadata::{Album, Artist, Audiobook, Metadata, Playlist, Show},
+};
+
+#[derive(Debug, Error)]
+pub enum PrecacheError {
+    #[error("cannot pre-cache <{0}>, only tracks, episodes, albums, artists, playlists, shows and audiobooks are supported")]
+    Context(String),
+}
+
+impl From<PrecacheError> for Error {
+    fn from(err: PrecacheError) -> Self {
+        Error::invalid_argument(err)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PrecacheConfig {
+    pub bitrate: Bitrate,
+    // Pause between files, to leave bandwidth to whatever is playing.
+    pub delay: Duration,
+}
+
+impl Default for PrecacheConfig {
+    fn default() -> Self {
+        Self {
+            bitrate: Bitrate::default(),
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PrecacheEvent {
+    Started {
+        context_uri: String,
+        total: usize,
+    },
+    // Sent after every track, whether it was cached or failed.
+    Progress {
+        context_uri: String,
+        track_id: SpotifyId,
+        cached: usize,
+        failed: usize,
+        total: usize,
+    },
+    Finished {
+        context_uri: String,
+        cached: usize,
+        failed: usize,
+    },
+}
+
+pub struct Precacher {
+    session: Session,
+    config: PrecacheConfig,
+    event_senders: Mutex<Vec<mpsc::UnboundedSender<PrecacheEvent>>>,
+}
+
+impl Precacher {
+    pub fn new(session: Session, config: PrecacheConfig) -> Self {
+        Self {
+            session,
+            config,
+            event_senders: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get_event_channel(&self) -> mpsc::UnboundedReceiver<PrecacheEvent> {
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+        self.event_senders.lock().push(event_sender);
+        event_receiver
+    }
+
+    /// Downloads the audio files of all tracks of a context into the cache,
+    /// one at a time. Tracks that are cached already are skipped. Tracks that
+    /// fail are reported and skipped as well.
+    pub async fn precache_context(&self, context_uri: &str) -> Result<(), Error> {
+        if self.session.cache().is_none() {
+            return Err(CacheError::Path.into());
+        }
+
+        let tracks = self.context_tracks(context_uri).await?;
+        let total = tracks.len();
+
+        if let Err(e) = self.session.spclient().prefetch_metadata(&tracks).await {
+            warn!("Unable to prefetch metadata of <{}>: {}", context_uri, e);
+        }
+
+        info!("Pre-caching {} tracks of <{}>", total, context_uri);
+        self.send_event(PrecacheEvent::Started {
+            context_uri: context_uri.to_owned(),
+            total,
+        });
+
+        let (mut cached, mut failed) = (0, 0);
+
+        for (i, track_id) in tracks.into_iter().enumerate() {
+            match self.precache_track(track_id).await {
+                Ok(downloaded) => {
+                    cached += 1;
+
+                    if downloaded && i + 1 < total && !self.config.delay.is_zero() {
+                        tokio::time::sleep(self.config.delay).await;
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Unable to pre-cache <{}>: {}",
+                        track_id.to_uri().unwrap_or_default(),
+                        e
+                    );
+                    failed += 1;
+                }
+            }
+
+            self.send_event(PrecacheEvent::Progress {
+                context_uri: context_uri.to_owned(),
+                track_id,
+                cached,
+                failed,
+                total,
+            });
+        }
+
+        info!(
+            "Pre-cached {} of {} tracks of <{}>",
+            cached, total, context_uri
+        );
+        self.send_event(PrecacheEvent::Finished {
+            context_uri: context_uri.to_owned(),
+            cached,
+            failed,
+        });
+
+        Ok(())
+    }
+
+    async fn context_tracks(&self, context_uri: &str) -> Result<Vec<SpotifyId>, Error> {
+        let id = SpotifyId::from_uri(context_uri)?;
+        let session = &self.session;
+
+        let tracks = match id.item_type {
+            SpotifyItemType::Track | SpotifyItemType::Episode => vec![id],
+            SpotifyItemType::Album => Album::get(session, &id).await?.tracks().copied().collect(),
+            SpotifyItemType::Artist => {
+                let country = session.user_data().country;
+                Artist::get(session, &id)
+                    .await?
+                    .top_tracks
+                    .for_country(&country)
+                    .to_vec()
+            }
+            SpotifyItemType::Playlist => Playlist::get(session, &id)
+                .await?
+                .tracks()
+                .copied()
+                .filter(|id| {
+                    matches!(
+                        id.item_type,
+                        SpotifyItemType::Track | SpotifyItemType::Episode
+                    )
+                })
+                .collect(),
+            SpotifyItemType::Show => Show::get(session, &id).await?.episodes.to_vec(),
+            SpotifyItemType::Audiobook => Audiobook::get(session, &id).await?.chapters.to_vec(),
+            _ => return Err(PrecacheError::Context(context_uri.to_owned()).into()),
+        };
+
+        Ok(tracks)
+    }
+
+    // Returns whether the file had to be downloaded.
+    async fn precache_track(&self, track_id: SpotifyId) -> Result<bool, Error> {
+        let cache = self.session.cache().ok_or(CacheError::Path)?;
+        let (_, file_id) = resolve_file(&self.session, track_id, self.config.bitrate).await?;
+
+        if cache.file_path(file_id).is_none() {
+            return Err(CacheError::Path.into());
+        } else if cache.file(file_id).is_some() {
+            debug!("File {} already in cache", file_id);
+            return Ok(false);
+        }
+
+        let part_path = self
+            .session
+            .config()
+            .tmp_dir
+            .join(format!("{}.part", file_id.to_base16()?));
+
+        download_file(&self.session, file_id, &part_path, |_, _| Ok(())).await?;
+
+        let result = fs::File::open(&part_path)
+            .map_err(Error::from)
+            .and_then(|mut file| cache.save_file(file_id, &mut file));
+        let _ = fs::remove_file(&part_path);
+        result?;
+
+        Ok(true)
+    }
+
+    fn send_event(&self, event: PrecacheEvent) {
+        self.event_senders
+            .lock()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
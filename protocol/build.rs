@@ -16,6 +16,7 @@ fn compile() {
     let proto_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").expect("env")).join("proto");
 
     let files = &[
+        proto_dir.join("collection2v2.proto"),
         proto_dir.join("connect.proto"),
         proto_dir.join("connectivity.proto"),
         proto_dir.join("devices.proto"),
@@ -23,10 +24,12 @@ fn compile() {
         proto_dir.join("extended_metadata.proto"),
         proto_dir.join("extension_kind.proto"),
         proto_dir.join("metadata.proto"),
+        proto_dir.join("played_state.proto"),
         proto_dir.join("player.proto"),
         proto_dir.join("playlist_annotate3.proto"),
         proto_dir.join("playlist_permission.proto"),
         proto_dir.join("playlist4_external.proto"),
+        proto_dir.join("podcast_segments.proto"),
         proto_dir.join("spotify/clienttoken/v0/clienttoken_http.proto"),
         proto_dir.join("spotify/login5/v3/challenges/code.proto"),
         proto_dir.join("spotify/login5/v3/challenges/hashcash.proto"),
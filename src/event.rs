@@ -0,0 +1,400 @@
+//! A stable, serializable form of the events of librespot, for processes that
+//! consume them as JSON. Unlike [`PlayerEvent`] and friends, which change
+//! with the internals, this schema only changes together with
+//! [`SCHEMA_VERSION`].
+//!
+//! Every event serializes to an object with the schema version, the name of
+//! the event in snake case and its fields, e.g.:
+//!
+//! ```json
+//! {"version":1,"event":"playing","track_uri":"spotify:track:4uLU6hMCjMI75M1A2tKUQC","position_ms":0}
+//! ```
+//!
+//! URIs are `null` if the item has no URI form. Events and fields may be
+//! added within a version, so consumers should ignore those they don't know.
+
+use serde::Serialize;
+
+use crate::{
+    connect::spirc::SpircState,
+    core::SpotifyId,
+    discovery::DiscoveryEvent,
+    playback::{config::Bitrate, player::PlayerEvent},
+    protocol::spirc::PlayStatus,
+};
+
+/// The version of the JSON shape of [`Event`]. It is raised when fields are
+/// renamed, removed or change their meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// An [`Event`] with the schema version, which is what should be sent to
+/// other processes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionedEvent {
+    pub version: u32,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl From<Event> for VersionedEvent {
+    fn from(event: Event) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            event,
+        }
+    }
+}
+
+/// The playback status that is reported to Spotify Connect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackStatus {
+    Stopped,
+    Playing,
+    Paused,
+    Loading,
+}
+
+impl From<PlayStatus> for PlaybackStatus {
+    fn from(status: PlayStatus) -> Self {
+        match status {
+            PlayStatus::kPlayStatusStop => Self::Stopped,
+            PlayStatus::kPlayStatusPlay => Self::Playing,
+            PlayStatus::kPlayStatusPause => Self::Paused,
+            PlayStatus::kPlayStatusLoading => Self::Loading,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    /// Playback was stopped.
+    Stopped {
+        track_uri: Option<String>,
+    },
+    /// Playback waits for a track to load.
+    Loading {
+        track_uri: Option<String>,
+        position_ms: u32,
+    },
+    /// The next track is loaded in advance.
+    Preloading {
+        track_uri: Option<String>,
+    },
+    /// Playback started or resumed at `position_ms`.
+    Playing {
+        track_uri: Option<String>,
+        position_ms: u32,
+    },
+    Paused {
+        track_uri: Option<String>,
+        position_ms: u32,
+    },
+    /// Playback continues at `position_ms` after a seek.
+    Seeked {
+        track_uri: Option<String>,
+        position_ms: u32,
+    },
+    /// Playback drifted, and continues at `position_ms`.
+    PositionCorrection {
+        track_uri: Option<String>,
+        position_ms: u32,
+    },
+    /// Sent periodically while playing, for consumers that show the position
+    /// without interpolating it.
+    Position {
+        track_uri: Option<String>,
+        position_ms: u32,
+    },
+    /// Playback ran out of data. `progress` goes from 0 to 100 until a
+    /// `playing` event follows.
+    Buffering {
+        track_uri: Option<String>,
+        position_ms: u32,
+        progress: u8,
+    },
+    EndOfTrack {
+        track_uri: Option<String>,
+    },
+    /// The track can't be played, and is skipped.
+    Unavailable {
+        track_uri: Option<String>,
+    },
+    /// A new track was loaded. For episodes, `album` is the show.
+    TrackChanged {
+        track_uri: Option<String>,
+        title: String,
+        artists: Vec<String>,
+        album: Option<String>,
+        duration_ms: u32,
+        cover_url: Option<String>,
+        context_uri: Option<String>,
+    },
+    /// Playback is about to start after the player was idle.
+    PlaybackStarting,
+    /// Playback has been paused or stopped for `idle_timeout_ms`.
+    Idle {
+        idle_timeout_ms: u64,
+    },
+    /// The volume, from 0 to 65535.
+    VolumeChanged {
+        volume: u16,
+    },
+    /// The bitrate that following tracks are loaded with.
+    BitrateChanged {
+        bitrate_kbps: u32,
+    },
+    ShuffleChanged {
+        shuffle: bool,
+    },
+    RepeatChanged {
+        repeat: bool,
+    },
+    AutoPlayChanged {
+        auto_play: bool,
+    },
+    FilterExplicitContentChanged {
+        filter: bool,
+    },
+    /// Metadata that a client pushed for the playback backend.
+    BackendMetadataChanged {
+        key: String,
+        value: String,
+    },
+
+    SessionConnected {
+        connection_id: String,
+        user_name: String,
+    },
+    SessionDisconnected {
+        connection_id: String,
+        user_name: String,
+    },
+    /// Another Spotify Connect client took control.
+    SessionClientChanged {
+        client_id: String,
+        client_name: String,
+        client_brand_name: String,
+        client_model_name: String,
+    },
+
+    /// The state that is reported to Spotify Connect changed.
+    ConnectStateChanged {
+        active: bool,
+        status: PlaybackStatus,
+        context_uri: String,
+        playing_track_index: u32,
+        track_uris: Vec<Option<String>>,
+        shuffle: bool,
+        repeat: bool,
+        volume: u16,
+    },
+
+    /// The device is announced on the network, and clients can connect on `port`.
+    DiscoveryAnnounced {
+        name: String,
+        port: u16,
+    },
+    /// A client sent the credentials of `username` through discovery.
+    DiscoveryUserConnected {
+        username: String,
+    },
+    /// The credentials of `username` were received and are used to log in.
+    DiscoveryCredentialsReceived {
+        username: String,
+    },
+    DiscoveryAuthenticationFailed {
+        username: String,
+        reason: String,
+    },
+    DiscoveryError {
+        reason: String,
+    },
+}
+
+fn uri(id: &SpotifyId) -> Option<String> {
+    id.to_uri().ok()
+}
+
+impl Event {
+    /// The stable form of a player event, or `None` for events that are
+    /// internal to librespot or covered by another event.
+    pub fn from_player_event(event: &PlayerEvent) -> Option<Self> {
+        use PlayerEvent::*;
+
+        let event = match event {
+            Stopped { track_id, .. } => Self::Stopped {
+                track_uri: uri(track_id),
+            },
+            Loading {
+                track_id,
+                position_ms,
+                ..
+            } => Self::Loading {
+                track_uri: uri(track_id),
+                position_ms: *position_ms,
+            },
+            Preloading { track_id } => Self::Preloading {
+                track_uri: uri(track_id),
+            },
+            Playing {
+                track_id,
+                position_ms,
+                ..
+            } => Self::Playing {
+                track_uri: uri(track_id),
+                position_ms: *position_ms,
+            },
+            Paused {
+                track_id,
+                position_ms,
+                ..
+            } => Self::Paused {
+                track_uri: uri(track_id),
+                position_ms: *position_ms,
+            },
+            Seeked {
+                track_id,
+                position_ms,
+                ..
+            } => Self::Seeked {
+                track_uri: uri(track_id),
+                position_ms: *position_ms,
+            },
+            PositionCorrection {
+                track_id,
+                position_ms,
+                ..
+            } => Self::PositionCorrection {
+                track_uri: uri(track_id),
+                position_ms: *position_ms,
+            },
+            Buffering {
+                track_id,
+                position_ms,
+                progress,
+                ..
+            } => Self::Buffering {
+                track_uri: uri(track_id),
+                position_ms: *position_ms,
+                progress: *progress,
+            },
+            EndOfTrack { track_id, .. } => Self::EndOfTrack {
+                track_uri: uri(track_id),
+            },
+            Unavailable { track_id, .. } => Self::Unavailable {
+                track_uri: uri(track_id),
+            },
+            TrackMetadata {
+                track_id,
+                title,
+                artists,
+                album,
+                duration_ms,
+                cover_url,
+                context_uri,
+            } => Self::TrackChanged {
+                track_uri: uri(track_id),
+                title: title.clone(),
+                artists: artists.clone(),
+                album: album.clone(),
+                duration_ms: *duration_ms,
+                cover_url: cover_url.clone(),
+                context_uri: context_uri.clone(),
+            },
+            PlaybackStarting => Self::PlaybackStarting,
+            Idle { idle_timeout } => Self::Idle {
+                idle_timeout_ms: idle_timeout.as_millis() as u64,
+            },
+            VolumeChanged { volume } => Self::VolumeChanged { volume: *volume },
+            BitrateChanged { bitrate } => Self::BitrateChanged {
+                bitrate_kbps: match bitrate {
+                    Bitrate::Bitrate96 => 96,
+                    Bitrate::Bitrate160 => 160,
+                    Bitrate::Bitrate320 => 320,
+                },
+            },
+            ShuffleChanged { shuffle } => Self::ShuffleChanged { shuffle: *shuffle },
+            RepeatChanged { repeat } => Self::RepeatChanged { repeat: *repeat },
+            AutoPlayChanged { auto_play } => Self::AutoPlayChanged {
+                auto_play: *auto_play,
+            },
+            FilterExplicitContentChanged { filter } => {
+                Self::FilterExplicitContentChanged { filter: *filter }
+            }
+            BackendMetadataChanged { key, value } => Self::BackendMetadataChanged {
+                key: key.clone(),
+                value: value.clone(),
+            },
+            SessionConnected {
+                connection_id,
+                user_name,
+            } => Self::SessionConnected {
+                connection_id: connection_id.clone(),
+                user_name: user_name.clone(),
+            },
+            SessionDisconnected {
+                connection_id,
+                user_name,
+            } => Self::SessionDisconnected {
+                connection_id: connection_id.clone(),
+                user_name: user_name.clone(),
+            },
+            SessionClientChanged {
+                client_id,
+                client_name,
+                client_brand_name,
+                client_model_name,
+            } => Self::SessionClientChanged {
+                client_id: client_id.clone(),
+                client_name: client_name.clone(),
+                client_brand_name: client_brand_name.clone(),
+                client_model_name: client_model_name.clone(),
+            },
+            // Internal to librespot, or covered by `TrackMetadata`.
+            PlayRequestIdChanged { .. }
+            | TimeToPreloadNextTrack { .. }
+            | TrackChanged { .. }
+            | Stats { .. } => return None,
+        };
+
+        Some(event)
+    }
+}
+
+impl From<&SpircState> for Event {
+    fn from(state: &SpircState) -> Self {
+        Self::ConnectStateChanged {
+            active: state.is_active,
+            status: state.status.into(),
+            context_uri: state.context_uri.clone(),
+            playing_track_index: state.playing_track_index,
+            track_uris: state
+                .tracks
+                .iter()
+                .map(|track| SpotifyId::try_from(track).ok().as_ref().and_then(uri))
+                .collect(),
+            shuffle: state.shuffle,
+            repeat: state.repeat,
+            volume: state.volume,
+        }
+    }
+}
+
+impl From<DiscoveryEvent> for Event {
+    fn from(event: DiscoveryEvent) -> Self {
+        match event {
+            DiscoveryEvent::Announced { name, port } => Self::DiscoveryAnnounced { name, port },
+            DiscoveryEvent::UserConnected { username } => Self::DiscoveryUserConnected { username },
+            DiscoveryEvent::CredentialsReceived { username } => {
+                Self::DiscoveryCredentialsReceived { username }
+            }
+            DiscoveryEvent::AuthenticationFailed { username, reason } => {
+                Self::DiscoveryAuthenticationFailed { username, reason }
+            }
+            DiscoveryEvent::Error { reason } => Self::DiscoveryError { reason },
+        }
+    }
+}
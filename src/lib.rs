@@ -8,3 +8,5 @@ pub use librespot_metadata as metadata;
 pub use librespot_oauth as oauth;
 pub use librespot_playback as playback;
 pub use librespot_protocol as protocol;
+
+pub mod event;
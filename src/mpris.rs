@@ -0,0 +1,533 @@
+//! Exposes playback as an MPRIS media player on the session bus, so desktop
+//! environments can show what is playing and control it with media keys.
+
+use log::{debug, warn};
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::task::JoinHandle;
+use zbus::{
+    fdo,
+    object_server::{InterfaceRef, SignalEmitter},
+    zvariant::{ObjectPath, Value},
+    Connection,
+};
+
+use librespot::{
+    connect::spirc::Spirc,
+    playback::player::{PlayerEvent, PlayerEventChannel},
+};
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Status {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+#[derive(Default)]
+struct Track {
+    id: String,
+    uri: String,
+    title: String,
+    artists: Vec<String>,
+    album: Option<String>,
+    duration_ms: u32,
+    cover_url: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    status: Status,
+    track: Option<Track>,
+    volume: u16,
+    shuffle: bool,
+    repeat: bool,
+    position_ms: u32,
+    // When `position_ms` was reported while playing.
+    playing_since: Option<Instant>,
+}
+
+impl State {
+    fn position_ms(&self) -> u32 {
+        let elapsed = self
+            .playing_since
+            .map_or(0, |since| since.elapsed().as_millis() as u32);
+        self.position_ms.saturating_add(elapsed)
+    }
+
+    fn set_position(&mut self, position_ms: u32, playing: bool) {
+        self.position_ms = position_ms;
+        self.playing_since = playing.then(Instant::now);
+    }
+
+    fn update(&mut self, event: PlayerEvent) -> Option<Change> {
+        match event {
+            PlayerEvent::TrackMetadata {
+                track_id,
+                title,
+                artists,
+                album,
+                duration_ms,
+                cover_url,
+                ..
+            } => {
+                self.track = Some(Track {
+                    id: format!("/org/librespot/track/{}", track_id.id),
+                    uri: track_id.to_uri().unwrap_or_default(),
+                    title,
+                    artists,
+                    album,
+                    duration_ms,
+                    cover_url,
+                });
+                Some(Change::Metadata)
+            }
+            PlayerEvent::Playing { position_ms, .. } => {
+                self.status = Status::Playing;
+                self.set_position(position_ms, true);
+                Some(Change::PlaybackStatus)
+            }
+            PlayerEvent::Paused { position_ms, .. } => {
+                self.status = Status::Paused;
+                self.set_position(position_ms, false);
+                Some(Change::PlaybackStatus)
+            }
+            PlayerEvent::Loading { position_ms, .. }
+            | PlayerEvent::Buffering { position_ms, .. } => {
+                self.set_position(position_ms, false);
+                None
+            }
+            PlayerEvent::Seeked { position_ms, .. }
+            | PlayerEvent::PositionCorrection { position_ms, .. } => {
+                let playing = self.playing_since.is_some();
+                self.set_position(position_ms, playing);
+                Some(Change::Seeked(position_ms))
+            }
+            PlayerEvent::Stopped { .. } => {
+                self.status = Status::Stopped;
+                self.track = None;
+                self.set_position(0, false);
+                Some(Change::Stopped)
+            }
+            PlayerEvent::VolumeChanged { volume } => {
+                self.volume = volume;
+                Some(Change::Volume)
+            }
+            PlayerEvent::ShuffleChanged { shuffle } => {
+                self.shuffle = shuffle;
+                Some(Change::Shuffle)
+            }
+            PlayerEvent::RepeatChanged { repeat } => {
+                self.repeat = repeat;
+                Some(Change::LoopStatus)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The properties or signals to emit after an event.
+enum Change {
+    Metadata,
+    PlaybackStatus,
+    Stopped,
+    Seeked(u32),
+    Volume,
+    Shuffle,
+    LoopStatus,
+}
+
+#[derive(Default)]
+struct Shared {
+    spirc: Mutex<Option<Spirc>>,
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn command(
+        &self,
+        command: impl FnOnce(&Spirc) -> Result<(), librespot::core::Error>,
+    ) -> fdo::Result<()> {
+        let spirc = self
+            .spirc
+            .lock()
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        match spirc.as_ref() {
+            Some(spirc) => command(spirc).map_err(|e| fdo::Error::Failed(e.to_string())),
+            None => Err(fdo::Error::Failed("Not connected to Spotify".into())),
+        }
+    }
+
+    fn state<T>(&self, f: impl FnOnce(&State) -> T) -> T {
+        match self.state.lock() {
+            Ok(state) => f(&state),
+            Err(e) => f(e.get_ref()),
+        }
+    }
+}
+
+struct Root {
+    identity: String,
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+struct MprisPlayer {
+    shared: Arc<Shared>,
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl MprisPlayer {
+    fn next(&self) -> fdo::Result<()> {
+        self.shared.command(Spirc::next)
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.shared.command(Spirc::prev)
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.shared.command(Spirc::pause)
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.shared.command(Spirc::play_pause)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.shared.command(Spirc::pause)
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        self.shared.command(Spirc::play)
+    }
+
+    /// Seeks forwards, or backwards with a negative offset in microseconds.
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        let (position_ms, duration_ms) = self.shared.state(|state| {
+            let duration_ms = state.track.as_ref().map_or(0, |track| track.duration_ms);
+            (state.position_ms(), duration_ms)
+        });
+        let position_ms = position_ms as i64 + offset / 1000;
+
+        if position_ms >= duration_ms as i64 {
+            self.shared.command(Spirc::next)
+        } else {
+            let position_ms = position_ms.max(0) as u32;
+            self.shared
+                .command(|spirc| spirc.set_position_ms(position_ms))
+        }
+    }
+
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let (current, duration_ms) = self.shared.state(|state| {
+            state.track.as_ref().map_or((false, 0), |track| {
+                (track.id == track_id.as_str(), track.duration_ms)
+            })
+        });
+
+        // Requests for a track that is no longer playing are ignored, as are
+        // positions outside of it.
+        if !current || position < 0 || position / 1000 > duration_ms as i64 {
+            return Ok(());
+        }
+
+        let position_ms = (position / 1000) as u32;
+        self.shared
+            .command(|spirc| spirc.set_position_ms(position_ms))
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "Opening URIs is not supported".into(),
+        ))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &'static str {
+        match self.shared.state(|state| state.status) {
+            Status::Playing => "Playing",
+            Status::Paused => "Paused",
+            Status::Stopped => "Stopped",
+        }
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &'static str {
+        if self.shared.state(|state| state.repeat) {
+            "Playlist"
+        } else {
+            "None"
+        }
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, loop_status: &str) {
+        let repeat = loop_status != "None";
+        if let Err(e) = self.shared.command(|spirc| spirc.repeat(repeat)) {
+            warn!("Unable to set the loop status to {loop_status}: {e}");
+        }
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.shared.state(|state| state.shuffle)
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, shuffle: bool) {
+        if let Err(e) = self.shared.command(|spirc| spirc.shuffle(shuffle)) {
+            warn!("Unable to set shuffle: {e}");
+        }
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        self.shared.state(|state| metadata(state.track.as_ref()))
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.shared.state(|state| state.volume) as f64 / u16::MAX as f64
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) {
+        let volume = (volume.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16;
+        if let Err(e) = self.shared.command(|spirc| spirc.set_volume(volume)) {
+            warn!("Unable to set the volume: {e}");
+        }
+    }
+
+    /// In microseconds. Clients interpolate it between `Seeked` signals.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.shared.state(|state| state.position_ms()) as i64 * 1000
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn metadata(track: Option<&Track>) -> HashMap<String, Value<'static>> {
+    let mut metadata = HashMap::new();
+
+    let track = match track {
+        Some(track) => track,
+        None => {
+            metadata.insert(
+                "mpris:trackid".into(),
+                ObjectPath::from_static_str_unchecked(NO_TRACK).into(),
+            );
+            return metadata;
+        }
+    };
+
+    if let Ok(id) = ObjectPath::try_from(track.id.clone()) {
+        metadata.insert("mpris:trackid".into(), id.into());
+    }
+    metadata.insert(
+        "mpris:length".into(),
+        (track.duration_ms as i64 * 1000).into(),
+    );
+    metadata.insert("xesam:title".into(), track.title.clone().into());
+    metadata.insert("xesam:artist".into(), track.artists.clone().into());
+    metadata.insert("xesam:url".into(), track.uri.clone().into());
+    if let Some(album) = &track.album {
+        metadata.insert("xesam:album".into(), album.clone().into());
+    }
+    if let Some(cover_url) = &track.cover_url {
+        metadata.insert("mpris:artUrl".into(), cover_url.clone().into());
+    }
+
+    metadata
+}
+
+/// Keeps the MPRIS player registered until dropped.
+pub struct Mpris {
+    shared: Arc<Shared>,
+    _connection: Connection,
+    task: JoinHandle<()>,
+}
+
+impl Mpris {
+    /// Registers a player named `identity` on the session bus, which tracks
+    /// the state of the player through `player_events`.
+    pub async fn new(identity: String, player_events: PlayerEventChannel) -> zbus::Result<Self> {
+        let shared = Arc::<Shared>::default();
+
+        let connection = zbus::connection::Builder::session()?
+            .name(format!(
+                "org.mpris.MediaPlayer2.librespot.instance{}",
+                std::process::id()
+            ))?
+            .serve_at(OBJECT_PATH, Root { identity })?
+            .serve_at(
+                OBJECT_PATH,
+                MprisPlayer {
+                    shared: shared.clone(),
+                },
+            )?
+            .build()
+            .await?;
+
+        let player = connection
+            .object_server()
+            .interface::<_, MprisPlayer>(OBJECT_PATH)
+            .await?;
+        let task = tokio::spawn(Self::track_player(shared.clone(), player, player_events));
+
+        Ok(Self {
+            shared,
+            _connection: connection,
+            task,
+        })
+    }
+
+    /// Sets the Spotify Connect instance that commands go to, `None` while
+    /// there is none.
+    pub fn set_spirc(&self, spirc: Option<Spirc>) {
+        if let Ok(mut current) = self.shared.spirc.lock() {
+            *current = spirc;
+        }
+    }
+
+    async fn track_player(
+        shared: Arc<Shared>,
+        player: InterfaceRef<MprisPlayer>,
+        mut player_events: PlayerEventChannel,
+    ) {
+        while let Some(event) = player_events.recv().await {
+            if let Err(e) = Self::handle_event(&shared, &player, event).await {
+                debug!("Unable to signal an MPRIS change: {e}");
+            }
+        }
+    }
+
+    async fn handle_event(
+        shared: &Shared,
+        player: &InterfaceRef<MprisPlayer>,
+        event: PlayerEvent,
+    ) -> zbus::Result<()> {
+        let change = match shared.state.lock() {
+            Ok(mut state) => state.update(event),
+            Err(_) => None,
+        };
+
+        let emitter = player.signal_emitter();
+        let iface = player.get().await;
+        match change {
+            Some(Change::Metadata) => iface.metadata_changed(emitter).await,
+            Some(Change::PlaybackStatus) => iface.playback_status_changed(emitter).await,
+            Some(Change::Stopped) => {
+                iface.playback_status_changed(emitter).await?;
+                iface.metadata_changed(emitter).await
+            }
+            Some(Change::Seeked(position_ms)) => {
+                MprisPlayer::seeked(emitter, position_ms as i64 * 1000).await
+            }
+            Some(Change::Volume) => iface.volume_changed(emitter).await,
+            Some(Change::Shuffle) => iface.shuffle_changed(emitter).await,
+            Some(Change::LoopStatus) => iface.loop_status_changed(emitter).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Mpris {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
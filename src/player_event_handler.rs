@@ -93,6 +93,10 @@ impl EventHandler {
                                 }
                             }
                         }
+                        // The same metadata is passed on by track_changed.
+                        PlayerEvent::TrackMetadata { .. } => {}
+                        // Too frequent to run a program for.
+                        PlayerEvent::Stats { .. } => {}
                         PlayerEvent::Stopped { track_id, .. } => match track_id.to_base62() {
                             Err(e) => warn!("PlayerEvent::Stopped: Invalid track id: {}", e),
                             Ok(id) => {
@@ -180,6 +184,20 @@ impl EventHandler {
                                 env_vars.insert("POSITION_MS", position_ms.to_string());
                             }
                         },
+                        PlayerEvent::Buffering {
+                            track_id,
+                            position_ms,
+                            progress,
+                            ..
+                        } => match track_id.to_base62() {
+                            Err(e) => warn!("PlayerEvent::Buffering: Invalid track id: {}", e),
+                            Ok(id) => {
+                                env_vars.insert("PLAYER_EVENT", "buffering".to_string());
+                                env_vars.insert("TRACK_ID", id);
+                                env_vars.insert("POSITION_MS", position_ms.to_string());
+                                env_vars.insert("PROGRESS", progress.to_string());
+                            }
+                        },
                         PlayerEvent::PositionCorrection {
                             track_id,
                             position_ms,
@@ -235,6 +253,10 @@ impl EventHandler {
                             env_vars.insert("AUTO_PLAY", auto_play.to_string());
                         }
 
+                        PlayerEvent::BitrateChanged { bitrate } => {
+                            env_vars.insert("PLAYER_EVENT", "bitrate_changed".to_string());
+                            env_vars.insert("BITRATE", bitrate.to_string());
+                        }
                         PlayerEvent::FilterExplicitContentChanged { filter } => {
                             env_vars.insert(
                                 "PLAYER_EVENT",
@@ -242,6 +264,19 @@ impl EventHandler {
                             );
                             env_vars.insert("FILTER", filter.to_string());
                         }
+                        PlayerEvent::PlaybackStarting => {
+                            env_vars.insert("PLAYER_EVENT", "playback_starting".to_string());
+                        }
+                        PlayerEvent::Idle { idle_timeout } => {
+                            env_vars.insert("PLAYER_EVENT", "idle".to_string());
+                            env_vars
+                                .insert("IDLE_TIMEOUT_MS", idle_timeout.as_millis().to_string());
+                        }
+                        PlayerEvent::BackendMetadataChanged { key, value } => {
+                            env_vars.insert("PLAYER_EVENT", "backend_metadata_changed".to_string());
+                            env_vars.insert("METADATA_KEY", key);
+                            env_vars.insert("METADATA_VALUE", value);
+                        }
                     }
 
                     if !env_vars.is_empty() {
@@ -0,0 +1,499 @@
+use log::{debug, error, info};
+
+use std::{
+    convert::Infallible,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE, HOST, ORIGIN},
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::{net::TcpListener, sync::oneshot};
+
+use librespot::{
+    connect::spirc::Spirc,
+    core::SpotifyId,
+    playback::player::{PlayerEvent, PlayerEventChannel},
+    protocol::spirc::PlayStatus,
+};
+
+#[derive(Debug, Error)]
+enum ApiError {
+    #[error("not found")]
+    NotFound,
+    #[error("cross-origin requests are not allowed")]
+    ForeignOrigin,
+    #[error("requests that change playback must have a JSON content type")]
+    NotJson,
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("not connected to Spotify")]
+    NotConnected,
+    #[error("{0}")]
+    Failed(#[from] librespot::core::Error),
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::ForeignOrigin => StatusCode::FORBIDDEN,
+            Self::NotJson => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotConnected => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Default)]
+struct NowPlaying {
+    track: Option<Value>,
+    position_ms: u32,
+    // When `position_ms` was reported while playing.
+    playing_since: Option<Instant>,
+}
+
+impl NowPlaying {
+    fn position_ms(&self) -> u32 {
+        let elapsed = self
+            .playing_since
+            .map_or(0, |since| since.elapsed().as_millis() as u32);
+        self.position_ms.saturating_add(elapsed)
+    }
+
+    fn set_position(&mut self, position_ms: u32, playing: bool) {
+        self.position_ms = position_ms;
+        self.playing_since = playing.then(Instant::now);
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    spirc: Mutex<Option<Spirc>>,
+    now_playing: Mutex<NowPlaying>,
+}
+
+/// An HTTP API to control playback and to see what is playing, for home
+/// automation and the like. Requests go through Spotify Connect, so the state
+/// of other clients stays in sync.
+///
+/// Any web page could otherwise drive the API through the browser of someone
+/// on the same network, so requests from another origin are refused and
+/// `POST` and `PUT` requests must have `Content-Type: application/json`,
+/// which browsers don't send cross-origin without asking first.
+///
+/// - `GET /status`: the playback state and the current track
+/// - `GET /queue`: the tracks of the current context
+/// - `POST /play`, `/pause`, `/play-pause`, `/next`, `/prev`, `/volume-up`,
+///   `/volume-down`, `/activate`, `/disconnect`
+/// - `PUT /volume` with `{"volume": 0-100}`, `/position` with
+///   `{"position_ms": ...}`, `/shuffle` with `{"shuffle": bool}` and
+///   `/repeat` with `{"repeat": bool}`
+pub struct RestApi {
+    shared: Arc<Shared>,
+    _close_tx: oneshot::Sender<Infallible>,
+}
+
+impl RestApi {
+    pub async fn new(address: SocketAddr, player_events: PlayerEventChannel) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        info!("REST API listening on {}", listener.local_addr()?);
+
+        let shared = Arc::<Shared>::default();
+        let (close_tx, close_rx) = oneshot::channel();
+
+        tokio::spawn(Self::track_player(shared.clone(), player_events));
+        tokio::spawn(Self::serve(shared.clone(), listener, close_rx));
+
+        Ok(Self {
+            shared,
+            _close_tx: close_tx,
+        })
+    }
+
+    /// Sets the Spotify Connect instance that requests go to, `None` while
+    /// there is none.
+    pub fn set_spirc(&self, spirc: Option<Spirc>) {
+        if let Ok(mut current) = self.shared.spirc.lock() {
+            *current = spirc;
+        }
+    }
+
+    async fn serve(
+        shared: Arc<Shared>,
+        listener: TcpListener,
+        mut close_rx: oneshot::Receiver<Infallible>,
+    ) {
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let shared = shared.clone();
+                        let service = service_fn(move |request| {
+                            let shared = shared.clone();
+                            async move { Ok::<_, Infallible>(shared.handle(request).await) }
+                        });
+
+                        tokio::spawn(async move {
+                            let io = TokioIo::new(stream);
+                            if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
+                                debug!("REST API connection failed: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => error!("REST API failed to accept a connection: {e}"),
+                },
+                _ = &mut close_rx => break,
+            }
+        }
+    }
+
+    async fn track_player(shared: Arc<Shared>, mut player_events: PlayerEventChannel) {
+        while let Some(event) = player_events.recv().await {
+            let mut now_playing = match shared.now_playing.lock() {
+                Ok(now_playing) => now_playing,
+                Err(_) => break,
+            };
+
+            match event {
+                PlayerEvent::TrackMetadata {
+                    track_id,
+                    title,
+                    artists,
+                    album,
+                    duration_ms,
+                    cover_url,
+                    context_uri,
+                } => {
+                    now_playing.track = Some(json!({
+                        "uri": track_id.to_uri().ok(),
+                        "title": title,
+                        "artists": artists,
+                        "album": album,
+                        "duration_ms": duration_ms,
+                        "cover_url": cover_url,
+                        "context_uri": context_uri,
+                    }));
+                }
+                PlayerEvent::Playing { position_ms, .. } => {
+                    now_playing.set_position(position_ms, true);
+                }
+                PlayerEvent::Paused { position_ms, .. }
+                | PlayerEvent::Loading { position_ms, .. }
+                | PlayerEvent::Buffering { position_ms, .. } => {
+                    now_playing.set_position(position_ms, false);
+                }
+                PlayerEvent::Seeked { position_ms, .. }
+                | PlayerEvent::PositionCorrection { position_ms, .. } => {
+                    let playing = now_playing.playing_since.is_some();
+                    now_playing.set_position(position_ms, playing);
+                }
+                PlayerEvent::Stopped { .. } => *now_playing = NowPlaying::default(),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Shared {
+    async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let (parts, body) = request.into_parts();
+        debug!("REST API request: {} {}", parts.method, parts.uri.path());
+
+        let result = match check_request(&parts.method, &parts.headers) {
+            Ok(()) => match body.collect().await {
+                Ok(body) => self.route(&parts.method, parts.uri.path(), &body.to_bytes()),
+                Err(e) => Err(ApiError::BadRequest(e.to_string())),
+            },
+            Err(e) => Err(e),
+        };
+
+        let (status, body) = match result {
+            Ok(body) => (StatusCode::OK, body),
+            Err(e) => (e.status(), json!({ "error": e.to_string() })),
+        };
+
+        let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    }
+
+    fn route(&self, method: &Method, path: &str, body: &[u8]) -> Result<Value, ApiError> {
+        match (method, path) {
+            (&Method::GET, "/status") => Ok(self.status()),
+            (&Method::GET, "/queue") => self.queue(),
+            (&Method::POST, "/play") => self.command(Spirc::play),
+            (&Method::POST, "/pause") => self.command(Spirc::pause),
+            (&Method::POST, "/play-pause") => self.command(Spirc::play_pause),
+            (&Method::POST, "/next") => self.command(Spirc::next),
+            (&Method::POST, "/prev") => self.command(Spirc::prev),
+            (&Method::POST, "/volume-up") => self.command(Spirc::volume_up),
+            (&Method::POST, "/volume-down") => self.command(Spirc::volume_down),
+            (&Method::POST, "/activate") => self.command(Spirc::activate),
+            (&Method::POST, "/disconnect") => self.command(Spirc::disconnect),
+            (&Method::PUT, "/volume") => {
+                let volume = field(body, "volume")?
+                    .as_u64()
+                    .filter(|volume| *volume <= 100)
+                    .ok_or_else(|| ApiError::BadRequest("`volume` must be 0 - 100".into()))?;
+                let volume = (volume * u16::MAX as u64 / 100) as u16;
+                self.command(|spirc| spirc.set_volume(volume))
+            }
+            (&Method::PUT, "/position") => {
+                let position_ms = field(body, "position_ms")?
+                    .as_u64()
+                    .and_then(|position_ms| u32::try_from(position_ms).ok())
+                    .ok_or_else(|| ApiError::BadRequest("invalid `position_ms`".into()))?;
+                self.command(|spirc| spirc.set_position_ms(position_ms))
+            }
+            (&Method::PUT, "/shuffle") => {
+                let shuffle = bool_field(body, "shuffle")?;
+                self.command(|spirc| spirc.shuffle(shuffle))
+            }
+            (&Method::PUT, "/repeat") => {
+                let repeat = bool_field(body, "repeat")?;
+                self.command(|spirc| spirc.repeat(repeat))
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    fn command(
+        &self,
+        command: impl FnOnce(&Spirc) -> Result<(), librespot::core::Error>,
+    ) -> Result<Value, ApiError> {
+        let spirc = self.spirc.lock().map_err(|_| ApiError::NotConnected)?;
+        command(spirc.as_ref().ok_or(ApiError::NotConnected)?)?;
+        Ok(json!({}))
+    }
+
+    fn status(&self) -> Value {
+        let state = self.spirc.lock().ok().and_then(|spirc| {
+            spirc
+                .as_ref()
+                .map(|spirc| spirc.subscribe_state().borrow().clone())
+        });
+        let (track, position_ms) = self
+            .now_playing
+            .lock()
+            .map(|now_playing| (now_playing.track.clone(), now_playing.position_ms()))
+            .unwrap_or_default();
+
+        match state {
+            Some(state) => json!({
+                "connected": true,
+                "active": state.is_active,
+                "status": match state.status {
+                    PlayStatus::kPlayStatusPlay => "playing",
+                    PlayStatus::kPlayStatusPause => "paused",
+                    PlayStatus::kPlayStatusLoading => "loading",
+                    PlayStatus::kPlayStatusStop => "stopped",
+                },
+                "volume": (state.volume as u64 * 100 + u16::MAX as u64 / 2) / u16::MAX as u64,
+                "shuffle": state.shuffle,
+                "repeat": state.repeat,
+                "context_uri": state.context_uri,
+                "position_ms": position_ms,
+                "track": track,
+            }),
+            None => json!({ "connected": false }),
+        }
+    }
+
+    fn queue(&self) -> Result<Value, ApiError> {
+        let state = {
+            let spirc = self.spirc.lock().map_err(|_| ApiError::NotConnected)?;
+            let spirc = spirc.as_ref().ok_or(ApiError::NotConnected)?;
+            spirc.subscribe_state().borrow().clone()
+        };
+
+        let tracks: Vec<Value> = state
+            .tracks
+            .iter()
+            .map(|track_ref| {
+                let uri = SpotifyId::try_from(track_ref)
+                    .and_then(|id| id.to_uri())
+                    .unwrap_or_else(|_| track_ref.uri().to_owned());
+                json!({ "uri": uri })
+            })
+            .collect();
+
+        Ok(json!({
+            "context_uri": state.context_uri,
+            "playing_index": state.playing_track_index,
+            "tracks": tracks,
+        }))
+    }
+}
+
+fn check_request(method: &Method, headers: &HeaderMap) -> Result<(), ApiError> {
+    // Browsers send the origin of the page along with cross-origin requests.
+    if let Some(origin) = headers.get(ORIGIN) {
+        let host = headers.get(HOST).and_then(|host| host.to_str().ok());
+        let origin_host = origin
+            .to_str()
+            .ok()
+            .and_then(|origin| origin.split_once("://"))
+            .map(|(_, host)| host);
+        if host.is_none() || origin_host != host {
+            return Err(ApiError::ForeignOrigin);
+        }
+    }
+
+    if method == Method::POST || method == Method::PUT {
+        let json = headers
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.split(';').next())
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"));
+        if !json {
+            return Err(ApiError::NotJson);
+        }
+    }
+
+    Ok(())
+}
+
+fn field(body: &[u8], name: &str) -> Result<Value, ApiError> {
+    let mut body: Value = serde_json::from_slice(body)
+        .map_err(|e| ApiError::BadRequest(format!("invalid JSON: {e}")))?;
+    body.get_mut(name)
+        .map(Value::take)
+        .ok_or_else(|| ApiError::BadRequest(format!("missing `{name}`")))
+}
+
+fn bool_field(body: &[u8], name: &str) -> Result<bool, ApiError> {
+    field(body, name)?
+        .as_bool()
+        .ok_or_else(|| ApiError::BadRequest(format!("`{name}` must be a boolean")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(pairs: &[(hyper::header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_request() {
+        let json = (CONTENT_TYPE, "application/json; charset=utf-8");
+        let host = (HOST, "localhost:3000");
+
+        assert!(check_request(&Method::GET, &headers(&[])).is_ok());
+        assert!(check_request(&Method::POST, &headers(std::slice::from_ref(&json))).is_ok());
+        assert!(matches!(
+            check_request(&Method::POST, &headers(&[])),
+            Err(ApiError::NotJson)
+        ));
+        assert!(matches!(
+            check_request(&Method::PUT, &headers(&[(CONTENT_TYPE, "text/plain")])),
+            Err(ApiError::NotJson)
+        ));
+
+        let same_origin = (ORIGIN, "http://localhost:3000");
+        let foreign_origin = (ORIGIN, "https://example.com");
+        assert!(check_request(
+            &Method::POST,
+            &headers(&[json.clone(), host.clone(), same_origin])
+        )
+        .is_ok());
+        assert!(matches!(
+            check_request(&Method::GET, &headers(&[host, foreign_origin.clone()])),
+            Err(ApiError::ForeignOrigin)
+        ));
+        assert!(matches!(
+            check_request(&Method::GET, &headers(&[foreign_origin])),
+            Err(ApiError::ForeignOrigin)
+        ));
+        assert!(matches!(
+            check_request(&Method::GET, &headers(&[(ORIGIN, "null")])),
+            Err(ApiError::ForeignOrigin)
+        ));
+    }
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(field(br#"{"volume": 50}"#, "volume").unwrap(), json!(50));
+        assert!(matches!(
+            field(br#"{"volume": 50}"#, "position_ms"),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            field(b"volume=50", "volume"),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        assert!(bool_field(br#"{"shuffle": true}"#, "shuffle").unwrap());
+        assert!(!bool_field(br#"{"shuffle": false}"#, "shuffle").unwrap());
+        assert!(matches!(
+            bool_field(br#"{"shuffle": "yes"}"#, "shuffle"),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_route() {
+        let shared = Shared::default();
+
+        assert!(matches!(
+            shared.route(&Method::GET, "/nope", b""),
+            Err(ApiError::NotFound)
+        ));
+        assert!(matches!(
+            shared.route(&Method::GET, "/play", b""),
+            Err(ApiError::NotFound)
+        ));
+        assert_eq!(
+            shared.route(&Method::GET, "/status", b"").unwrap(),
+            json!({ "connected": false })
+        );
+        assert!(matches!(
+            shared.route(&Method::POST, "/play", b""),
+            Err(ApiError::NotConnected)
+        ));
+
+        // The volume is validated before looking for a Spotify Connect instance.
+        for volume in [b"0".as_slice(), b"100"] {
+            let body = [br#"{"volume": "#.as_slice(), volume, b"}"].concat();
+            assert!(matches!(
+                shared.route(&Method::PUT, "/volume", &body),
+                Err(ApiError::NotConnected)
+            ));
+        }
+        for body in [
+            br#"{"volume": 101}"#.as_slice(),
+            br#"{"volume": -1}"#,
+            br#"{"volume": 0.5}"#,
+            br#"{}"#,
+        ] {
+            assert!(matches!(
+                shared.route(&Method::PUT, "/volume", body),
+                Err(ApiError::BadRequest(_))
+            ));
+        }
+        assert!(matches!(
+            shared.route(&Method::PUT, "/position", br#"{"position_ms": 4294967296}"#),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
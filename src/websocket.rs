@@ -0,0 +1,281 @@
+use log::{debug, error, info, warn};
+
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use librespot::{
+    connect::spirc::Spirc,
+    discovery::DiscoveryEventChannel,
+    event::{Event, VersionedEvent},
+    playback::player::{PlayerEvent, PlayerEventChannel},
+};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Events that a slow client hasn't received yet before it misses some.
+const BUFFERED_EVENTS: usize = 64;
+
+// The latest event of each kind that describes the current state, which new
+// clients receive first so they don't have to wait for the next change.
+#[derive(Default)]
+struct Snapshot {
+    track: Option<String>,
+    playback: Option<String>,
+    volume: Option<String>,
+    connect: Option<String>,
+}
+
+impl Snapshot {
+    fn events(&self) -> Vec<String> {
+        [&self.connect, &self.track, &self.playback, &self.volume]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+struct Shared {
+    sender: broadcast::Sender<String>,
+    snapshot: Mutex<Snapshot>,
+}
+
+impl Shared {
+    fn send(&self, event: Event) {
+        let json = match serde_json::to_string(&VersionedEvent::from(event.clone())) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Unable to serialize {event:?}: {e}");
+                return;
+            }
+        };
+
+        if let Ok(mut snapshot) = self.snapshot.lock() {
+            match event {
+                Event::TrackChanged { .. } => snapshot.track = Some(json.clone()),
+                Event::Stopped { .. } => {
+                    snapshot.track = None;
+                    snapshot.playback = Some(json.clone());
+                }
+                Event::Loading { .. } | Event::Playing { .. } | Event::Paused { .. } => {
+                    snapshot.playback = Some(json.clone())
+                }
+                Event::VolumeChanged { .. } => snapshot.volume = Some(json.clone()),
+                Event::ConnectStateChanged { .. } => snapshot.connect = Some(json.clone()),
+                _ => (),
+            }
+        }
+
+        // Fails only without clients.
+        let _ = self.sender.send(json);
+    }
+}
+
+/// Broadcasts the events of [`librespot::event`] as JSON text messages to
+/// every WebSocket client, e.g. for live dashboards. While playing, a
+/// `position` event is sent every few seconds.
+pub struct EventServer {
+    shared: Arc<Shared>,
+    tasks: Vec<JoinHandle<()>>,
+    spirc_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl EventServer {
+    pub async fn new(
+        address: SocketAddr,
+        player_events: PlayerEventChannel,
+        discovery_events: Option<DiscoveryEventChannel>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        info!(
+            "WebSocket event server listening on {}",
+            listener.local_addr()?
+        );
+
+        let shared = Arc::new(Shared {
+            sender: broadcast::channel(BUFFERED_EVENTS).0,
+            snapshot: Mutex::default(),
+        });
+
+        let mut tasks = vec![
+            tokio::spawn(Self::serve(shared.clone(), listener)),
+            tokio::spawn(Self::track_player(shared.clone(), player_events)),
+        ];
+
+        if let Some(mut discovery_events) = discovery_events {
+            let shared = shared.clone();
+            tasks.push(tokio::spawn(async move {
+                while let Some(event) = discovery_events.recv().await {
+                    shared.send(event.into());
+                }
+            }));
+        }
+
+        Ok(Self {
+            shared,
+            tasks,
+            spirc_task: Mutex::new(None),
+        })
+    }
+
+    /// Sends the Spotify Connect state of `spirc` whenever it changes, e.g.
+    /// when the queue does. `None` while there is no Spirc instance.
+    pub fn set_spirc(&self, spirc: Option<&Spirc>) {
+        let mut spirc_task = match self.spirc_task.lock() {
+            Ok(spirc_task) => spirc_task,
+            Err(_) => return,
+        };
+
+        if let Some(task) = spirc_task.take() {
+            task.abort();
+        }
+
+        if let Some(spirc) = spirc {
+            let shared = self.shared.clone();
+            let mut state_rx = spirc.subscribe_state();
+            *spirc_task = Some(tokio::spawn(async move {
+                loop {
+                    let event = Event::from(&*state_rx.borrow_and_update());
+                    shared.send(event);
+
+                    if state_rx.changed().await.is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+    }
+
+    async fn serve(shared: Arc<Shared>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, address)) => {
+                    let receiver = shared.sender.subscribe();
+                    let initial = shared
+                        .snapshot
+                        .lock()
+                        .map(|snapshot| snapshot.events())
+                        .unwrap_or_default();
+
+                    tokio::spawn(async move {
+                        if let Err(e) = Self::serve_client(stream, initial, receiver).await {
+                            debug!("WebSocket connection from {address} failed: {e}");
+                        }
+                    });
+                }
+                Err(e) => error!("WebSocket server failed to accept a connection: {e}"),
+            }
+        }
+    }
+
+    async fn serve_client(
+        stream: TcpStream,
+        initial: Vec<String>,
+        mut receiver: broadcast::Receiver<String>,
+    ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+        let mut websocket = tokio_tungstenite::accept_async(stream).await?;
+
+        for json in initial {
+            websocket.send(Message::Text(json)).await?;
+        }
+
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(json) => websocket.send(Message::Text(json)).await?,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        debug!("WebSocket client missed {missed} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                // Clients only send pings, which are answered while reading,
+                // and close frames.
+                message = websocket.next() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => (),
+                },
+            }
+        }
+
+        websocket.close(None).await
+    }
+
+    async fn track_player(shared: Arc<Shared>, mut player_events: PlayerEventChannel) {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        // The track and position, and when it started playing from there.
+        let mut playing: Option<(Option<String>, u32, Instant)> = None;
+
+        loop {
+            tokio::select! {
+                event = player_events.recv() => {
+                    let event = match event {
+                        Some(event) => event,
+                        None => break,
+                    };
+
+                    match &event {
+                        PlayerEvent::Playing {
+                            track_id,
+                            position_ms,
+                            ..
+                        } => playing = Some((track_id.to_uri().ok(), *position_ms, Instant::now())),
+                        PlayerEvent::Seeked {
+                            track_id,
+                            position_ms,
+                            ..
+                        }
+                        | PlayerEvent::PositionCorrection {
+                            track_id,
+                            position_ms,
+                            ..
+                        } if playing.is_some() => {
+                            playing = Some((track_id.to_uri().ok(), *position_ms, Instant::now()))
+                        }
+                        PlayerEvent::Paused { .. }
+                        | PlayerEvent::Stopped { .. }
+                        | PlayerEvent::Loading { .. }
+                        | PlayerEvent::Buffering { .. }
+                        | PlayerEvent::EndOfTrack { .. } => playing = None,
+                        _ => (),
+                    }
+
+                    if let Some(event) = Event::from_player_event(&event) {
+                        shared.send(event);
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if let Some((track_uri, position_ms, since)) = &playing {
+                        shared.send(Event::Position {
+                            track_uri: track_uri.clone(),
+                            position_ms: position_ms
+                                .saturating_add(since.elapsed().as_millis() as u32),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for EventServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Ok(Some(task)) = self.spirc_task.lock().map(|mut task| task.take()) {
+            task.abort();
+        }
+    }
+}